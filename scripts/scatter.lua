@@ -0,0 +1,13 @@
+-- Scatter extra red seeds over the grid before the rules start, then let the rules run.
+-- Run with: cargo run --features lua -- --script scripts/scatter.lua
+
+-- Seeds come from the simulation's RNG, so the same --seed always scatters them the same way.
+
+function step(grid, n, rng)
+    if n == 0 then
+        for _ = 1, 8 do
+            grid:set(rng:int(0, grid:width() - 1), rng:int(0, grid:height() - 1), "Red")
+        end
+    end
+    return false
+end
@@ -0,0 +1,226 @@
+//! Plain text grids: one character per tile (see `AsciiSymbol`), one line per row.
+
+use std::io::{self, BufRead, Write};
+
+use crate::error::{BimpError, Result};
+use crate::ndcoord::Coord;
+use crate::ndgrid::NGrid;
+use crate::rewrite::Grid;
+use crate::tile::AsciiSymbol;
+
+pub fn write_grid<T: AsciiSymbol, O: Write, const W: usize, const H: usize>(
+    grid: &Grid<T, W, H>,
+    out: &mut O,
+) -> io::Result<()> {
+    for row in grid.items.iter() {
+        let line: String = row.iter().map(AsciiSymbol::to_char).collect();
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Read exactly H lines of W symbols. Blank lines before the grid are skipped, so grids written
+/// one after the other separated by blank lines can be read back one at a time.
+pub fn read_grid<T, I, const W: usize, const H: usize>(input: &mut I) -> Result<Grid<T, W, H>>
+where
+    T: AsciiSymbol + Default + Copy,
+    I: BufRead,
+{
+    let mut grid: Grid<T, W, H> = Default::default();
+    let mut lines = input.lines();
+    // line number in the input, including skipped blank lines
+    let mut line_number = 0;
+    let mut y = 0;
+    while y < H {
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => {
+                return Err(BimpError::Parse {
+                    line: line_number + 1,
+                    message: format!("expected {} rows, got {}", H, y),
+                })
+            }
+        };
+        line_number += 1;
+        let line = line.trim_end();
+        if y == 0 && line.is_empty() {
+            continue;
+        }
+        let symbols = line.chars().collect::<Vec<_>>();
+        if symbols.len() != W {
+            return Err(BimpError::Parse {
+                line: line_number,
+                message: format!("row has {} tiles, expected {}", symbols.len(), W),
+            });
+        }
+        for (x, &c) in symbols.iter().enumerate() {
+            grid.items[y][x] = T::from_char(c).ok_or_else(|| BimpError::BadSymbol {
+                symbol: c.to_string(),
+                line: line_number,
+                column: x + 1,
+            })?;
+        }
+        y += 1;
+    }
+    Ok(grid)
+}
+
+/// Like read_grid, but returns None instead of an error if the input ends before the next
+/// grid starts, for reading a stream of grids separated by blank lines
+pub fn read_next_grid<T, I, const W: usize, const H: usize>(
+    input: &mut I,
+) -> Result<Option<Grid<T, W, H>>>
+where
+    T: AsciiSymbol + Default + Copy,
+    I: BufRead,
+{
+    loop {
+        let buf = input.fill_buf()?;
+        match buf.first() {
+            None => return Ok(None),
+            Some(b'\n' | b'\r') => input.consume(1),
+            Some(_) => return read_grid(input).map(Some),
+        }
+    }
+}
+
+/// Read a 3D grid of (x, y, layer) written as its layers one after the other, the bottom layer
+/// first, separated by blank lines. The first layer sets the width and height.
+pub fn read_volume<T, I>(input: &mut I) -> Result<NGrid<T, 3>>
+where
+    T: AsciiSymbol,
+    I: BufRead,
+{
+    let mut items = Vec::new();
+    let (mut width, mut height, mut depth) = (0, 0, 0);
+    // rows read of the current layer
+    let mut rows = 0;
+    let end_layer = |rows: usize, height: &mut usize, depth: &mut usize, line: usize| {
+        if *depth > 0 && rows != *height {
+            return Err(BimpError::Parse {
+                line,
+                message: format!("layer has {} rows, expected {}", rows, height),
+            });
+        }
+        *height = rows;
+        *depth += 1;
+        Ok(())
+    };
+    let mut line_number = 0;
+    for line in input.lines() {
+        let line = line?;
+        line_number += 1;
+        let line = line.trim_end();
+        if line.is_empty() {
+            if rows > 0 {
+                end_layer(rows, &mut height, &mut depth, line_number)?;
+                rows = 0;
+            }
+            continue;
+        }
+        let symbols = line.chars().collect::<Vec<_>>();
+        if width == 0 {
+            width = symbols.len();
+        } else if symbols.len() != width {
+            return Err(BimpError::Parse {
+                line: line_number,
+                message: format!("row has {} tiles, expected {}", symbols.len(), width),
+            });
+        }
+        for (x, &c) in symbols.iter().enumerate() {
+            items.push(T::from_char(c).ok_or_else(|| BimpError::BadSymbol {
+                symbol: c.to_string(),
+                line: line_number,
+                column: x + 1,
+            })?);
+        }
+        rows += 1;
+    }
+    if rows > 0 {
+        end_layer(rows, &mut height, &mut depth, line_number + 1)?;
+    }
+    if depth == 0 {
+        return Err(BimpError::Parse {
+            line: line_number + 1,
+            message: "expected at least one layer".to_string(),
+        });
+    }
+    NGrid::new(
+        items,
+        Coord::new_3d(width as isize, height as isize, depth as isize),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile;
+
+    #[test]
+    fn round_trip() {
+        let mut grid: Grid<Tile, 3, 2> = Default::default();
+        grid.items[0][1] = Tile::Red;
+        grid.items[1][2] = Tile::White;
+
+        let mut text = Vec::new();
+        write_grid(&grid, &mut text).unwrap();
+        assert_eq!(String::from_utf8(text.clone()).unwrap(), "BRB\nBBW\n");
+
+        let read: Grid<Tile, 3, 2> = read_grid(&mut text.as_slice()).unwrap();
+        assert_eq!(read.items, grid.items);
+    }
+
+    #[test]
+    fn consecutive_grids() {
+        let mut text = "RB\nBB\n\nBB\nBR\n".as_bytes();
+        let first: Grid<Tile, 2, 2> = read_grid(&mut text).unwrap();
+        let second: Grid<Tile, 2, 2> = read_grid(&mut text).unwrap();
+        assert_eq!(first.items[0][0], Tile::Red);
+        assert_eq!(second.items[1][1], Tile::Red);
+    }
+
+    #[test]
+    fn grid_stream() {
+        let mut text = "\nRB\nBB\n\n\nBB\nBR\n\n".as_bytes();
+        let mut grids = Vec::new();
+        while let Some(grid) = read_next_grid::<Tile, _, 2, 2>(&mut text).unwrap() {
+            grids.push(grid);
+        }
+        assert_eq!(grids.len(), 2);
+        assert_eq!(grids[1].items[1][1], Tile::Red);
+        // a grid cut short is still an error
+        assert!(read_next_grid::<Tile, _, 2, 2>(&mut "BB\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let short = read_grid::<Tile, _, 2, 2>(&mut "BB\n".as_bytes());
+        assert!(short.is_err());
+        let narrow = read_grid::<Tile, _, 2, 2>(&mut "BB\nB\n".as_bytes());
+        assert!(narrow.is_err());
+        let unknown = read_grid::<Tile, _, 2, 2>(&mut "\nBB\nBZ\n".as_bytes());
+        assert!(matches!(
+            unknown,
+            Err(BimpError::BadSymbol {
+                line: 3,
+                column: 2,
+                ..
+            })
+        ));
+        assert!(unknown.unwrap_err().to_string().contains("'Z'"));
+    }
+
+    #[test]
+    fn volume_layers() {
+        let volume: NGrid<Tile, 3> =
+            read_volume(&mut "\nRB\nBB\nBB\n\n\nBB\nBB\nBW\n".as_bytes()).unwrap();
+        assert_eq!(volume.size(), &Coord::new_3d(2, 3, 2));
+        assert_eq!(volume[&Coord::new_3d(0, 0, 0)], Tile::Red);
+        assert_eq!(volume[&Coord::new_3d(1, 2, 1)], Tile::White);
+
+        let uneven = read_volume::<Tile, _>(&mut "BB\nBB\n\nBB\n".as_bytes());
+        assert!(matches!(uneven, Err(BimpError::Parse { line: 5, .. })));
+        assert!(read_volume::<Tile, _>(&mut "BB\nB\n".as_bytes()).is_err());
+        assert!(read_volume::<Tile, _>(&mut "\n\n".as_bytes()).is_err());
+    }
+}
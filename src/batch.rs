@@ -0,0 +1,231 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use bimp::ascii;
+use bimp::error::{BimpError, Result};
+use bimp::metadata::{self, Metadata};
+use bimp::metrics;
+use bimp::models;
+use bimp::rewrite::Grid;
+#[cfg(feature = "lua")]
+use bimp::script::LuaScore;
+use bimp::tile::{Named, Tile};
+use tracing::info;
+
+use crate::cli::Options;
+use crate::export;
+use crate::progress::{Progress, Status};
+
+/// How the final grid of a run is rated, higher is better
+#[derive(Clone, Debug, PartialEq)]
+pub enum Metric {
+    /// `count:Tile`, number of cells holding the tile
+    Count(Tile),
+    /// `components:Tile`, number of separate 4-connected regions of the tile
+    Components(Tile),
+    /// `adjacent:Tile,Tile`, number of 4-neighbour pairs of the two tiles
+    Adjacent(Tile, Tile),
+    /// `lua:path`, a script defining `score(grid)`, see `LuaScore`
+    Lua(PathBuf),
+}
+
+impl Metric {
+    pub fn parse(s: &str) -> std::result::Result<Self, String> {
+        let (kind, arg) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected a metric like count:Red, got '{}'", s))?;
+        let tile =
+            |name: &str| Tile::from_name(name).ok_or_else(|| format!("no tile named '{}'", name));
+        match kind {
+            "count" => Ok(Metric::Count(tile(arg)?)),
+            "components" => Ok(Metric::Components(tile(arg)?)),
+            "adjacent" => {
+                let (a, b) = arg
+                    .split_once(',')
+                    .ok_or_else(|| format!("expected two tiles like Red,White, got '{}'", arg))?;
+                Ok(Metric::Adjacent(tile(a)?, tile(b)?))
+            }
+            "lua" if cfg!(feature = "lua") => Ok(Metric::Lua(PathBuf::from(arg))),
+            "lua" => Err("lua metrics need bimp to be built with the lua feature".to_string()),
+            other => Err(format!(
+                "unknown metric '{}', expected count, components, adjacent or lua",
+                other
+            )),
+        }
+    }
+}
+
+/// Rates grids for one thread. Lua states can't be shared between threads, so each thread loads
+/// its own copy of a script.
+pub enum Scorer {
+    Count(Tile),
+    Components(Tile),
+    Adjacent(Tile, Tile),
+    #[cfg(feature = "lua")]
+    Lua(LuaScore),
+}
+
+impl Scorer {
+    pub fn new(metric: &Metric) -> Result<Self> {
+        Ok(match metric {
+            Metric::Count(tile) => Scorer::Count(*tile),
+            Metric::Components(tile) => Scorer::Components(*tile),
+            Metric::Adjacent(a, b) => Scorer::Adjacent(*a, *b),
+            #[cfg(feature = "lua")]
+            Metric::Lua(path) => Scorer::Lua(LuaScore::load(path)?),
+            #[cfg(not(feature = "lua"))]
+            Metric::Lua(_) => unreachable!("rejected by Metric::parse"),
+        })
+    }
+
+    pub fn score<const W: usize, const H: usize>(&self, grid: &Grid<Tile, W, H>) -> Result<f64> {
+        Ok(match self {
+            Scorer::Count(tile) => metrics::count(grid, tile) as f64,
+            Scorer::Components(tile) => metrics::components(grid, tile) as f64,
+            Scorer::Adjacent(a, b) => metrics::adjacent(grid, a, b) as f64,
+            #[cfg(feature = "lua")]
+            Scorer::Lua(script) => script.score(grid)?,
+        })
+    }
+}
+
+struct Outcome {
+    seed: u64,
+    score: f64,
+    steps: usize,
+    grid: Grid<Tile, { models::WIDTH }, { models::HEIGHT }>,
+    metadata: Metadata,
+}
+
+/// Run `runs` seeds, starting at `first_seed`, across all cores. Writes summary.csv ranking every
+/// run, and the final grids of the best `options.top` runs in the ascii format. Reports how many
+/// runs have finished as it goes, see Progress.
+pub fn run(options: &Options, runs: usize, first_seed: u64) -> Result<()> {
+    let metric = options.metric.as_ref().expect("checked by cli");
+    let max_steps = options.max_steps.expect("checked by cli");
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(runs.max(1));
+    options.warn_about_rules(&options.initial_grid(first_seed));
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let progress = Mutex::new(Progress::new(options));
+
+    let mut outcomes = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| -> Result<Vec<Outcome>> {
+                    let scorer = Scorer::new(metric)?;
+                    let mut outcomes = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= runs {
+                            return Ok(outcomes);
+                        }
+                        let seed = first_seed.wrapping_add(i as u64);
+                        let mut sim = options.simulation(seed);
+                        options.configure(&mut sim)?;
+                        sim.run(max_steps);
+                        let score = scorer.score(&sim.grid)?;
+                        info!(seed, score, steps = sim.steps, "run finished");
+                        let finished = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                        let mut progress = progress.lock().expect("progress lock poisoned");
+                        if progress.due() || finished == runs {
+                            let status = Status {
+                                unit: "run",
+                                done: finished,
+                                total: Some(runs),
+                                matches: None,
+                                elapsed: progress.elapsed(),
+                            };
+                            progress.report(&status);
+                        }
+                        outcomes.push(Outcome {
+                            seed,
+                            score,
+                            steps: sim.steps,
+                            metadata: export::metadata(&sim),
+                            grid: sim.grid,
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("batch worker panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    // best first, ties broken by seed so the ranking doesn't depend on thread timing
+    outcomes.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.seed.cmp(&b.seed)));
+    write_results(&outcomes, options)
+}
+
+fn write_results(outcomes: &[Outcome], options: &Options) -> Result<()> {
+    let dir = &options.out_dir("batch");
+    let file_error = |path: PathBuf| move |source| BimpError::File { path, source };
+    fs::create_dir_all(dir).map_err(file_error(dir.clone()))?;
+
+    let summary_path = dir.join("summary.csv");
+    let mut summary =
+        BufWriter::new(File::create(&summary_path).map_err(file_error(summary_path.clone()))?);
+    writeln!(summary, "rank,seed,score,steps")?;
+    for (rank, outcome) in outcomes.iter().enumerate() {
+        writeln!(
+            summary,
+            "{},{},{},{}",
+            rank + 1,
+            outcome.seed,
+            outcome.score,
+            outcome.steps
+        )?;
+    }
+    summary.flush()?;
+
+    for (rank, outcome) in outcomes.iter().take(options.top).enumerate() {
+        let path = dir.join(format!("{:03}-seed-{}.txt", rank + 1, outcome.seed));
+        let mut out = BufWriter::new(File::create(&path).map_err(file_error(path.clone()))?);
+        ascii::write_grid(&outcome.grid, &mut out)?;
+        out.flush()?;
+        let sidecar = metadata::sidecar_path(&path);
+        outcome
+            .metadata
+            .write_sidecar(&path)
+            .map_err(file_error(sidecar))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_metrics() {
+        assert_eq!(Metric::parse("count:Red"), Ok(Metric::Count(Tile::Red)));
+        assert_eq!(
+            Metric::parse("components:White"),
+            Ok(Metric::Components(Tile::White))
+        );
+        assert_eq!(
+            Metric::parse("adjacent:Red,White"),
+            Ok(Metric::Adjacent(Tile::Red, Tile::White))
+        );
+        assert!(Metric::parse("adjacent:Red").is_err());
+        assert!(Metric::parse("count").is_err());
+        assert!(Metric::parse("volume:Red").is_err());
+        assert_eq!(
+            Metric::parse("lua:score.lua").is_ok(),
+            cfg!(feature = "lua")
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! What lies beyond the edges of a grid, set separately for each axis. Eg. wrapping x and
+//! clamping y makes a cylinder, for outputs which are rolled into a ring.
+
+use std::ops::Range;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Boundary {
+    /// Nothing: patches can't match cells beyond the edge and writes there are dropped
+    #[default]
+    Clamp,
+    /// The opposite edge, so cell -1 is the last cell
+    Wrap,
+    /// The grid reflected at the edge, so cell -1 is cell 0 and cell -2 is cell 1. Matches read
+    /// the reflected cells but writes beyond the edge are dropped.
+    Mirror,
+}
+
+impl Boundary {
+    pub const NAMES: [&'static str; 3] = ["clamp", "wrap", "mirror"];
+
+    /// The name FromStr reads
+    pub fn name(&self) -> &'static str {
+        match self {
+            Boundary::Clamp => "clamp",
+            Boundary::Wrap => "wrap",
+            Boundary::Mirror => "mirror",
+        }
+    }
+
+    /// Cell read at index `i` of an axis `len` cells long, or None if there is none
+    pub fn resolve(&self, i: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if len == 0 {
+            return None;
+        }
+        match self {
+            _ if (0..len).contains(&i) => Some(i as usize),
+            Boundary::Clamp => None,
+            Boundary::Wrap => Some(i.rem_euclid(len) as usize),
+            Boundary::Mirror => {
+                let folded = i.rem_euclid(2 * len);
+                Some(if folded < len {
+                    folded
+                } else {
+                    2 * len - 1 - folded
+                } as usize)
+            }
+        }
+    }
+
+    /// Cell written at index `i`, or None if the write is dropped
+    pub fn resolve_write(&self, i: isize, len: usize) -> Option<usize> {
+        match self {
+            Boundary::Mirror => Boundary::Clamp.resolve(i, len),
+            other => other.resolve(i, len),
+        }
+    }
+
+    /// Offsets of a patch `size` cells long which overlap the axis. When wrapping every offset
+    /// outside the grid is the same as one inside it, so only those are tried.
+    pub fn offsets(&self, len: usize, size: usize) -> Range<isize> {
+        match self {
+            Boundary::Wrap => 0..len as isize,
+            _ => -(size as isize - 1)..len as isize,
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(Boundary::Clamp),
+            "wrap" => Ok(Boundary::Wrap),
+            "mirror" => Ok(Boundary::Mirror),
+            _ => Err(format!(
+                "unknown boundary '{}', expected one of {}",
+                s,
+                Boundary::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Boundaries {
+    pub x: Boundary,
+    pub y: Boundary,
+}
+
+impl Boundaries {
+    /// Every patch cell has to be inside the grid, the default
+    pub const CLAMP: Self = Self::new(Boundary::Clamp, Boundary::Clamp);
+
+    pub const fn new(x: Boundary, y: Boundary) -> Self {
+        Self { x, y }
+    }
+
+    /// (x, y) of the cell read at (x, y) in a `width` x `height` grid
+    pub fn resolve(
+        &self,
+        (x, y): (isize, isize),
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        Some((self.x.resolve(x, width)?, self.y.resolve(y, height)?))
+    }
+
+    /// (x, y) of the cell written at (x, y), or None if the write is dropped
+    pub fn resolve_write(
+        &self,
+        (x, y): (isize, isize),
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        Some((
+            self.x.resolve_write(x, width)?,
+            self.y.resolve_write(y, height)?,
+        ))
+    }
+
+    /// Move a position on a wrapped axis back inside the grid, so the same placement always has
+    /// the same position
+    pub fn normalize(&self, (x, y): (isize, isize), width: usize, height: usize) -> (isize, isize) {
+        let wrap = |boundary: Boundary, i: isize, len: usize| match boundary {
+            Boundary::Wrap if len > 0 => i.rem_euclid(len as isize),
+            _ => i,
+        };
+        (wrap(self.x, x, width), wrap(self.y, y, height))
+    }
+}
+
+/// "X,Y" where each is a boundary name, or a single name for both axes
+impl FromStr for Boundaries {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(',') {
+            Some((x, y)) => Ok(Self::new(x.parse()?, y.parse()?)),
+            None => {
+                let both = s.parse()?;
+                Ok(Self::new(both, both))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        assert_eq!(Boundary::Clamp.resolve(-1, 4), None);
+        assert_eq!(Boundary::Clamp.resolve(3, 4), Some(3));
+        assert_eq!(Boundary::Wrap.resolve(-1, 4), Some(3));
+        assert_eq!(Boundary::Wrap.resolve(9, 4), Some(1));
+        let mirrored = (-3..7)
+            .map(|i| Boundary::Mirror.resolve(i, 4).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(mirrored, [2, 1, 0, 0, 1, 2, 3, 3, 2, 1]);
+        assert_eq!(Boundary::Mirror.resolve_write(-1, 4), None);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "wrap,clamp".parse(),
+            Ok(Boundaries::new(Boundary::Wrap, Boundary::Clamp))
+        );
+        assert_eq!(
+            "mirror".parse(),
+            Ok(Boundaries::new(Boundary::Mirror, Boundary::Mirror))
+        );
+        assert!("wrap,torus".parse::<Boundaries>().is_err());
+        for name in Boundary::NAMES {
+            assert_eq!(name.parse::<Boundary>().unwrap().name(), name);
+        }
+    }
+}
@@ -0,0 +1,851 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bimp::boundary::Boundaries;
+use bimp::constraint::Connected;
+use bimp::matcher;
+use bimp::model_file::{self, ModelFile};
+use bimp::models;
+use bimp::rewrite::{Grid, ReplacementRule};
+use bimp::scheduler::{self, Annealing};
+use bimp::search::Strategy;
+use bimp::simulation::Simulation;
+use bimp::sonify::{self, RuleSound};
+use bimp::symmetry::Symmetry;
+use bimp::tile::{AsciiSymbol, Tile};
+use bimp::validate;
+
+use crate::batch::{Metric, Scorer};
+use crate::debug_view::DebugView;
+use crate::keyframes::KeyframeFormat;
+use crate::solve::Goal;
+
+/// Scales a --model can refine by, each divides the grid's size
+const REFINE_SCALES: [usize; 3] = [2, 4, 8];
+
+/// Options given on the command line
+pub struct Options {
+    /// (width, height) in pixels of screenshots, independent of the window size
+    pub screenshot_size: (u32, u32),
+    /// Lua script run every step before the rules, needs the `lua` feature
+    pub script: Option<PathBuf>,
+    /// RNG seed, random if not given
+    pub seed: Option<u64>,
+    /// Run without a window and write grids to stdout
+    pub headless: bool,
+    /// Show the hex model instead of the square one. Only in the window.
+    pub hex: bool,
+    /// Show the 3D grid in this file instead of the model, see ascii::read_volume. Only in the
+    /// window.
+    pub volume: Option<PathBuf>,
+    /// Run the model in this file instead of the built in one, see model_file. Not with --hex or
+    /// --volume.
+    pub model: Option<PathBuf>,
+    /// The text of --model, read and checked by from_env
+    pub(crate) model_text: Option<String>,
+    /// Open a second window showing this view of the simulation, updated along with the first
+    pub second_window: Option<DebugView>,
+    /// Steps per second in the window, instead of 100 steps every frame. Also the rate notes
+    /// are played at with --sonify, 20 by default.
+    pub speed: Option<f64>,
+    /// Step for this long every frame in the window, instead of 100 steps every frame, so the
+    /// frame rate holds up however slow matching is
+    pub frame_budget: Option<Duration>,
+    /// When running at a --speed, seconds changed cells take to fade to their new color. 0
+    /// snaps them straight away.
+    pub tween: f32,
+    /// Read the initial grid from stdin instead of using the model's. Implies headless.
+    pub stdin: bool,
+    /// Run the model from each grid read from stdin, separated by blank lines, writing each
+    /// result to stdout. Implies headless.
+    pub stream: bool,
+    /// Run the model from each grid file which appears in this folder, see stream::run_watch.
+    /// Implies headless.
+    pub watch: Option<PathBuf>,
+    /// In headless mode, write the grid after every step instead of only the final grid
+    pub every_step: bool,
+    /// Save the grid into keyframe_dir every this many steps, see keyframes::Keyframes.
+    /// Implies headless.
+    pub keyframes: Option<usize>,
+    pub keyframe_dir: PathBuf,
+    pub keyframe_format: KeyframeFormat,
+    /// Don't write progress to stderr during headless runs
+    pub quiet: bool,
+    /// File rewritten with the progress of a headless run every second
+    pub status_file: Option<PathBuf>,
+    /// In headless mode, stop after this many steps even if rules still match. Steps where
+    /// annealing rejected the application count too.
+    pub max_steps: Option<usize>,
+    /// Name of the match strategy, one of matcher::NAMES
+    pub matcher: String,
+    /// Strict deterministic mode, results don't depend on the match strategy
+    pub deterministic: bool,
+    /// Stop when a grid from the last this many steps comes back, see
+    /// Simulation::set_cycle_detection
+    pub cycle_window: Option<usize>,
+    /// Tiles whose regions rules may never split, see constraint::Connected. Empty for none.
+    pub keep_connected: Vec<Tile>,
+    /// In headless mode, write a WAV file of a note for every rule application, see sonify
+    pub sonify: Option<PathBuf>,
+    /// MIDI note of each rule for --sonify, None for a silent rule. Rules without one go up the
+    /// pentatonic scale.
+    pub rule_notes: Vec<Option<i32>>,
+    /// Time each rule. Shown in the window's overlay (F3), or written to stderr when a headless
+    /// run ends.
+    pub profile: bool,
+    /// Name of the rule scheduler, one of scheduler::NAMES
+    pub scheduler: String,
+    /// Replaces the symmetry of every rule in the model
+    pub symmetry: Option<Symmetry>,
+    /// Replaces the boundaries of every rule in the model
+    pub boundaries: Option<Boundaries>,
+    /// Run this many seeds in parallel and rank the results instead of running once, each for
+    /// at most --max-steps. Implies headless.
+    pub batch: Option<usize>,
+    /// How batch results are ranked
+    pub metric: Option<Metric>,
+    /// Number of best batch results to keep
+    pub top: usize,
+    /// Directory batch results (batch by default) or imported models (models by default) are
+    /// written to
+    pub out: Option<PathBuf>,
+    /// Search for a sequence of rule applications reaching the goal and write it to stdout
+    /// instead of running. Implies headless.
+    pub search: Option<Strategy>,
+    pub goal: Option<Goal>,
+    /// Longest sequence of rule applications searched
+    pub max_depth: usize,
+    /// Distinct grids visited before a search gives up
+    pub max_states: usize,
+    /// Apply the rule applications in this file, as written by a search, and write the final
+    /// grid. Implies headless.
+    pub replay: Option<PathBuf>,
+    /// Convert the MarkovJunior resources in this directory (palette.xml and models/) to model
+    /// files in --out, reporting what couldn't be converted. Implies headless.
+    pub import_mj: Option<PathBuf>,
+    /// Starting temperature for simulated annealing, replaces the scheduler. Needs --energy.
+    pub anneal: Option<f64>,
+    /// The annealing temperature is multiplied by this after every step
+    pub cooling: f64,
+    /// Energy minimised by annealing
+    pub energy: Option<Metric>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            screenshot_size: (1024, 1024),
+            script: None,
+            seed: None,
+            headless: false,
+            hex: false,
+            volume: None,
+            model: None,
+            model_text: None,
+            second_window: None,
+            speed: None,
+            frame_budget: None,
+            tween: 0.25,
+            stdin: false,
+            stream: false,
+            watch: None,
+            every_step: false,
+            keyframes: None,
+            keyframe_dir: PathBuf::from("keyframes"),
+            keyframe_format: KeyframeFormat::Both,
+            quiet: false,
+            status_file: None,
+            max_steps: None,
+            matcher: "naive".to_string(),
+            deterministic: false,
+            profile: false,
+            sonify: None,
+            rule_notes: Vec::new(),
+            cycle_window: None,
+            keep_connected: Vec::new(),
+            scheduler: "priority".to_string(),
+            symmetry: None,
+            boundaries: None,
+            batch: None,
+            metric: None,
+            top: 10,
+            out: None,
+            search: None,
+            goal: None,
+            max_depth: 1000,
+            max_states: 1_000_000,
+            replay: None,
+            import_mj: None,
+            anneal: None,
+            cooling: 0.999,
+            energy: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the process's arguments and read --model
+    pub fn from_env() -> Result<Self, String> {
+        let mut options = Self::parse(env::args().skip(1))?;
+        if let Some(path) = &options.model {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            let model = model_file::read_model::<Tile, _, 3>(&mut text.as_bytes())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            if let Some(refinement) = model.refine {
+                if !REFINE_SCALES.contains(&refinement.scale) {
+                    return Err(format!(
+                        "{}: refine's scale has to be one of {:?} for the {}x{} grid",
+                        path.display(),
+                        REFINE_SCALES,
+                        models::WIDTH,
+                        models::HEIGHT
+                    ));
+                }
+            }
+            options.model_text = Some(text);
+        }
+        Ok(options)
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--screenshot-size" => {
+                    let value = args.next().ok_or("--screenshot-size needs a value")?;
+                    options.screenshot_size = parse_size(&value)?;
+                }
+                "--script" => {
+                    let value = args.next().ok_or("--script needs a file")?;
+                    options.script = Some(PathBuf::from(value));
+                }
+                "--seed" => options.seed = Some(parse_number(args.next(), "--seed")?),
+                "--headless" => options.headless = true,
+                "--hex" => options.hex = true,
+                "--volume" => {
+                    let value = args.next().ok_or("--volume needs a file")?;
+                    options.volume = Some(PathBuf::from(value));
+                }
+                "--model" => {
+                    let value = args.next().ok_or("--model needs a file")?;
+                    options.model = Some(PathBuf::from(value));
+                }
+                "--second-window" => {
+                    let value = args.next().ok_or("--second-window needs a view")?;
+                    options.second_window = Some(value.parse()?);
+                }
+                "--speed" => options.speed = Some(parse_number(args.next(), "--speed")?),
+                "--frame-budget" => {
+                    let millis = parse_number(args.next(), "--frame-budget")?;
+                    options.frame_budget = Some(Duration::from_millis(millis));
+                }
+                "--tween" => options.tween = parse_number(args.next(), "--tween")?,
+                "--stdin" => {
+                    options.stdin = true;
+                    options.headless = true;
+                }
+                "--stream" => {
+                    options.stream = true;
+                    options.headless = true;
+                }
+                "--watch" => {
+                    let value = args.next().ok_or("--watch needs a folder")?;
+                    options.watch = Some(PathBuf::from(value));
+                    options.headless = true;
+                }
+                "--every-step" => options.every_step = true,
+                "--keyframes" => {
+                    let every = parse_number(args.next(), "--keyframes")?;
+                    if every == 0 {
+                        return Err("--keyframes needs a number of steps above 0".to_string());
+                    }
+                    options.keyframes = Some(every);
+                    options.headless = true;
+                }
+                "--keyframe-dir" => {
+                    let value = args.next().ok_or("--keyframe-dir needs a directory")?;
+                    options.keyframe_dir = PathBuf::from(value);
+                }
+                "--keyframe-format" => {
+                    let value = args.next().ok_or("--keyframe-format needs a value")?;
+                    options.keyframe_format = value.parse()?;
+                }
+                "--quiet" => options.quiet = true,
+                "--status-file" => {
+                    let value = args.next().ok_or("--status-file needs a file")?;
+                    options.status_file = Some(PathBuf::from(value));
+                }
+                "--max-steps" => {
+                    options.max_steps = Some(parse_number(args.next(), "--max-steps")?)
+                }
+                "--matcher" => {
+                    let value = args.next().ok_or("--matcher needs a name")?;
+                    if !matcher::NAMES.contains(&value.as_str()) {
+                        return Err(format!(
+                            "unknown matcher '{}', expected one of {}",
+                            value,
+                            matcher::NAMES.join(", ")
+                        ));
+                    }
+                    options.matcher = value;
+                }
+                "--deterministic" => options.deterministic = true,
+                "--profile" => options.profile = true,
+                "--sonify" => {
+                    let value = args.next().ok_or("--sonify needs a file")?;
+                    options.sonify = Some(PathBuf::from(value));
+                }
+                "--rule-notes" => {
+                    let value = args.next().ok_or("--rule-notes needs a value")?;
+                    options.rule_notes = parse_rule_notes(&value)?;
+                }
+                "--detect-cycles" => {
+                    options.cycle_window = Some(parse_number(args.next(), "--detect-cycles")?)
+                }
+                "--keep-connected" => {
+                    let value = args.next().ok_or("--keep-connected needs tile letters")?;
+                    options.keep_connected = parse_tiles(&value)?;
+                }
+                "--scheduler" => {
+                    let value = args.next().ok_or("--scheduler needs a name")?;
+                    if !scheduler::NAMES.contains(&value.as_str()) {
+                        return Err(format!(
+                            "unknown scheduler '{}', expected one of {}",
+                            value,
+                            scheduler::NAMES.join(", ")
+                        ));
+                    }
+                    options.scheduler = value;
+                }
+                "--symmetry" => {
+                    let value = args.next().ok_or("--symmetry needs a value")?;
+                    options.symmetry = Some(value.parse()?);
+                }
+                "--boundary" => {
+                    let value = args.next().ok_or("--boundary needs a value")?;
+                    options.boundaries = Some(value.parse()?);
+                }
+                "--batch" => {
+                    options.batch = Some(parse_number(args.next(), "--batch")?);
+                    options.headless = true;
+                }
+                "--metric" => {
+                    let value = args.next().ok_or("--metric needs a value")?;
+                    options.metric = Some(Metric::parse(&value)?);
+                }
+                "--top" => options.top = parse_number(args.next(), "--top")?,
+                "--out" => {
+                    let value = args.next().ok_or("--out needs a directory")?;
+                    options.out = Some(PathBuf::from(value));
+                }
+                "--search" => {
+                    options.search = Some(match args.next().as_deref() {
+                        Some("dfs") => Strategy::DepthFirst,
+                        Some("bfs") => Strategy::BreadthFirst,
+                        _ => return Err("--search needs dfs or bfs".to_string()),
+                    });
+                    options.headless = true;
+                }
+                "--goal" => {
+                    let value = args.next().ok_or("--goal needs a value")?;
+                    options.goal = Some(Goal::parse(&value)?);
+                }
+                "--max-depth" => options.max_depth = parse_number(args.next(), "--max-depth")?,
+                "--max-states" => options.max_states = parse_number(args.next(), "--max-states")?,
+                "--replay" => {
+                    let value = args.next().ok_or("--replay needs a file")?;
+                    options.replay = Some(PathBuf::from(value));
+                    options.headless = true;
+                }
+                "--import-mj" => {
+                    let value = args.next().ok_or("--import-mj needs a directory")?;
+                    options.import_mj = Some(PathBuf::from(value));
+                    options.headless = true;
+                }
+                "--anneal" => options.anneal = Some(parse_number(args.next(), "--anneal")?),
+                "--cooling" => options.cooling = parse_number(args.next(), "--cooling")?,
+                "--energy" => {
+                    let value = args.next().ok_or("--energy needs a value")?;
+                    options.energy = Some(Metric::parse(&value)?);
+                }
+                other => return Err(format!("unknown argument '{}'", other)),
+            }
+        }
+        if options.hex && options.headless {
+            return Err("--hex is only supported in the window".to_string());
+        }
+        if options.volume.is_some() && (options.hex || options.headless) {
+            return Err("--volume is only supported in the window, without --hex".to_string());
+        }
+        if options.model.is_some() && (options.hex || options.volume.is_some()) {
+            return Err("--model can't be used with --hex or --volume".to_string());
+        }
+        if options.second_window.is_some() && (options.hex || options.headless) {
+            return Err(
+                "--second-window only works with the square grid in the window".to_string(),
+            );
+        }
+        if options.speed.is_some() && options.frame_budget.is_some() {
+            return Err("--speed and --frame-budget can't be used together".to_string());
+        }
+        let inputs = [options.stdin, options.stream, options.watch.is_some()];
+        if inputs.into_iter().filter(|&input| input).count() > 1 {
+            return Err("only one of --stdin, --stream and --watch can be used".to_string());
+        }
+        let other_runs = options.batch.is_some()
+            || options.search.is_some()
+            || options.replay.is_some()
+            || options.stream
+            || options.watch.is_some();
+        if options.keyframes.is_some() && other_runs {
+            return Err("--keyframes only works with a single headless run".to_string());
+        }
+        if options.batch.is_some() && options.metric.is_none() {
+            return Err("--batch needs a --metric".to_string());
+        }
+        // every run has to end for the batch to, and most models never stop changing
+        if options.batch.is_some() && options.max_steps.is_none() {
+            return Err("--batch needs --max-steps".to_string());
+        }
+        if options.anneal.is_some() && options.energy.is_none() {
+            return Err("--anneal needs an --energy".to_string());
+        }
+        if options.search.is_some() && options.goal.is_none() {
+            return Err("--search needs a --goal".to_string());
+        }
+        Ok(options)
+    }
+
+    /// The model's rules, with the symmetry and boundaries chosen on the command line
+    pub fn rules(&self) -> Vec<ReplacementRule<Tile, 3>> {
+        let mut rules = self.model_rules();
+        self.override_rules(&mut rules);
+        rules
+    }
+
+    /// The rules of --model, or the built in model's
+    fn model_rules(&self) -> Vec<ReplacementRule<Tile, 3>> {
+        match self.model_file() {
+            Some(model) => model.rules,
+            None => models::rules(),
+        }
+    }
+
+    /// The initial grid of --model, drawn with `seed`, or the built in model's
+    pub fn initial_grid(&self, seed: u64) -> Grid<Tile, { models::WIDTH }, { models::HEIGHT }> {
+        const W: usize = models::WIDTH;
+        const H: usize = models::HEIGHT;
+        let Some(mut model) = self.model_file() else {
+            return models::initial_grid();
+        };
+        match model.refine.as_ref().map(|refinement| refinement.scale) {
+            None => model.initial_grid(seed),
+            Some(2) => model.refined_grid::<W, H, { W / 2 }, { H / 2 }, 2>(seed),
+            Some(4) => model.refined_grid::<W, H, { W / 4 }, { H / 4 }, 4>(seed),
+            Some(8) => model.refined_grid::<W, H, { W / 8 }, { H / 8 }, 8>(seed),
+            Some(_) => unreachable!("checked by from_env"),
+        }
+    }
+
+    /// The model's simulation, before configure
+    pub fn simulation(
+        &self,
+        seed: u64,
+    ) -> Simulation<Tile, { models::WIDTH }, { models::HEIGHT }, 3> {
+        Simulation::new(self.initial_grid(seed), self.model_rules(), seed)
+    }
+
+    fn model_file(&self) -> Option<ModelFile<Tile, 3>> {
+        self.model_text
+            .as_ref()
+            .map(|text| model_file::read_model(&mut text.as_bytes()).expect("checked by from_env"))
+    }
+
+    /// Where --batch or --import-mj write to
+    pub fn out_dir(&self, default: &str) -> PathBuf {
+        self.out.clone().unwrap_or_else(|| PathBuf::from(default))
+    }
+
+    fn override_rules<const S: usize>(&self, rules: &mut [ReplacementRule<Tile, S>]) {
+        for rule in rules.iter_mut() {
+            if let Some(symmetry) = self.symmetry {
+                rule.symmetry = symmetry;
+            }
+            if let Some(boundaries) = self.boundaries {
+                rule.boundaries = boundaries;
+            }
+        }
+    }
+
+    /// Sounds of `rules` rules for --sonify, from --rule-notes where given
+    pub fn rule_sounds(&self, rules: usize) -> Vec<Option<RuleSound>> {
+        let mut sounds = sonify::default_sounds(rules);
+        for (sound, note) in sounds.iter_mut().zip(&self.rule_notes) {
+            *sound = note.map(RuleSound::new);
+        }
+        sounds
+    }
+
+    /// Warn on stderr about rules which can't do anything useful when run from `grid`, see
+    /// validate::validate
+    pub fn warn_about_rules<const W: usize, const H: usize>(&self, grid: &Grid<Tile, W, H>) {
+        let priority = self.anneal.is_none() && self.scheduler == "priority";
+        for diagnostic in validate::validate(grid, &self.rules(), priority) {
+            eprintln!("warning: {}", diagnostic);
+        }
+    }
+
+    /// The constraint keeping --keep-connected regions whole, if any
+    pub fn connected(&self) -> Option<Connected<Tile>> {
+        (!self.keep_connected.is_empty()).then(|| Connected::new(self.keep_connected.clone()))
+    }
+
+    /// Use the symmetry, boundaries, matcher, scheduler, energy, profiling, cycle detection and
+    /// connectivity constraint chosen on the command line
+    pub fn configure<const W: usize, const H: usize, const S: usize>(
+        &self,
+        sim: &mut Simulation<Tile, W, H, S>,
+    ) -> bimp::error::Result<()> {
+        self.override_rules(&mut sim.rules);
+        if self.deterministic {
+            sim.set_deterministic();
+        }
+        sim.set_profiling(self.profile);
+        if let Some(window) = self.cycle_window {
+            sim.set_cycle_detection(window);
+        }
+        if let Some(connected) = self.connected() {
+            sim.add_constraint(connected);
+        }
+        sim.set_matcher(matcher::by_name(&self.matcher).expect("checked when parsing"));
+        match self.anneal {
+            Some(temperature) => {
+                sim.set_scheduler(Box::new(Annealing::new(temperature, self.cooling)))
+            }
+            None => sim
+                .set_scheduler(scheduler::by_name(&self.scheduler).expect("checked when parsing")),
+        }
+        if let Some(metric) = &self.energy {
+            let scorer = Scorer::new(metric)?;
+            sim.set_energy(move |grid| {
+                scorer.score(grid).unwrap_or_else(|e| {
+                    eprintln!("energy failed: {}", e);
+                    std::process::exit(1);
+                })
+            });
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<N: std::str::FromStr>(value: Option<String>, name: &str) -> Result<N, String> {
+    let value = value.ok_or_else(|| format!("{} needs a number", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} needs a number, got '{}'", name, value))
+}
+
+/// Parse comma separated MIDI notes, "-" for a silent rule, eg. "60,-,67"
+fn parse_rule_notes(s: &str) -> Result<Vec<Option<i32>>, String> {
+    s.split(',')
+        .map(|note| match note.trim() {
+            "-" => Ok(None),
+            note => note
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid note '{}' in --rule-notes", note)),
+        })
+        .collect()
+}
+
+/// Parse tile letters as in the ascii format, eg. "WR" for White and Red
+fn parse_tiles(s: &str) -> Result<Vec<Tile>, String> {
+    s.chars()
+        .map(|c| Tile::from_char(c).ok_or_else(|| format!("unknown tile '{}'", c)))
+        .collect()
+}
+
+/// Parse "WIDTHxHEIGHT", eg. "1920x1080"
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let err = || format!("expected a size like 1024x1024, got '{}'", s);
+    let (w, h) = s.split_once('x').ok_or_else(err)?;
+    let w = w.parse().map_err(|_| err())?;
+    let h = h.parse().map_err(|_| err())?;
+    if w == 0 || h == 0 {
+        return Err(err());
+    }
+    Ok((w, h))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bimp::boundary::Boundary;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn screenshot_size() {
+        let options = Options::parse(args("--screenshot-size 1920x1080")).unwrap();
+        assert_eq!(options.screenshot_size, (1920, 1080));
+    }
+
+    #[test]
+    fn script() {
+        let options = Options::parse(args("--script grow.lua")).unwrap();
+        assert_eq!(options.script, Some(PathBuf::from("grow.lua")));
+        assert!(Options::parse(args("--script")).is_err());
+    }
+
+    #[test]
+    fn headless() {
+        let options = Options::parse(args("--stdin --every-step --max-steps 10 --seed 3")).unwrap();
+        assert!(options.headless && options.stdin && options.every_step);
+        assert_eq!(options.max_steps, Some(10));
+        assert_eq!(options.seed, Some(3));
+        assert!(Options::parse(args("--max-steps ten")).is_err());
+    }
+
+    #[test]
+    fn progress() {
+        let options = Options::parse(args("--headless --quiet --status-file status.txt")).unwrap();
+        assert!(options.quiet);
+        assert_eq!(options.status_file, Some(PathBuf::from("status.txt")));
+        assert!(Options::parse(args("--status-file")).is_err());
+    }
+
+    #[test]
+    fn hex() {
+        assert!(Options::parse(args("--hex")).unwrap().hex);
+        assert!(Options::parse(args("--hex --headless")).is_err());
+    }
+
+    #[test]
+    fn volume() {
+        let options = Options::parse(args("--volume tower.txt")).unwrap();
+        assert_eq!(options.volume, Some(PathBuf::from("tower.txt")));
+        assert!(Options::parse(args("--volume")).is_err());
+        assert!(Options::parse(args("--volume tower.txt --hex")).is_err());
+        assert!(Options::parse(args("--volume tower.txt --headless")).is_err());
+    }
+
+    #[test]
+    fn model() {
+        let mut options = Options::parse(args("--model grow.bimp --headless")).unwrap();
+        assert_eq!(options.model, Some(PathBuf::from("grow.bimp")));
+        options.model_text = Some("fill W\nrule W -> R symmetry ()\n".to_string());
+        let grid = options.initial_grid(0);
+        assert!(grid.items.iter().flatten().all(|&tile| tile == Tile::White));
+        let rules = options.rules();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].symmetry, Symmetry::Identity);
+        assert!(Options::parse(args("--model")).is_err());
+        assert!(Options::parse(args("--model grow.bimp --hex")).is_err());
+        assert!(Options::parse(args("--model grow.bimp --volume tower.txt")).is_err());
+    }
+
+    #[test]
+    fn refined_model() {
+        let mut options = Options::parse(args("--model maze.bimp --headless")).unwrap();
+        // a coarse White line across the middle, each coarse White becomes a White ring, then
+        // the fine rule turns Black to Red
+        options.model_text = Some(
+            "fill B\nline 0,8 15,8 W\nrule W -> W\nrefine 4 10\nblock W WWWW/WBBW/WBBW/WWWW\n\
+             rule B -> R symmetry ()\n"
+                .to_string(),
+        );
+        let grid = options.initial_grid(0);
+        let white = grid
+            .items
+            .iter()
+            .flatten()
+            .filter(|&&tile| tile == Tile::White);
+        assert_eq!(white.count(), 16 * 12);
+        assert_eq!(
+            grid.items[33][..4],
+            [Tile::White, Tile::Black, Tile::Black, Tile::White]
+        );
+        assert_eq!(grid.items[0][0], Tile::Black);
+
+        let mut sim = options.simulation(0);
+        assert_eq!(sim.grid, grid);
+        assert_eq!(sim.rules.len(), 1);
+        assert_eq!(sim.run(10), 10);
+        let red = sim
+            .grid
+            .items
+            .iter()
+            .flatten()
+            .filter(|&&tile| tile == Tile::Red);
+        assert_eq!(red.count(), 10);
+    }
+
+    #[test]
+    fn import_mj() {
+        let options = Options::parse(args("--import-mj resources")).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.import_mj, Some(PathBuf::from("resources")));
+        assert_eq!(options.out_dir("models"), PathBuf::from("models"));
+    }
+
+    #[test]
+    fn second_window() {
+        let options = Options::parse(args("--second-window field:heat")).unwrap();
+        assert_eq!(
+            options.second_window,
+            Some(DebugView::Field("heat".to_string()))
+        );
+        assert!(Options::parse(args("--second-window")).is_err());
+        assert!(Options::parse(args("--second-window layers")).is_err());
+        assert!(Options::parse(args("--second-window matches --hex")).is_err());
+    }
+
+    #[test]
+    fn speed() {
+        let options = Options::parse(args("--speed 20 --tween 0.5")).unwrap();
+        assert_eq!(options.speed, Some(20.0));
+        assert_eq!(options.tween, 0.5);
+        assert_eq!(Options::parse(args("")).unwrap().speed, None);
+        assert!(Options::parse(args("--tween")).is_err());
+        let options = Options::parse(args("--frame-budget 10")).unwrap();
+        assert_eq!(options.frame_budget, Some(Duration::from_millis(10)));
+        assert!(Options::parse(args("--frame-budget 10 --speed 5")).is_err());
+    }
+
+    #[test]
+    fn sonify() {
+        let options = Options::parse(args("--sonify run.wav --rule-notes 60,-,67")).unwrap();
+        assert_eq!(options.sonify, Some(PathBuf::from("run.wav")));
+        assert_eq!(options.rule_notes, vec![Some(60), None, Some(67)]);
+        assert!(Options::parse(args("--rule-notes 60,C")).is_err());
+    }
+
+    #[test]
+    fn keyframes() {
+        let options = Options::parse(args("--keyframes 500 --keyframe-dir frames")).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.keyframes, Some(500));
+        assert_eq!(options.keyframe_dir, PathBuf::from("frames"));
+        assert_eq!(options.keyframe_format, KeyframeFormat::Both);
+        let options = Options::parse(args("--keyframes 10 --keyframe-format ascii")).unwrap();
+        assert_eq!(options.keyframe_format, KeyframeFormat::Ascii);
+        assert!(Options::parse(args("--keyframes 0")).is_err());
+        assert!(Options::parse(args("--keyframes 10 --keyframe-format gif")).is_err());
+        assert!(Options::parse(args("--keyframes 10 --stream")).is_err());
+    }
+
+    #[test]
+    fn stream() {
+        let options = Options::parse(args("--stream --max-steps 100")).unwrap();
+        assert!(options.stream && options.headless);
+        let options = Options::parse(args("--watch levels")).unwrap();
+        assert_eq!(options.watch, Some(PathBuf::from("levels")));
+        assert!(options.headless);
+        assert!(Options::parse(args("--watch")).is_err());
+        assert!(Options::parse(args("--stream --stdin")).is_err());
+    }
+
+    #[test]
+    fn bad_size() {
+        assert!(Options::parse(args("--screenshot-size 1920")).is_err());
+        assert!(Options::parse(args("--screenshot-size 0x10")).is_err());
+        assert!(Options::parse(args("--screenshot-size")).is_err());
+    }
+
+    #[test]
+    fn matcher() {
+        let options = Options::parse(args("--matcher anchor")).unwrap();
+        assert_eq!(options.matcher, "anchor");
+        assert!(
+            Options::parse(args("--deterministic"))
+                .unwrap()
+                .deterministic
+        );
+        assert!(Options::parse(args("--profile")).unwrap().profile);
+        let options = Options::parse(args("--detect-cycles 16")).unwrap();
+        assert_eq!(options.cycle_window, Some(16));
+        assert!(Options::parse(args("--detect-cycles")).is_err());
+        let options = Options::parse(args("--keep-connected WR")).unwrap();
+        assert_eq!(options.keep_connected, vec![Tile::White, Tile::Red]);
+        assert!(Options::parse(args("--keep-connected W?")).is_err());
+        assert!(Options::parse(args("--matcher gpu")).is_err());
+    }
+
+    #[test]
+    fn scheduler() {
+        let options = Options::parse(args("--scheduler round-robin")).unwrap();
+        assert_eq!(options.scheduler, "round-robin");
+        assert!(Options::parse(args("--scheduler weighted")).is_err());
+    }
+
+    #[test]
+    fn symmetry() {
+        let options = Options::parse(args("--symmetry (x)(y)")).unwrap();
+        assert_eq!(options.symmetry, Some(Symmetry::ReflectXY));
+        assert!(options
+            .rules()
+            .iter()
+            .all(|rule| rule.symmetry == Symmetry::ReflectXY));
+        assert!(Options::parse(args("--symmetry (z)")).is_err());
+    }
+
+    #[test]
+    fn boundary() {
+        let options = Options::parse(args("--boundary wrap,clamp")).unwrap();
+        let cylinder = Boundaries::new(Boundary::Wrap, Boundary::Clamp);
+        assert_eq!(options.boundaries, Some(cylinder));
+        assert!(options
+            .rules()
+            .iter()
+            .all(|rule| rule.boundaries == cylinder));
+        assert!(Options::parse(args("--boundary wrap,")).is_err());
+    }
+
+    #[test]
+    fn batch() {
+        let options = Options::parse(args(
+            "--batch 8 --metric count:Red --top 3 --out runs --max-steps 50",
+        ))
+        .unwrap();
+        assert!(options.headless);
+        assert_eq!(options.batch, Some(8));
+        assert_eq!(options.top, 3);
+        assert_eq!(options.out_dir("batch"), PathBuf::from("runs"));
+        assert!(Options::parse(args("--batch 8")).is_err());
+        assert!(Options::parse(args("--batch 8 --metric count:Nope --max-steps 50")).is_err());
+        assert!(Options::parse(args("--batch 8 --metric count:Red")).is_err());
+    }
+
+    #[test]
+    fn search() {
+        let options =
+            Options::parse(args("--search bfs --goal count:Red>=10 --max-depth 5")).unwrap();
+        assert!(options.headless);
+        assert_eq!(options.search, Some(Strategy::BreadthFirst));
+        assert_eq!(options.max_depth, 5);
+        assert!(Options::parse(args("--search bfs")).is_err());
+        assert!(Options::parse(args("--search astar --goal count:Red>=1")).is_err());
+    }
+
+    #[test]
+    fn anneal() {
+        let options = Options::parse(args(
+            "--anneal 2.5 --cooling 0.9 --energy adjacent:Red,White",
+        ))
+        .unwrap();
+        assert_eq!(options.anneal, Some(2.5));
+        assert_eq!(options.cooling, 0.9);
+        assert!(Options::parse(args("--anneal 2.5")).is_err());
+    }
+
+    #[test]
+    fn unknown_argument() {
+        assert!(Options::parse(args("--nope")).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Conditions on the whole grid which must hold before a rule's matches are considered, eg.
+//! "only while there are fewer than 100 White tiles" or "only on even steps".
+
+use std::any::Any;
+
+use crate::counters::Counters;
+use crate::rewrite::Grid;
+
+/// Everything a condition can look at. The grid is seen as a flat row-major slice so conditions
+/// don't depend on the grid's size.
+pub struct Context<'a, T> {
+    pub cells: &'a [T],
+    pub width: usize,
+    pub height: usize,
+    /// Steps which applied a rule so far
+    pub steps: usize,
+    pub counters: &'a Counters,
+}
+
+impl<'a, T> Context<'a, T> {
+    pub fn new<const W: usize, const H: usize>(
+        grid: &'a Grid<T, W, H>,
+        steps: usize,
+        counters: &'a Counters,
+    ) -> Self {
+        Self {
+            cells: grid.items.as_flattened(),
+            width: W,
+            height: H,
+            steps,
+            counters,
+        }
+    }
+
+    pub fn get(&self, x: isize, y: isize) -> Option<&T> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        self.cells.get(y as usize * self.width + x as usize)
+    }
+}
+
+impl<'a, T: PartialEq> Context<'a, T> {
+    pub fn count(&self, tile: &T) -> usize {
+        self.cells.iter().filter(|cell| *cell == tile).count()
+    }
+}
+
+impl<'a, T: Eq + Copy> Context<'a, T> {
+    /// Whether the pattern matches anywhere, in any rotation. None is don't-care, like in rules.
+    pub fn contains<const S: usize>(&self, pattern: &Grid<Option<T>, S, S>) -> bool {
+        let fits = |rotated: &Grid<Option<T>, S, S>, offset_x: isize, offset_y: isize| {
+            rotated.items.iter().enumerate().all(|(y, row)| {
+                row.iter().enumerate().all(|(x, cell)| match cell {
+                    None => true,
+                    Some(tile) => {
+                        self.get(x as isize + offset_x, y as isize + offset_y) == Some(tile)
+                    }
+                })
+            })
+        };
+        (0..4).any(|rotation_times| {
+            let rotated = pattern.rotate(rotation_times);
+            (-(S as isize - 1)..self.height as isize).any(|offset_y| {
+                (-(S as isize - 1)..self.width as isize)
+                    .any(|offset_x| fits(&rotated, offset_x, offset_y))
+            })
+        })
+    }
+}
+
+pub trait Condition<T> {
+    fn holds(&self, context: &Context<T>) -> bool;
+
+    /// The condition as Any, for the conditions model files can hold, see model_file
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
+}
+
+/// Any closure over the context is a condition
+impl<T, F: Fn(&Context<T>) -> bool> Condition<T> for F {
+    fn holds(&self, context: &Context<T>) -> bool {
+        self(context)
+    }
+}
+
+/// Holds while the number of `tile` cells is within min..=max
+pub struct TileCount<T> {
+    pub tile: T,
+    pub min: usize,
+    pub max: usize,
+}
+
+impl<T> TileCount<T> {
+    pub fn at_least(tile: T, min: usize) -> Self {
+        Self {
+            tile,
+            min,
+            max: usize::MAX,
+        }
+    }
+
+    pub fn at_most(tile: T, max: usize) -> Self {
+        Self { tile, min: 0, max }
+    }
+}
+
+impl<T: PartialEq + 'static> Condition<T> for TileCount<T> {
+    fn holds(&self, context: &Context<T>) -> bool {
+        (self.min..=self.max).contains(&context.count(&self.tile))
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// Holds while the pattern matches somewhere, or with `present: false` while it matches nowhere
+pub struct Contains<T, const S: usize> {
+    pub pattern: Grid<Option<T>, S, S>,
+    pub present: bool,
+}
+
+impl<T: Eq + Copy + 'static, const S: usize> Condition<T> for Contains<T, S> {
+    fn holds(&self, context: &Context<T>) -> bool {
+        context.contains(&self.pattern) == self.present
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// Holds on steps where steps % period == phase, eg. period 2 and phase 0 for even steps
+pub struct StepPeriod {
+    pub period: usize,
+    pub phase: usize,
+}
+
+impl<T> Condition<T> for StepPeriod {
+    fn holds(&self, context: &Context<T>) -> bool {
+        context.steps % self.period.max(1) == self.phase
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile::{self, Black as B, Red as R};
+
+    fn grid() -> Grid<Tile, 3, 2> {
+        Grid {
+            items: [[R, B, B], [R, R, B]],
+        }
+    }
+
+    #[test]
+    fn tile_count() {
+        let grid = grid();
+        let counters = Counters::default();
+        let context = Context::new(&grid, 0, &counters);
+        assert!(TileCount::at_least(R, 3).holds(&context));
+        assert!(!TileCount::at_least(R, 4).holds(&context));
+        assert!(TileCount::at_most(B, 3).holds(&context));
+    }
+
+    #[test]
+    fn contains_any_rotation() {
+        let grid = grid();
+        let counters = Counters::default();
+        let context = Context::new(&grid, 0, &counters);
+        // vertical in the grid, only matches rotated
+        let pair = Contains {
+            pattern: Grid {
+                items: [[Some(R), None], [Some(B), None]],
+            },
+            present: true,
+        };
+        assert!(pair.holds(&context));
+        let three_reds = Grid {
+            items: [
+                [Some(R), Some(R), Some(R)],
+                [None, None, None],
+                [None, None, None],
+            ],
+        };
+        assert!(!context.contains(&three_reds));
+    }
+
+    #[test]
+    fn step_period_and_closures() {
+        let grid = grid();
+        let counters = Counters::default();
+        let even = StepPeriod {
+            period: 2,
+            phase: 0,
+        };
+        assert!(even.holds(&Context::new(&grid, 4, &counters)));
+        assert!(!even.holds(&Context::new(&grid, 5, &counters)));
+
+        let corner_red = |context: &Context<Tile>| context.get(0, 0) == Some(&R);
+        assert!(corner_red.holds(&Context::new(&grid, 0, &counters)));
+    }
+}
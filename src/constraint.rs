@@ -0,0 +1,175 @@
+//! Constraints on the whole grid which every replacement must keep, eg. "never seal off part of
+//! the open area". Matches whose changes a constraint rejects are skipped as if they didn't
+//! match, so the rule is applied at another match, or not at all.
+
+use std::collections::HashSet;
+
+use crate::rewrite::Grid;
+
+/// Cells around a change searched for a way around it before searching the whole grid
+const LOCAL_MARGIN: usize = 2;
+
+pub trait Constraint<T, const W: usize, const H: usize> {
+    /// Whether `grid` may have `changes` written to it, in order. Checked before every
+    /// replacement, so should only look around the changed cells where it can.
+    fn allows(&self, grid: &Grid<T, W, H>, changes: &[((usize, usize), T)]) -> bool;
+}
+
+/// Keeps 4-connected regions of cells holding any of `tiles` in one piece, eg. open floor and
+/// doors: a replacement may grow, join or remove a whole region, but not split one. Cells which
+/// leave the region must leave their remaining neighbours in it connected, which is first looked
+/// for close to the change and only then over the whole grid.
+pub struct Connected<T> {
+    pub tiles: Vec<T>,
+}
+
+impl<T> Connected<T> {
+    pub fn new(tiles: Vec<T>) -> Self {
+        Self { tiles }
+    }
+}
+
+impl<T: Copy + PartialEq, const W: usize, const H: usize> Constraint<T, W, H> for Connected<T> {
+    fn allows(&self, grid: &Grid<T, W, H>, changes: &[((usize, usize), T)]) -> bool {
+        let before = |(x, y): (usize, usize)| self.tiles.contains(&grid.items[y][x]);
+        let after = |at: (usize, usize)| match changes.iter().rev().find(|(cell, _)| *cell == at) {
+            Some((_, tile)) => self.tiles.contains(tile),
+            None => before(at),
+        };
+        // cells of the region next to a cell it loses, which must all still be connected. Cells
+        // which only join the region can't be cut off from anything.
+        let mut ends = changes
+            .iter()
+            .map(|&(at, _)| at)
+            .filter(|&at| before(at) && !after(at))
+            .flat_map(neighbours::<W, H>)
+            .filter(|&at| before(at) && after(at))
+            .collect::<Vec<_>>();
+        ends.sort_unstable();
+        ends.dedup();
+        if ends.len() < 2 {
+            return true;
+        }
+
+        let (min_x, min_y, max_x, max_y) = changes.iter().fold(
+            (usize::MAX, usize::MAX, 0, 0),
+            |(min_x, min_y, max_x, max_y), &((x, y), _)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        );
+        let near = |(x, y): (usize, usize)| {
+            (min_x.saturating_sub(LOCAL_MARGIN)..=max_x + LOCAL_MARGIN).contains(&x)
+                && (min_y.saturating_sub(LOCAL_MARGIN)..=max_y + LOCAL_MARGIN).contains(&y)
+        };
+        let mut reached = HashSet::new();
+        flood::<W, H>(ends[0], |at| near(at) && after(at), &mut reached);
+        if ends.iter().all(|end| reached.contains(end)) {
+            return true;
+        }
+
+        // ends in different pieces after the change must have been apart before it too
+        let mut reached = HashSet::new();
+        let mut pieces = Vec::new();
+        for &end in &ends {
+            if !reached.contains(&end) {
+                pieces.push(end);
+                flood::<W, H>(end, after, &mut reached);
+            }
+        }
+        let mut reached_before = HashSet::new();
+        for &piece in &pieces {
+            if reached_before.contains(&piece) {
+                return false;
+            }
+            flood::<W, H>(piece, before, &mut reached_before);
+        }
+        true
+    }
+}
+
+/// 4-neighbours of (x, y) inside a W x H grid
+fn neighbours<const W: usize, const H: usize>(
+    (x, y): (usize, usize),
+) -> impl Iterator<Item = (usize, usize)> {
+    // wrapping_sub turns -1 into usize::MAX, which is out of bounds too
+    [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ]
+    .into_iter()
+    .filter(|&(x, y)| x < W && y < H)
+}
+
+/// Add every cell 4-connected to `start` through `open` cells to `reached`
+fn flood<const W: usize, const H: usize>(
+    start: (usize, usize),
+    open: impl Fn((usize, usize)) -> bool,
+    reached: &mut HashSet<(usize, usize)>,
+) {
+    let mut stack = vec![start];
+    reached.insert(start);
+    while let Some(at) = stack.pop() {
+        for neighbour in neighbours::<W, H>(at) {
+            if open(neighbour) && reached.insert(neighbour) {
+                stack.push(neighbour);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile::{self, Black as B, White as O};
+
+    fn allows<const W: usize, const H: usize>(
+        grid: &Grid<Tile, W, H>,
+        changes: &[((usize, usize), Tile)],
+    ) -> bool {
+        Connected::new(vec![O, Tile::Yellow]).allows(grid, changes)
+    }
+
+    #[test]
+    fn bridges_stay_open() {
+        let grid = Grid {
+            items: [[O, O, O], [B, O, B], [O, O, O]],
+        };
+        // the middle cell is the only way between the top and bottom rows
+        assert!(!allows(&grid, &[((1, 1), B)]));
+        // opening another way first makes it free to close
+        assert!(allows(&grid, &[((0, 1), O), ((1, 1), B)]));
+        // the end of a row can go, and so can cells of other tiles
+        assert!(allows(&grid, &[((0, 0), B)]));
+        assert!(allows(&grid, &[((0, 1), Tile::Red)]));
+        // every tile of the class counts as the region
+        assert!(allows(&grid, &[((1, 1), Tile::Yellow)]));
+    }
+
+    #[test]
+    fn ways_around_far_from_the_change() {
+        // closing (3, 1) leaves (2, 1) and (4, 1) connected only through the bottom row
+        let grid = Grid {
+            items: [
+                [B, B, B, B, B, B, B],
+                [O, O, O, O, O, O, O],
+                [O, B, B, B, B, B, O],
+                [O, B, B, B, B, B, O],
+                [O, B, B, B, B, B, O],
+                [O, O, O, O, O, O, O],
+            ],
+        };
+        assert!(allows(&grid, &[((3, 1), B)]));
+        // regions which were apart already can stay apart
+        let grid = Grid {
+            items: [[O, O, B, O, O]],
+        };
+        assert!(allows(&grid, &[((1, 0), B), ((3, 0), B)]));
+        // cells which only just joined a region aren't split from it
+        assert!(allows(&grid, &[((2, 0), O), ((3, 0), B)]));
+        let grid = Grid { items: [[O; 5]] };
+        assert!(!allows(&grid, &[((2, 0), B)]));
+        assert!(!allows(&grid, &[((3, 0), B), ((1, 0), B)]));
+    }
+}
@@ -2,10 +2,8 @@
 pub trait Coord: Sized + Copy {
     const ZERO: Self;
 
-    /// If coord is N-dim width, height, depth, calculate volume
-    fn extent(self) -> usize;
-    /// Convert N-dim coord to flat array index
-    fn to_flat(self, size: Self) -> usize;
+    /// the coord moved by `by` along each axis
+    fn translated(self, by: Self) -> Self;
 
     const NUM_ROTATIONS: usize;
 
@@ -33,11 +31,8 @@ pub struct CoordIter<C: Coord> {
 impl Coord for usize {
     const ZERO: Self = 0;
 
-    fn extent(self) -> usize {
-        self
-    }
-    fn to_flat(self, _: Self) -> Self {
-        self
+    fn translated(self, by: Self) -> Self {
+        self + by
     }
 
     const NUM_ROTATIONS: usize = 2;
@@ -70,12 +65,8 @@ impl Iterator for CoordIter<usize> {
 impl Coord for (usize, usize) {
     const ZERO: Self = (0, 0);
 
-    fn extent(self) -> usize {
-        self.0 * self.1
-    }
-
-    fn to_flat(self, size: Self) -> usize {
-        size.1 * self.0 + self.1
+    fn translated(self, by: Self) -> Self {
+        (self.0 + by.0, self.1 + by.1)
     }
 
     const NUM_ROTATIONS: usize = 4;
@@ -92,7 +83,7 @@ impl Coord for (usize, usize) {
     }
 }
 
-impl<'target> Iterator for CoordIter<(usize, usize)> {
+impl Iterator for CoordIter<(usize, usize)> {
     type Item = (usize, usize);
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -117,6 +108,49 @@ impl<'target> Iterator for CoordIter<(usize, usize)> {
 
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::rewrite::Grid;
+
+    /// A 2D grid size with a position inside it
+    fn sized_2d() -> impl Strategy<Value = ((usize, usize), (usize, usize))> {
+        (1..32usize, 1..32usize).prop_flat_map(|size| ((0..size.0, 0..size.1), Just(size)))
+    }
+
+    proptest! {
+        #[test]
+        fn two_flips_are_identity_1d(
+            (x, len) in (1..64usize).prop_flat_map(|len| (0..len, Just(len)))
+        ) {
+            prop_assert!(x.rotated(1, len) < len);
+            prop_assert_eq!(x.rotated(1, len).rotated(1, len), x);
+            prop_assert_eq!(x.rotated(2, len), x);
+        }
+
+        #[test]
+        fn four_quarter_turns_are_identity_2d((at, (w, h)) in sized_2d()) {
+            // every quarter turn swaps the width and height of the grid
+            let turns = [(w, h), (h, w), (w, h), (h, w)];
+            let mut rotated = at;
+            for (times, size) in turns.into_iter().enumerate() {
+                prop_assert_eq!(rotated, at.rotated(times, (w, h)));
+                prop_assert!(rotated.0 < size.0 && rotated.1 < size.1);
+                rotated = rotated.rotated(1, size);
+            }
+            prop_assert_eq!(rotated, at);
+            prop_assert_eq!(at.rotated(4, (w, h)), at);
+        }
+
+        #[test]
+        fn rotated_agrees_with_patch_rotation(x in 0..5usize, y in 0..5usize, times in 0..8usize) {
+            let mut patch: Grid<u8, 5, 5> = Default::default();
+            patch.items[y][x] = 1;
+            let (rotated_x, rotated_y) = (x, y).rotated(times, (5, 5));
+            prop_assert_eq!(patch.rotate(times).items[rotated_y][rotated_x], 1);
+        }
+    }
+
     /*
     use super::*;
 
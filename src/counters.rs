@@ -0,0 +1,124 @@
+//! Named integer counters shared by all rules of a simulation. Rules can change counters when
+//! they are applied and only be considered while a condition on counters holds, eg. "add 1 to
+//! rooms" and "only while rooms < 8".
+
+use std::collections::BTreeMap;
+
+/// Counters which have never been set are 0
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    values: BTreeMap<String, i64>,
+}
+
+impl Counters {
+    pub fn get(&self, name: &str) -> i64 {
+        self.values.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, name: &str, value: i64) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn add(&mut self, name: &str, amount: i64) {
+        *self.values.entry(name.to_string()).or_insert(0) += amount;
+    }
+
+    /// (name, value) of every counter which has been set, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i64)> {
+        self.values
+            .iter()
+            .map(|(name, &value)| (name.as_str(), value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// `lhs <self> rhs`
+    pub fn compare<N: PartialOrd>(&self, lhs: N, rhs: N) -> bool {
+        match self {
+            Comparison::Less => lhs < rhs,
+            Comparison::LessOrEqual => lhs <= rhs,
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+            Comparison::Greater => lhs > rhs,
+        }
+    }
+}
+
+/// Holds when `counter <comparison> value`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guard {
+    pub counter: String,
+    pub comparison: Comparison,
+    pub value: i64,
+}
+
+impl Guard {
+    pub fn new(counter: &str, comparison: Comparison, value: i64) -> Self {
+        Self {
+            counter: counter.to_string(),
+            comparison,
+            value,
+        }
+    }
+
+    pub fn holds(&self, counters: &Counters) -> bool {
+        self.comparison
+            .compare(counters.get(&self.counter), self.value)
+    }
+}
+
+/// A change made to a counter when a rule is applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    Add(String, i64),
+    Set(String, i64),
+}
+
+impl Effect {
+    pub fn apply(&self, counters: &mut Counters) {
+        match self {
+            Effect::Add(name, amount) => counters.add(name, *amount),
+            Effect::Set(name, value) => counters.set(name, *value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unset_counters_are_zero() {
+        let mut counters = Counters::default();
+        assert_eq!(counters.get("rooms"), 0);
+        counters.add("rooms", 2);
+        counters.add("rooms", 3);
+        assert_eq!(counters.get("rooms"), 5);
+        assert_eq!(counters.iter().collect::<Vec<_>>(), vec![("rooms", 5)]);
+    }
+
+    #[test]
+    fn guards_and_effects() {
+        let mut counters = Counters::default();
+        let guard = Guard::new("rooms", Comparison::Less, 2);
+        let effect = Effect::Add("rooms".to_string(), 1);
+        assert!(guard.holds(&counters));
+        effect.apply(&mut counters);
+        assert!(guard.holds(&counters));
+        effect.apply(&mut counters);
+        assert!(!guard.holds(&counters));
+        Effect::Set("rooms".to_string(), -1).apply(&mut counters);
+        assert!(Guard::new("rooms", Comparison::Equal, -1).holds(&counters));
+    }
+}
@@ -0,0 +1,153 @@
+//! Spotting models which oscillate between the same few grids forever. Grids are hashed with a
+//! Zobrist hash, the XOR of a key for every (cell, tile) pair, so a step only rehashes the cells
+//! it touched. A grid whose hash was seen in the last few steps means the model is in a cycle.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+use crate::rewrite::Grid;
+
+/// Hash of a tile. DefaultHasher::new always uses the same keys, so this is stable within a
+/// build, which is all the detector needs.
+pub fn tile_hash<T: Hash>(tile: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tile.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Zobrist key of `tile_hash` at flat cell index `cell`
+fn cell_key(cell: usize, tile_hash: u64) -> u64 {
+    // splitmix64 finalizer
+    let mut z = tile_hash ^ (cell as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Hashes of the last `window` grids, see Simulation::set_cycle_detection
+pub struct CycleDetector<T, const W: usize, const H: usize> {
+    /// The grid as of the last update, compared against to find changed cells
+    hashed: Grid<T, W, H>,
+    hash: u64,
+    recent: VecDeque<u64>,
+    window: usize,
+    tile_hash: fn(&T) -> u64,
+}
+
+impl<T: Eq + Copy, const W: usize, const H: usize> CycleDetector<T, W, H> {
+    /// Starts with `grid` as the only grid seen
+    pub fn new(grid: &Grid<T, W, H>, window: usize, tile_hash: fn(&T) -> u64) -> Self {
+        let mut detector = Self {
+            hashed: grid.clone(),
+            hash: 0,
+            recent: VecDeque::with_capacity(window),
+            window,
+            tile_hash,
+        };
+        detector.rehash(grid);
+        detector.record();
+        detector
+    }
+
+    /// Hash of the grid as of the last update
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Hash the whole grid again, for when it may have changed anywhere
+    pub fn rehash(&mut self, grid: &Grid<T, W, H>) {
+        self.hashed = grid.clone();
+        self.hash = grid
+            .items
+            .iter()
+            .flatten()
+            .enumerate()
+            .fold(0, |hash, (cell, item)| {
+                hash ^ cell_key(cell, (self.tile_hash)(item))
+            });
+    }
+
+    /// Update the hash for the cells in `cells`, the only ones which may have changed since the
+    /// last update
+    pub fn update<I: IntoIterator<Item = (usize, usize)>>(
+        &mut self,
+        grid: &Grid<T, W, H>,
+        cells: I,
+    ) {
+        for (x, y) in cells {
+            let (old, new) = (self.hashed.items[y][x], grid.items[y][x]);
+            if old != new {
+                let cell = y * W + x;
+                self.hash ^=
+                    cell_key(cell, (self.tile_hash)(&old)) ^ cell_key(cell, (self.tile_hash)(&new));
+                self.hashed.items[y][x] = new;
+            }
+        }
+    }
+
+    /// Remember the current hash. Returns the length of the cycle if the same grid was seen
+    /// within the window, eg. 2 for a model flipping between two grids.
+    pub fn record(&mut self) -> Option<usize> {
+        let cycle = self
+            .recent
+            .iter()
+            .rev()
+            .position(|&hash| hash == self.hash)
+            .map(|steps_ago| steps_ago + 1);
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        if self.window > 0 {
+            self.recent.push_back(self.hash);
+        }
+        cycle
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile;
+
+    #[test]
+    fn incremental_hash_matches_full_hash() {
+        let mut grid = Grid {
+            items: [[Tile::Red, Tile::Green], [Tile::Blue, Tile::Red]],
+        };
+        let mut detector = CycleDetector::new(&grid, 4, tile_hash);
+        grid.items[1][0] = Tile::White;
+        detector.update(&grid, [(0, 1), (1, 1)]);
+        assert_eq!(
+            detector.hash(),
+            CycleDetector::new(&grid, 4, tile_hash).hash()
+        );
+        // the same tiles in other cells hash differently
+        let swapped = Grid {
+            items: [[Tile::Green, Tile::Red], [Tile::Red, Tile::White]],
+        };
+        assert_ne!(
+            detector.hash(),
+            CycleDetector::new(&swapped, 4, tile_hash).hash()
+        );
+    }
+
+    #[test]
+    fn finds_cycle_length() {
+        let a = Grid {
+            items: [[Tile::Red]],
+        };
+        let b = Grid {
+            items: [[Tile::Blue]],
+        };
+        let c = Grid {
+            items: [[Tile::Green]],
+        };
+        // the starting grid counts as seen
+        let mut detector = CycleDetector::new(&a, 2, tile_hash);
+        for (grid, expected) in [(&b, None), (&c, None), (&a, None), (&c, Some(2))] {
+            detector.update(grid, [(0, 0)]);
+            assert_eq!(detector.record(), expected);
+        }
+    }
+}
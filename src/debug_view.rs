@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+use nannou::prelude::*;
+
+use bimp::rewrite::Grid;
+use bimp::simulation::Simulation;
+use bimp::tile::{Colorable, Tile};
+
+use crate::nannou_color;
+
+/// What the second window shows, see --second-window
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugView {
+    /// How many matches of the active rules cover each cell
+    Matches,
+    /// The simulation's field with this name
+    Field(String),
+}
+
+impl DebugView {
+    /// Window title
+    pub fn title(&self) -> String {
+        match self {
+            DebugView::Matches => "bimp matches".to_string(),
+            DebugView::Field(name) => format!("bimp field {}", name),
+        }
+    }
+
+    /// The values to show for the current state of `sim`
+    pub fn values<const W: usize, const H: usize, const S: usize>(
+        &self,
+        sim: &mut Simulation<Tile, W, H, S>,
+    ) -> Grid<f32, W, H> {
+        match self {
+            DebugView::Matches => match_heatmap(sim),
+            DebugView::Field(name) => sim.fields.get(name).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// "matches" or "field:NAME"
+impl FromStr for DebugView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "matches" => Ok(DebugView::Matches),
+            Some(("field", name)) if !name.is_empty() => Ok(DebugView::Field(name.to_string())),
+            _ => Err(format!(
+                "unknown view '{}', expected matches or field:NAME",
+                s
+            )),
+        }
+    }
+}
+
+/// Number of matches of active rules whose find patch covers each cell, don't-cares left out
+fn match_heatmap<const W: usize, const H: usize, const S: usize>(
+    sim: &mut Simulation<Tile, W, H, S>,
+) -> Grid<f32, W, H> {
+    let mut heatmap = Grid::default();
+    for rule_id in 0..sim.rules.len() {
+        if !sim.rules[rule_id].active {
+            continue;
+        }
+        let matches = sim.rule_matches(rule_id);
+        let rule = &sim.rules[rule_id];
+        for orientation in matches {
+            let find = rule
+                .find
+                .orient(orientation.rotation_times, orientation.reflected);
+            for (y, row) in find.items.iter().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    let at = (
+                        x as isize + orientation.position.0,
+                        y as isize + orientation.position.1,
+                    );
+                    if let (Some(_), Some((x, y))) = (cell, rule.boundaries.resolve(at, W, H)) {
+                        heatmap.items[y][x] += 1.0;
+                    }
+                }
+            }
+        }
+    }
+    heatmap
+}
+
+/// Each value as a cell shaded from black at the lowest value to yellow at the highest
+pub fn draw_heatmap<const W: usize, const H: usize>(
+    values: &Grid<f32, W, H>,
+    draw: &Draw,
+    rect: Rect,
+) {
+    let (low, high) = values
+        .items
+        .iter()
+        .flatten()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &value| {
+            (low.min(value), high.max(value))
+        });
+    let range = (high - low).max(f32::EPSILON);
+    let (cold, hot) = (Tile::Black.color(), Tile::Yellow.color());
+
+    let tile_w = rect.w() / W as f32;
+    let tile_h = rect.h() / H as f32;
+    for (y, row) in values.items.iter().enumerate() {
+        for (x, &value) in row.iter().enumerate() {
+            let center = pt2(
+                rect.left() + (x as f32 + 0.5) * tile_w,
+                rect.top() - (y as f32 + 0.5) * tile_h,
+            );
+            let color = cold.lerp(hot, (value - low) / range);
+            draw.rect()
+                .xy(center)
+                .w_h(tile_w, tile_h)
+                .color(nannou_color(color));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bimp::rewrite::ReplacementRule;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("matches".parse(), Ok(DebugView::Matches));
+        assert_eq!(
+            "field:heat".parse(),
+            Ok(DebugView::Field("heat".to_string()))
+        );
+        assert!("field:".parse::<DebugView>().is_err());
+        assert!("heat".parse::<DebugView>().is_err());
+    }
+
+    #[test]
+    fn matches_cover_their_cells() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const K: Option<Tile> = Some(Tile::Black);
+        // a red cell followed by black, in any rotation
+        let rule = ReplacementRule::new(
+            Grid {
+                items: [[R, K], [None, None]],
+            },
+            Grid {
+                items: [[K, R], [None, None]],
+            },
+        );
+        let mut grid: Grid<Tile, 3, 1> = Default::default();
+        grid.items[0][1] = Tile::Red;
+        let mut sim = Simulation::new(grid, vec![rule], 0);
+        let heatmap = DebugView::Matches.values(&mut sim);
+        assert_eq!(heatmap.items, [[1.0, 2.0, 1.0]]);
+        sim.set_rule_active(0, false);
+        assert_eq!(DebugView::Matches.values(&mut sim).items, [[0.0; 3]]);
+    }
+}
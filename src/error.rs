@@ -0,0 +1,53 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, BimpError>;
+
+/// Everything that can go wrong building or loading grids, rules and scripts
+#[derive(Debug, Error)]
+pub enum BimpError {
+    /// Items given for a grid don't fill it exactly
+    #[error("grid needs {expected} items, got {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
+
+    /// A symbol which doesn't map to any tile. Lines and columns count from 1.
+    #[error("unknown tile '{symbol}' at line {line} column {column}")]
+    BadSymbol {
+        symbol: String,
+        line: usize,
+        column: usize,
+    },
+
+    /// Malformed input. Lines count from 1.
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    /// A recorded rule application which doesn't fit the grid it is replayed on. Moves count
+    /// from 1.
+    #[error("move {index} can't be applied: {reason}")]
+    InvalidMove { index: usize, reason: String },
+
+    /// A rule using something a search can't follow, see search::check_searchable
+    #[error("rule {rule_id} can't be searched: {reason}")]
+    Unsearchable { rule_id: usize, reason: String },
+
+    /// Something imported from another tool which bimp can't run, see markov_junior
+    #[error("unsupported {0}")]
+    Unsupported(String),
+
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[cfg(feature = "lua")]
+    #[error(transparent)]
+    Lua(#[from] mlua::Error),
+}
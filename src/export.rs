@@ -0,0 +1,125 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bimp::metadata::{self, Metadata};
+use bimp::rewrite::Grid;
+use bimp::simulation::Simulation;
+use bimp::tile::{AsciiSymbol, Colorable, Rgb};
+use nannou::image::{self, RgbImage};
+
+/// Render the grid into an image of the given size with the same layout as the window view:
+/// square, padded tiles centered on the background color. Sprites are not drawn, only the tile
+/// colors.
+pub fn grid_image<T: Colorable, const W: usize, const H: usize>(
+    grid: &Grid<T, W, H>,
+    background: Rgb,
+    (width, height): (u32, u32),
+) -> RgbImage {
+    let mut img = RgbImage::from_pixel(width, height, to_pixel(background));
+
+    let tile = (width / W as u32).min(height / H as u32).max(1);
+    let pad = tile / 10;
+    let offset_x = width.saturating_sub(tile * W as u32) / 2;
+    let offset_y = height.saturating_sub(tile * H as u32) / 2;
+
+    for (tile_y, row) in grid.items.iter().enumerate() {
+        for (tile_x, item) in row.iter().enumerate() {
+            let pixel = to_pixel(item.color());
+            let left = offset_x + tile_x as u32 * tile;
+            let top = offset_y + tile_y as u32 * tile;
+            for y in (top + pad)..(top + tile - pad).min(height) {
+                for x in (left + pad)..(left + tile - pad).min(width) {
+                    img.put_pixel(x, y, pixel);
+                }
+            }
+        }
+    }
+    img
+}
+
+/// "<prefix>_<unix time in ms>.<extension>" in the current directory
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    PathBuf::from(format!("{}_{}.{}", prefix, millis, extension))
+}
+
+/// Metadata of the run so far, with the command line it was started with
+pub fn metadata<
+    T: AsciiSymbol + Eq + Copy + Default + 'static,
+    const W: usize,
+    const H: usize,
+    const S: usize,
+>(
+    sim: &Simulation<T, W, H, S>,
+) -> Metadata {
+    let args = env::args().skip(1).collect::<Vec<_>>().join(" ");
+    sim.metadata().with("args", args)
+}
+
+/// Write the metadata sidecar of the export at `path`, reporting failures on the console. An
+/// export without its sidecar is still worth keeping, so this doesn't fail the export.
+pub fn save_sidecar(metadata: &Metadata, path: &Path) {
+    if let Err(e) = metadata.write_sidecar(path) {
+        eprintln!(
+            "failed to save {}: {}",
+            metadata::sidecar_path(path).display(),
+            e
+        );
+    }
+}
+
+/// Write a new timestamped file with `write` and its metadata sidecar, reporting the outcome on
+/// the console
+pub fn save_timestamped<F>(extension: &str, metadata: &Metadata, write: F)
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let path = timestamped_path("bimp", extension);
+    let result = File::create(&path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()
+    });
+    match result {
+        Ok(()) => {
+            println!("saved {}", path.display());
+            save_sidecar(metadata, &path);
+        }
+        Err(e) => eprintln!("failed to save {}: {}", path.display(), e),
+    }
+}
+
+fn to_pixel(color: Rgb) -> image::Rgb<u8> {
+    image::Rgb([color.red, color.green, color.blue])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bimp::tile::Tile;
+
+    #[test]
+    fn image_is_requested_size() {
+        let grid: Grid<Tile, 4, 2> = Default::default();
+        let img = grid_image(&grid, Tile::White.color(), (100, 30));
+        assert_eq!(img.dimensions(), (100, 30));
+    }
+
+    #[test]
+    fn tiles_are_centered() {
+        let mut grid: Grid<Tile, 2, 2> = Default::default();
+        grid.items[0][0] = Tile::Red;
+        // 2x2 grid in 40x20 => 10px tiles, 10px letterbox either side, 1px padding
+        let img = grid_image(&grid, Tile::White.color(), (40, 20));
+        assert_eq!(*img.get_pixel(5, 5), to_pixel(Tile::White.color()));
+        assert_eq!(*img.get_pixel(10, 0), to_pixel(Tile::White.color()));
+        assert_eq!(*img.get_pixel(15, 5), to_pixel(Tile::Red.color()));
+        assert_eq!(*img.get_pixel(25, 5), to_pixel(Tile::Black.color()));
+    }
+}
@@ -0,0 +1,171 @@
+//! C ABI for embedding the engine (feature `ffi`). The matching header is `include/bimp.h`;
+//! regenerate it after changing this file with
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output include/bimp.h
+//! ```
+//!
+//! Tiles are passed across the boundary as their index in `Tile::ALL`.
+
+use std::slice;
+
+use crate::models;
+use crate::simulation::Simulation;
+use crate::tile::{Colorable, Tile};
+
+/// Opaque handle to a running simulation of the default model
+pub struct BimpSim(Simulation<Tile, { models::WIDTH }, { models::HEIGHT }, 3>);
+
+/// Create a simulation of the default model. Free it with `bimp_sim_free`.
+#[no_mangle]
+pub extern "C" fn bimp_sim_new(seed: u64) -> *mut BimpSim {
+    Box::into_raw(Box::new(BimpSim(models::simulation(seed))))
+}
+
+/// # Safety
+/// `sim` must be null or a pointer returned by `bimp_sim_new` which has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn bimp_sim_free(sim: *mut BimpSim) {
+    if !sim.is_null() {
+        drop(Box::from_raw(sim));
+    }
+}
+
+/// Apply up to `steps` rules. Returns the number of steps that applied a rule, which is less than
+/// `steps` once the simulation has finished.
+///
+/// # Safety
+/// `sim` must be null or a live pointer returned by `bimp_sim_new`.
+#[no_mangle]
+pub unsafe extern "C" fn bimp_sim_step(sim: *mut BimpSim, steps: u32) -> u32 {
+    match sim.as_mut() {
+        Some(sim) => sim.0.run(steps as usize) as u32,
+        None => 0,
+    }
+}
+
+/// Width of the grid in tiles
+///
+/// # Safety
+/// `sim` must be null or a live pointer returned by `bimp_sim_new`.
+#[no_mangle]
+pub unsafe extern "C" fn bimp_sim_width(sim: *const BimpSim) -> usize {
+    sim.as_ref().map_or(0, |sim| sim.0.grid.size().0)
+}
+
+/// Height of the grid in tiles
+///
+/// # Safety
+/// `sim` must be null or a live pointer returned by `bimp_sim_new`.
+#[no_mangle]
+pub unsafe extern "C" fn bimp_sim_height(sim: *const BimpSim) -> usize {
+    sim.as_ref().map_or(0, |sim| sim.0.grid.size().1)
+}
+
+/// Copy the tiles into `out` in row major order. At most `len` tiles are written. Returns the
+/// number of tiles written.
+///
+/// # Safety
+/// `sim` must be null or a live pointer returned by `bimp_sim_new`. `out` must be null or valid
+/// for writing `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bimp_sim_get_cells(
+    sim: *const BimpSim,
+    out: *mut u8,
+    len: usize,
+) -> usize {
+    let sim = match sim.as_ref() {
+        Some(sim) if !out.is_null() => sim,
+        _ => return 0,
+    };
+    let out = slice::from_raw_parts_mut(out, len);
+    let cells = sim.0.grid.items.iter().flatten();
+    let mut written = 0;
+    for (o, tile) in out.iter_mut().zip(cells) {
+        *o = *tile as u8;
+        written += 1;
+    }
+    written
+}
+
+/// Set the tile at (x, y), so the next step matches against it. Returns false if the coordinate
+/// or tile is out of range.
+///
+/// # Safety
+/// `sim` must be null or a live pointer returned by `bimp_sim_new`.
+#[no_mangle]
+pub unsafe extern "C" fn bimp_sim_set_cell(
+    sim: *mut BimpSim,
+    x: usize,
+    y: usize,
+    tile: u8,
+) -> bool {
+    let sim = match sim.as_mut() {
+        Some(sim) => sim,
+        None => return false,
+    };
+    let (w, h) = sim.0.grid.size();
+    match Tile::ALL.get(tile as usize) {
+        Some(&tile) if x < w && y < h => {
+            sim.0.set((x, y), tile);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Color of a tile as 0xRRGGBB, or 0 for an unknown tile
+#[no_mangle]
+pub extern "C" fn bimp_tile_color(tile: u8) -> u32 {
+    Tile::ALL.get(tile as usize).map_or(0, |tile| {
+        let c = tile.color();
+        (c.red as u32) << 16 | (c.green as u32) << 8 | c.blue as u32
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        unsafe {
+            let sim = bimp_sim_new(1);
+            let (w, h) = (bimp_sim_width(sim), bimp_sim_height(sim));
+            assert_eq!((w, h), (models::WIDTH, models::HEIGHT));
+
+            assert!(bimp_sim_set_cell(sim, 1, 2, Tile::Blue as u8));
+            assert!(!bimp_sim_set_cell(sim, w, 0, Tile::Blue as u8));
+            assert!(!bimp_sim_set_cell(sim, 0, 0, 200));
+
+            let mut cells = vec![0xff; w * h];
+            assert_eq!(
+                bimp_sim_get_cells(sim, cells.as_mut_ptr(), cells.len()),
+                w * h
+            );
+            assert_eq!(cells[2 * w + 1], Tile::Blue as u8);
+            assert_eq!(cells[0], Tile::Black as u8);
+
+            assert_eq!(bimp_sim_step(sim, 10), 10);
+            bimp_sim_free(sim);
+        }
+    }
+
+    #[test]
+    fn null_is_ignored() {
+        unsafe {
+            assert_eq!(bimp_sim_step(ptr::null_mut(), 10), 0);
+            assert_eq!(bimp_sim_width(ptr::null()), 0);
+            assert_eq!(bimp_sim_get_cells(ptr::null(), ptr::null_mut(), 10), 0);
+            bimp_sim_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(bimp_tile_color(Tile::Red as u8), 0xff004d);
+        assert_eq!(bimp_tile_color(200), 0);
+    }
+}
@@ -0,0 +1,300 @@
+//! Continuous layers laid over the tile grid, eg. heat or moisture. Field nodes change the fields
+//! after every step, spreading, fading or feeding them from tiles, and rules can be guarded on
+//! the field value under a cell of their match, eg. "ice melts where heat > 0.5", so smooth
+//! reaction-diffusion style processes can drive the discrete rewriting.
+
+use std::collections::BTreeMap;
+
+use crate::boundary::{Boundaries, Boundary};
+use crate::counters::Comparison;
+use crate::morphology::Neighbourhood;
+use crate::rewrite::{orient_position, Grid, PatchOrientation};
+
+/// Named f32 fields the size of the grid. Fields which have never been written are 0 everywhere,
+/// like counters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fields<const W: usize, const H: usize> {
+    values: BTreeMap<String, Grid<f32, W, H>>,
+}
+
+impl<const W: usize, const H: usize> Fields<W, H> {
+    pub fn get(&self, name: &str) -> Option<&Grid<f32, W, H>> {
+        self.values.get(name)
+    }
+
+    /// The field called `name`, added as all 0 if there isn't one yet
+    pub fn get_mut(&mut self, name: &str) -> &mut Grid<f32, W, H> {
+        self.values.entry(name.to_string()).or_default()
+    }
+
+    pub fn value(&self, name: &str, x: usize, y: usize) -> f32 {
+        self.get(name).map_or(0.0, |field| field.items[y][x])
+    }
+
+    /// (name, field) of every field which has been written, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Grid<f32, W, H>)> {
+        self.values
+            .iter()
+            .map(|(name, field)| (name.as_str(), field))
+    }
+}
+
+/// Extra condition on one cell of a find patch: `field <comparison> value` at the grid cell under
+/// it, eg. "heat at least 0.5 under the top left cell". A cell with no grid cell to read, through
+/// the rule's boundaries, fails every guard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldGuard {
+    pub field: String,
+    /// (x, y) in the find patch, before rotation
+    pub position: (usize, usize),
+    pub comparison: Comparison,
+    pub value: f32,
+}
+
+impl FieldGuard {
+    pub fn new(field: &str, position: (usize, usize), comparison: Comparison, value: f32) -> Self {
+        Self {
+            field: field.to_string(),
+            position,
+            comparison,
+            value,
+        }
+    }
+
+    /// Whether the guard holds with an S x S patch (S being `size`) placed at `orientation`
+    pub fn holds<const W: usize, const H: usize>(
+        &self,
+        fields: &Fields<W, H>,
+        size: usize,
+        orientation: &PatchOrientation,
+        boundaries: &Boundaries,
+    ) -> bool {
+        let (x, y) = orient_position(
+            self.position,
+            orientation.rotation_times,
+            orientation.reflected,
+            size,
+        );
+        let at = (
+            x as isize + orientation.position.0,
+            y as isize + orientation.position.1,
+        );
+        match boundaries.resolve(at, W, H) {
+            Some((x, y)) => self
+                .comparison
+                .compare(fields.value(&self.field, x, y), self.value),
+            None => false,
+        }
+    }
+}
+
+/// Changes fields once per step, after the step's rule is applied, see Simulation::add_field_node
+pub trait FieldNode<T, const W: usize, const H: usize> {
+    fn update(&mut self, fields: &mut Fields<W, H>, grid: &Grid<T, W, H>);
+}
+
+/// Any closure over the fields and grid is a field node
+impl<T, F, const W: usize, const H: usize> FieldNode<T, W, H> for F
+where
+    F: FnMut(&mut Fields<W, H>, &Grid<T, W, H>),
+{
+    fn update(&mut self, fields: &mut Fields<W, H>, grid: &Grid<T, W, H>) {
+        self(fields, grid)
+    }
+}
+
+/// Moves each cell's value `rate` of the way towards the mean of its 4 neighbours. Rates above
+/// 0.8 overshoot and oscillate. Neighbours beyond the edges are found through the boundaries,
+/// mirrored by default so nothing flows over the edges and the total is kept. Wrapped edges
+/// flow into each other, and with clamped ones the field drains away into the 0 beyond them.
+pub struct Diffuse {
+    pub field: String,
+    pub rate: f32,
+    pub boundaries: Boundaries,
+}
+
+impl Diffuse {
+    pub fn new(field: &str, rate: f32) -> Self {
+        Self {
+            field: field.to_string(),
+            rate,
+            boundaries: Boundaries::new(Boundary::Mirror, Boundary::Mirror),
+        }
+    }
+
+    pub fn with_boundaries(mut self, boundaries: Boundaries) -> Self {
+        self.boundaries = boundaries;
+        self
+    }
+}
+
+impl<T, const W: usize, const H: usize> FieldNode<T, W, H> for Diffuse {
+    fn update(&mut self, fields: &mut Fields<W, H>, _grid: &Grid<T, W, H>) {
+        let field = fields.get_mut(&self.field);
+        let before = field.items;
+        for y in 0..H {
+            for x in 0..W {
+                // flow to and from each neighbour, a quarter of the rate each
+                let flow = Neighbourhood::VonNeumann
+                    .offsets()
+                    .iter()
+                    .map(|&(dx, dy)| {
+                        let at = (x as isize + dx, y as isize + dy);
+                        let neighbour = match self.boundaries.resolve(at, W, H) {
+                            Some((nx, ny)) => before[ny][nx],
+                            None => 0.0,
+                        };
+                        neighbour - before[y][x]
+                    })
+                    .sum::<f32>();
+                field.items[y][x] += self.rate / 4.0 * flow;
+            }
+        }
+    }
+}
+
+/// Multiplies every value by 1 - `rate`
+pub struct Decay {
+    pub field: String,
+    pub rate: f32,
+}
+
+impl Decay {
+    pub fn new(field: &str, rate: f32) -> Self {
+        Self {
+            field: field.to_string(),
+            rate,
+        }
+    }
+}
+
+impl<T, const W: usize, const H: usize> FieldNode<T, W, H> for Decay {
+    fn update(&mut self, fields: &mut Fields<W, H>, _grid: &Grid<T, W, H>) {
+        let field = fields.get_mut(&self.field);
+        for value in field.items.iter_mut().flatten() {
+            *value *= 1.0 - self.rate;
+        }
+    }
+}
+
+/// Sets the field to `value` under every `tile` cell, eg. fire keeping its cell hot
+pub struct Source<T> {
+    pub field: String,
+    pub tile: T,
+    pub value: f32,
+}
+
+impl<T> Source<T> {
+    pub fn new(field: &str, tile: T, value: f32) -> Self {
+        Self {
+            field: field.to_string(),
+            tile,
+            value,
+        }
+    }
+}
+
+impl<T: PartialEq, const W: usize, const H: usize> FieldNode<T, W, H> for Source<T> {
+    fn update(&mut self, fields: &mut Fields<W, H>, grid: &Grid<T, W, H>) {
+        let field = fields.get_mut(&self.field);
+        for (value, tile) in field
+            .items
+            .iter_mut()
+            .flatten()
+            .zip(grid.items.iter().flatten())
+        {
+            if *tile == self.tile {
+                *value = self.value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile;
+
+    #[test]
+    fn diffusion_spreads_and_keeps_the_total() {
+        let grid: Grid<Tile, 3, 3> = Default::default();
+        let mut fields = Fields::default();
+        fields.get_mut("heat").items[1][1] = 9.0;
+        Diffuse::new("heat", 0.5).update(&mut fields, &grid);
+        assert_eq!(fields.value("heat", 1, 1), 4.5);
+        assert_eq!(fields.value("heat", 0, 1), 1.125);
+        assert_eq!(fields.value("heat", 0, 0), 0.0);
+        let total = |fields: &Fields<3, 3>| -> f32 {
+            fields.get("heat").unwrap().items.iter().flatten().sum()
+        };
+        assert_eq!(total(&fields), 9.0);
+        for _ in 0..50 {
+            Diffuse::new("heat", 0.5).update(&mut fields, &grid);
+        }
+        assert!((total(&fields) - 9.0).abs() < 1e-4);
+        assert!((fields.value("heat", 0, 0) - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn diffusion_boundaries() {
+        let grid: Grid<Tile, 3, 1> = Default::default();
+        let mut fields = Fields::default();
+        fields.get_mut("heat").items[0] = [4.0, 0.0, 0.0];
+        let mut mirrored = fields.clone();
+        Diffuse::new("heat", 1.0).update(&mut mirrored, &grid);
+        assert_eq!(mirrored.get("heat").unwrap().items, [[3.0, 1.0, 0.0]]);
+
+        let mut wrapped = fields.clone();
+        Diffuse::new("heat", 1.0)
+            .with_boundaries(Boundaries::new(Boundary::Wrap, Boundary::Mirror))
+            .update(&mut wrapped, &grid);
+        assert_eq!(wrapped.get("heat").unwrap().items, [[2.0, 1.0, 1.0]]);
+
+        // a quarter of the heat drains out past each of the left, top and bottom edges
+        let mut clamped = fields.clone();
+        Diffuse::new("heat", 1.0)
+            .with_boundaries(Boundaries::CLAMP)
+            .update(&mut clamped, &grid);
+        assert_eq!(clamped.get("heat").unwrap().items, [[0.0, 1.0, 0.0]]);
+    }
+
+    #[test]
+    fn sources_and_decay() {
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Black]],
+        };
+        let mut fields = Fields::default();
+        assert_eq!(fields.value("heat", 0, 0), 0.0);
+        Source::new("heat", Tile::Red, 1.0).update(&mut fields, &grid);
+        let mut decay = Decay::new("heat", 0.25);
+        FieldNode::update(&mut decay, &mut fields, &grid);
+        assert_eq!(fields.value("heat", 0, 0), 0.75);
+        assert_eq!(fields.value("heat", 1, 0), 0.0);
+        let mut closure = |fields: &mut Fields<2, 1>, _: &Grid<Tile, 2, 1>| {
+            fields.get_mut("heat").items[0][1] = 2.0;
+        };
+        closure.update(&mut fields, &grid);
+        assert_eq!(fields.iter().count(), 1);
+        assert_eq!(fields.value("heat", 1, 0), 2.0);
+    }
+
+    #[test]
+    fn guards_follow_the_orientation() {
+        let mut fields: Fields<2, 2> = Fields::default();
+        fields.get_mut("heat").items[0][1] = 1.0;
+        let guard = FieldGuard::new("heat", (0, 0), Comparison::Greater, 0.5);
+        let at = |rotation_times| PatchOrientation {
+            position: (0, 0),
+            rotation_times,
+            reflected: false,
+        };
+        // the top left cell of a 2x2 patch is at the top right once rotated
+        assert!(!guard.holds(&fields, 2, &at(0), &Boundaries::CLAMP));
+        assert!(guard.holds(&fields, 2, &at(1), &Boundaries::CLAMP));
+        let outside = PatchOrientation {
+            position: (-1, 0),
+            ..at(0)
+        };
+        assert!(!guard.holds(&fields, 2, &outside, &Boundaries::CLAMP));
+    }
+}
@@ -1,90 +1,127 @@
-use std::iter;
 use std::ops::Index;
 
 use crate::coord::{Coord, CoordIter};
-
-struct Grid<TItem, TCoord: Coord> {
-    items: Vec<TItem>,
-    size: TCoord,
-}
+use crate::rewrite;
 
 /// A Gridview is any type which implemts Index[Coord]->T
-trait GridView<TItem, TCoord: Coord>: Index<TCoord, Output = TItem> {
-    fn size(&self) -> TCoord;
-    fn flat_items_view(&self) -> &[TItem];
-    /// Todo dont require same TItem, just PartialEq with other's item
-    fn matches<TOther: GridView<TItem, TCoord>>(other: TOther) -> bool {
-        false
-        //todo maybe just include rotation for every grid? fuck this
+pub(crate) trait GridView<TItem, TCoord: Coord>: Index<TCoord, Output = TItem> {
+    /// Iterator over (coord, item) in the order of TCoord::cartesian_iter. An associated type
+    /// rather than a boxed iterator, so iterating a view doesn't allocate and can be inlined.
+    type Iter<'a>: Iterator<Item = (TCoord, &'a TItem)>
+    where
+        Self: 'a,
+        TItem: 'a;
+
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// The part of this view of `size` starting at `offset`, which must be inside it
+    fn sub_view(&self, offset: TCoord, size: TCoord) -> SubView<'_, Self, TCoord>
+    where
+        Self: Sized,
+    {
+        SubView {
+            view: self,
+            offset,
+            size,
+        }
     }
-}
 
-impl<TItem, TCoord: Coord> Grid<TItem, TCoord> {
-    fn new(items: Vec<TItem>, size: TCoord) -> Self {
-        assert!(items.len() == size.extent());
-        Self { items, size }
+    /// Every cell of the patch which isn't a don't-care (None) equal to this view's item at the
+    /// same coord. The patch must fit inside the view.
+    fn fits<TPatch>(&self, patch: &TPatch) -> bool
+    where
+        TItem: PartialEq,
+        TPatch: GridView<Option<TItem>, TCoord>,
+    {
+        patch
+            .iter()
+            .all(|(at, cell)| cell.as_ref().is_none_or(|item| self[at] == *item))
     }
+}
 
-    fn with_rotation(&self, times: usize) -> RotatedGridView<TItem, TCoord> {
-        RotatedGridView {
-            grid_view: self,
-            rotation_times: times,
-        }
-    }
+/// GridView::Iter of any view, indexing it at each coord in turn
+pub(crate) struct ViewIter<'a, TView, TCoord: Coord> {
+    view: &'a TView,
+    coords: CoordIter<TCoord>,
 }
 
-impl<TItem: Default, TCoord: Coord> Grid<TItem, TCoord> {
-    fn from_default(size: TCoord) -> Self {
+impl<'a, TView, TCoord: Coord> ViewIter<'a, TView, TCoord> {
+    fn new(view: &'a TView, size: TCoord) -> Self {
         Self {
-            items: iter::repeat_with(|| Default::default())
-                .take(size.extent())
-                .collect(),
-            size,
+            view,
+            coords: size.cartesian_iter(),
         }
     }
 }
 
-impl<TItem, TCoord: Coord> GridView<TItem, TCoord> for Grid<TItem, TCoord> {
-    fn size(&self) -> TCoord {
-        self.size
-    }
+impl<'a, TItem: 'a, TView, TCoord> Iterator for ViewIter<'a, TView, TCoord>
+where
+    TView: Index<TCoord, Output = TItem>,
+    TCoord: Coord,
+    CoordIter<TCoord>: Iterator<Item = TCoord>,
+{
+    type Item = (TCoord, &'a TItem);
 
-    fn flat_items_view(&self) -> &[TItem] {
-        &self.items
+    fn next(&mut self) -> Option<Self::Item> {
+        let coord = self.coords.next()?;
+        Some((coord, &self.view[coord]))
     }
 }
 
-impl<TItem, TCoord: Coord> Index<TCoord> for Grid<TItem, TCoord> {
-    type Output = TItem;
+/// Part of another view, see GridView::sub_view
+pub(crate) struct SubView<'view, TView, TCoord: Coord> {
+    view: &'view TView,
+    offset: TCoord,
+    size: TCoord,
+}
+
+impl<'view, TView, TCoord> Index<TCoord> for SubView<'view, TView, TCoord>
+where
+    TView: Index<TCoord>,
+    TCoord: Coord,
+{
+    type Output = TView::Output;
 
     fn index(&self, index: TCoord) -> &Self::Output {
-        &self.items[index.to_flat(self.size)]
+        &self.view[index.translated(self.offset)]
     }
 }
 
-/// Has a reference to a grid, and a rotation amount.
-/// Replaces the Index trait with one that accesses the grid in a rotated fashion
-struct RotatedGridView<'grid, TItem, TCoord: Coord> {
-    grid_view: &'grid Grid<TItem, TCoord>,
-    rotation_times: usize,
+impl<'view, TItem: 'view, TView, TCoord> GridView<TItem, TCoord> for SubView<'view, TView, TCoord>
+where
+    TView: Index<TCoord, Output = TItem>,
+    TCoord: Coord,
+    CoordIter<TCoord>: Iterator<Item = TCoord>,
+{
+    type Iter<'a>
+        = ViewIter<'a, Self, TCoord>
+    where
+        Self: 'a,
+        TItem: 'a;
+
+    fn iter(&self) -> Self::Iter<'_> {
+        ViewIter::new(self, self.size)
+    }
 }
 
-impl<'grid, TItem, TCoord: Coord> Index<TCoord> for RotatedGridView<'grid, TItem, TCoord> {
-    type Output = TItem;
+/// The engine's grids are views of (x, y), so rules can be matched through views
+impl<T, const W: usize, const H: usize> Index<(usize, usize)> for rewrite::Grid<T, W, H> {
+    type Output = T;
 
-    fn index(&self, index: TCoord) -> &Self::Output {
-        &self.grid_view[index.rotated(self.rotation_times, self.grid_view.size)]
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        &self.items[y][x]
     }
 }
 
-impl<'grid, TItem, TCoord: Coord> GridView<TItem, TCoord>
-    for RotatedGridView<'grid, TItem, TCoord>
-{
-    fn size(&self) -> TCoord {
-        self.grid_view.size
-    }
-    fn flat_items_view(&self) -> &[TItem] {
-        &self.grid_view.items
+impl<T, const W: usize, const H: usize> GridView<T, (usize, usize)> for rewrite::Grid<T, W, H> {
+    type Iter<'a>
+        = ViewIter<'a, Self, (usize, usize)>
+    where
+        Self: 'a,
+        T: 'a;
+
+    fn iter(&self) -> Self::Iter<'_> {
+        ViewIter::new(self, (W, H))
     }
 }
 
@@ -93,8 +130,40 @@ mod test {
     use super::*;
 
     #[test]
-    fn test_rotation_1d() {
-        let g: Grid<usize, usize> = Grid::new(vec![1, 2, 3], 3);
-        assert_eq!(g.items, vec![1, 2, 3]);
+    fn iter_in_row_order() {
+        let grid = rewrite::Grid {
+            items: [[1, 2, 3], [4, 5, 6]],
+        };
+        assert_eq!(grid.size(), (3, 2));
+        assert_eq!(
+            grid.iter().map(|(_, item)| *item).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(grid.iter().nth(4).map(|(at, _)| at), Some((1, 1)));
+    }
+
+    #[test]
+    fn patches_fit_sub_views() {
+        const R: Option<u8> = Some(1);
+        let mut grid: rewrite::Grid<u8, 4, 3> = Default::default();
+        grid.items[1][2] = 1;
+        grid.items[2][3] = 1;
+        let patch = rewrite::Grid {
+            items: [[R, None], [None, R]],
+        };
+        let window = grid.sub_view((2, 1), (2, 2));
+        assert_eq!(window[(1, 1)], 1);
+        assert_eq!(
+            window
+                .iter()
+                .map(|(at, item)| (at, *item))
+                .collect::<Vec<_>>(),
+            vec![((0, 0), 1), ((1, 0), 0), ((0, 1), 0), ((1, 1), 1)]
+        );
+        assert!(window.fits(&patch));
+        assert!(!grid.sub_view((1, 1), (2, 2)).fits(&patch));
+        // only don't-cares fit anywhere
+        let anything: rewrite::Grid<Option<u8>, 2, 2> = Default::default();
+        assert!(grid.sub_view((0, 0), (2, 2)).fits(&anything));
     }
 }
@@ -0,0 +1,212 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use bimp::ascii;
+use bimp::error::{BimpError, Result};
+use bimp::models;
+use bimp::rewrite::Grid;
+use bimp::simulation::Simulation;
+use bimp::sonify::{self, Note, Sonifier};
+use bimp::stats::Summary;
+use bimp::tile::Tile;
+use tracing::info;
+
+use crate::cli::Options;
+use crate::export;
+use crate::keyframes::Keyframes;
+use crate::progress::{Progress, Status};
+
+/// Run the model without a window, writing grids to stdout in the ascii format. Grids written
+/// every step are separated by a blank line. If the run converges, how often each rule fired is
+/// written to stderr. With cycle detection the run also stops when the model starts repeating.
+/// With --sonify the rule applications are written to a WAV file at the end, and with
+/// --keyframes the grid is saved every so many steps.
+pub fn run(options: &Options, seed: u64) -> Result<()> {
+    let grid = initial_grid(options, seed)?;
+    options.warn_about_rules(&grid);
+    let mut sim = Simulation::new(grid, options.rules(), seed);
+    options.configure(&mut sim)?;
+    let notes = Rc::new(RefCell::new(Vec::new()));
+    if options.sonify.is_some() {
+        let sink = {
+            let notes = notes.clone();
+            move |note| notes.borrow_mut().push(note)
+        };
+        let sounds = options.rule_sounds(sim.rules.len());
+        sim.add_observer(Sonifier::<_, { models::WIDTH }, { models::HEIGHT }>::new(
+            sounds, sink,
+        ));
+    }
+
+    let mut keyframes = Keyframes::new(options)?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result =
+        write_steps(&mut sim, options, &mut keyframes, &mut out).and_then(|()| Ok(out.flush()?));
+    if let Some(profile) = sim.profile() {
+        eprint!("{}", profile);
+    }
+    if let Some(path) = &options.sonify {
+        let step_seconds = 1.0 / options.speed.unwrap_or(DEFAULT_NOTE_RATE) as f32;
+        write_sound(path, &notes.borrow(), step_seconds)?;
+        export::save_sidecar(&export::metadata(&sim), path);
+    }
+    ignore_broken_pipe(result)
+}
+
+/// Notes per second with --sonify when no --speed is given
+const DEFAULT_NOTE_RATE: f64 = 20.0;
+const SAMPLE_RATE: u32 = 44_100;
+
+fn write_sound(path: &Path, notes: &[Note], step_seconds: f32) -> Result<()> {
+    let samples = sonify::render(notes, SAMPLE_RATE, step_seconds);
+    let mut out = BufWriter::new(File::create(path)?);
+    sonify::write_wav(&samples, SAMPLE_RATE, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The grid read from stdin if asked for, otherwise the model's
+pub fn initial_grid(
+    options: &Options,
+    seed: u64,
+) -> Result<Grid<Tile, { models::WIDTH }, { models::HEIGHT }>> {
+    if options.stdin {
+        ascii::read_grid(&mut io::stdin().lock())
+    } else {
+        Ok(options.initial_grid(seed))
+    }
+}
+
+/// Ignore the reader going away, eg. when piped into head
+pub fn ignore_broken_pipe<E: Into<BimpError>>(result: std::result::Result<(), E>) -> Result<()> {
+    match result.map_err(Into::into) {
+        Err(BimpError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    }
+}
+
+fn write_steps<O: Write, const W: usize, const H: usize, const S: usize>(
+    sim: &mut Simulation<Tile, W, H, S>,
+    options: &Options,
+    keyframes: &mut Option<Keyframes>,
+    out: &mut O,
+) -> Result<()> {
+    // counts steps rejected by annealing too, so a run which only gets rejections still ends
+    let max_steps = options.max_steps.unwrap_or(usize::MAX);
+    let mut progress = Progress::new(options);
+    let mut step = 0;
+    if let Some(keyframes) = keyframes {
+        keyframes.step(sim.steps, &sim.grid)?;
+    }
+    while step < max_steps {
+        step += 1;
+        if !sim.step() {
+            eprint!("{}", Summary(&sim.rule_stats()));
+            break;
+        }
+        if let Some(length) = sim.cycle() {
+            eprintln!(
+                "stopped after {} steps in a cycle of length {}",
+                sim.steps, length
+            );
+            break;
+        }
+        if progress.due() {
+            let matches = sim.rule_stats().iter().map(|rule| rule.matches).sum();
+            progress.report(&status(step, options.max_steps, Some(matches), &progress));
+        }
+        if options.every_step {
+            ascii::write_grid(&sim.grid, out)?;
+            writeln!(out)?;
+        }
+        if let Some(keyframes) = keyframes {
+            keyframes.step(sim.steps, &sim.grid)?;
+        }
+    }
+    info!(steps = sim.steps, "finished");
+    if let Some(keyframes) = keyframes {
+        keyframes.finish(sim.steps, &sim.grid)?;
+    }
+    progress.report(&status(step, options.max_steps, None, &progress));
+    if !options.every_step {
+        ascii::write_grid(&sim.grid, out)?;
+    }
+    Ok(())
+}
+
+fn status(
+    step: usize,
+    total: Option<usize>,
+    matches: Option<usize>,
+    progress: &Progress,
+) -> Status {
+    Status {
+        unit: "step",
+        done: step,
+        total,
+        matches,
+        elapsed: progress.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use bimp::rewrite::ReplacementRule;
+
+    use super::*;
+    use crate::keyframes::KeyframeFormat;
+
+    #[test]
+    fn keyframes_are_labelled_with_applied_steps() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const K: Option<Tile> = Some(Tile::Black);
+        // Red fills the row in 3 steps, then the run converges
+        let rule = ReplacementRule::new(
+            Grid {
+                items: [[R, K], [None, None]],
+            },
+            Grid {
+                items: [[R, R], [None, None]],
+            },
+        );
+        let mut grid: Grid<Tile, 4, 1> = Default::default();
+        grid.items[0][0] = Tile::Red;
+        let mut sim = Simulation::new(grid, vec![rule], 0);
+        let dir = std::env::temp_dir().join(format!("bimp-keyframes-{}", std::process::id()));
+        let options = Options {
+            quiet: true,
+            keyframes: Some(2),
+            keyframe_dir: dir.clone(),
+            keyframe_format: KeyframeFormat::Ascii,
+            ..Options::default()
+        };
+
+        let mut keyframes = Keyframes::new(&options).unwrap();
+        write_steps(&mut sim, &options, &mut keyframes, &mut Vec::new()).unwrap();
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        let last = fs::read_to_string(dir.join("step-00000003.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(sim.steps, 3);
+        assert_eq!(
+            names,
+            [
+                "step-00000000.txt",
+                "step-00000002.txt",
+                "step-00000003.txt"
+            ]
+        );
+        assert_eq!(last.unwrap().trim(), "RRRR");
+    }
+}
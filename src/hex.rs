@@ -0,0 +1,215 @@
+//! Hexagonal grids in axial coordinates. Hexes are pointy topped, q counts to the right along a
+//! row and r counts rows downwards, so the grid is stored as a rhombus leaning to the right. Rules
+//! list their cells as axial offsets from an origin and match in any of the 6 rotations.
+
+use std::collections::HashSet;
+
+use crate::rewrite::RandomReplace;
+
+/// Axial offsets of the 6 neighbours, clockwise from the right
+pub const DIRECTIONS: [(isize, isize); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+
+/// `items[r][q]`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HexGrid<T, const W: usize, const H: usize> {
+    pub items: [[T; W]; H],
+}
+
+impl<T: Default + Copy, const W: usize, const H: usize> Default for HexGrid<T, W, H> {
+    fn default() -> Self {
+        Self {
+            items: [[Default::default(); W]; H],
+        }
+    }
+}
+
+impl<T, const W: usize, const H: usize> HexGrid<T, W, H> {
+    pub fn get(&self, (q, r): (isize, isize)) -> Option<&T> {
+        let q = usize::try_from(q).ok()?;
+        let r = usize::try_from(r).ok()?;
+        self.items.get(r)?.get(q)
+    }
+
+    pub fn get_mut(&mut self, (q, r): (isize, isize)) -> Option<&mut T> {
+        let q = usize::try_from(q).ok()?;
+        let r = usize::try_from(r).ok()?;
+        self.items.get_mut(r)?.get_mut(q)
+    }
+
+    /// Neighbours of (q, r) which are inside the grid
+    pub fn neighbours(&self, (q, r): (isize, isize)) -> impl Iterator<Item = (isize, isize)> + '_ {
+        DIRECTIONS
+            .iter()
+            .map(move |&(dq, dr)| (q + dq, r + dr))
+            .filter(|&at| self.get(at).is_some())
+    }
+}
+
+/// Rotate an axial offset clockwise by 60 degrees `times` times
+pub fn rotate((q, r): (isize, isize), times: usize) -> (isize, isize) {
+    (0..times % 6).fold((q, r), |(q, r), _| (-r, q + r))
+}
+
+/// Cells are (axial offset from the origin, tile). Cells which aren't listed are don't-cares in
+/// the find pattern and left alone by the replacement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexRule<T> {
+    pub find: Vec<((isize, isize), T)>,
+    pub replace: Vec<((isize, isize), T)>,
+}
+
+impl<T> HexRule<T> {
+    pub fn new(find: Vec<((isize, isize), T)>, replace: Vec<((isize, isize), T)>) -> Self {
+        Self { find, replace }
+    }
+}
+
+impl<T: Eq + Copy> HexRule<T> {
+    /// For each rotation, the first rotation with exactly the same effect, so symmetric rules
+    /// aren't more likely to be applied than others. See ReplacementRule::equivalent_orientations.
+    pub fn equivalent_rotations(&self) -> [usize; 6] {
+        let rotated = |times: usize| {
+            let rotate_cells = |cells: &[((isize, isize), T)]| {
+                let mut cells = cells
+                    .iter()
+                    .map(|&(offset, tile)| (rotate(offset, times), tile))
+                    .collect::<Vec<_>>();
+                // cells are unique by offset
+                cells.sort_by_key(|&(offset, _)| offset);
+                cells
+            };
+            (rotate_cells(&self.find), rotate_cells(&self.replace))
+        };
+        let rotations = [0, 1, 2, 3, 4, 5].map(rotated);
+        [0, 1, 2, 3, 4, 5].map(|times| {
+            (0..times)
+                .find(|&earlier| rotations[earlier] == rotations[times])
+                .unwrap_or(times)
+        })
+    }
+}
+
+/// Where a hex rule was placed: rotated clockwise by 60 degrees `rotation_times` times, with its
+/// origin on `position`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HexMatch {
+    pub rotation_times: usize,
+    pub position: (isize, isize),
+}
+
+impl HexMatch {
+    fn place(&self, offset: (isize, isize)) -> (isize, isize) {
+        let (dq, dr) = rotate(offset, self.rotation_times);
+        (self.position.0 + dq, self.position.1 + dr)
+    }
+}
+
+impl<T: Eq + Copy, const W: usize, const H: usize> HexGrid<T, W, H> {
+    /// Every find cell matches, and every replace cell lands inside the grid
+    pub fn check_rule_at(&self, rule: &HexRule<T>, at: &HexMatch) -> bool {
+        rule.find
+            .iter()
+            .all(|&(offset, tile)| self.get(at.place(offset)) == Some(&tile))
+            && rule
+                .replace
+                .iter()
+                .all(|&(offset, _)| self.get(at.place(offset)).is_some())
+    }
+
+    /// Every match with the origin on a grid cell. Rotations of a symmetric rule which land in
+    /// the same place only count once.
+    pub fn get_rule_matches(&self, rule: &HexRule<T>) -> Vec<HexMatch> {
+        let equivalent = rule.equivalent_rotations();
+        let mut seen = HashSet::new();
+        let mut matches = Vec::new();
+        for (rotation_times, &canonical) in equivalent.iter().enumerate() {
+            for r in 0..H as isize {
+                for q in 0..W as isize {
+                    let at = HexMatch {
+                        rotation_times,
+                        position: (q, r),
+                    };
+                    if self.check_rule_at(rule, &at) && seen.insert((canonical, at.position)) {
+                        matches.push(at);
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    pub fn replace_at(&mut self, rule: &HexRule<T>, at: &HexMatch) {
+        for &(offset, tile) in rule.replace.iter() {
+            if let Some(cell) = self.get_mut(at.place(offset)) {
+                *cell = tile;
+            }
+        }
+    }
+}
+
+impl<T: Eq + Copy, const W: usize, const H: usize> RandomReplace<HexRule<T>> for HexGrid<T, W, H> {
+    type Match = HexMatch;
+
+    fn rule_matches(&self, rule: &HexRule<T>) -> Vec<HexMatch> {
+        self.get_rule_matches(rule)
+    }
+
+    fn apply_match(&mut self, rule: &HexRule<T>, at: &HexMatch) {
+        self.replace_at(rule, at);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::tile::Tile::{self, Black as B, Red as R, White as W};
+
+    #[test]
+    fn rotation_visits_every_direction() {
+        for (i, &direction) in DIRECTIONS.iter().enumerate() {
+            assert_eq!(rotate((1, 0), i), direction);
+        }
+        assert_eq!(rotate((2, -1), 6), (2, -1));
+    }
+
+    #[test]
+    fn neighbours_stay_in_bounds() {
+        let grid = HexGrid::<Tile, 3, 3>::default();
+        assert_eq!(grid.neighbours((1, 1)).count(), 6);
+        assert_eq!(grid.neighbours((0, 0)).count(), 2);
+        assert_eq!(grid.neighbours((2, 0)).count(), 3);
+    }
+
+    #[test]
+    fn matches_in_six_rotations() {
+        let mut grid = HexGrid::<Tile, 5, 5>::default();
+        grid.items[2][2] = R;
+        let grow = HexRule::new(vec![((0, 0), R), ((1, 0), B)], vec![((1, 0), R)]);
+        assert_eq!(grid.get_rule_matches(&grow).len(), 6);
+
+        // the same cell whichever way it's rotated
+        let recolor = HexRule::new(vec![((0, 0), R)], vec![((0, 0), W)]);
+        assert_eq!(recolor.equivalent_rotations(), [0; 6]);
+        assert_eq!(grid.get_rule_matches(&recolor).len(), 1);
+    }
+
+    #[test]
+    fn growth_fills_grid() {
+        let mut grid = HexGrid::<Tile, 6, 4>::default();
+        grid.items[0][0] = R;
+        let rules = [HexRule::new(
+            vec![((0, 0), R), ((1, 0), B)],
+            vec![((1, 0), R)],
+        )];
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut steps = 0;
+        while grid.priority_random_replace(&rules, &mut rng).is_some() {
+            steps += 1;
+        }
+        assert_eq!(steps, 23);
+        assert!(grid.items.iter().flatten().all(|&tile| tile == R));
+    }
+}
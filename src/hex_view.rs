@@ -0,0 +1,70 @@
+use nannou::prelude::*;
+use rand::SeedableRng;
+
+use bimp::determinism::SimRng;
+use bimp::hex::{HexGrid, HexRule};
+use bimp::models::{self, HEX_HEIGHT, HEX_WIDTH};
+use bimp::rewrite::RandomReplace;
+use bimp::tile::{Colorable, Tile};
+
+use crate::nannou_color;
+
+/// The hex model, shown instead of the square grid with --hex
+pub struct HexModel {
+    pub grid: HexGrid<Tile, HEX_WIDTH, HEX_HEIGHT>,
+    pub rules: Vec<HexRule<Tile>>,
+    rng: SimRng,
+}
+
+impl HexModel {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            grid: models::hex_initial_grid(),
+            rules: models::hex_rules(),
+            rng: SimRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Returns false once no rule matches
+    pub fn step(&mut self) -> bool {
+        self.grid
+            .priority_random_replace(&self.rules, &mut self.rng)
+            .is_some()
+    }
+}
+
+/// Draws pointy topped hexes, as large as fits in bounds. The grid is a rhombus leaning right,
+/// since each row is shifted half a hex right of the one above.
+pub fn draw<T: Colorable, const W: usize, const H: usize>(
+    grid: &HexGrid<T, W, H>,
+    draw: &Draw,
+    bounds: Rect,
+) {
+    let sqrt3 = 3f32.sqrt();
+    // extent of the grid in units of the hex radius
+    let units_w = sqrt3 * (W as f32 + (H as f32 - 1.0) / 2.0);
+    let units_h = 1.5 * (H as f32 - 1.0) + 2.0;
+    let radius = (bounds.w() / units_w).min(bounds.h() / units_h);
+    let top_left = pt2(
+        bounds.x() - units_w * radius / 2.0,
+        bounds.y() + units_h * radius / 2.0,
+    );
+
+    for (r, row) in grid.items.iter().enumerate() {
+        for (q, item) in row.iter().enumerate() {
+            let center = top_left
+                + vec2(
+                    sqrt3 * radius * (q as f32 + r as f32 / 2.0 + 0.5),
+                    -radius * (1.5 * r as f32 + 1.0),
+                );
+            let corners = (0..6).map(|i| {
+                let angle = deg_to_rad(30.0 + 60.0 * i as f32);
+                // same padding as the square tiles
+                center + vec2(angle.cos(), angle.sin()) * radius * 0.8
+            });
+            draw.polygon()
+                .points(corners)
+                .color(nannou_color(item.color()));
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Past grids kept for undo and scrubbing. The simulation's grid is still a plain array, this is
+//! deduplication done when a snapshot is recorded rather than copy-on-write storage: each row is
+//! compared with the latest snapshot's, and rows which didn't change share its Arc instead of
+//! being copied. Recording still reads the whole grid, but a rule touching a few rows per step
+//! only stores those rows, so thousands of snapshots of a large grid stay affordable.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::rewrite::Grid;
+
+/// A copy of a grid whose rows may be shared with other snapshots
+#[derive(Clone, Debug)]
+pub struct Snapshot<T, const W: usize, const H: usize> {
+    rows: Vec<Arc<[T; W]>>,
+}
+
+impl<T: Copy + PartialEq, const W: usize, const H: usize> Snapshot<T, W, H> {
+    pub fn new(grid: &Grid<T, W, H>) -> Self {
+        Self {
+            rows: grid.items.iter().map(|row| Arc::new(*row)).collect(),
+        }
+    }
+
+    /// Snapshot of `grid` sharing the rows which are the same as in `previous`. Every row is
+    /// compared, so this costs a pass over the grid whatever changed.
+    pub fn after(previous: &Self, grid: &Grid<T, W, H>) -> Self {
+        let rows = previous
+            .rows
+            .iter()
+            .zip(grid.items.iter())
+            .map(|(old, new)| {
+                if **old == *new {
+                    Arc::clone(old)
+                } else {
+                    Arc::new(*new)
+                }
+            })
+            .collect();
+        Self { rows }
+    }
+
+    pub fn grid(&self) -> Grid<T, W, H> {
+        Grid {
+            items: std::array::from_fn(|y| *self.rows[y]),
+        }
+    }
+
+    /// Number of rows whose storage is shared with `other`
+    pub fn shared_rows(&self, other: &Self) -> usize {
+        self.rows
+            .iter()
+            .zip(other.rows.iter())
+            .filter(|(a, b)| Arc::ptr_eq(a, b))
+            .count()
+    }
+}
+
+/// The last `capacity` snapshots, oldest first, each with the step it was taken at
+pub struct History<T, const W: usize, const H: usize> {
+    snapshots: VecDeque<(usize, Snapshot<T, W, H>)>,
+    capacity: usize,
+}
+
+impl<T: Copy + PartialEq, const W: usize, const H: usize> History<T, W, H> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Take a snapshot of `grid`, dropping the oldest one when full. Nothing is recorded if the
+    /// grid is the same as in the latest snapshot.
+    pub fn record(&mut self, step: usize, grid: &Grid<T, W, H>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let snapshot = match self.snapshots.back() {
+            Some((_, latest)) => {
+                let snapshot = Snapshot::after(latest, grid);
+                if snapshot.shared_rows(latest) == H {
+                    return;
+                }
+                snapshot
+            }
+            None => Snapshot::new(grid),
+        };
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((step, snapshot));
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// The `index`th snapshot, oldest first, and the step it was taken at
+    pub fn get(&self, index: usize) -> Option<(usize, &Snapshot<T, W, H>)> {
+        self.snapshots
+            .get(index)
+            .map(|(step, snapshot)| (*step, snapshot))
+    }
+
+    /// Drop every snapshot after the `index`th, eg. when running on from a rewound grid
+    pub fn truncate(&mut self, index: usize) {
+        self.snapshots.truncate(index + 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile;
+
+    #[test]
+    fn snapshots_share_unchanged_rows() {
+        let mut grid: Grid<Tile, 4, 3> = Default::default();
+        let mut history = History::new(10);
+        history.record(0, &grid);
+        grid.items[1][2] = Tile::Red;
+        history.record(1, &grid);
+        let (_, first) = history.get(0).unwrap();
+        let (step, second) = history.get(1).unwrap();
+        assert_eq!(step, 1);
+        assert_eq!(second.shared_rows(first), 2);
+        assert_eq!(second.grid(), grid);
+        assert_eq!(first.grid(), Grid::default());
+    }
+
+    #[test]
+    fn unchanged_grids_and_capacity() {
+        let mut grid: Grid<Tile, 2, 2> = Default::default();
+        let mut history = History::new(2);
+        history.record(0, &grid);
+        history.record(1, &grid);
+        assert_eq!(history.len(), 1);
+        for step in 2..5 {
+            grid.items[0][0] = if step % 2 == 0 { Tile::Red } else { Tile::Blue };
+            history.record(step, &grid);
+        }
+        // the oldest snapshots are dropped
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0).unwrap().0, 3);
+        history.truncate(0);
+        assert_eq!(history.len(), 1);
+        assert!(History::<Tile, 2, 2>::new(0).is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+//! Text drawn over the grid
+
+use nannou::prelude::*;
+
+const FONT_SIZE: u32 = 11;
+const LINE_HEIGHT: f32 = 14.0;
+const PADDING: f32 = 4.0;
+
+/// Height of the box drawn for `lines`
+fn height(lines: &[String]) -> f32 {
+    LINE_HEIGHT * lines.len() as f32 + 2.0 * PADDING
+}
+
+/// Like draw_lines, but across the bottom of `bounds`
+pub fn draw_lines_at_bottom(lines: &[String], draw: &Draw, bounds: Rect) {
+    draw_lines(lines, draw, bounds.pad_top(bounds.h() - height(lines)));
+}
+
+/// Lines of text on a translucent box across the top of `bounds`. Draws nothing if there are no
+/// lines.
+pub fn draw_lines(lines: &[String], draw: &Draw, bounds: Rect) {
+    if lines.is_empty() {
+        return;
+    }
+    let rect = Rect::from_w_h(bounds.w(), height(lines)).top_left_of(bounds);
+    draw.rect()
+        .xy(rect.xy())
+        .wh(rect.wh())
+        .color(rgba(0.0, 0.0, 0.0, 0.6));
+    let text_rect = rect.pad(PADDING);
+    draw.text(&lines.join("\n"))
+        .xy(text_rect.xy())
+        .wh(text_rect.wh())
+        .font_size(FONT_SIZE)
+        .line_spacing(LINE_HEIGHT - FONT_SIZE as f32)
+        .left_justify()
+        .align_text_top()
+        .color(WHITE);
+}
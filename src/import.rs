@@ -0,0 +1,117 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bimp::error::{BimpError, Result};
+use bimp::markov_junior;
+use bimp::model_file;
+
+/// Convert the MarkovJunior resources in `dir` to bimp files in `out`: alphabet.txt with the
+/// tile each palette symbol became, and <name>.bimp for each model in models/ which converted,
+/// see markov_junior. Every model is reported on `report`, with its notes or why it was skipped.
+pub fn run<R: Write>(dir: &Path, out: &Path, report: &mut R) -> Result<()> {
+    let file_error = |path: PathBuf| move |source| BimpError::File { path, source };
+    let palette_path = dir.join("palette.xml");
+    let palette = fs::read_to_string(&palette_path).map_err(file_error(palette_path.clone()))?;
+    let palette = markov_junior::read_palette(&palette).map_err(|e| with_path(e, &palette_path))?;
+
+    fs::create_dir_all(out).map_err(file_error(out.to_path_buf()))?;
+    let alphabet_path = out.join("alphabet.txt");
+    let mut alphabet =
+        BufWriter::new(File::create(&alphabet_path).map_err(file_error(alphabet_path.clone()))?);
+    palette.write_alphabet(&mut alphabet)?;
+    alphabet.flush()?;
+
+    let models_dir = dir.join("models");
+    let mut paths = fs::read_dir(&models_dir)
+        .map_err(file_error(models_dir.clone()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "xml"));
+    paths.sort();
+
+    let mut converted = 0;
+    for path in paths.iter() {
+        let name = path.file_stem().expect("read_dir entries have names");
+        let xml = fs::read_to_string(path).map_err(file_error(path.clone()))?;
+        match markov_junior::convert_model(&xml, &palette) {
+            Ok(conversion) => {
+                let model_path = out.join(format!("{}.bimp", name.to_string_lossy()));
+                let mut model = BufWriter::new(
+                    File::create(&model_path).map_err(file_error(model_path.clone()))?,
+                );
+                model_file::write_model(&conversion.model, &mut model)?;
+                model.flush()?;
+                converted += 1;
+                writeln!(report, "{}: converted", name.to_string_lossy())?;
+                for note in conversion.notes {
+                    writeln!(report, "    {}", note)?;
+                }
+            }
+            Err(e) => writeln!(report, "{}: skipped, {}", name.to_string_lossy(), e)?,
+        }
+    }
+    writeln!(report, "converted {} of {} models", converted, paths.len())?;
+    Ok(())
+}
+
+/// Say which file a parse error is in
+fn with_path(error: BimpError, path: &Path) -> BimpError {
+    match error {
+        BimpError::Parse { line, message } => BimpError::Parse {
+            line,
+            message: format!("{}: {}", path.display(), message),
+        },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bimp::tile::Tile;
+    use std::io::BufReader;
+
+    #[test]
+    fn resources() {
+        let dir = std::env::temp_dir().join(format!("bimp-import-{}", std::process::id()));
+        let resources = dir.join("resources");
+        fs::create_dir_all(resources.join("models")).unwrap();
+        fs::write(
+            resources.join("palette.xml"),
+            concat!(
+                r#"<colors><color symbol="B" value="000000"/>"#,
+                r#"<color symbol="W" value="FFFFFF"/></colors>"#
+            ),
+        )
+        .unwrap();
+        fs::write(
+            resources.join("models/Growth.xml"),
+            r#"<one values="BW" origin="True" in="WB" out="WW"/>"#,
+        )
+        .unwrap();
+        fs::write(
+            resources.join("models/Maze.xml"),
+            r#"<markov values="BW"><wfc tileset="Knots"/></markov>"#,
+        )
+        .unwrap();
+        let out = dir.join("models");
+        let mut report = Vec::new();
+        run(&resources, &out, &mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "Growth: converted\nMaze: skipped, unsupported node <wfc> at line 1\n\
+             converted 1 of 2 models\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("alphabet.txt")).unwrap(),
+            "B B Black\nW W White (nearest to FFFFFF)\n"
+        );
+        let model = model_file::read_model::<Tile, _, 3>(&mut BufReader::new(
+            File::open(out.join("Growth.bimp")).unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(model.origin, Some(Tile::White));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
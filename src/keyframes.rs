@@ -0,0 +1,163 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bimp::ascii;
+use bimp::error::{BimpError, Result};
+use bimp::rewrite::Grid;
+use bimp::tile::{Colorable, Tile};
+
+use crate::cli::Options;
+use crate::export;
+
+/// Which files --keyframes writes for each keyframe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyframeFormat {
+    Png,
+    Ascii,
+    Both,
+}
+
+impl KeyframeFormat {
+    fn png(self) -> bool {
+        self != KeyframeFormat::Ascii
+    }
+
+    fn ascii(self) -> bool {
+        self != KeyframeFormat::Png
+    }
+}
+
+/// "png", "ascii" or "both"
+impl FromStr for KeyframeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(KeyframeFormat::Png),
+            "ascii" => Ok(KeyframeFormat::Ascii),
+            "both" => Ok(KeyframeFormat::Both),
+            _ => Err(format!(
+                "unknown keyframe format '{}', expected png, ascii or both",
+                s
+            )),
+        }
+    }
+}
+
+/// Saves the grid of a headless run before the first step, every `every` steps and after the
+/// last one, so the run can be looked through or stitched into a timelapse afterwards. Steps are
+/// counted like Simulation::steps, only those which applied a rule. PNGs are --screenshot-size,
+/// rendered like screenshots.
+pub struct Keyframes {
+    every: usize,
+    dir: PathBuf,
+    format: KeyframeFormat,
+    size: (u32, u32),
+    /// Step of the last keyframe saved
+    last: Option<usize>,
+}
+
+impl Keyframes {
+    /// Keyframes into the --keyframe-dir folder, created if needed. None without --keyframes.
+    pub fn new(options: &Options) -> Result<Option<Self>> {
+        let Some(every) = options.keyframes else {
+            return Ok(None);
+        };
+        let dir = options.keyframe_dir.clone();
+        fs::create_dir_all(&dir).map_err(|source| BimpError::File {
+            path: dir.clone(),
+            source,
+        })?;
+        Ok(Some(Self {
+            every,
+            dir,
+            format: options.keyframe_format,
+            size: options.screenshot_size,
+            last: None,
+        }))
+    }
+
+    /// Save `grid` if a keyframe is due after `step` applied steps and it wasn't saved yet,
+    /// since steps annealing rejects leave the count where it was
+    pub fn step<const W: usize, const H: usize>(
+        &mut self,
+        step: usize,
+        grid: &Grid<Tile, W, H>,
+    ) -> Result<()> {
+        if step.is_multiple_of(self.every) && self.last != Some(step) {
+            self.save(step, grid)?;
+        }
+        Ok(())
+    }
+
+    /// Save the final `grid` after `step` applied steps, unless it just was
+    pub fn finish<const W: usize, const H: usize>(
+        &mut self,
+        step: usize,
+        grid: &Grid<Tile, W, H>,
+    ) -> Result<()> {
+        if self.last != Some(step) {
+            self.save(step, grid)?;
+        }
+        Ok(())
+    }
+
+    fn save<const W: usize, const H: usize>(
+        &mut self,
+        step: usize,
+        grid: &Grid<Tile, W, H>,
+    ) -> Result<()> {
+        if self.format.png() {
+            let path = keyframe_path(&self.dir, step, "png");
+            export::grid_image(grid, Tile::LightGrey.color(), self.size)
+                .save(&path)
+                .map_err(|e| BimpError::File {
+                    path,
+                    source: io::Error::other(e),
+                })?;
+        }
+        if self.format.ascii() {
+            let path = keyframe_path(&self.dir, step, "txt");
+            File::create(&path)
+                .and_then(|file| {
+                    let mut out = BufWriter::new(file);
+                    ascii::write_grid(grid, &mut out)?;
+                    out.flush()
+                })
+                .map_err(|source| BimpError::File { path, source })?;
+        }
+        self.last = Some(step);
+        Ok(())
+    }
+}
+
+/// "<dir>/step-00000100.<extension>", padded so the files sort in step order
+fn keyframe_path(dir: &Path, step: usize, extension: &str) -> PathBuf {
+    dir.join(format!("step-{:08}.{}", step, extension))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("png".parse(), Ok(KeyframeFormat::Png));
+        assert_eq!("both".parse(), Ok(KeyframeFormat::Both));
+        assert!("gif".parse::<KeyframeFormat>().is_err());
+        assert!(KeyframeFormat::Both.png() && KeyframeFormat::Both.ascii());
+        assert!(!KeyframeFormat::Ascii.png());
+    }
+
+    #[test]
+    fn paths_sort_by_step() {
+        let dir = Path::new("frames");
+        assert_eq!(
+            keyframe_path(dir, 100, "png"),
+            PathBuf::from("frames/step-00000100.png")
+        );
+        assert!(keyframe_path(dir, 900, "txt") < keyframe_path(dir, 1000, "txt"));
+    }
+}
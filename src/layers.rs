@@ -0,0 +1,121 @@
+//! Rules which read and write several layers of tiles of the same size. Each cell of a rule's
+//! patches has a layer it is matched on and a layer it is written to, so eg. a "scent" layer can
+//! drive replacements on the "terrain" layer. Layer 0 is the simulation's grid, the others are
+//! added with Simulation::add_layer.
+
+/// The layers one cell of a rule's patches is matched on (`read`, for its find cell) and written
+/// to (`write`, for its replace cell)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CellLayers {
+    pub read: usize,
+    pub write: usize,
+}
+
+impl CellLayers {
+    /// Both on the simulation's grid
+    pub const GRID: Self = Self { read: 0, write: 0 };
+
+    pub fn new(read: usize, write: usize) -> Self {
+        Self { read, write }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::matcher::PackedScan;
+    use crate::rewrite::{Grid, ReplacementRule};
+    use crate::simulation::Simulation;
+    use crate::symmetry::Symmetry;
+    use crate::tile::Tile;
+
+    const TERRAIN: usize = 0;
+    const SCENT: usize = 1;
+
+    #[test]
+    fn scent_drives_terrain() {
+        const Y: Option<Tile> = Some(Tile::Yellow);
+        const G: Option<Tile> = Some(Tile::Green);
+        const K: Option<Tile> = Some(Tile::Black);
+        // grass grows on black terrain right of the scent
+        let rule = ReplacementRule::new(
+            Grid {
+                items: [[Y, K], [None, None]],
+            },
+            Grid {
+                items: [[None, G], [None, None]],
+            },
+        )
+        .with_cell_layers((0, 0), CellLayers::new(SCENT, TERRAIN));
+
+        let mut sim = Simulation::new(Grid::<Tile, 4, 1>::default(), vec![rule], 0);
+        let mut scent = Grid::default();
+        scent.items[0][0] = Tile::Yellow;
+        scent.items[0][2] = Tile::Yellow;
+        assert_eq!(sim.add_layer(scent), SCENT);
+        sim.set_matcher(Box::new(PackedScan::default()));
+        assert_eq!(sim.run(10), 2);
+
+        assert_eq!(
+            sim.grid.items,
+            [[Tile::Black, Tile::Green, Tile::Black, Tile::Green]]
+        );
+        // the scent layer is only read
+        assert_eq!(crate::metrics::count(&sim.layers[0], &Tile::Yellow), 2);
+    }
+
+    #[test]
+    fn writes_other_layers() {
+        const Y: Option<Tile> = Some(Tile::Yellow);
+        const R: Option<Tile> = Some(Tile::Red);
+        // red terrain leaves scent, in the same place on the scent layer
+        let rule = ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[Y]] })
+            .with_cell_layers((0, 0), CellLayers::new(TERRAIN, SCENT));
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Black, Tile::Red]],
+        };
+        let mut sim = Simulation::new(grid.clone(), vec![rule], 0);
+        sim.add_layer(Grid::default());
+        assert!(sim.step());
+        assert_eq!(sim.grid, grid);
+        let scent = sim.layers[0].items[0];
+        assert_eq!(scent[1], Tile::Black);
+        assert_eq!(
+            scent.iter().filter(|&&tile| tile == Tile::Yellow).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn layered_rules_match_once_per_placement() {
+        const Y: Option<Tile> = Some(Tile::Yellow);
+        const K: Option<Tile> = Some(Tile::Black);
+        // a 1x1 rule in a 2x2 patch is the same in every orientation, on every layer
+        let rule = ReplacementRule::new(
+            Grid {
+                items: [[Y, None], [None, None]],
+            },
+            Grid {
+                items: [[K, None], [None, None]],
+            },
+        )
+        .with_cell_layers((0, 0), CellLayers::new(SCENT, SCENT))
+        .with_symmetry(Symmetry::All);
+        let mut sim = Simulation::new(Grid::<Tile, 3, 3>::default(), vec![rule], 0);
+        sim.add_layer(Grid {
+            items: [[Tile::Yellow; 3]; 3],
+        });
+        assert_eq!(sim.rule_matches(0).len(), 9);
+        assert_eq!(sim.run(100), 9);
+        assert_eq!(crate::metrics::count(&sim.layers[0], &Tile::Black), 9);
+    }
+
+    #[test]
+    fn missing_layers_never_match() {
+        const Y: Option<Tile> = Some(Tile::Yellow);
+        let rule = ReplacementRule::new(Grid { items: [[Y]] }, Grid { items: [[None]] })
+            .with_cell_layers((0, 0), CellLayers::new(SCENT, TERRAIN));
+        let mut sim = Simulation::new(Grid::<Tile, 2, 2>::default(), vec![rule], 0);
+        assert!(!sim.step());
+    }
+}
@@ -0,0 +1,113 @@
+use nannou::prelude::*;
+
+/// How a grid is scaled to fit inside the window
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Scaling {
+    /// Tiles are as large as possible while staying square. The unused space is letterboxed.
+    Fit,
+    /// Like Fit, but tiles are a whole number of pixels wide and the grid is aligned to the pixel
+    /// grid, so every tile is exactly the same size on screen
+    PixelPerfect,
+}
+
+impl Scaling {
+    pub fn toggled(self) -> Self {
+        match self {
+            Scaling::Fit => Scaling::PixelPerfect,
+            Scaling::PixelPerfect => Scaling::Fit,
+        }
+    }
+}
+
+/// Rect to draw a grid of cols x rows square tiles in, centered within bounds
+pub fn grid_rect(bounds: Rect, cols: usize, rows: usize, scaling: Scaling) -> Rect {
+    let cols = cols.max(1) as f32;
+    let rows = rows.max(1) as f32;
+    let tile_size = (bounds.w() / cols).min(bounds.h() / rows);
+
+    match scaling {
+        Scaling::Fit => Rect::from_xy_wh(bounds.xy(), vec2(tile_size * cols, tile_size * rows)),
+        Scaling::PixelPerfect => {
+            // never go smaller than one pixel per tile, even if that overflows the bounds
+            let tile_size = tile_size.floor().max(1.0);
+            let w = tile_size * cols;
+            let h = tile_size * rows;
+            let left = (bounds.x() - w / 2.0).round();
+            let top = (bounds.y() + h / 2.0).round();
+            Rect::from_corner_points([left, top], [left + w, top - h])
+        }
+    }
+}
+
+/// Split bounds into the part left for the grid and a panel down the right hand side taking
+/// `fraction` of the width
+pub fn split_panel(bounds: Rect, fraction: f32) -> (Rect, Rect) {
+    let panel_w = bounds.w() * fraction.clamp(0.0, 1.0);
+    let split = bounds.right() - panel_w;
+    let rest = Rect::from_corner_points([bounds.left(), bounds.top()], [split, bounds.bottom()]);
+    let panel = Rect::from_corner_points([split, bounds.top()], [bounds.right(), bounds.bottom()]);
+    (rest, panel)
+}
+
+/// `count` rows from the top of bounds, each at most `max_h` high
+pub fn rows(bounds: Rect, count: usize, max_h: f32) -> Vec<Rect> {
+    let h = (bounds.h() / count.max(1) as f32).min(max_h);
+    (0..count)
+        .map(|i| {
+            let top = bounds.top() - i as f32 * h;
+            Rect::from_corner_points([bounds.left(), top], [bounds.right(), top - h])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fit_letterboxes_wide_bounds() {
+        let r = grid_rect(Rect::from_w_h(200.0, 100.0), 10, 10, Scaling::Fit);
+        assert_eq!(r.wh(), vec2(100.0, 100.0));
+        assert_eq!(r.xy(), vec2(0.0, 0.0));
+    }
+
+    #[test]
+    fn fit_keeps_tiles_square() {
+        let r = grid_rect(Rect::from_w_h(300.0, 300.0), 4, 2, Scaling::Fit);
+        assert_eq!(r.w() / 4.0, r.h() / 2.0);
+    }
+
+    #[test]
+    fn pixel_perfect_whole_tiles() {
+        let r = grid_rect(Rect::from_w_h(250.0, 250.0), 64, 64, Scaling::PixelPerfect);
+        assert_eq!(r.wh(), vec2(192.0, 192.0));
+        assert_eq!(r.left(), r.left().round());
+        assert_eq!(r.top(), r.top().round());
+    }
+
+    #[test]
+    fn pixel_perfect_minimum_one_pixel() {
+        let r = grid_rect(Rect::from_w_h(10.0, 10.0), 64, 64, Scaling::PixelPerfect);
+        assert_eq!(r.wh(), vec2(64.0, 64.0));
+    }
+
+    #[test]
+    fn panel_takes_right_side() {
+        let (rest, panel) = split_panel(Rect::from_w_h(400.0, 200.0), 0.25);
+        assert_eq!(panel.w(), 100.0);
+        assert_eq!(rest.w(), 300.0);
+        assert_eq!(panel.right(), 200.0);
+        assert_eq!(rest.right(), panel.left());
+        assert_eq!(panel.h(), 200.0);
+    }
+
+    #[test]
+    fn rows_stack_from_the_top() {
+        let bounds = Rect::from_w_h(100.0, 100.0);
+        let rows = rows(bounds, 4, 20.0);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].top(), 50.0);
+        assert!(rows.iter().all(|row| row.h() == 20.0 && row.w() == 100.0));
+        assert_eq!(rows[1].top(), rows[0].bottom());
+    }
+}
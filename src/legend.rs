@@ -0,0 +1,96 @@
+use nannou::prelude::*;
+
+use bimp::rewrite::{Grid, ReplacementRule};
+use bimp::tile::Colorable;
+
+use crate::layout;
+use crate::nannou_color;
+
+/// Rows are never taller than this, so short rule lists stay readable
+const MAX_ROW_H: f32 = 48.0;
+
+/// Each rule as its find and replace patches side by side with an arrow between them, one rule
+/// per row from the top of `bounds`. Don't-care cells are drawn as outlines, and inactive rules
+/// are crossed out.
+pub fn draw<T: Colorable, const S: usize>(
+    rules: &[ReplacementRule<T, S>],
+    draw: &Draw,
+    bounds: Rect,
+) {
+    draw.rect()
+        .xy(bounds.xy())
+        .wh(bounds.wh())
+        .color(rgba(0.0, 0.0, 0.0, 0.6));
+    for (rule_id, row) in layout::rows(bounds, rules.len(), MAX_ROW_H)
+        .into_iter()
+        .enumerate()
+    {
+        let row = row.pad(row.h() / 8.0);
+        // label, find patch, arrow and replace patch get a quarter of the row each
+        let cell = (row.w() / 4.0 / S as f32).min(row.h() / S as f32);
+        let patch = cell * S as f32;
+        let slot = row.w() / 4.0;
+        let slot_x = |i: f32| row.left() + slot * (i + 0.5);
+
+        draw.text(&rule_id.to_string())
+            .x_y(slot_x(0.0), row.y())
+            .wh(vec2(slot, row.h()))
+            .font_size((row.h() / 2.0).clamp(8.0, 14.0) as u32)
+            .color(WHITE);
+        let rule = &rules[rule_id];
+        draw_patch(
+            &rule.find,
+            draw,
+            Rect::from_x_y_w_h(slot_x(1.0), row.y(), patch, patch),
+        );
+        draw.arrow()
+            .start(pt2(slot_x(2.0) - slot / 3.0, row.y()))
+            .end(pt2(slot_x(2.0) + slot / 3.0, row.y()))
+            .weight((cell / 4.0).max(1.0))
+            .color(WHITE);
+        draw_patch(
+            &rule.replace,
+            draw,
+            Rect::from_x_y_w_h(slot_x(3.0), row.y(), patch, patch),
+        );
+        if !rule.active {
+            draw.line()
+                .start(pt2(row.left(), row.y()))
+                .end(pt2(row.right(), row.y()))
+                .weight(2.0)
+                .color(RED);
+        }
+    }
+}
+
+fn draw_patch<T: Colorable, const S: usize>(
+    patch: &Grid<Option<T>, S, S>,
+    draw: &Draw,
+    rect: Rect,
+) {
+    let cell = rect.w() / S as f32;
+    for (y, row) in patch.items.iter().enumerate() {
+        for (x, item) in row.iter().enumerate() {
+            let xy = pt2(
+                rect.left() + (x as f32 + 0.5) * cell,
+                rect.top() - (y as f32 + 0.5) * cell,
+            );
+            match item {
+                Some(item) => {
+                    draw.rect()
+                        .xy(xy)
+                        .w_h(cell, cell)
+                        .color(nannou_color(item.color()));
+                }
+                None => {
+                    draw.rect()
+                        .xy(xy)
+                        .w_h(cell * 0.8, cell * 0.8)
+                        .no_fill()
+                        .stroke_weight(1.0)
+                        .stroke(GREY);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Grid rewriting engine. The nannou frontend is the `bimp` binary (feature `gui`), the browser
+//! frontend is in `web` (feature `web`) and the C ABI is in `ffi` (feature `ffi`).
+
+pub mod ascii;
+pub mod boundary;
+pub mod condition;
+pub mod constraint;
+mod coord;
+pub mod counters;
+pub mod cycle;
+pub mod determinism;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod field;
+mod grid;
+pub mod hex;
+pub mod history;
+pub mod layers;
+pub mod markov_junior;
+pub mod matcher;
+pub mod metadata;
+pub mod metrics;
+pub mod model_file;
+pub mod models;
+pub mod morphology;
+pub mod ndcoord;
+pub mod ndgrid;
+pub mod node;
+pub mod placement;
+pub mod profile;
+pub mod refine;
+pub mod rewrite;
+pub mod rotation;
+pub mod scatter;
+pub mod scheduler;
+#[cfg(feature = "lua")]
+pub mod script;
+pub mod search;
+pub mod simulation;
+#[cfg(test)]
+mod snapshot;
+pub mod sonify;
+pub mod stamp;
+pub mod stats;
+pub mod symmetry;
+pub mod tile;
+pub mod tiled;
+pub mod upscale;
+pub mod validate;
+#[cfg(feature = "web")]
+pub mod web;
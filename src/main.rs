@@ -1,359 +1,532 @@
-use nannou::prelude::*;
-
-mod coord;
-mod grid;
-mod ndcoord;
-mod ndgrid;
-mod rotation;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Instant;
 
-use rotation::*;
+use nannou::prelude::*;
 
-use crate::ndcoord::{CartesianIter, Coord};
+use bimp::ascii;
+use bimp::error::BimpError;
+use bimp::history::History;
+use bimp::models;
+use bimp::ndgrid::NGrid;
+use bimp::rewrite::Grid;
+#[cfg(feature = "lua")]
+use bimp::script::LuaScript;
+use bimp::simulation::Simulation;
+use bimp::stats::Summary;
+use bimp::tile::{self, Colorable, Sprite, Tile};
+use bimp::tiled::TiledExport;
+use tracing::info_span;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+mod batch;
+mod cli;
+mod debug_view;
+mod export;
+mod headless;
+mod hex_view;
+mod hud;
+mod import;
+mod keyframes;
+mod layout;
+mod legend;
+mod progress;
+mod solve;
+mod sprite;
+mod stream;
+mod tween;
+mod volume_view;
+
+use hex_view::HexModel;
+use layout::Scaling;
+use sprite::SpriteSheet;
+use tween::Tween;
+use volume_view::VolumeView;
+
+/// Frames of history kept for scrubbing
+const HISTORY_FRAMES: usize = 10_000;
 
 struct Model {
-    window: window::Id,
-    grid: Grid<Tile, 64, 64>,
-    rules: Vec<ReplacementRule<Tile, 3>>,
-}
-
-#[derive(Copy, Clone, Eq, PartialEq)]
-enum Tile {
-    Black,
-    DarkBlue,
-    DarkPurple,
-    DarkGreen,
-    Brown,
-    DarkGrey,
-    LightGrey,
-    White,
-    Red,
-    Orange,
-    Yellow,
-    Green,
-    Blue,
-    Lavender,
-    Pink,
-    LightPeach,
+    options: cli::Options,
+    /// Opened with --second-window to show options.second_window
+    second_window: Option<window::Id>,
+    /// What the second window shows, as of the last update
+    second_values: Grid<f32, { models::WIDTH }, { models::HEIGHT }>,
+    sim: Simulation<Tile, { models::WIDTH }, { models::HEIGHT }, 3>,
+    /// Number of steps run so far, including ones where no rule matched
+    step: usize,
+    /// No rule matched on the last step. Rule stats are printed when this becomes true.
+    converged: bool,
+    /// Fractional steps carried over to the next update when running at a --speed
+    pending_steps: f64,
+    /// The grid after every frame which changed it
+    history: History<Tile, { models::WIDTH }, { models::HEIGHT }>,
+    /// Space stops stepping. While paused, Left and Right scrub through the history.
+    paused: bool,
+    /// Index in the history being shown instead of the grid, while scrubbing
+    scrub: Option<usize>,
+    /// Fades changed cells when running at a --speed
+    tween: Option<Tween<Tile, { models::WIDTH }, { models::HEIGHT }>>,
+    #[cfg(feature = "lua")]
+    script: Option<LuaScript>,
+    /// Tile sprites, if assets/tiles.png exists. Otherwise tiles are drawn as flat colors.
+    sprites: Option<SpriteSheet>,
+    /// 3D grid of (x, y, layer) loaded with --volume. When present it is shown instead of the 2D
+    /// grid.
+    volume: Option<NGrid<Tile, 3>>,
+    volume_view: VolumeView,
+    /// Shown and stepped instead of the square grid when present
+    hex: Option<HexModel>,
+    /// P toggles between fit and pixel perfect scaling
+    scaling: Scaling,
+    /// L shows the rules in a panel beside the grid
+    legend: bool,
+    /// T saves the grid as a Tiled map, C as a CSV layer
+    tiled: TiledExport<Tile>,
 }
 
-trait Colorable {
-    fn color(&self) -> Rgb<u8>;
+/// Engine colors in nannou's color type
+fn nannou_color(color: tile::Rgb) -> Rgb<u8> {
+    Rgb::new(color.red, color.green, color.blue)
 }
 
-/// If T implements Default, Option<T> implements Colorable with color = Default::default().color()
-impl<T: Colorable + Default> Colorable for Option<T> {
-    fn color(&self) -> Rgb<u8> {
-        match self {
-            Some(c) => c.color(),
-            None => {
-                let def: T = Default::default();
-                def.color()
+/// Draw each tile as a sprite from the sheet when one is given and the tile has a sprite,
+/// otherwise as a flat colored rect.
+/// Cells which are still fading in `tween` are drawn as flat colors.
+fn draw_grid<T: Colorable + Sprite + Copy + PartialEq, const W: usize, const H: usize>(
+    grid: &Grid<T, W, H>,
+    draw: &Draw,
+    rect: Rect,
+    sprites: Option<&SpriteSheet>,
+    tween: Option<&Tween<T, W, H>>,
+) {
+    let textured_draw = draw.sampler(SpriteSheet::sampler());
+
+    let x = rect.top_left()[0];
+    let y = rect.top_left()[1];
+
+    let tile_w = rect.w() / W as f32;
+    let tile_h = rect.h() / H as f32;
+
+    for (tile_y_int, row) in grid.items.iter().enumerate() {
+        for (tile_x_int, item) in row.iter().enumerate() {
+            let corner_x = x + tile_x_int as f32 * tile_w;
+            let corner_y = y - tile_y_int as f32 * tile_h;
+            let tile_rect = Rect::from_corner_points(
+                [corner_x, corner_y],
+                [corner_x + tile_w, corner_y - tile_h],
+            )
+            .pad(tile_w / 10.0);
+
+            let fading = tween.filter(|tween| tween.fading(tile_x_int, tile_y_int));
+            let sprite = sprites.filter(|_| fading.is_none()).and_then(|sheet| {
+                item.sprite_index()
+                    .filter(|&index| index < sheet.sprite_count())
+                    .map(|index| (sheet, index))
+            });
+
+            match sprite {
+                Some((sheet, index)) => {
+                    textured_draw
+                        .texture(sheet.texture())
+                        .xy(tile_rect.xy())
+                        .wh(tile_rect.wh())
+                        .area(sheet.area(index));
+                }
+                None => {
+                    let color = match fading {
+                        Some(tween) => tween.color(tile_x_int, tile_y_int),
+                        None => item.color(),
+                    };
+                    draw.rect()
+                        .xy(tile_rect.xy())
+                        .wh(tile_rect.wh())
+                        .color(nannou_color(color));
+                }
             }
         }
     }
 }
 
-impl Colorable for Tile {
-    fn color(&self) -> Rgb<u8> {
-        match self {
-            Tile::Black => Rgb::new(0, 0, 0),
-            Tile::DarkBlue => Rgb::new(29, 43, 83),
-            Tile::DarkPurple => Rgb::new(126, 37, 83),
-            Tile::DarkGreen => Rgb::new(0, 135, 81),
-            Tile::Brown => Rgb::new(171, 82, 54),
-            Tile::DarkGrey => Rgb::new(95, 87, 79),
-            Tile::LightGrey => Rgb::new(194, 195, 199),
-            Tile::White => Rgb::new(255, 241, 232),
-            Tile::Red => Rgb::new(255, 0, 77),
-            Tile::Orange => Rgb::new(255, 163, 0),
-            Tile::Yellow => Rgb::new(255, 236, 39),
-            Tile::Green => Rgb::new(0, 228, 54),
-            Tile::Blue => Rgb::new(41, 173, 255),
-            Tile::Lavender => Rgb::new(131, 118, 156),
-            Tile::Pink => Rgb::new(255, 119, 168),
-            Tile::LightPeach => Rgb::new(255, 204, 170),
+fn main() {
+    // eg. RUST_LOG=bimp=debug for step timings, RUST_LOG=bimp=trace for per rule timings
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let options = cli::Options::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    if options.headless {
+        let seed = options.seed.unwrap_or_else(nannou::rand::random);
+        let result = if let Some(dir) = &options.import_mj {
+            import::run(dir, &options.out_dir("models"), &mut std::io::stdout())
+        } else if let Some(runs) = options.batch {
+            batch::run(&options, runs, seed)
+        } else if options.search.is_some() {
+            solve::run_search(&options, seed)
+        } else if options.replay.is_some() {
+            solve::run_replay(&options, seed)
+        } else if options.stream {
+            stream::run_stdin(&options, seed)
+        } else if let Some(dir) = &options.watch {
+            stream::run_watch(&options, dir, seed)
+        } else {
+            headless::run(&options, seed)
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
+        return;
     }
+
+    nannou::app(model).event(event).update(update).run();
 }
 
-impl Default for Tile {
-    fn default() -> Self {
-        Self::Black
+fn model(app: &App) -> Model {
+    let options = cli::Options::from_env().expect("arguments are checked in main");
+
+    let open_window = |title: String, view: nannou::window::ViewFn<Model>| {
+        app.new_window()
+            .title(title)
+            .size(256, 256)
+            .key_pressed(key_pressed_fn)
+            .view(view)
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("failed to open a window: {}", e);
+                std::process::exit(1);
+            })
+    };
+    open_window("bimp".to_string(), view);
+    let second_window = options
+        .second_window
+        .as_ref()
+        .map(|second| open_window(second.title(), view_second_window));
+
+    let sprites = app
+        .assets_path()
+        .ok()
+        .and_then(|assets| SpriteSheet::load(app, assets.join("tiles.png"), 4, 4).ok());
+
+    #[cfg(feature = "lua")]
+    let script = options.script.as_ref().map(|path| {
+        LuaScript::load(path).unwrap_or_else(|e| {
+            eprintln!("failed to load script: {}", e);
+            std::process::exit(1);
+        })
+    });
+    #[cfg(not(feature = "lua"))]
+    if options.script.is_some() {
+        eprintln!("--script needs bimp to be built with the lua feature");
+        std::process::exit(1);
     }
-}
 
-struct Grid<T, const W: usize, const H: usize> {
-    items: [[T; W]; H],
-}
+    let seed = options.seed.unwrap_or_else(random);
+    let mut sim = options.simulation(seed);
+    options.warn_about_rules(&sim.grid);
+    options.configure(&mut sim).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    let hex = options.hex.then(|| HexModel::new(seed));
+    let volume = options.volume.as_ref().map(|path| {
+        File::open(path)
+            .map_err(|source| BimpError::File {
+                path: path.clone(),
+                source,
+            })
+            .and_then(|file| ascii::read_volume(&mut BufReader::new(file)))
+            .unwrap_or_else(|e| {
+                eprintln!("failed to load volume: {}", e);
+                std::process::exit(1);
+            })
+    });
+    let mut history = History::new(HISTORY_FRAMES);
+    history.record(0, &sim.grid);
+    let tween = options
+        .speed
+        .filter(|_| options.tween > 0.0)
+        .map(|_| Tween::new(&sim.grid, options.tween, app.time));
+
+    let second_values = match &options.second_window {
+        Some(second) => second.values(&mut sim),
+        None => Grid::default(),
+    };
 
-/// Grid of "T: Default" itself also defined default, filling the entire grid
-impl<T: Default + Copy, const W: usize, const H: usize> Default for Grid<T, W, H> {
-    fn default() -> Self {
-        Self {
-            items: [[Default::default(); W]; H],
-        }
+    Model {
+        options,
+        second_window,
+        second_values,
+        sprites,
+        volume,
+        volume_view: Default::default(),
+        hex,
+        scaling: Scaling::Fit,
+        legend: false,
+        tiled: models::tiled_export(),
+        sim,
+        step: 0,
+        converged: false,
+        pending_steps: 0.0,
+        history,
+        paused: false,
+        scrub: None,
+        tween,
+        #[cfg(feature = "lua")]
+        script,
     }
 }
 
-#[derive(Debug)]
-struct PatchOrientation {
-    rotation_times: usize,
-    position: (isize, isize),
+fn event(_app: &App, _model: &mut Model, _event: Event) {}
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    let _span = info_span!("update").entered();
+    if model.paused {
+        // rules can still be switched on and off
+        update_second_window(app, model);
+        return;
+    }
+    if let Some(budget) = model.options.frame_budget {
+        // at least one step, then as many as fit, stopping early once there is nothing to do
+        let started = Instant::now();
+        loop {
+            step(model);
+            let finished = model.converged || model.sim.cycle().is_some();
+            if finished || started.elapsed() >= budget {
+                break;
+            }
+        }
+    } else {
+        let steps = match model.options.speed {
+            Some(speed) => {
+                model.pending_steps += update.since_last.as_secs_f64() * speed;
+                let steps = model.pending_steps.floor();
+                model.pending_steps -= steps;
+                steps as usize
+            }
+            None => 100,
+        };
+        for _ in 0..steps {
+            step(model);
+        }
+    }
+    model.history.record(model.sim.steps, &model.sim.grid);
+    if let Some(tween) = &mut model.tween {
+        tween.update(&model.sim.grid, app.time);
+    }
+    update_second_window(app, model);
 }
 
-struct ReplacementRule<T, const S: usize> {
-    find: Grid<Option<T>, S, S>,
-    replace: Grid<Option<T>, S, S>,
+/// Refresh what the second window shows, unless it has been closed
+fn update_second_window(app: &App, model: &mut Model) {
+    let open = model
+        .second_window
+        .is_some_and(|id| app.window(id).is_some());
+    if let (true, Some(second)) = (open, &model.options.second_window) {
+        model.second_values = second.values(&mut model.sim);
+    }
 }
 
-impl<T: Eq + Copy, const W: usize, const H: usize> Grid<T, W, H> {
-    fn check_patch_at<const S: usize>(
-        &self,
-        patch: &Grid<Option<T>, S, S>,
-        offset_x: isize,
-        offset_y: isize,
-    ) -> bool {
-        for (patch_y, row) in patch.items.iter().enumerate() {
-            'inner: for (patch_x, item) in row.iter().enumerate() {
-                match item {
-                    // None is a 'dont care' value and matches anything
-                    None => continue 'inner,
-                    Some(item) => {
-                        let grid_x = patch_x as isize + offset_x;
-                        let grid_y = patch_y as isize + offset_y;
-                        // patch has a value but is outside of the grid, BAD!
-                        if grid_x < 0
-                            || grid_y < 0
-                            || grid_x >= (W as isize)
-                            || grid_y >= (H as isize)
-                        {
-                            return false;
-                        }
-                        let grid_item = &self.items[grid_y as usize][grid_x as usize];
-                        // if _any_ items fail to match, the whole patch fails
-                        if grid_item != item {
-                            return false;
-                        }
-                    }
+/// Run the script, if there is one, then the rules
+fn step(model: &mut Model) {
+    // a loaded volume doesn't change
+    if model.volume.is_some() {
+        return;
+    }
+    if let Some(hex) = &mut model.hex {
+        hex.step();
+        model.step += 1;
+        return;
+    }
+    // stepping stops for good once the model is in a cycle
+    if model.sim.cycle().is_some() {
+        return;
+    }
+    #[cfg(feature = "lua")]
+    if let Some(script) = &model.script {
+        match script.step(&mut model.sim.grid, model.step, &mut model.sim.rng) {
+            Ok(skip_rules) => {
+                // the script may have written anywhere
+                model.sim.grid_changed();
+                if skip_rules {
+                    model.step += 1;
+                    return;
                 }
             }
+            Err(e) => {
+                eprintln!("script error, disabling script: {}", e);
+                model.script = None;
+            }
         }
-        true
     }
+    let converged = !model.sim.step();
+    if converged && !model.converged {
+        print!("{}", Summary(&model.sim.rule_stats()));
+    }
+    model.converged = converged;
+    if let Some(length) = model.sim.cycle() {
+        println!(
+            "stopped after {} steps in a cycle of length {}",
+            model.sim.steps, length
+        );
+    }
+    model.step += 1;
+}
 
-    fn get_patch_matches<const S: usize>(
-        &self,
-        patch: &Grid<Option<T>, S, S>,
-    ) -> Vec<PatchOrientation> {
-        let mut matches = Vec::new();
-        for rotation_times in [0, 1, 2, 3] {
-            let rotated_patch = patch.rotate(rotation_times);
-            for offset_x in (-(S as isize - 1))..W as isize {
-                for offset_y in (-(S as isize - 1))..H as isize {
-                    if self.check_patch_at(&rotated_patch, offset_x, offset_y) {
-                        matches.push(PatchOrientation {
-                            rotation_times,
-                            position: (offset_x, offset_y),
-                        });
-                    }
+fn key_pressed_fn(app: &App, model: &mut Model, k: Key) {
+    match k {
+        Key::P => model.scaling = model.scaling.toggled(),
+        Key::L => model.legend = !model.legend,
+        Key::Space => {
+            model.paused = !model.paused;
+            // carry on from the grid being shown, forgetting what came after it
+            if let Some(index) = model.scrub.take() {
+                if let Some((_, snapshot)) = model.history.get(index) {
+                    model.sim.grid = snapshot.grid();
+                    model.sim.grid_changed();
                 }
+                model.history.truncate(index);
             }
         }
-        matches
-    }
-
-    fn replace_at<const S: usize>(
-        &mut self,
-        replacement_patch: &Grid<Option<T>, S, S>,
-        orientation: &PatchOrientation,
-    ) {
-        let rotated = replacement_patch.rotate(orientation.rotation_times);
-        // TODO abstract 2d iteration out of Grid
-        for (y, row) in rotated.items.iter().enumerate() {
-            for (x, item) in row.iter().enumerate() {
-                if let Some(item) = item {
-                    self.items[((y as isize) + orientation.position.1) as usize]
-                        [((x as isize) + orientation.position.0) as usize] = *item;
-                }
+        Key::Left | Key::Right if model.paused && !model.history.is_empty() => {
+            let last = model.history.len() - 1;
+            let index = model.scrub.unwrap_or(last);
+            let index = match k {
+                Key::Left => index.saturating_sub(1),
+                _ => (index + 1).min(last),
+            };
+            model.scrub = Some(index);
+        }
+        // switch the first 9 rules on and off
+        Key::Key1
+        | Key::Key2
+        | Key::Key3
+        | Key::Key4
+        | Key::Key5
+        | Key::Key6
+        | Key::Key7
+        | Key::Key8
+        | Key::Key9 => {
+            let rule_id = k as usize - Key::Key1 as usize;
+            if let Some(rule) = model.sim.rules.get(rule_id) {
+                let active = !rule.active;
+                model.sim.set_rule_active(rule_id, active);
             }
         }
-    }
-
-    fn single_random_replace<const S: usize>(&mut self, rule: &ReplacementRule<T, S>) -> bool {
-        let matches = self.get_patch_matches(&rule.find);
-        if matches.is_empty() {
-            return false;
+        // profiling overlay
+        Key::F3 => {
+            let profiling = model.sim.profile().is_none();
+            model.sim.set_profiling(profiling);
         }
-        let chosen_match = &matches[random::<usize>() % matches.len()];
-        self.replace_at(&rule.replace, chosen_match);
-        return true;
-    }
-
-    fn priority_random_repace<const S: usize>(&mut self, rules: &[ReplacementRule<T, S>]) {
-        for rule in rules {
-            if self.single_random_replace(rule) {
-                break;
+        Key::F11 => {
+            if let Some(window) = app.window(app.window_id()) {
+                window.set_fullscreen(!window.is_fullscreen());
             }
         }
+        Key::S => {
+            let path = export::timestamped_path("bimp", "png");
+            let image = export::grid_image(
+                &model.sim.grid,
+                Tile::LightGrey.color(),
+                model.options.screenshot_size,
+            );
+            match image.save(&path) {
+                Ok(()) => {
+                    println!("saved {}", path.display());
+                    export::save_sidecar(&export::metadata(&model.sim), &path);
+                }
+                Err(e) => eprintln!("failed to save {}: {}", path.display(), e),
+            }
+        }
+        Key::T => export::save_timestamped("tmx", &export::metadata(&model.sim), |out| {
+            model.tiled.write_tmx(&model.sim.grid, out)
+        }),
+        Key::C => export::save_timestamped("csv", &export::metadata(&model.sim), |out| {
+            model.tiled.write_csv(&model.sim.grid, out)
+        }),
+        _ => {}
+    }
+    if let Some(volume) = &model.volume {
+        model.volume_view.key_pressed(volume, k);
     }
 }
 
-/// Rotation only implemented for square grids (W==H)
-impl<T: Default + Copy, const S: usize> Grid<T, S, S> {
-    /// x_transform: lambda of (old_x, old_y, size) -> new_x
-    /// y_transform: lambda of (old_x, old_y, size) -> new_y
-    fn transform_indices<R1, R2>(&self, x_transform: R1, y_transform: R2) -> Self
-    where
-        R1: Fn(usize, usize, usize) -> usize,
-        R2: Fn(usize, usize, usize) -> usize,
-    {
-        let mut ret: Self = Default::default();
-        self.items.iter().enumerate().for_each(|(y, row)| {
-            row.iter().enumerate().for_each(|(x, item)| {
-                let new_x = x_transform(x, y, S);
-                let new_y = y_transform(x, y, S);
-                ret.items[new_y][new_x] = *item;
-            })
-        });
-        ret
+fn view(app: &App, model: &Model, frame: Frame) {
+    let _span = info_span!("render").entered();
+    let draw = app.draw();
+    draw.background()
+        .color(nannou_color(Tile::LightGrey.color()));
+
+    let mut bounds = frame.rect().pad(20.0);
+    if model.legend && model.hex.is_none() {
+        let (rest, panel) = layout::split_panel(bounds, 0.3);
+        legend::draw(&model.sim.rules, &draw, panel);
+        bounds = rest.pad_right(10.0);
     }
-
-    fn rotate(&self, times: usize) -> Self {
-        match times {
-            // 0 degrees (no-op)
-            0 => self.transform_indices(|x, _, _| x, |_, y, _| y),
-            // 90 degrees
-            1 => self.transform_indices(|_, y, size| size - 1 - y, |x, _, _| x),
-            // 180 degrees
-            2 => self.transform_indices(|x, _, size| size - 1 - x, |_, y, size| size - 1 - y),
-            // 270 degrees
-            3 => self.transform_indices(|_, y, _| y, |x, _, size| size - 1 - x),
-            // else
-            n => self.rotate(n % 4),
-        }
+    let (grid_w, grid_h) = model.sim.grid.size();
+    let scrubbed = model
+        .scrub
+        .and_then(|index| model.history.get(index))
+        .map(|(step, snapshot)| (step, snapshot.grid()));
+    match (&model.volume, &model.hex) {
+        (Some(volume), _) => model.volume_view.draw(volume, &draw, bounds, model.scaling),
+        (None, Some(hex)) => hex_view::draw(&hex.grid, &draw, bounds),
+        (None, None) => draw_grid(
+            scrubbed.as_ref().map_or(&model.sim.grid, |(_, grid)| grid),
+            &draw,
+            layout::grid_rect(bounds, grid_w, grid_h, model.scaling),
+            model.sprites.as_ref(),
+            model.tween.as_ref().filter(|_| scrubbed.is_none()),
+        ),
     }
-}
-
-impl<T: Colorable, const W: usize, const H: usize> Grid<T, W, H> {
-    fn draw(&self, draw: &Draw, rect: Rect) {
-        let x = rect.top_left()[0];
-        let y = rect.top_left()[1];
-
-        let tile_w = rect.w() / W as f32;
-        let tile_h = rect.h() / H as f32;
-
-        for (tile_y_int, row) in self.items.iter().enumerate() {
-            for (tile_x_int, item) in row.iter().enumerate() {
-                let corner_x = x + tile_x_int as f32 * tile_w;
-                let corner_y = y - tile_y_int as f32 * tile_h;
-                let tile_rect = Rect::from_corner_points(
-                    [corner_x, corner_y],
-                    [corner_x - tile_w, corner_y - tile_h],
-                )
-                .pad(tile_w / 10.0);
-
-                draw.rect()
-                    .xy(tile_rect.xy())
-                    .wh(tile_rect.wh())
-                    .color(item.color());
-            }
-        }
+    let inactive = (0..model.sim.rules.len())
+        .filter(|&rule_id| !model.sim.rules[rule_id].active)
+        .map(|rule_id| rule_id.to_string())
+        .collect::<Vec<_>>();
+    let mut status = Vec::new();
+    if !inactive.is_empty() && !model.legend && model.hex.is_none() {
+        status.push(format!("rules off: {}", inactive.join(", ")));
     }
-}
-
-fn main() {
-    //nannou::app(model).event(event).update(update).run();
-    // println!("{:#?}", rotation_permutations(3));
-    //println!("{:#?}", i.collect::<Vec<_>>())
-    let mut i = Coord::new_3d(1, 1, 1).iter_volume(&Coord::new_3d(3, 3, 3));
-    println!("{:#?}", i.collect::<Vec<_>>())
-}
-
-fn model(app: &App) -> Model {
-    let window = app
-        .new_window()
-        .size(256, 256)
-        .key_pressed(key_pressed_fn)
-        .view(view)
-        .build()
-        .unwrap();
-
-    let mut grid: Grid<Tile, 64, 64> = Default::default();
-    grid.items[32][32] = Tile::Red;
-
-    const R: Option<Tile> = Some(Tile::Red);
-    const K: Option<Tile> = Some(Tile::Black);
-    const W: Option<Tile> = Some(Tile::White);
-    const G: Option<Tile> = Some(Tile::Green);
-    const O: Option<Tile> = Some(Tile::Orange);
-    const B: Option<Tile> = Some(Tile::Blue);
-    const X: Option<Tile> = None;
-
-    Model {
-        window,
-        grid,
-        rules: vec![
-            ReplacementRule {
-                find: Grid {
-                    items: [[R, K, K], [X, X, X], [X, X, X]],
-                },
-                replace: Grid {
-                    items: [[W, W, R], [X, X, X], [X, X, X]],
-                },
-            },
-            ReplacementRule {
-                find: Grid {
-                    items: [[R, K, W], [X, X, X], [X, X, X]],
-                },
-                replace: Grid {
-                    items: [[G, W, O], [X, X, X], [X, X, X]],
-                },
-            },
-            ReplacementRule {
-                find: Grid {
-                    items: [[O, W, G], [X, X, X], [X, X, X]],
-                },
-                replace: Grid {
-                    items: [[O, K, B], [X, X, X], [X, X, X]],
-                },
-            },
-            ReplacementRule {
-                find: Grid {
-                    items: [[B, W, W], [X, X, X], [X, X, X]],
-                },
-                replace: Grid {
-                    items: [[K, K, B], [X, X, X], [X, X, X]],
-                },
-            },
-            ReplacementRule {
-                find: Grid {
-                    items: [[B, W, O], [X, X, X], [X, X, X]],
-                },
-                replace: Grid {
-                    items: [[K, K, R], [X, X, X], [X, X, X]],
-                },
-            },
-        ],
+    if model.paused && model.hex.is_none() {
+        status.push(match &scrubbed {
+            Some((step, _)) => format!(
+                "paused, showing step {} ({}/{})",
+                step,
+                model.scrub.unwrap_or_default() + 1,
+                model.history.len()
+            ),
+            None => "paused, left and right to scrub".to_string(),
+        });
     }
-}
-
-fn event(_app: &App, _model: &mut Model, _event: Event) {}
-
-fn update(_app: &App, model: &mut Model, _update: Update) {
-    for _ in 0..100 {
-        model.grid.priority_random_repace(&model.rules);
+    hud::draw_lines_at_bottom(&status, &draw, frame.rect());
+    if let Some(profile) = model.sim.profile() {
+        let lines = profile
+            .to_string()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        hud::draw_lines(&lines, &draw, frame.rect());
     }
-}
 
-fn key_pressed_fn(_app: &App, model: &mut Model, _k: Key) {
-    //model.grid.priority_random_repace(&model.rules)
+    draw.to_frame(app, &frame).unwrap();
 }
 
-fn view(app: &App, model: &Model, frame: Frame) {
+/// The view chosen with --second-window, laid out like the grid in the main window
+fn view_second_window(app: &App, model: &Model, frame: Frame) {
+    let _span = info_span!("render_second").entered();
     let draw = app.draw();
-    draw.background().color(Tile::LightGrey.color());
-
-    model.grid.draw(&draw, app.window_rect().pad(20.0));
-
+    draw.background()
+        .color(nannou_color(Tile::LightGrey.color()));
+    let (grid_w, grid_h) = model.sim.grid.size();
+    let rect = layout::grid_rect(frame.rect().pad(20.0), grid_w, grid_h, model.scaling);
+    debug_view::draw_heatmap(&model.second_values, &draw, rect);
     draw.to_frame(app, &frame).unwrap();
 }
@@ -0,0 +1,463 @@
+//! Import of MarkovJunior models. Its palette.xml gives each symbol a color, and each symbol
+//! becomes the tile with the nearest color, see Palette. A model is converted when it is a tree
+//! of the nodes bimp can run as a list of rules, otherwise the node it can't run is reported:
+//!
+//! - `one` becomes its rules. Bimp applies the first rule with a match where MarkovJunior picks
+//!   any match of any rule, which only differs for nodes with several rules.
+//! - `all` and `prl` are applied one match per step rather than every match at once, which ends
+//!   in the same grid when the matches don't overlap.
+//! - `markov` and `sequence` become the rules of their children in order. A sequence never
+//!   goes back to an earlier child, but bimp tries them again every step.
+//!
+//! Anything converted with a difference like these is noted in the conversion.
+
+use std::io::{self, Write};
+
+use roxmltree::{Document, Node};
+
+use crate::error::{BimpError, Result};
+use crate::model_file::ModelFile;
+use crate::rewrite::{Grid, ReplacementRule};
+use crate::symmetry::Symmetry;
+use crate::tile::{AsciiSymbol, Colorable, Named, Rgb, Tile};
+
+/// The tile each MarkovJunior symbol becomes
+pub struct Palette {
+    symbols: Vec<(char, Rgb, Tile)>,
+}
+
+impl Palette {
+    pub fn tile(&self, symbol: char) -> Option<Tile> {
+        self.symbols
+            .iter()
+            .find(|(other, ..)| *other == symbol)
+            .map(|&(.., tile)| tile)
+    }
+
+    /// One line per symbol: the symbol, its tile's ascii symbol and name, and the symbol's color
+    /// when the tile's isn't the same
+    pub fn write_alphabet<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        for &(symbol, color, tile) in self.symbols.iter() {
+            write!(out, "{} {} {}", symbol, tile.to_char(), tile.name())?;
+            if tile.color() != color {
+                write!(
+                    out,
+                    " (nearest to {:02X}{:02X}{:02X})",
+                    color.red, color.green, color.blue
+                )?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read palette.xml, ie. `<color symbol="B" value="000000"/>` elements
+pub fn read_palette(xml: &str) -> Result<Palette> {
+    let document = parse(xml)?;
+    let mut symbols = Vec::new();
+    for node in document
+        .descendants()
+        .filter(|node| node.has_tag_name("color"))
+    {
+        let symbol = single_char(attribute(&document, node, "symbol")?)
+            .ok_or_else(|| error_at(&document, node, "symbol must be one character"))?;
+        let color = parse_color(attribute(&document, node, "value")?)
+            .ok_or_else(|| error_at(&document, node, "value must be a RRGGBB color"))?;
+        symbols.push((symbol, color, nearest_tile(color)));
+    }
+    Ok(Palette { symbols })
+}
+
+fn parse_color(value: &str) -> Option<Rgb> {
+    let value = value.trim_start_matches('#');
+    if value.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(value.get(i..i + 2)?, 16).ok();
+    Some(Rgb::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn nearest_tile(color: Rgb) -> Tile {
+    let distance = |tile: &Tile| {
+        let other = tile.color();
+        [
+            (color.red, other.red),
+            (color.green, other.green),
+            (color.blue, other.blue),
+        ]
+        .into_iter()
+        .map(|(a, b)| (a as i32 - b as i32).pow(2))
+        .sum::<i32>()
+    };
+    Tile::ALL
+        .into_iter()
+        .min_by_key(distance)
+        .expect("there are tiles")
+}
+
+/// A model converted from MarkovJunior, with how it will run differently
+pub struct Conversion {
+    pub model: ModelFile<Tile, 3>,
+    pub notes: Vec<String>,
+}
+
+/// Convert a model's XML. Fails with BimpError::Unsupported naming the first thing bimp can't
+/// run, eg. a `wfc` node, a 3D or larger than 3x3 pattern, or two symbols becoming one tile.
+pub fn convert_model(xml: &str, palette: &Palette) -> Result<Conversion> {
+    let document = parse(xml)?;
+    let root = document.root_element();
+    let values = attribute(&document, root, "values")?
+        .chars()
+        .collect::<Vec<_>>();
+    let mut tiles = Vec::new();
+    for &symbol in values.iter() {
+        let tile = palette.tile(symbol).ok_or_else(|| {
+            error_at(
+                &document,
+                root,
+                &format!("'{}' isn't in the palette", symbol),
+            )
+        })?;
+        if let Some((other, _)) = tiles.iter().find(|(_, other)| *other == tile) {
+            return Err(BimpError::Unsupported(format!(
+                "symbols '{}' and '{}' which both become {}",
+                other,
+                symbol,
+                tile.name()
+            )));
+        }
+        tiles.push((symbol, tile));
+    }
+    let tile = |symbol: char| {
+        tiles
+            .iter()
+            .find(|(other, _)| *other == symbol)
+            .map(|&(_, tile)| tile)
+    };
+
+    let mut converter = Converter {
+        document: &document,
+        tile: &tile,
+        rules: Vec::new(),
+        notes: Vec::new(),
+    };
+    converter.node(root, Symmetry::All)?;
+    if converter.rules.is_empty() {
+        return Err(BimpError::Unsupported("model without rules".to_string()));
+    }
+    let origin = match root.attribute("origin") {
+        Some("True") => Some(
+            tile(
+                values
+                    .get(1)
+                    .copied()
+                    .ok_or_else(|| error_at(&document, root, "origin needs a second value"))?,
+            )
+            .expect("values are all in the palette"),
+        ),
+        _ => None,
+    };
+    Ok(Conversion {
+        model: ModelFile {
+            fill: tiles[0].1,
+            origin,
+            init: Vec::new(),
+            rules: converter.rules,
+            refine: None,
+        },
+        notes: converter.notes,
+    })
+}
+
+struct Converter<'a, 'input, F> {
+    document: &'a Document<'input>,
+    tile: &'a F,
+    rules: Vec<ReplacementRule<Tile, 3>>,
+    notes: Vec<String>,
+}
+
+type Patch = Grid<Option<Tile>, 3, 3>;
+
+/// Attributes which only change how a node's rules are picked or how long it runs
+const IGNORED_ATTRIBUTES: [&str; 4] = ["steps", "temperature", "search", "limit"];
+
+impl<'a, 'input, F> Converter<'a, 'input, F>
+where
+    F: Fn(char) -> Option<Tile>,
+{
+    /// Add the rules of `node` and its children, `symmetry` being its parent's
+    fn node(&mut self, node: Node, symmetry: Symmetry) -> Result<()> {
+        let name = node.tag_name().name();
+        let symmetry = match node.attribute("symmetry") {
+            Some(text) => text
+                .parse()
+                .map_err(|message: String| error_at(self.document, node, &message))?,
+            None => symmetry,
+        };
+        for attribute in IGNORED_ATTRIBUTES {
+            if node.has_attribute(attribute) {
+                self.note(node, &format!("{} is ignored", attribute));
+            }
+        }
+        match name {
+            "one" | "all" | "prl" => {
+                if name != "one" {
+                    self.note(node, "applied one match per step");
+                }
+                let mut rules = 0;
+                if node.has_attribute("in") {
+                    self.rule(node, symmetry)?;
+                    rules += 1;
+                }
+                for child in node.children().filter(Node::is_element) {
+                    match child.tag_name().name() {
+                        "rule" => {
+                            self.rule(child, symmetry)?;
+                            rules += 1;
+                        }
+                        other => return Err(unsupported(self.document, child, other)),
+                    }
+                }
+                if name == "one" && rules > 1 {
+                    self.note(node, "rules are tried in order");
+                }
+                Ok(())
+            }
+            "markov" | "sequence" => {
+                if name == "sequence" {
+                    self.note(node, "earlier children are tried again every step");
+                }
+                for child in node.children().filter(Node::is_element) {
+                    self.node(child, symmetry)?;
+                }
+                Ok(())
+            }
+            other => Err(unsupported(self.document, node, other)),
+        }
+    }
+
+    fn rule(&mut self, node: Node, symmetry: Symmetry) -> Result<()> {
+        let symmetry = match node.attribute("symmetry") {
+            Some(text) => text
+                .parse()
+                .map_err(|message: String| error_at(self.document, node, &message))?,
+            None => symmetry,
+        };
+        if node.has_attribute("p") {
+            self.note(node, "p is ignored");
+        }
+        if node.has_attribute("file") || node.has_attribute("fin") {
+            return Err(BimpError::Unsupported(format!(
+                "rule patterns from images, {}",
+                position(self.document, node)
+            )));
+        }
+        let input = self.pattern(node, attribute(self.document, node, "in")?)?;
+        let output = self.pattern(node, attribute(self.document, node, "out")?)?;
+        if input.1 != output.1 {
+            return Err(error_at(
+                self.document,
+                node,
+                "in and out patterns have different sizes",
+            ));
+        }
+        self.rules
+            .push(ReplacementRule::new(input.0, output.0).with_symmetry(symmetry));
+        Ok(())
+    }
+
+    /// A pattern with rows separated by '/' and '*' for don't-care, and its (width, height)
+    fn pattern(&self, node: Node, text: &str) -> Result<(Patch, (usize, usize))> {
+        if text.contains(' ') {
+            return Err(BimpError::Unsupported(format!(
+                "3D pattern '{}', {}",
+                text,
+                position(self.document, node)
+            )));
+        }
+        let rows = text
+            .split('/')
+            .map(|row| row.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let width = rows[0].len();
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(error_at(
+                self.document,
+                node,
+                "pattern rows have different lengths",
+            ));
+        }
+        if width > 3 || rows.len() > 3 {
+            return Err(BimpError::Unsupported(format!(
+                "pattern '{}' bigger than 3x3, {}",
+                text,
+                position(self.document, node)
+            )));
+        }
+        let mut patch = Grid {
+            items: [[None; 3]; 3],
+        };
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &symbol) in row.iter().enumerate() {
+                if symbol != '*' {
+                    let tile = (self.tile)(symbol).ok_or_else(|| {
+                        error_at(
+                            self.document,
+                            node,
+                            &format!("'{}' isn't one of the model's values", symbol),
+                        )
+                    })?;
+                    patch.items[y][x] = Some(tile);
+                }
+            }
+        }
+        Ok((patch, (width, rows.len())))
+    }
+
+    fn note(&mut self, node: Node, note: &str) {
+        self.notes.push(format!(
+            "{} {}: {}",
+            node.tag_name().name(),
+            position(self.document, node),
+            note
+        ));
+    }
+}
+
+fn parse(xml: &str) -> Result<Document<'_>> {
+    Document::parse(xml).map_err(|e| BimpError::Parse {
+        line: e.pos().row as usize,
+        message: e.to_string(),
+    })
+}
+
+fn attribute<'a>(document: &Document, node: Node<'a, '_>, name: &str) -> Result<&'a str> {
+    node.attribute(name).ok_or_else(|| {
+        error_at(
+            document,
+            node,
+            &format!("<{}> needs {}", node.tag_name().name(), name),
+        )
+    })
+}
+
+fn single_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    chars.next().filter(|_| chars.next().is_none())
+}
+
+fn position(document: &Document, node: Node) -> String {
+    format!("at line {}", document.text_pos_at(node.range().start).row)
+}
+
+fn error_at(document: &Document, node: Node, message: &str) -> BimpError {
+    BimpError::Parse {
+        line: document.text_pos_at(node.range().start).row as usize,
+        message: message.to_string(),
+    }
+}
+
+fn unsupported(document: &Document, node: Node, name: &str) -> BimpError {
+    BimpError::Unsupported(format!("node <{}> {}", name, position(document, node)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PALETTE: &str = r#"<colors>
+  <color symbol="B" value="000000"/>
+  <color symbol="W" value="FFF1E8"/>
+  <color symbol="R" value="FF004D"/>
+  <color symbol="A" value="C2C3C7"/>
+  <color symbol="a" value="C0C0C0"/>
+</colors>"#;
+
+    fn palette() -> Palette {
+        read_palette(PALETTE).unwrap()
+    }
+
+    #[test]
+    fn symbols_become_the_nearest_tile() {
+        let palette = palette();
+        assert_eq!(palette.tile('R'), Some(Tile::Red));
+        assert_eq!(palette.tile('a'), Some(Tile::LightGrey));
+        assert_eq!(palette.tile('Z'), None);
+        let mut alphabet = Vec::new();
+        palette.write_alphabet(&mut alphabet).unwrap();
+        let alphabet = String::from_utf8(alphabet).unwrap();
+        assert!(alphabet.starts_with("B B Black\n"));
+        assert!(alphabet.contains("a A LightGrey (nearest to C0C0C0)\n"));
+        assert!(read_palette(r#"<colors><color symbol="BB" value="000000"/></colors>"#).is_err());
+    }
+
+    #[test]
+    fn growth() {
+        let xml = r#"<one values="BW" origin="True" in="WB" out="WW"/>"#;
+        let conversion = convert_model(xml, &palette()).unwrap();
+        let model = conversion.model;
+        assert!(conversion.notes.is_empty());
+        assert_eq!((model.fill, model.origin), (Tile::Black, Some(Tile::White)));
+        assert_eq!(model.rules.len(), 1);
+        assert_eq!(model.rules[0].symmetry, Symmetry::All);
+        assert_eq!(
+            model.rules[0].find.items[0],
+            [Some(Tile::White), Some(Tile::Black), None]
+        );
+    }
+
+    #[test]
+    fn nested_nodes() {
+        let xml = r#"
+<sequence values="BRW" symmetry="(x)">
+  <all in="B" out="W" steps="3"/>
+  <markov>
+    <one in="R*/W*" out="WW/**" symmetry="()"/>
+    <prl>
+      <rule in="W" out="R"/>
+      <rule in="R" out="B" p="0.5"/>
+    </prl>
+  </markov>
+</sequence>"#;
+        let conversion = convert_model(xml, &palette()).unwrap();
+        let rules = &conversion.model.rules;
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[0].symmetry, Symmetry::ReflectX);
+        assert_eq!(rules[1].symmetry, Symmetry::Identity);
+        assert_eq!(rules[1].find.items[0], [Some(Tile::Red), None, None]);
+        assert_eq!(rules[1].replace.items[1], [None; 3]);
+        assert_eq!(rules[3].replace.items[0][0], Some(Tile::Black));
+        assert_eq!(conversion.model.origin, None);
+        let notes = conversion.notes.join("\n");
+        for note in ["sequence at line 2", "steps is ignored", "p is ignored"] {
+            assert!(notes.contains(note), "{} missing from {}", note, notes);
+        }
+    }
+
+    #[test]
+    fn unsupported_models() {
+        let convert = |xml: &str| match convert_model(xml, &palette()) {
+            Err(BimpError::Unsupported(what)) => what,
+            Err(e) => panic!("{} isn't an unsupported error", e),
+            Ok(_) => panic!("{} converted", xml),
+        };
+        assert_eq!(
+            convert(
+                r#"<markov values="BW">
+  <path from="W" to="B" on="B" color="W"/>
+</markov>"#
+            ),
+            "node <path> at line 2"
+        );
+        assert!(convert(r#"<one values="BW" in="WBBB" out="WWWW"/>"#).contains("bigger than 3x3"));
+        assert!(convert(r#"<one values="BW" in="W B" out="W W"/>"#).contains("3D"));
+        assert!(convert(r#"<one values="Aa" in="A" out="a"/>"#).contains("both become LightGrey"));
+        // malformed rather than unsupported
+        assert!(matches!(
+            convert_model(r#"<one values="BW" in="WB" out="W"/>"#, &palette()),
+            Err(BimpError::Parse { line: 1, .. })
+        ));
+        assert!(convert_model(r#"<one values="BQ" in="B" out="Q"/>"#, &palette()).is_err());
+    }
+}
@@ -0,0 +1,511 @@
+//! Ways of finding every placement of a patch in a grid. All strategies find the same set of
+//! matches, but may differ in speed and in the order the matches are returned.
+
+use crate::boundary::{Boundaries, Boundary};
+use crate::placement::{self, Placement};
+use crate::rewrite::{Grid, PatchOrientation};
+use crate::symmetry::Symmetry;
+use crate::tile::PackedTile;
+
+/// Strategies may keep what they learnt about the grid between calls. The grid's replace
+/// functions report the cells they write with cells_changed, anything else which changes the grid
+/// or moves on to a different one has to call cells_changed or grid_changed itself.
+pub trait MatchStrategy<T, const W: usize, const H: usize, const S: usize> {
+    /// Every (orientation, offset) where the patch matches the grid and the placement allows it,
+    /// for each of the symmetry's orientations, reading cells beyond the edges through the
+    /// boundaries. Strategies should rule out positions by placement before comparing any cells.
+    fn find_matches(
+        &mut self,
+        grid: &Grid<T, W, H>,
+        patch: &Grid<Option<T>, S, S>,
+        placement: &Placement,
+        symmetry: Symmetry,
+        boundaries: &Boundaries,
+    ) -> Vec<PatchOrientation>;
+
+    /// The cells at `cells` now hold what `grid` has there, nothing else changed since the last
+    /// call
+    fn cells_changed(&mut self, _grid: &Grid<T, W, H>, _cells: &[(usize, usize)]) {}
+
+    /// Forget everything about the grid, the next call may be for any grid
+    fn grid_changed(&mut self) {}
+}
+
+/// Check the patch at every offset in every rotation
+#[derive(Default)]
+pub struct NaiveScan;
+
+impl<T, const W: usize, const H: usize, const S: usize> MatchStrategy<T, W, H, S> for NaiveScan
+where
+    T: Eq + Copy,
+{
+    fn find_matches(
+        &mut self,
+        grid: &Grid<T, W, H>,
+        patch: &Grid<Option<T>, S, S>,
+        placement: &Placement,
+        symmetry: Symmetry,
+        boundaries: &Boundaries,
+    ) -> Vec<PatchOrientation> {
+        grid.get_placed_matches(patch, placement, symmetry, boundaries)
+    }
+}
+
+/// Only check offsets where the first non don't-care cell of the patch lines up with a grid cell
+/// holding the same tile. Much faster than NaiveScan when the patch starts with a rare tile.
+#[derive(Default)]
+pub struct AnchorScan;
+
+impl<T, const W: usize, const H: usize, const S: usize> MatchStrategy<T, W, H, S> for AnchorScan
+where
+    T: Eq + Copy,
+{
+    fn find_matches(
+        &mut self,
+        grid: &Grid<T, W, H>,
+        patch: &Grid<Option<T>, S, S>,
+        placement: &Placement,
+        symmetry: Symmetry,
+        boundaries: &Boundaries,
+    ) -> Vec<PatchOrientation> {
+        // a mirrored cell beyond the edge could be the anchor, so there's no grid cell to
+        // start from
+        let mirrored = boundaries.x == Boundary::Mirror || boundaries.y == Boundary::Mirror;
+        let mut matches = Vec::new();
+        for &(rotation_times, reflected) in symmetry.orientations() {
+            let oriented = patch.orient(rotation_times, reflected);
+            let anchor = oriented.items.iter().enumerate().find_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .find_map(|(x, cell)| cell.map(|tile| (x as isize, y as isize, tile)))
+            });
+            let (origin_x, origin_y) = placement::origin_in_patch(rotation_times, reflected, S);
+            match anchor {
+                // a list of cells is already cheaper to check directly than any scan
+                Some((anchor_x, anchor_y, tile))
+                    if !matches!(placement, Placement::Cells(_)) && !mirrored =>
+                {
+                    for (y, row) in grid.items.iter().enumerate() {
+                        for (x, item) in row.iter().enumerate() {
+                            let (offset_x, offset_y) = boundaries.normalize(
+                                (x as isize - anchor_x, y as isize - anchor_y),
+                                W,
+                                H,
+                            );
+                            let origin = boundaries.normalize(
+                                (offset_x + origin_x, offset_y + origin_y),
+                                W,
+                                H,
+                            );
+                            if placement.allows(origin, W, H)
+                                && *item == tile
+                                && grid.check_bounded_patch_at(
+                                    &oriented, offset_x, offset_y, boundaries,
+                                )
+                            {
+                                matches.push(PatchOrientation {
+                                    rotation_times,
+                                    reflected,
+                                    position: (offset_x, offset_y),
+                                });
+                            }
+                        }
+                    }
+                }
+                // only don't-cares, so it matches everywhere the placement allows
+                _ => grid.push_oriented_matches(
+                    &oriented,
+                    (rotation_times, reflected),
+                    placement,
+                    boundaries,
+                    &mut matches,
+                ),
+            }
+        }
+        matches
+    }
+}
+
+/// Cells compared at once by PackedScan, one byte each in a u64
+const WORD: usize = 8;
+
+/// Like NaiveScan, but with the grid packed into bytes so each patch row is compared 8 cells at
+/// a time within a u64 (SWAR, no vector instructions): a word of grid bytes is XORed with the
+/// row's tiles and masked with its don't-cares, one branch per word instead of one per cell. Rows
+/// of only don't-cares aren't read at all. Placements reaching past the edges are checked cell by
+/// cell. Finds the same matches in the same order as NaiveScan.
+///
+/// The grid is packed on the first call and after grid_changed, and only the changed cells are
+/// repacked after that, so a step costs a scan rather than a scan and a copy of the grid.
+#[derive(Default)]
+pub struct PackedScan {
+    /// The grid's bytes row by row, each row padded so a word can be read from any of its cells
+    packed: Vec<u8>,
+    /// Whether `packed` holds the grid, cleared by grid_changed
+    valid: bool,
+}
+
+/// Up to WORD cells of a patch row, starting at (x, y) in the patch
+struct PackedWord {
+    x: usize,
+    y: usize,
+    tiles: u64,
+    /// 0xff for each cell which isn't a don't-care
+    mask: u64,
+}
+
+impl PackedScan {
+    fn pack<T: PackedTile, const W: usize, const H: usize>(&mut self, grid: &Grid<T, W, H>) {
+        self.packed.clear();
+        for row in grid.items.iter() {
+            self.packed.extend(row.iter().map(|tile| tile.to_byte()));
+            self.packed.extend([0; WORD]);
+        }
+        self.valid = true;
+    }
+
+    /// Whether the packed bytes are those of `grid`, ie. every change was reported
+    fn packs<T: PackedTile, const W: usize, const H: usize>(&self, grid: &Grid<T, W, H>) -> bool {
+        let stride = W + WORD;
+        grid.items.iter().enumerate().all(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .all(|(x, tile)| self.packed[y * stride + x] == tile.to_byte())
+        })
+    }
+
+    /// Whether the words match with the patch's top left corner at grid cell (x, y), which
+    /// must leave the whole patch inside the grid
+    fn check_words<const W: usize>(&self, words: &[PackedWord], x: usize, y: usize) -> bool {
+        let stride = W + WORD;
+        words.iter().all(|word| {
+            let start = (y + word.y) * stride + x + word.x;
+            let bytes = self.packed[start..start + WORD]
+                .try_into()
+                .expect("rows are padded by a word");
+            (u64::from_le_bytes(bytes) ^ word.tiles) & word.mask == 0
+        })
+    }
+}
+
+/// The words of every patch row with at least one cell which isn't a don't-care
+fn pack_patch<T: PackedTile, const S: usize>(patch: &Grid<Option<T>, S, S>) -> Vec<PackedWord> {
+    let mut words = Vec::new();
+    for (y, row) in patch.items.iter().enumerate() {
+        for (index, cells) in row.chunks(WORD).enumerate() {
+            let (mut tiles, mut mask) = (0, 0);
+            for (i, cell) in cells.iter().enumerate() {
+                if let Some(tile) = cell {
+                    tiles |= (tile.to_byte() as u64) << (8 * i);
+                    mask |= 0xff << (8 * i);
+                }
+            }
+            if mask != 0 {
+                words.push(PackedWord {
+                    x: index * WORD,
+                    y,
+                    tiles,
+                    mask,
+                });
+            }
+        }
+    }
+    words
+}
+
+impl<T, const W: usize, const H: usize, const S: usize> MatchStrategy<T, W, H, S> for PackedScan
+where
+    T: PackedTile,
+{
+    fn find_matches(
+        &mut self,
+        grid: &Grid<T, W, H>,
+        patch: &Grid<Option<T>, S, S>,
+        placement: &Placement,
+        symmetry: Symmetry,
+        boundaries: &Boundaries,
+    ) -> Vec<PatchOrientation> {
+        if !self.valid {
+            self.pack(grid);
+        }
+        debug_assert!(self.packs(grid), "grid changed without telling PackedScan");
+        let mut matches = Vec::new();
+        for &orientation in symmetry.orientations() {
+            let oriented = patch.orient(orientation.0, orientation.1);
+            let words = pack_patch(&oriented);
+            grid.push_matches_where::<S>(
+                orientation,
+                placement,
+                boundaries,
+                &mut matches,
+                |x, y| {
+                    let inside = x >= 0 && y >= 0 && x as usize + S <= W && y as usize + S <= H;
+                    if inside {
+                        self.check_words::<W>(&words, x as usize, y as usize)
+                    } else {
+                        grid.check_bounded_patch_at(&oriented, x, y, boundaries)
+                    }
+                },
+            );
+        }
+        matches
+    }
+
+    fn cells_changed(&mut self, grid: &Grid<T, W, H>, cells: &[(usize, usize)]) {
+        if self.valid {
+            for &(x, y) in cells {
+                self.packed[y * (W + WORD) + x] = grid.items[y][x].to_byte();
+            }
+        }
+    }
+
+    fn grid_changed(&mut self) {
+        self.valid = false;
+    }
+}
+
+/// Names accepted by by_name
+pub const NAMES: [&str; 3] = ["naive", "anchor", "packed"];
+
+/// Look up a strategy by the name used on the command line
+pub fn by_name<T, const W: usize, const H: usize, const S: usize>(
+    name: &str,
+) -> Option<Box<dyn MatchStrategy<T, W, H, S>>>
+where
+    T: PackedTile,
+{
+    match name {
+        "naive" => Some(Box::new(NaiveScan)),
+        "anchor" => Some(Box::new(AnchorScan)),
+        "packed" => Some(Box::new(PackedScan::default())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::placement::Edge;
+    use crate::tile::Tile;
+
+    fn sorted(mut matches: Vec<PatchOrientation>) -> Vec<(usize, (isize, isize))> {
+        let mut out = matches
+            .drain(..)
+            .map(|m| (m.index(), m.position))
+            .collect::<Vec<_>>();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn placement_restricts_origin() {
+        const R: Option<Tile> = Some(Tile::Red);
+        let patch = Grid {
+            items: [[R, None], [None, None]],
+        };
+        let mut grid: Grid<Tile, 4, 4> = Default::default();
+        grid.items[0][2] = Tile::Red;
+        grid.items[3][3] = Tile::Red;
+
+        let top = NaiveScan.find_matches(
+            &grid,
+            &patch,
+            &Placement::Edge(Edge::Top),
+            Symmetry::Rotations,
+            &Boundaries::CLAMP,
+        );
+        // every rotation matches the top Red, none the bottom one
+        assert_eq!(top.len(), 4);
+        assert!(top.iter().all(|m| placement::origin(m, 2) == (2, 0)));
+        let listed = NaiveScan.find_matches(
+            &grid,
+            &patch,
+            &Placement::Cells(vec![(3, 3)]),
+            Symmetry::Rotations,
+            &Boundaries::CLAMP,
+        );
+        assert_eq!(listed.len(), 4);
+        // mirroring the corner only moves it to another corner
+        let all = NaiveScan.find_matches(
+            &grid,
+            &patch,
+            &Placement::Edge(Edge::Top),
+            Symmetry::All,
+            &Boundaries::CLAMP,
+        );
+        assert_eq!(all.len(), 8);
+        assert!(all.iter().all(|m| placement::origin(m, 2) == (2, 0)));
+    }
+
+    #[test]
+    fn strategies_find_same_matches_as_naive() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const K: Option<Tile> = Some(Tile::Black);
+        const X: Option<Tile> = None;
+        let patches = [
+            Grid {
+                items: [[R, K, K], [X, X, X], [X, X, X]],
+            },
+            Grid {
+                items: [[X, X, X], [X, K, R], [X, X, R]],
+            },
+            Grid {
+                items: [[X, X, X], [X, X, X], [X, X, X]],
+            },
+        ];
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut grid: Grid<Tile, 8, 6> = Default::default();
+        for row in grid.items.iter_mut() {
+            for item in row.iter_mut() {
+                if rng.gen_bool(0.3) {
+                    *item = Tile::Red;
+                }
+            }
+        }
+
+        let placements = [
+            Placement::Anywhere,
+            Placement::Edge(Edge::Any),
+            Placement::EvenCoordinates,
+            Placement::Cells(vec![(0, 0), (3, 2), (7, 5)]),
+        ];
+        let boundaries = [
+            Boundaries::CLAMP,
+            Boundaries::new(Boundary::Wrap, Boundary::Clamp),
+            Boundaries::new(Boundary::Wrap, Boundary::Wrap),
+            Boundaries::new(Boundary::Clamp, Boundary::Mirror),
+        ];
+        for patch in patches.iter() {
+            for placement in placements.iter() {
+                for symmetry in Symmetry::ALL {
+                    for boundaries in boundaries.iter() {
+                        let naive =
+                            NaiveScan.find_matches(&grid, patch, placement, symmetry, boundaries);
+                        assert_eq!(
+                            sorted(
+                                AnchorScan
+                                    .find_matches(&grid, patch, placement, symmetry, boundaries)
+                            ),
+                            sorted(naive.clone())
+                        );
+                        // packed keeps the order too
+                        assert_eq!(
+                            PackedScan::default()
+                                .find_matches(&grid, patch, placement, symmetry, boundaries),
+                            naive
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_rows_wider_than_a_word() {
+        const R: Option<Tile> = Some(Tile::Red);
+        // the Red in the second word of each row has to line up as well as the first
+        let mut patch: Grid<Option<Tile>, 10, 10> = Grid {
+            items: [[None; 10]; 10],
+        };
+        patch.items[0][0] = R;
+        patch.items[0][9] = R;
+        patch.items[9][8] = R;
+        let mut grid: Grid<Tile, 16, 12> = Default::default();
+        for (x, y) in [
+            (2, 1),
+            (11, 1),
+            (10, 10),
+            (3, 1),
+            (12, 1),
+            (11, 10),
+            (11, 2),
+        ] {
+            grid.items[y][x] = Tile::Red;
+        }
+        let find = |boundaries: &Boundaries| {
+            let packed = PackedScan::default().find_matches(
+                &grid,
+                &patch,
+                &Placement::Anywhere,
+                Symmetry::All,
+                boundaries,
+            );
+            let naive = NaiveScan.find_matches(
+                &grid,
+                &patch,
+                &Placement::Anywhere,
+                Symmetry::All,
+                boundaries,
+            );
+            assert_eq!(packed, naive);
+            sorted(packed)
+        };
+        assert_eq!(find(&Boundaries::CLAMP), [(0, (2, 1)), (0, (3, 1))]);
+        // most placements on a torus cross an edge and are checked cell by cell
+        let torus = Boundaries::new(Boundary::Wrap, Boundary::Wrap);
+        assert!(find(&torus).starts_with(&[(0, (2, 1)), (0, (3, 1))]));
+    }
+
+    #[test]
+    fn packed_grid_follows_changes() {
+        const R: Option<Tile> = Some(Tile::Red);
+        let patch = Grid {
+            items: [[R, R], [None, None]],
+        };
+        let mut grid: Grid<Tile, 4, 2> = Default::default();
+        let mut packed = PackedScan::default();
+        let find = |grid: &Grid<Tile, 4, 2>, packed: &mut PackedScan| {
+            sorted(packed.find_matches(
+                grid,
+                &patch,
+                &Placement::Anywhere,
+                Symmetry::Identity,
+                &Boundaries::CLAMP,
+            ))
+        };
+        assert_eq!(find(&grid, &mut packed), []);
+
+        grid.items[1][1] = Tile::Red;
+        grid.items[1][2] = Tile::Red;
+        MatchStrategy::<_, 4, 2, 2>::cells_changed(&mut packed, &grid, &[(1, 1), (2, 1)]);
+        assert_eq!(find(&grid, &mut packed), [(0, (1, 1))]);
+
+        // a whole new grid is packed again
+        grid = Default::default();
+        grid.items[0][0] = Tile::Red;
+        grid.items[0][1] = Tile::Red;
+        MatchStrategy::<Tile, 4, 2, 2>::grid_changed(&mut packed);
+        assert_eq!(find(&grid, &mut packed), [(0, (0, 0))]);
+    }
+
+    #[test]
+    fn wrapped_matches_cross_the_edge() {
+        const R: Option<Tile> = Some(Tile::Red);
+        let patch = Grid {
+            items: [[R, R], [None, None]],
+        };
+        let mut grid: Grid<Tile, 4, 2> = Default::default();
+        grid.items[1][0] = Tile::Red;
+        grid.items[1][3] = Tile::Red;
+        let find = |boundaries: Boundaries| {
+            sorted(NaiveScan.find_matches(
+                &grid,
+                &patch,
+                &Placement::Anywhere,
+                Symmetry::Identity,
+                &boundaries,
+            ))
+        };
+
+        assert_eq!(find(Boundaries::CLAMP), []);
+        // positions on wrapped axes stay inside the grid
+        let cylinder = Boundaries::new(Boundary::Wrap, Boundary::Clamp);
+        assert_eq!(find(cylinder), [(0, (3, 1))]);
+        let torus = Boundaries::new(Boundary::Wrap, Boundary::Wrap);
+        assert_eq!(find(torus), [(0, (3, 1))]);
+    }
+}
@@ -0,0 +1,242 @@
+//! What it takes to reproduce a run, written as a JSON sidecar next to anything exported from it:
+//! the seed, the rules, a hash of the model, the number of steps and the crate version. The same
+//! seed, model and version give the same grid (see Simulation), so the sidecar is enough to get
+//! an output back later.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ascii;
+use crate::determinism;
+use crate::rewrite::{Grid, ReplacementRule};
+use crate::tile::AsciiSymbol;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub seed: u64,
+    /// determinism::fnv1a of the initial grid in the ascii format followed by the rules as
+    /// described in `rules`
+    pub model_hash: u64,
+    /// One line per rule, see describe_rule
+    pub rules: Vec<String>,
+    pub steps: usize,
+    pub version: &'static str,
+    /// Anything else the frontend knows about the run, eg. the command line
+    pub extra: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn new<T: AsciiSymbol, const W: usize, const H: usize, const S: usize>(
+        seed: u64,
+        initial: &Grid<T, W, H>,
+        rules: &[ReplacementRule<T, S>],
+        steps: usize,
+    ) -> Self {
+        let rules = rules.iter().map(describe_rule).collect::<Vec<_>>();
+        let mut model = Vec::new();
+        // writing to a Vec can't fail
+        let _ = ascii::write_grid(initial, &mut model);
+        for rule in &rules {
+            model.extend_from_slice(rule.as_bytes());
+            model.push(b'\n');
+        }
+        Self {
+            seed,
+            model_hash: determinism::fnv1a(&model),
+            rules,
+            steps,
+            version: env!("CARGO_PKG_VERSION"),
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: String) -> Self {
+        self.extra.push((key.to_string(), value));
+        self
+    }
+
+    /// The seed and hash are strings, since JSON numbers lose precision past 2^53
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"version\": {},", quote(self.version));
+        let _ = writeln!(json, "  \"seed\": \"{}\",", self.seed);
+        let _ = writeln!(json, "  \"model_hash\": \"{:016x}\",", self.model_hash);
+        let _ = writeln!(json, "  \"steps\": {},", self.steps);
+        for (key, value) in &self.extra {
+            let _ = writeln!(json, "  {}: {},", quote(key), quote(value));
+        }
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| format!("    {}", quote(rule)))
+            .collect::<Vec<_>>();
+        let _ = writeln!(json, "  \"rules\": [\n{}\n  ]", rules.join(",\n"));
+        json.push_str("}\n");
+        json
+    }
+
+    /// Write the sidecar of `export`, returning its path
+    pub fn write_sidecar(&self, export: &Path) -> io::Result<PathBuf> {
+        let path = sidecar_path(export);
+        fs::write(&path, self.to_json())?;
+        Ok(path)
+    }
+}
+
+/// `export` with ".json" added, eg. "bimp_123.png.json", so exports of different kinds from the
+/// same run don't share a sidecar path
+pub fn sidecar_path(export: &Path) -> PathBuf {
+    let mut path = export.as_os_str().to_owned();
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+/// One line describing everything about a rule which affects a run, except conditions, which
+/// are code and only counted: the patches (rows separated by '/', don't-cares as '.'), the
+/// symmetry, placement and boundaries, then any guards, effects, random cells, neighbour
+/// counts and field guards
+pub fn describe_rule<T: AsciiSymbol, const S: usize>(rule: &ReplacementRule<T, S>) -> String {
+    let patch = |patch: &Grid<Option<T>, S, S>| {
+        patch
+            .items
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.as_ref().map_or('.', AsciiSymbol::to_char))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+    let mut line = format!(
+        "{} -> {} symmetry {}",
+        patch(&rule.find),
+        patch(&rule.replace),
+        rule.symmetry.name()
+    );
+    let _ = write!(line, " placement {:?}", rule.placement);
+    let _ = write!(
+        line,
+        " boundaries {:?},{:?}",
+        rule.boundaries.x, rule.boundaries.y
+    );
+    for guard in &rule.guards {
+        let _ = write!(line, " guard {:?}", guard);
+    }
+    for effect in &rule.effects {
+        let _ = write!(line, " effect {:?}", effect);
+    }
+    for cell in &rule.random_cells {
+        let choices = cell
+            .choices
+            .iter()
+            .map(|(tile, weight)| format!("{}:{}", tile.to_char(), weight))
+            .collect::<Vec<_>>();
+        let _ = write!(line, " random {:?} {}", cell.position, choices.join(","));
+    }
+    for count in &rule.neighbour_counts {
+        let _ = write!(
+            line,
+            " count {:?} {} {:?} {:?} {}",
+            count.position,
+            count.tile.to_char(),
+            count.neighbourhood,
+            count.comparison,
+            count.count
+        );
+    }
+    for guard in &rule.field_guards {
+        let _ = write!(
+            line,
+            " field {} {:?} {:?} {}",
+            quote(&guard.field),
+            guard.position,
+            guard.comparison,
+            guard.value
+        );
+    }
+    if !rule.conditions.is_empty() {
+        let _ = write!(line, " conditions {}", rule.conditions.len());
+    }
+    if !rule.active {
+        line.push_str(" inactive");
+    }
+    line
+}
+
+/// `s` as a JSON string
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models;
+    use crate::symmetry::Symmetry;
+    use crate::tile::Tile;
+
+    const R: Option<Tile> = Some(Tile::Red);
+    const X: Option<Tile> = None;
+
+    #[test]
+    fn rule_lines() {
+        let rule = ReplacementRule::new(
+            Grid {
+                items: [[R, X], [X, X]],
+            },
+            Grid {
+                items: [[X, R], [X, X]],
+            },
+        )
+        .with_symmetry(Symmetry::Identity);
+        assert_eq!(
+            describe_rule(&rule),
+            "R./.. -> .R/.. symmetry () placement Anywhere boundaries Clamp,Clamp"
+        );
+    }
+
+    #[test]
+    fn json_sidecar() {
+        let metadata = Metadata::new(u64::MAX, &models::initial_grid(), &models::rules(), 42)
+            .with("args", "--seed \"x\"".to_string());
+        let json = metadata.to_json();
+        assert!(json.starts_with("{\n  \"version\": \""), "{}", json);
+        assert!(json.contains("\"seed\": \"18446744073709551615\",\n"));
+        assert!(json.contains("\"steps\": 42,\n"));
+        assert!(json.contains("\"args\": \"--seed \\\"x\\\"\",\n"));
+        assert!(json.ends_with("\n  ]\n}\n"));
+        assert_eq!(metadata.rules.len(), models::rules().len());
+
+        // the hash changes with the model, not the seed or steps
+        let same = Metadata::new(1, &models::initial_grid(), &models::rules(), 0);
+        assert_eq!(same.model_hash, metadata.model_hash);
+        let mut rules = models::rules();
+        rules.pop();
+        let other = Metadata::new(1, &models::initial_grid(), &rules, 0);
+        assert_ne!(other.model_hash, metadata.model_hash);
+
+        assert_eq!(
+            sidecar_path(Path::new("out/bimp_1.png")),
+            PathBuf::from("out/bimp_1.png.json")
+        );
+    }
+}
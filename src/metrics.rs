@@ -0,0 +1,104 @@
+//! Numbers describing a grid, eg. for ranking the results of many runs
+
+use crate::rewrite::Grid;
+
+/// Number of cells holding `tile`
+pub fn count<T: PartialEq, const W: usize, const H: usize>(
+    grid: &Grid<T, W, H>,
+    tile: &T,
+) -> usize {
+    grid.items
+        .iter()
+        .flatten()
+        .filter(|item| *item == tile)
+        .count()
+}
+
+/// Number of 4-connected regions of `tile`
+pub fn components<T: PartialEq, const W: usize, const H: usize>(
+    grid: &Grid<T, W, H>,
+    tile: &T,
+) -> usize {
+    let mut seen = [[false; W]; H];
+    let mut stack = Vec::new();
+    let mut regions = 0;
+    for y in 0..H {
+        for x in 0..W {
+            if seen[y][x] || grid.items[y][x] != *tile {
+                continue;
+            }
+            regions += 1;
+            seen[y][x] = true;
+            stack.push((x, y));
+            while let Some((x, y)) = stack.pop() {
+                let neighbours = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                for (nx, ny) in neighbours {
+                    // wrapping_sub turns -1 into usize::MAX, which is out of bounds too
+                    if nx < W && ny < H && !seen[ny][nx] && grid.items[ny][nx] == *tile {
+                        seen[ny][nx] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+    regions
+}
+
+/// Number of pairs of 4-neighbours where one cell holds `a` and the other `b`
+pub fn adjacent<T: PartialEq, const W: usize, const H: usize>(
+    grid: &Grid<T, W, H>,
+    a: &T,
+    b: &T,
+) -> usize {
+    let is_pair = |p: &T, q: &T| (p == a && q == b) || (p == b && q == a);
+    let horizontal = grid
+        .items
+        .iter()
+        .flat_map(|row| row.windows(2))
+        .filter(|pair| is_pair(&pair[0], &pair[1]))
+        .count();
+    let vertical = grid
+        .items
+        .windows(2)
+        .flat_map(|rows| rows[0].iter().zip(rows[1].iter()))
+        .filter(|(p, q)| is_pair(p, q))
+        .count();
+    horizontal + vertical
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile::{self, Black as B, Red as R};
+
+    fn grid() -> Grid<Tile, 4, 3> {
+        Grid {
+            items: [[R, R, B, R], [B, B, B, R], [R, B, R, B]],
+        }
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(count(&grid(), &R), 6);
+        assert_eq!(count(&grid(), &Tile::Blue), 0);
+    }
+
+    #[test]
+    fn diagonals_are_separate_components() {
+        assert_eq!(components(&grid(), &R), 4);
+        assert_eq!(components(&grid(), &B), 2);
+    }
+
+    #[test]
+    fn adjacent_pairs() {
+        assert_eq!(adjacent(&grid(), &R, &B), 11);
+        assert_eq!(adjacent(&grid(), &B, &R), 11);
+        assert_eq!(adjacent(&grid(), &R, &R), 2);
+    }
+}
@@ -0,0 +1,985 @@
+//! Models as text, so they can be written by other tools and loaded with --model instead of the
+//! built in one. One directive per line, blank lines and lines starting with '#' are ignored:
+//!
+//! ```text
+//! # the grid starts filled with Black, with a Red cell in the middle
+//! fill B
+//! origin R
+//! # drawn over the fill in order, positions are x,y and may be partly outside the grid
+//! line 0,0 63,0 W
+//! rect 4,4 8,2 G
+//! circle 48,48 5 O
+//! scatter B 0.01
+//! stamp 10,40 RRR/R.R/RRR
+//! mix G:3 Y:1 noise 8 only B
+//! # rules are tried in order, patches are written like the ascii grids with rows separated by
+//! # '/' and '.' for don't-care
+//! rule RBB -> WWR symmetry (xy)
+//! # options follow the patches in pairs, this rule only runs while there are at most 100 White
+//! # cells and wraps around the left and right edges
+//! rule RB -> RR boundary wrap,clamp count W:..100
+//! ```
+//!
+//! A model can also generate structure then detail, see Refine. Everything before `refine`
+//! makes a coarse model which runs for at most `<max steps>` on a grid `<scale>` times smaller
+//! than the output, then each coarse cell becomes a `<scale>` x `<scale>` block of the output.
+//! `block` gives the block of a tile, tiles without one fill their block. Rules after `refine`
+//! carry on from there at full resolution:
+//!
+//! ```text
+//! fill B
+//! origin W
+//! rule WB -> WW
+//! refine 4 1000
+//! block W WWWW/WRRW/WRRW/WWWW
+//! rule RB -> RR
+//! ```
+//!
+//! Drawing directives are, see stamp:
+//!
+//! - `line <x0>,<y0> <x1>,<y1> <tile>`, both ends included
+//! - `rect <x>,<y> <width>,<height> <tile>` with its top left corner at x,y
+//! - `circle <x>,<y> <radius> <tile>`
+//! - `scatter <tile> <density>` sets each cell with probability `density`
+//! - `stamp <x>,<y> <pattern>` writes the pattern's cells which aren't '.', at most 16x16
+//! - `mix <tile>:<weight>...` sets every cell to a tile drawn from the weights, see Scatter.
+//!   The weights can be followed by `noise <scale>` for blobs about `scale` cells across
+//!   instead of independent cells, `only <tile>` to only set cells holding `tile`, and
+//!   `region <x>,<y>,<width>,<height>` to only set cells in the rectangle.
+//!
+//! Patches smaller than the model's rules are padded with don't-cares on the right and bottom.
+//! Rule options are:
+//!
+//! - `symmetry <name>` in MarkovJunior notation, see Symmetry, "(xy+)" by default
+//! - `boundary <x>,<y>` or `boundary <both>`, see Boundaries, "clamp" by default
+//! - `count <tile>:<min>..<max>` holds while the number of `tile` cells is in the range, either
+//!   end can be left out, see TileCount
+//! - `period <period>:<phase>` holds on steps where steps % period == phase, see StepPeriod
+//! - `contains <pattern>` and `lacks <pattern>` hold while the pattern matches somewhere, or
+//!   nowhere, see Contains
+//!
+//! Conditions can be repeated and must all hold. Guards, effects, placement, random cells,
+//! neighbour counts, field guards, cell layers, inactive rules and other conditions can't be
+//! written in a model file, and write_model refuses rules which have them.
+
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use rand::SeedableRng;
+
+use crate::boundary::Boundaries;
+use crate::condition::{Contains, StepPeriod, TileCount};
+use crate::determinism::SimRng;
+use crate::error::{BimpError, Result};
+use crate::layers::CellLayers;
+use crate::node::Node;
+use crate::placement::Placement;
+use crate::refine::Refine;
+use crate::rewrite::{Grid, ReplacementRule};
+use crate::scatter::{Sampling, Scatter};
+use crate::tile::AsciiSymbol;
+
+/// A model read from or written to a model file, with rules of S x S patches
+pub struct ModelFile<T, const S: usize> {
+    /// Every cell of the initial grid
+    pub fill: T,
+    /// Placed in the middle of the initial grid, if set
+    pub origin: Option<T>,
+    /// Drawn over the fill and origin in order
+    pub init: Vec<Draw<T>>,
+    /// Rules run on the output grid
+    pub rules: Vec<ReplacementRule<T, S>>,
+    /// With refine, the initial grid and the coarse rules are the coarse model's
+    pub refine: Option<Refinement<T, S>>,
+}
+
+/// The refine directive and the blocks after it
+pub struct Refinement<T, const S: usize> {
+    /// Width and height of the block each coarse cell becomes
+    pub scale: usize,
+    /// The coarse model stops after this many steps if it hasn't converged
+    pub max_steps: usize,
+    pub rules: Vec<ReplacementRule<T, S>>,
+    /// (tile, rows of its block)
+    pub blocks: Vec<(T, Vec<Vec<T>>)>,
+}
+
+/// Largest stamp patch, in both directions
+pub const STAMP_SIZE: usize = 16;
+
+/// A drawing directive, see stamp for what each one draws
+#[derive(Debug, Clone, PartialEq)]
+pub enum Draw<T> {
+    Line {
+        from: (isize, isize),
+        to: (isize, isize),
+        tile: T,
+    },
+    Rect {
+        at: (isize, isize),
+        size: (usize, usize),
+        tile: T,
+    },
+    Circle {
+        center: (isize, isize),
+        radius: usize,
+        tile: T,
+    },
+    Scatter {
+        tile: T,
+        density: f64,
+    },
+    Stamp {
+        at: (isize, isize),
+        patch: Grid<Option<T>, STAMP_SIZE, STAMP_SIZE>,
+    },
+    Mix(Scatter<T>),
+}
+
+impl<T: Copy + PartialEq, const S: usize> ModelFile<T, S> {
+    /// The grid before any rules run. `seed` is only used by directives which draw randomly, so
+    /// the same seed always gives the same grid.
+    pub fn initial_grid<const W: usize, const H: usize>(&self, seed: u64) -> Grid<T, W, H> {
+        let mut grid = Grid {
+            items: [[self.fill; W]; H],
+        };
+        if let Some(origin) = self.origin {
+            grid.items[H / 2][W / 2] = origin;
+        }
+        let mut rng = SimRng::seed_from_u64(seed);
+        for draw in self.init.iter() {
+            match *draw {
+                Draw::Line { from, to, tile } => grid.draw_line(from, to, tile),
+                Draw::Rect { at, size, tile } => grid.draw_rect(at, size, tile),
+                Draw::Circle {
+                    center,
+                    radius,
+                    tile,
+                } => grid.draw_circle(center, radius, tile),
+                Draw::Scatter { tile, density } => grid.scatter(tile, density, &mut rng),
+                Draw::Stamp { at, ref patch } => grid.stamp(patch, at),
+                Draw::Mix(ref scatter) => {
+                    scatter.clone().apply(&mut grid, &mut rng);
+                }
+            }
+        }
+        grid
+    }
+}
+
+impl<T: Eq + Copy + Default + 'static, const S: usize> ModelFile<T, S> {
+    /// The grid the rules start from: the initial grid, or with refine the result of the coarse
+    /// model, a `CW` x `CH` grid whose cells become `N` x `N` blocks. Panics if refine's scale
+    /// isn't N or the grids don't fit, so callers pick the sizes from `refine`.
+    pub fn refined_grid<
+        const W: usize,
+        const H: usize,
+        const CW: usize,
+        const CH: usize,
+        const N: usize,
+    >(
+        &mut self,
+        seed: u64,
+    ) -> Grid<T, W, H> {
+        let Some(refinement) = self.refine.as_mut() else {
+            return self.initial_grid(seed);
+        };
+        assert_eq!(refinement.scale, N, "blocks are {}x{}", N, N);
+        let rules = std::mem::take(&mut refinement.rules);
+        let max_steps = refinement.max_steps;
+        let mut refine = Refine::<T, CW, CH, S, N>::new(self.initial_grid(seed), rules, max_steps);
+        for (tile, rows) in self.refine.as_ref().expect("checked above").blocks.iter() {
+            let block = Grid {
+                items: std::array::from_fn(|y| std::array::from_fn(|x| rows[y][x])),
+            };
+            refine = refine.with_block(*tile, block);
+        }
+        let mut grid = Grid::default();
+        refine.apply(&mut grid, &mut SimRng::seed_from_u64(seed));
+        grid
+    }
+}
+
+/// Read a model file. Every directive is checked, so a model which loads runs as written.
+pub fn read_model<T, I, const S: usize>(input: &mut I) -> Result<ModelFile<T, S>>
+where
+    T: AsciiSymbol + Default + Eq + Copy + 'static,
+    I: BufRead,
+{
+    let mut model = ModelFile {
+        fill: T::default(),
+        origin: None,
+        init: Vec::new(),
+        rules: Vec::new(),
+        refine: None,
+    };
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let line_number = index + 1;
+        let parse_error = |message: String| BimpError::Parse {
+            line: line_number,
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let initial = [
+            "fill", "origin", "line", "rect", "circle", "scatter", "stamp", "mix",
+        ];
+        if model.refine.is_some() && (initial.contains(&directive) || directive == "refine") {
+            return Err(parse_error(format!(
+                "'{}' has to come before refine, the initial grid is the coarse model's",
+                directive
+            )));
+        }
+        match directive {
+            "fill" => model.fill = read_tile(rest.trim(), line_number)?,
+            "origin" => model.origin = Some(read_tile(rest.trim(), line_number)?),
+            "line" | "rect" | "circle" | "scatter" | "stamp" | "mix" => {
+                model.init.push(read_draw(directive, rest, line_number)?)
+            }
+            "rule" => model.rules.push(read_rule(rest, line_number)?),
+            "refine" => {
+                let words = rest.split_whitespace().collect::<Vec<_>>();
+                let [scale, max_steps] = words.as_slice() else {
+                    return Err(parse_error(
+                        "expected 'refine <scale> <max steps>'".to_string(),
+                    ));
+                };
+                let scale = read_number(scale, line_number)?;
+                if !(1..=STAMP_SIZE).contains(&scale) {
+                    return Err(parse_error(format!(
+                        "refine's scale has to be 1 to {}",
+                        STAMP_SIZE
+                    )));
+                }
+                model.refine = Some(Refinement {
+                    scale,
+                    max_steps: read_number(max_steps, line_number)?,
+                    rules: std::mem::take(&mut model.rules),
+                    blocks: Vec::new(),
+                });
+            }
+            "block" => {
+                let Some(refinement) = model.refine.as_mut() else {
+                    return Err(parse_error("'block' has to come after refine".to_string()));
+                };
+                let (tile, block) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+                let tile = read_tile(tile, line_number)?;
+                let (patch, size) = read_patch::<T, STAMP_SIZE>(block.trim(), line_number)?;
+                let scale = refinement.scale;
+                let rows = patch.items[..size.1]
+                    .iter()
+                    .map(|row| row[..size.0].iter().copied().collect::<Option<Vec<_>>>())
+                    .collect::<Option<Vec<_>>>();
+                match rows {
+                    Some(rows) if size == (scale, scale) => refinement.blocks.push((tile, rows)),
+                    _ => {
+                        return Err(parse_error(format!(
+                            "expected 'block <tile> <pattern>' with a {}x{} pattern and no '.'",
+                            scale, scale
+                        )))
+                    }
+                }
+            }
+            _ => return Err(parse_error(format!("unknown directive '{}'", directive))),
+        }
+    }
+    Ok(model)
+}
+
+fn read_tile<T: AsciiSymbol>(text: &str, line: usize) -> Result<T> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => T::from_char(c).ok_or_else(|| BimpError::BadSymbol {
+            symbol: c.to_string(),
+            line,
+            column: 1,
+        }),
+        _ => Err(BimpError::Parse {
+            line,
+            message: format!("expected one tile, got '{}'", text),
+        }),
+    }
+}
+
+/// A drawing directive's arguments, see the module docs
+fn read_draw<T>(directive: &str, text: &str, line: usize) -> Result<Draw<T>>
+where
+    T: AsciiSymbol + Copy,
+{
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    if directive == "mix" {
+        return read_mix(&words, line).map(Draw::Mix);
+    }
+    let draw = match (directive, words.as_slice()) {
+        ("line", [from, to, tile]) => Draw::Line {
+            from: read_pair(from, line)?,
+            to: read_pair(to, line)?,
+            tile: read_tile(tile, line)?,
+        },
+        ("rect", [at, size, tile]) => Draw::Rect {
+            at: read_pair(at, line)?,
+            size: read_pair(size, line)?,
+            tile: read_tile(tile, line)?,
+        },
+        ("circle", [center, radius, tile]) => Draw::Circle {
+            center: read_pair(center, line)?,
+            radius: read_number(radius, line)?,
+            tile: read_tile(tile, line)?,
+        },
+        ("scatter", [tile, density]) => Draw::Scatter {
+            tile: read_tile(tile, line)?,
+            density: read_number(density, line)?,
+        },
+        ("stamp", [at, patch]) => Draw::Stamp {
+            at: read_pair(at, line)?,
+            patch: read_patch(patch, line)?.0,
+        },
+        _ => {
+            let expected = match directive {
+                "line" => "<x0>,<y0> <x1>,<y1> <tile>",
+                "rect" => "<x>,<y> <width>,<height> <tile>",
+                "circle" => "<x>,<y> <radius> <tile>",
+                "scatter" => "<tile> <density>",
+                _ => "<x>,<y> <pattern>",
+            };
+            return Err(BimpError::Parse {
+                line,
+                message: format!("expected '{} {}'", directive, expected),
+            });
+        }
+    };
+    Ok(draw)
+}
+
+/// "<tile>:<weight>..." followed by option pairs, see the module docs
+fn read_mix<T: AsciiSymbol + Copy>(words: &[&str], line: usize) -> Result<Scatter<T>> {
+    let parse_error = |message: String| BimpError::Parse { line, message };
+    let weights = words.iter().take_while(|word| word.contains(':')).count();
+    if weights == 0 || (words.len() - weights) % 2 == 1 {
+        return Err(parse_error(
+            "expected 'mix <tile>:<weight>...', optionally followed by option pairs like \
+             'noise <scale>'"
+                .to_string(),
+        ));
+    }
+    let choices = words[..weights]
+        .iter()
+        .map(|word| {
+            let (tile, weight) = word.split_once(':').expect("checked above");
+            Ok((read_tile(tile, line)?, read_number(weight, line)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut scatter = Scatter::new(choices);
+    for option in words[weights..].chunks(2) {
+        let value = option[1];
+        match option[0] {
+            "noise" => scatter = scatter.with_noise(read_number(value, line)?),
+            "only" => scatter = scatter.only_over(read_tile(value, line)?),
+            "region" => {
+                let numbers = value
+                    .split(',')
+                    .map(|number| read_number(number, line))
+                    .collect::<Result<Vec<usize>>>()?;
+                let &[x, y, width, height] = numbers.as_slice() else {
+                    return Err(parse_error(format!(
+                        "expected 'region <x>,<y>,<width>,<height>', got '{}'",
+                        value
+                    )));
+                };
+                scatter = scatter.with_region(x, y, width, height);
+            }
+            other => return Err(parse_error(format!("unknown mix option '{}'", other))),
+        }
+    }
+    Ok(scatter)
+}
+
+fn read_number<N: FromStr>(text: &str, line: usize) -> Result<N> {
+    text.parse().map_err(|_| BimpError::Parse {
+        line,
+        message: format!("expected a number, got '{}'", text),
+    })
+}
+
+/// "<a>,<b>"
+fn read_pair<N: FromStr>(text: &str, line: usize) -> Result<(N, N)> {
+    let (a, b) = text.split_once(',').ok_or_else(|| BimpError::Parse {
+        line,
+        message: format!("expected '<a>,<b>', got '{}'", text),
+    })?;
+    Ok((read_number(a, line)?, read_number(b, line)?))
+}
+
+/// "<find> -> <replace>" followed by option pairs, see the module docs
+fn read_rule<T, const S: usize>(text: &str, line: usize) -> Result<ReplacementRule<T, S>>
+where
+    T: AsciiSymbol + Eq + Copy + 'static,
+{
+    let parse_error = |message: String| BimpError::Parse { line, message };
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    if words.len() < 3 || words[1] != "->" || words.len() % 2 == 0 {
+        return Err(parse_error(
+            "expected '<find> -> <replace>', optionally followed by option pairs like \
+             'symmetry <name>'"
+                .to_string(),
+        ));
+    }
+    let (find, find_size) = read_patch(words[0], line)?;
+    let (replace, replace_size) = read_patch(words[2], line)?;
+    if find_size != replace_size {
+        return Err(parse_error(format!(
+            "find patch is {}x{} but replace patch is {}x{}",
+            find_size.0, find_size.1, replace_size.0, replace_size.1
+        )));
+    }
+    let mut rule = ReplacementRule::new(find, replace);
+    for option in words[3..].chunks(2) {
+        let value = option[1];
+        match option[0] {
+            "symmetry" => rule.symmetry = value.parse().map_err(parse_error)?,
+            "boundary" => rule.boundaries = value.parse().map_err(parse_error)?,
+            "count" => rule = rule.with_condition(read_count(value, line)?),
+            "period" => {
+                let period = value
+                    .split_once(':')
+                    .and_then(|(period, phase)| Some((period.parse().ok()?, phase.parse().ok()?)));
+                let Some((period, phase)) = period else {
+                    return Err(parse_error(format!(
+                        "expected 'period <period>:<phase>', got '{}'",
+                        value
+                    )));
+                };
+                rule = rule.with_condition(StepPeriod { period, phase });
+            }
+            "contains" | "lacks" => {
+                rule = rule.with_condition(Contains {
+                    pattern: read_patch::<T, S>(value, line)?.0,
+                    present: option[0] == "contains",
+                })
+            }
+            other => return Err(parse_error(format!("unknown rule option '{}'", other))),
+        }
+    }
+    Ok(rule)
+}
+
+/// "<tile>:<min>..<max>", either end can be left out
+fn read_count<T: AsciiSymbol>(text: &str, line: usize) -> Result<TileCount<T>> {
+    let parse_error = || BimpError::Parse {
+        line,
+        message: format!("expected 'count <tile>:<min>..<max>', got '{}'", text),
+    };
+    let (tile, range) = text.split_once(':').ok_or_else(parse_error)?;
+    let (min, max) = range.split_once("..").ok_or_else(parse_error)?;
+    let bound = |text: &str, default| match text {
+        "" => Ok(default),
+        text => text.parse().map_err(|_| parse_error()),
+    };
+    Ok(TileCount {
+        tile: read_tile(tile, line)?,
+        min: bound(min, 0)?,
+        max: bound(max, usize::MAX)?,
+    })
+}
+
+/// A patch and its (width, height) before padding
+type SizedPatch<T, const S: usize> = (Grid<Option<T>, S, S>, (usize, usize));
+
+fn read_patch<T, const S: usize>(text: &str, line: usize) -> Result<SizedPatch<T, S>>
+where
+    T: AsciiSymbol + Copy,
+{
+    let rows = text.split('/').collect::<Vec<_>>();
+    let width = rows[0].chars().count();
+    if rows.iter().any(|row| row.chars().count() != width) {
+        return Err(BimpError::Parse {
+            line,
+            message: format!("rows of patch '{}' have different lengths", text),
+        });
+    }
+    if width > S || rows.len() > S {
+        return Err(BimpError::Parse {
+            line,
+            message: format!("patch '{}' is bigger than {}x{}", text, S, S),
+        });
+    }
+    let mut patch = Grid {
+        items: [[None; S]; S],
+    };
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if c != '.' {
+                patch.items[y][x] = Some(T::from_char(c).ok_or_else(|| BimpError::BadSymbol {
+                    symbol: c.to_string(),
+                    line,
+                    column: 1,
+                })?);
+            }
+        }
+    }
+    Ok((patch, (width, rows.len())))
+}
+
+/// Write a model in the format read_model reads, with patches trimmed to the smallest size
+/// both fit in. Fails with BimpError::Unsupported if a rule has anything a model file can't
+/// hold, see the module docs.
+pub fn write_model<T, O, const S: usize>(model: &ModelFile<T, S>, out: &mut O) -> Result<()>
+where
+    T: AsciiSymbol + 'static,
+    O: Write,
+{
+    // check every rule first so nothing is written for a model which can't be
+    let write_rules = |rules: &[ReplacementRule<T, S>], which: &str| {
+        rules
+            .iter()
+            .enumerate()
+            .map(|(rule_id, rule)| write_rule(rule, &format!("{}rule {}", which, rule_id)))
+            .collect::<Result<Vec<_>>>()
+    };
+    let coarse_rules = match &model.refine {
+        Some(refinement) => write_rules(&refinement.rules, "coarse ")?,
+        None => Vec::new(),
+    };
+    let rules = write_rules(&model.rules, "")?;
+    writeln!(out, "fill {}", model.fill.to_char())?;
+    if let Some(origin) = &model.origin {
+        writeln!(out, "origin {}", origin.to_char())?;
+    }
+    for draw in model.init.iter() {
+        writeln!(out, "{}", write_draw(draw))?;
+    }
+    if let Some(refinement) = &model.refine {
+        for rule in coarse_rules {
+            writeln!(out, "rule {}", rule)?;
+        }
+        writeln!(out, "refine {} {}", refinement.scale, refinement.max_steps)?;
+        for (tile, rows) in refinement.blocks.iter() {
+            let rows = rows
+                .iter()
+                .map(|row| row.iter().map(AsciiSymbol::to_char).collect::<String>())
+                .collect::<Vec<_>>();
+            writeln!(out, "block {} {}", tile.to_char(), rows.join("/"))?;
+        }
+    }
+    for rule in rules {
+        writeln!(out, "rule {}", rule)?;
+    }
+    Ok(())
+}
+
+fn write_draw<T: AsciiSymbol>(draw: &Draw<T>) -> String {
+    match draw {
+        Draw::Line { from, to, tile } => format!(
+            "line {},{} {},{} {}",
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            tile.to_char()
+        ),
+        Draw::Rect { at, size, tile } => format!(
+            "rect {},{} {},{} {}",
+            at.0,
+            at.1,
+            size.0,
+            size.1,
+            tile.to_char()
+        ),
+        Draw::Circle {
+            center,
+            radius,
+            tile,
+        } => format!(
+            "circle {},{} {} {}",
+            center.0,
+            center.1,
+            radius,
+            tile.to_char()
+        ),
+        Draw::Scatter { tile, density } => format!("scatter {} {}", tile.to_char(), density),
+        Draw::Mix(scatter) => {
+            let mut text = "mix".to_string();
+            for (tile, weight) in scatter.choices.iter() {
+                text += &format!(" {}:{}", tile.to_char(), weight);
+            }
+            if let Sampling::ValueNoise { scale } = scatter.sampling {
+                text += &format!(" noise {}", scale);
+            }
+            if let Some(only) = &scatter.only {
+                text += &format!(" only {}", only.to_char());
+            }
+            if let Some((x, y, width, height)) = scatter.region {
+                text += &format!(" region {},{},{},{}", x, y, width, height);
+            }
+            text
+        }
+        Draw::Stamp { at, patch } => {
+            let (width, height) = used_size(patch);
+            format!(
+                "stamp {},{} {}",
+                at.0,
+                at.1,
+                write_patch(patch, width, height)
+            )
+        }
+    }
+}
+
+/// The rule directive's text, after "rule "
+fn write_rule<T, const S: usize>(rule: &ReplacementRule<T, S>, which: &str) -> Result<String>
+where
+    T: AsciiSymbol + 'static,
+{
+    let unsupported = |what: &str| {
+        Err(BimpError::Unsupported(format!(
+            "{} in {}, model files can't hold them",
+            what, which
+        )))
+    };
+    let default_layers = rule
+        .layers
+        .items
+        .iter()
+        .flatten()
+        .all(|&layers| layers == CellLayers::GRID);
+    for (has, what) in [
+        (!rule.guards.is_empty(), "guards"),
+        (!rule.effects.is_empty(), "effects"),
+        (rule.placement != Placement::Anywhere, "placements"),
+        (!rule.random_cells.is_empty(), "random cells"),
+        (!rule.neighbour_counts.is_empty(), "neighbour counts"),
+        (!rule.field_guards.is_empty(), "field guards"),
+        (!default_layers, "cell layers"),
+        (!rule.active, "inactive rules"),
+    ] {
+        if has {
+            return unsupported(what);
+        }
+    }
+
+    let (find, replace) = (used_size(&rule.find), used_size(&rule.replace));
+    let (width, height) = (find.0.max(replace.0), find.1.max(replace.1));
+    let mut text = format!(
+        "{} -> {} symmetry {}",
+        write_patch(&rule.find, width, height),
+        write_patch(&rule.replace, width, height),
+        rule.symmetry.name()
+    );
+    if rule.boundaries != Boundaries::CLAMP {
+        text += &format!(
+            " boundary {},{}",
+            rule.boundaries.x.name(),
+            rule.boundaries.y.name()
+        );
+    }
+    for condition in rule.conditions.iter() {
+        let any = condition.as_any();
+        if let Some(count) = any.and_then(|any| any.downcast_ref::<TileCount<T>>()) {
+            let bound = |bound: usize, default| {
+                if bound == default {
+                    String::new()
+                } else {
+                    bound.to_string()
+                }
+            };
+            text += &format!(
+                " count {}:{}..{}",
+                count.tile.to_char(),
+                bound(count.min, 0),
+                bound(count.max, usize::MAX)
+            );
+        } else if let Some(period) = any.and_then(|any| any.downcast_ref::<StepPeriod>()) {
+            text += &format!(" period {}:{}", period.period, period.phase);
+        } else if let Some(contains) = any.and_then(|any| any.downcast_ref::<Contains<T, S>>()) {
+            let (width, height) = used_size(&contains.pattern);
+            text += &format!(
+                " {} {}",
+                if contains.present {
+                    "contains"
+                } else {
+                    "lacks"
+                },
+                write_patch(&contains.pattern, width, height)
+            );
+        } else {
+            return unsupported("conditions other than count, period, contains and lacks");
+        }
+    }
+    Ok(text)
+}
+
+/// (width, height) of the smallest top left part of the patch holding all of its cells which
+/// aren't don't-cares, at least 1x1
+fn used_size<T, const S: usize>(patch: &Grid<Option<T>, S, S>) -> (usize, usize) {
+    let mut size = (1, 1);
+    for (y, row) in patch.items.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if cell.is_some() {
+                size = (size.0.max(x + 1), size.1.max(y + 1));
+            }
+        }
+    }
+    size
+}
+
+fn write_patch<T: AsciiSymbol, const S: usize>(
+    patch: &Grid<Option<T>, S, S>,
+    width: usize,
+    height: usize,
+) -> String {
+    patch.items[..height]
+        .iter()
+        .map(|row| {
+            row[..width]
+                .iter()
+                .map(|cell| cell.as_ref().map_or('.', AsciiSymbol::to_char))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::boundary::Boundary;
+    use crate::condition::Context;
+    use crate::counters::Counters;
+    use crate::symmetry::Symmetry;
+    use crate::tile::Tile;
+
+    const MODEL: &str = "\
+# grows a red line
+fill B
+origin R
+
+rule RB -> RR symmetry (x)
+rule R/B -> ./W
+";
+
+    #[test]
+    fn read() {
+        let model: ModelFile<Tile, 3> = read_model(&mut MODEL.as_bytes()).unwrap();
+        assert_eq!(model.fill, Tile::Black);
+        assert_eq!(model.rules.len(), 2);
+        let grid: Grid<Tile, 5, 4> = model.initial_grid(0);
+        assert_eq!(grid.items[2][2], Tile::Red);
+        assert_eq!(grid.items[0][0], Tile::Black);
+
+        let grow = &model.rules[0];
+        assert_eq!(grow.symmetry, Symmetry::ReflectX);
+        assert_eq!(
+            grow.find.items[0],
+            [Some(Tile::Red), Some(Tile::Black), None]
+        );
+        assert_eq!(grow.find.items[1], [None; 3]);
+        let down = &model.rules[1];
+        assert_eq!(down.symmetry, Symmetry::default());
+        assert_eq!(down.replace.items[0][0], None);
+        assert_eq!(down.replace.items[1][0], Some(Tile::White));
+    }
+
+    #[test]
+    fn round_trip() {
+        let model: ModelFile<Tile, 3> = read_model(&mut MODEL.as_bytes()).unwrap();
+        let mut text = Vec::new();
+        write_model(&model, &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text.clone()).unwrap(),
+            "fill B\norigin R\nrule RB -> RR symmetry (x)\nrule R/B -> ./W symmetry (xy+)\n"
+        );
+        let again: ModelFile<Tile, 3> = read_model(&mut text.as_slice()).unwrap();
+        assert_eq!(again.rules[1].find.items, model.rules[1].find.items);
+    }
+
+    #[test]
+    fn errors() {
+        let read = |text: &str| read_model::<Tile, _, 3>(&mut text.as_bytes());
+        assert!(matches!(
+            read("fill B\nlayer 2\n"),
+            Err(BimpError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(read("fill Z"), Err(BimpError::BadSymbol { .. })));
+        assert!(read("rule RBBB -> RRRR").is_err());
+        assert!(read("rule RB -> R").is_err());
+        assert!(read("rule RB/B -> RR/RR").is_err());
+        assert!(read("rule RB RR").is_err());
+        assert!(read("rule RB -> RR symmetry (z)").is_err());
+        assert!(read("rule RB -> RR symmetry").is_err());
+        assert!(read("rule RB -> RR size 2").is_err());
+        assert!(read("rule RB -> RR boundary torus").is_err());
+        assert!(read("rule RB -> RR count R:3").is_err());
+        assert!(read("rule RB -> RR count R:x..").is_err());
+        assert!(read("rule RB -> RR period 2").is_err());
+        assert!(read("rule RB -> RR contains RBBB").is_err());
+    }
+
+    #[test]
+    fn drawing() {
+        let text = "\
+fill W
+line 0,0 7,0 R
+rect -1,6 3,10 G
+circle 5,5 1 O
+stamp 0,2 B.B/BBB
+scatter K 0.5
+mix Y:1 noise 2.5 only W region 0,1,8,1
+";
+        let model: ModelFile<Tile, 3> = read_model(&mut text.as_bytes()).unwrap();
+        assert_eq!(model.init.len(), 6);
+        let rows = |grid: &Grid<Tile, 8, 8>| {
+            grid.items
+                .iter()
+                .map(|row| row.iter().map(AsciiSymbol::to_char).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+        let grid: Grid<Tile, 8, 8> = model.initial_grid(0);
+        // everything but the scatter, which can only have turned cells Pink, and the mix
+        let expected = [
+            "RRRRRRRR", "YYYYYYYY", "BWBWWWWW", "BBBWWWWW", "WWWWWOWW", "WWWWOOOW", "GGWWWOWW",
+            "GGWWWWWW",
+        ];
+        for (row, expected) in rows(&grid).iter().zip(expected) {
+            for (cell, expected) in row.chars().zip(expected.chars()) {
+                assert!(cell == expected || cell == 'K', "{} {}", row, expected);
+            }
+        }
+        assert!(rows(&grid).concat().contains('K'));
+        // scattering follows the seed
+        assert_eq!(model.initial_grid::<8, 8>(0), grid);
+        assert_ne!(model.initial_grid::<8, 8>(1), grid);
+
+        let mut written = Vec::new();
+        write_model(&model, &mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), text);
+
+        let read = |text: &str| read_model::<Tile, _, 3>(&mut text.as_bytes());
+        assert!(read("line 0,0 R").is_err());
+        assert!(read("rect 0,0 -1,2 R").is_err());
+        assert!(read("circle 0;0 2 R").is_err());
+        assert!(read("scatter R lots").is_err());
+        assert!(read("mix noise 2").is_err());
+        assert!(read("mix R:1 noise").is_err());
+        assert!(read("mix R:1 only").is_err());
+        assert!(read("mix R:1 region 0,0,1").is_err());
+        assert!(read("mix R:1 smooth 2").is_err());
+        assert!(read(&format!("stamp 0,0 {}", "R".repeat(STAMP_SIZE + 1))).is_err());
+    }
+
+    #[test]
+    fn refinement() {
+        let text = "\
+fill B
+origin R
+rule RB -> RR symmetry (xy+)
+refine 2 100
+block R RG/GR
+rule G -> Y symmetry (xy+)
+";
+        let mut model: ModelFile<Tile, 3> = read_model(&mut text.as_bytes()).unwrap();
+        let refinement = model.refine.as_ref().unwrap();
+        assert_eq!((refinement.scale, refinement.max_steps), (2, 100));
+        assert_eq!(refinement.rules.len(), 1);
+        assert_eq!(model.rules.len(), 1);
+
+        let mut written = Vec::new();
+        write_model(&model, &mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), text);
+
+        // the coarse line grows across its 3x1 grid, and every Red becomes a block
+        let grid: Grid<Tile, 6, 2> = model.refined_grid::<6, 2, 3, 1, 2>(0);
+        use Tile::{Green as G, Red as R};
+        assert_eq!(grid.items, [[R, G, R, G, R, G], [G, R, G, R, G, R]]);
+
+        let read = |text: &str| read_model::<Tile, _, 3>(&mut text.as_bytes());
+        assert!(read("refine 2").is_err());
+        assert!(read("refine 0 10").is_err());
+        assert!(read("block R RG/GR").is_err());
+        assert!(read("refine 2 10\nblock R RGG/GRR").is_err());
+        assert!(read("refine 2 10\nblock R R./GR").is_err());
+        assert!(read("refine 2 10\nfill R").is_err());
+        assert!(read("refine 2 10\nrefine 2 10").is_err());
+    }
+
+    #[test]
+    fn rule_options() {
+        let text = "\
+rule RB -> RR symmetry () boundary wrap,clamp count W:..100 period 2:1 lacks RR/.R count B:3..
+";
+        let model: ModelFile<Tile, 3> = read_model(&mut text.as_bytes()).unwrap();
+        let rule = &model.rules[0];
+        assert_eq!(rule.symmetry, Symmetry::Identity);
+        assert_eq!(
+            rule.boundaries,
+            Boundaries::new(Boundary::Wrap, Boundary::Clamp)
+        );
+        assert_eq!(rule.conditions.len(), 4);
+
+        let mut grid = Grid {
+            items: [[Tile::White; 3]; 2],
+        };
+        let counters = Counters::default();
+        let enabled =
+            |grid: &Grid<Tile, 3, 2>, steps| rule.enabled(&Context::new(grid, steps, &counters));
+        // fewer than 3 Black
+        assert!(!enabled(&grid, 1));
+        grid.items[0] = [Tile::Black; 3];
+        // odd steps only
+        assert!(enabled(&grid, 1));
+        assert!(!enabled(&grid, 2));
+        // and the pattern mustn't be anywhere, in any rotation
+        grid.items[0] = [Tile::Red, Tile::Black, Tile::Black];
+        grid.items[1] = [Tile::Red, Tile::Red, Tile::Black];
+        assert!(!enabled(&grid, 1));
+
+        let mut written = Vec::new();
+        write_model(&model, &mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            format!("fill B\n{}", text)
+        );
+    }
+
+    #[test]
+    fn unwritable_rules() {
+        let write = |rule: ReplacementRule<Tile, 1>| {
+            let model = ModelFile {
+                fill: Tile::Black,
+                origin: None,
+                init: Vec::new(),
+                rules: vec![
+                    ReplacementRule::new(Grid { items: [[None]] }, Grid { items: [[None]] }),
+                    rule,
+                ],
+                refine: None,
+            };
+            let mut written = Vec::new();
+            let result = write_model(&model, &mut written);
+            assert!(written.is_empty());
+            match result {
+                Err(BimpError::Unsupported(what)) => what,
+                other => panic!("{:?}", other),
+            }
+        };
+        let rule = || ReplacementRule::new(Grid { items: [[None]] }, Grid { items: [[None]] });
+        assert_eq!(
+            write(rule().with_placement(Placement::EvenCoordinates)),
+            "placements in rule 1, model files can't hold them"
+        );
+        assert!(
+            write(rule().with_cell_layers((0, 0), CellLayers::new(1, 0)))
+                .starts_with("cell layers")
+        );
+        let mut inactive = rule();
+        inactive.active = false;
+        assert!(write(inactive).starts_with("inactive rules"));
+        assert!(
+            write(rule().with_condition(|context: &Context<Tile>| context.steps < 10))
+                .starts_with("conditions other than")
+        );
+    }
+}
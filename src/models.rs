@@ -0,0 +1,107 @@
+//! The model run by the frontends: its initial grid, rules and export settings.
+
+use crate::hex::{HexGrid, HexRule};
+use crate::rewrite::{Grid, ReplacementRule};
+use crate::simulation::Simulation;
+use crate::tile::Tile;
+use crate::tiled::TiledExport;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 64;
+
+pub fn initial_grid() -> Grid<Tile, WIDTH, HEIGHT> {
+    // see stamp for lines, rects, circles and scattering
+    let mut grid: Grid<Tile, WIDTH, HEIGHT> = Default::default();
+    grid.draw_point((32, 32), Tile::Red);
+    grid
+}
+
+pub fn rules() -> Vec<ReplacementRule<Tile, 3>> {
+    const R: Option<Tile> = Some(Tile::Red);
+    const K: Option<Tile> = Some(Tile::Black);
+    const W: Option<Tile> = Some(Tile::White);
+    const G: Option<Tile> = Some(Tile::Green);
+    const O: Option<Tile> = Some(Tile::Orange);
+    const B: Option<Tile> = Some(Tile::Blue);
+    const X: Option<Tile> = None;
+
+    vec![
+        ReplacementRule::new(
+            Grid {
+                items: [[R, K, K], [X, X, X], [X, X, X]],
+            },
+            Grid {
+                items: [[W, W, R], [X, X, X], [X, X, X]],
+            },
+        ),
+        ReplacementRule::new(
+            Grid {
+                items: [[R, K, W], [X, X, X], [X, X, X]],
+            },
+            Grid {
+                items: [[G, W, O], [X, X, X], [X, X, X]],
+            },
+        ),
+        ReplacementRule::new(
+            Grid {
+                items: [[O, W, G], [X, X, X], [X, X, X]],
+            },
+            Grid {
+                items: [[O, K, B], [X, X, X], [X, X, X]],
+            },
+        ),
+        ReplacementRule::new(
+            Grid {
+                items: [[B, W, W], [X, X, X], [X, X, X]],
+            },
+            Grid {
+                items: [[K, K, B], [X, X, X], [X, X, X]],
+            },
+        ),
+        ReplacementRule::new(
+            Grid {
+                items: [[B, W, O], [X, X, X], [X, X, X]],
+            },
+            Grid {
+                items: [[K, K, R], [X, X, X], [X, X, X]],
+            },
+        ),
+    ]
+}
+
+pub fn simulation(seed: u64) -> Simulation<Tile, WIDTH, HEIGHT, 3> {
+    Simulation::new(initial_grid(), rules(), seed)
+}
+
+pub const HEX_WIDTH: usize = 48;
+pub const HEX_HEIGHT: usize = 48;
+
+pub fn hex_initial_grid() -> HexGrid<Tile, HEX_WIDTH, HEX_HEIGHT> {
+    let mut grid: HexGrid<Tile, HEX_WIDTH, HEX_HEIGHT> = Default::default();
+    grid.items[24][24] = Tile::Red;
+    grid
+}
+
+/// The same straight line maze growth as the first rule of `rules`, in 6 directions instead of 4
+pub fn hex_rules() -> Vec<HexRule<Tile>> {
+    use Tile::{Black as K, Red as R, White as W};
+    vec![HexRule::new(
+        vec![((0, 0), R), ((1, 0), K), ((2, 0), K)],
+        vec![((0, 0), W), ((1, 0), W), ((2, 0), R)],
+    )]
+}
+
+pub fn tiled_export() -> TiledExport<Tile> {
+    TiledExport {
+        tileset: "tiles.tsx".to_string(),
+        tile_size: (16, 16),
+        // same order as the sprite sheet, Black is left empty
+        gids: vec![
+            (Tile::White, 8),
+            (Tile::Red, 9),
+            (Tile::Orange, 10),
+            (Tile::Green, 12),
+            (Tile::Blue, 13),
+        ],
+    }
+}
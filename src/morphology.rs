@@ -0,0 +1,311 @@
+//! Dilate, erode and outline passes. Every cell is decided from the grid as it was before the
+//! pass, so each pass grows or shrinks regions by exactly one cell.
+
+use rand::RngCore;
+
+use crate::boundary::Boundaries;
+use crate::node::Node;
+use crate::rewrite::Grid;
+
+/// Which cells count as neighbours of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Neighbourhood {
+    /// The 4 orthogonal neighbours
+    #[default]
+    VonNeumann,
+    /// The 8 orthogonal and diagonal neighbours
+    Moore,
+}
+
+impl Neighbourhood {
+    pub fn offsets(&self) -> &'static [(isize, isize)] {
+        match self {
+            Neighbourhood::VonNeumann => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighbourhood::Moore => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+
+    /// Neighbours of (x, y) which are inside a W x H grid
+    pub fn neighbours<const W: usize, const H: usize>(
+        &self,
+        at: (usize, usize),
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbours_within::<W, H>(at, &Boundaries::CLAMP)
+    }
+
+    /// Neighbours of (x, y) in a W x H grid, found through the boundaries beyond the edges
+    pub fn neighbours_within<'a, const W: usize, const H: usize>(
+        &self,
+        (x, y): (usize, usize),
+        boundaries: &'a Boundaries,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.offsets().iter().filter_map(move |&(dx, dy)| {
+            boundaries.resolve((x as isize + dx, y as isize + dy), W, H)
+        })
+    }
+}
+
+/// Set every cell for which `rule` returns Some, given the grid before the pass. Returns true if
+/// any cell changed.
+fn map_cells<T, F, const W: usize, const H: usize>(grid: &mut Grid<T, W, H>, mut rule: F) -> bool
+where
+    T: Copy + PartialEq,
+    F: FnMut(&Grid<T, W, H>, (usize, usize)) -> Option<T>,
+{
+    let before = grid.clone();
+    let mut changed = false;
+    for y in 0..H {
+        for x in 0..W {
+            if let Some(tile) = rule(&before, (x, y)) {
+                changed |= grid.items[y][x] != tile;
+                grid.items[y][x] = tile;
+            }
+        }
+    }
+    changed
+}
+
+/// Grow `tile` by one cell: every `into` cell next to a `tile` cell becomes `tile`
+pub fn dilate<T: Copy + PartialEq, const W: usize, const H: usize>(
+    grid: &mut Grid<T, W, H>,
+    tile: T,
+    into: T,
+    neighbourhood: Neighbourhood,
+    boundaries: &Boundaries,
+) -> bool {
+    map_cells(grid, |before, (x, y)| {
+        let grows = before.items[y][x] == into
+            && neighbourhood
+                .neighbours_within::<W, H>((x, y), boundaries)
+                .any(|(nx, ny)| before.items[ny][nx] == tile);
+        grows.then_some(tile)
+    })
+}
+
+/// Shrink `tile` by one cell: every `tile` cell next to a cell holding anything else becomes
+/// `replacement`. Cells beyond the edge of the grid only count through the boundaries, so with
+/// clamped boundaries regions touching the edge don't shrink away from it.
+pub fn erode<T: Copy + PartialEq, const W: usize, const H: usize>(
+    grid: &mut Grid<T, W, H>,
+    tile: T,
+    replacement: T,
+    neighbourhood: Neighbourhood,
+    boundaries: &Boundaries,
+) -> bool {
+    map_cells(grid, |before, (x, y)| {
+        let shrinks = before.items[y][x] == tile
+            && neighbourhood
+                .neighbours_within::<W, H>((x, y), boundaries)
+                .any(|(nx, ny)| before.items[ny][nx] != tile);
+        shrinks.then_some(replacement)
+    })
+}
+
+/// Surround `tile` with a one cell border: every cell not holding `tile` which is next to a
+/// `tile` cell becomes `outline`
+pub fn outline<T: Copy + PartialEq, const W: usize, const H: usize>(
+    grid: &mut Grid<T, W, H>,
+    tile: T,
+    outline: T,
+    neighbourhood: Neighbourhood,
+    boundaries: &Boundaries,
+) -> bool {
+    map_cells(grid, |before, (x, y)| {
+        let borders = before.items[y][x] != tile
+            && neighbourhood
+                .neighbours_within::<W, H>((x, y), boundaries)
+                .any(|(nx, ny)| before.items[ny][nx] == tile);
+        borders.then_some(outline)
+    })
+}
+
+/// One of the passes above, as a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Morphology<T> {
+    Dilate { tile: T, into: T },
+    Erode { tile: T, replacement: T },
+    Outline { tile: T, outline: T },
+}
+
+/// A morphological pass repeated a number of times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MorphologyNode<T> {
+    pub operation: Morphology<T>,
+    pub neighbourhood: Neighbourhood,
+    pub boundaries: Boundaries,
+    pub times: usize,
+}
+
+impl<T> MorphologyNode<T> {
+    pub fn new(operation: Morphology<T>) -> Self {
+        Self {
+            operation,
+            neighbourhood: Neighbourhood::VonNeumann,
+            boundaries: Boundaries::CLAMP,
+            times: 1,
+        }
+    }
+
+    pub fn with_neighbourhood(mut self, neighbourhood: Neighbourhood) -> Self {
+        self.neighbourhood = neighbourhood;
+        self
+    }
+
+    pub fn with_boundaries(mut self, boundaries: Boundaries) -> Self {
+        self.boundaries = boundaries;
+        self
+    }
+
+    pub fn repeated(mut self, times: usize) -> Self {
+        self.times = times;
+        self
+    }
+}
+
+impl<T: Copy + PartialEq, const W: usize, const H: usize> Node<T, W, H> for MorphologyNode<T> {
+    fn apply(&mut self, grid: &mut Grid<T, W, H>, _rng: &mut dyn RngCore) -> bool {
+        let mut changed = false;
+        for _ in 0..self.times {
+            let changed_now = match self.operation {
+                Morphology::Dilate { tile, into } => {
+                    dilate(grid, tile, into, self.neighbourhood, &self.boundaries)
+                }
+                Morphology::Erode { tile, replacement } => erode(
+                    grid,
+                    tile,
+                    replacement,
+                    self.neighbourhood,
+                    &self.boundaries,
+                ),
+                Morphology::Outline { tile, outline: o } => {
+                    outline(grid, tile, o, self.neighbourhood, &self.boundaries)
+                }
+            };
+            if !changed_now {
+                break;
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::boundary::Boundary;
+    use crate::metrics::count;
+    use crate::tile::Tile::{self, Black as B, Red as R, White as W, Yellow as Y};
+    use rand::rngs::mock::StepRng;
+
+    fn dot() -> Grid<Tile, 5, 5> {
+        let mut grid = Grid::default();
+        grid.items[2][2] = R;
+        grid
+    }
+
+    #[test]
+    fn dilate_neighbourhoods() {
+        let mut grid = dot();
+        assert!(dilate(
+            &mut grid,
+            R,
+            B,
+            Neighbourhood::VonNeumann,
+            &Boundaries::CLAMP
+        ));
+        assert_eq!(count(&grid, &R), 5);
+        assert!(dilate(
+            &mut grid,
+            R,
+            B,
+            Neighbourhood::VonNeumann,
+            &Boundaries::CLAMP
+        ));
+        assert_eq!(count(&grid, &R), 13);
+
+        let mut grid = dot();
+        dilate(&mut grid, R, B, Neighbourhood::Moore, &Boundaries::CLAMP);
+        assert_eq!(count(&grid, &R), 9);
+        // only grows into `into`
+        assert!(!dilate(
+            &mut grid,
+            R,
+            W,
+            Neighbourhood::Moore,
+            &Boundaries::CLAMP
+        ));
+    }
+
+    #[test]
+    fn erode_undoes_dilate_away_from_edges() {
+        let mut grid = dot();
+        dilate(&mut grid, R, B, Neighbourhood::Moore, &Boundaries::CLAMP);
+        assert!(erode(
+            &mut grid,
+            R,
+            B,
+            Neighbourhood::Moore,
+            &Boundaries::CLAMP
+        ));
+        assert_eq!(grid, dot());
+    }
+
+    #[test]
+    fn erode_keeps_edges() {
+        let mut grid = Grid {
+            items: [[R, R, R], [R, R, R], [R, R, B]],
+        };
+        erode(
+            &mut grid,
+            R,
+            W,
+            Neighbourhood::VonNeumann,
+            &Boundaries::CLAMP,
+        );
+        assert_eq!(grid.items, [[R, R, R], [R, R, W], [R, W, B]]);
+    }
+
+    #[test]
+    fn wrapped_erode_shrinks_from_edges() {
+        let wrap = Boundaries::new(Boundary::Wrap, Boundary::Wrap);
+        let mut grid = Grid {
+            items: [[R, R, R], [R, R, R], [R, R, B]],
+        };
+        erode(&mut grid, R, W, Neighbourhood::VonNeumann, &wrap);
+        assert_eq!(grid.items, [[R, R, W], [R, R, W], [W, W, B]]);
+    }
+
+    #[test]
+    fn outline_surrounds_any_tile() {
+        let mut grid = Grid {
+            items: [[B, W, B], [B, R, B], [B, B, B]],
+        };
+        outline(
+            &mut grid,
+            R,
+            Y,
+            Neighbourhood::VonNeumann,
+            &Boundaries::CLAMP,
+        );
+        assert_eq!(grid.items, [[B, Y, B], [Y, R, Y], [B, Y, B]]);
+    }
+
+    #[test]
+    fn node_repeats_until_unchanged() {
+        let mut grid = dot();
+        let mut node = MorphologyNode::new(Morphology::Dilate { tile: R, into: B }).repeated(100);
+        assert!(node.apply(&mut grid, &mut StepRng::new(0, 1)));
+        assert_eq!(count(&grid, &R), 25);
+        assert!(!node.apply(&mut grid, &mut StepRng::new(0, 1)));
+    }
+}
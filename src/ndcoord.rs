@@ -6,15 +6,37 @@ pub struct Coord<const D: usize> {
 }
 
 impl<const D: usize> Coord<D> {
-    const ZERO: Self = Self { axes: [0; D] };
+    pub const ZERO: Self = Self { axes: [0; D] };
     const ONE: Self = Self { axes: [1; D] };
 
     pub fn new(axes: [isize; D]) -> Self {
         Self { axes }
     }
 
+    pub fn axes(&self) -> &[isize; D] {
+        &self.axes
+    }
+
     pub fn volume(&self) -> usize {
-        self.axes.iter().sum::<isize>() as usize
+        self.axes.iter().product::<isize>() as usize
+    }
+
+    /// Whether self lies inside a grid of the given size with its origin at zero
+    pub fn is_within(&self, size: &Self) -> bool {
+        self.axes
+            .iter()
+            .zip(size.axes.iter())
+            .all(|(&ax, &size)| ax >= 0 && ax < size)
+    }
+
+    /// Convert to a flat array index into a grid of the given size. The first axis varies fastest,
+    /// the same order as CartesianIter.
+    pub fn to_flat(&self, size: &Self) -> usize {
+        self.axes
+            .iter()
+            .zip(size.axes.iter())
+            .rev()
+            .fold(0, |acc, (&ax, &size)| acc * size + ax) as usize
     }
 
     pub fn iter_volume(&self, size: &Self) -> CartesianIter<D> {
@@ -42,11 +64,9 @@ impl<const D: usize> Sub for Coord<D> {
     type Output = Coord<D>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        let mut out = [0; D];
-        for i in 0..D {
-            out[i] = self.axes[i] - rhs.axes[i];
+        Coord {
+            axes: std::array::from_fn(|i| self.axes[i] - rhs.axes[i]),
         }
-        Coord { axes: out }
     }
 }
 
@@ -54,11 +74,9 @@ impl<const D: usize> Add for Coord<D> {
     type Output = Coord<D>;
 
     fn add(self, rhs: Self) -> Self::Output {
-        let mut out = [0; D];
-        for i in 0..D {
-            out[i] = self.axes[i] + rhs.axes[i];
+        Coord {
+            axes: std::array::from_fn(|i| self.axes[i] + rhs.axes[i]),
         }
-        Coord { axes: out }
     }
 }
 
@@ -150,6 +168,20 @@ mod test {
         );
     }
 
+    #[test]
+    fn volume() {
+        assert_eq!(Coord::new_3d(2, 3, 4).volume(), 24);
+        assert_eq!(Coord::new_2d(5, 0).volume(), 0);
+    }
+
+    #[test]
+    fn to_flat_matches_iter_order() {
+        let size = Coord::new_3d(3, 4, 5);
+        for (i, c) in Coord::ZERO.iter_volume(&size).enumerate() {
+            assert_eq!(c.to_flat(&size), i);
+        }
+    }
+
     #[test]
     fn iter_origin() {
         let mut i = Coord::new_3d(0, 0, 0).iter_volume(&Coord::new_3d(3, 3, 3));
@@ -1,18 +1,59 @@
+use std::ops::{Index, IndexMut};
+
+use crate::error::{BimpError, Result};
 use crate::ndcoord::Coord;
 
-struct NGrid<T, const D: usize> {
+pub struct NGrid<T, const D: usize> {
     items: Vec<T>,
     size: Coord<D>,
 }
 
 impl<T, const D: usize> NGrid<T, D> {
-    fn new(items: Vec<T>, size: Coord<D>) -> Self {
-        assert!(items.len() == size.volume());
-        Self { items, size }
+    /// Items are in the same order as Coord::to_flat, ie. first axis fastest
+    pub fn new(items: Vec<T>, size: Coord<D>) -> Result<Self> {
+        if items.len() != size.volume() {
+            return Err(BimpError::DimensionMismatch {
+                expected: size.volume(),
+                actual: items.len(),
+            });
+        }
+        Ok(Self { items, size })
+    }
+
+    pub fn size(&self) -> &Coord<D> {
+        &self.size
+    }
+
+    pub fn get(&self, at: &Coord<D>) -> Option<&T> {
+        if at.is_within(&self.size) {
+            Some(&self.items[at.to_flat(&self.size)])
+        } else {
+            None
+        }
     }
 }
 
-struct RotatedCartesianIter<const D: usize> {
-    rotation: usize,
-    current_index: Coord<D>,
+impl<T: Default, const D: usize> NGrid<T, D> {
+    pub fn from_default(size: Coord<D>) -> Self {
+        Self {
+            items: std::iter::repeat_with(Default::default)
+                .take(size.volume())
+                .collect(),
+            size,
+        }
+    }
+}
+
+impl<T, const D: usize> Index<&Coord<D>> for NGrid<T, D> {
+    type Output = T;
+
+    fn index(&self, index: &Coord<D>) -> &Self::Output {
+        &self.items[index.to_flat(&self.size)]
+    }
+}
+
+impl<T, const D: usize> IndexMut<&Coord<D>> for NGrid<T, D> {
+    fn index_mut(&mut self, index: &Coord<D>) -> &mut Self::Output {
+        &mut self.items[index.to_flat(&self.size)]
+    }
 }
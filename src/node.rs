@@ -0,0 +1,12 @@
+//! Whole-grid operations which run once, rather than rewriting one match at a time like rules.
+//! Passes like growing a region by one cell need hundreds of generated patch rules and are slow
+//! when done through rule matching, so they are implemented directly on the grid instead.
+
+use rand::RngCore;
+
+use crate::rewrite::Grid;
+
+pub trait Node<T, const W: usize, const H: usize> {
+    /// Returns true if any cell changed
+    fn apply(&mut self, grid: &mut Grid<T, W, H>, rng: &mut dyn RngCore) -> bool;
+}
@@ -0,0 +1,88 @@
+//! Restrictions on where a rule may match. A match is placed by the grid cell that the patch's
+//! top left cell (before rotation) lands on, called its origin, so eg. a rule anchored to the
+//! top edge only matches with its top left find cell in the top row.
+
+use crate::rewrite::{self, PatchOrientation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+    /// Any of the four
+    Any,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Placement {
+    #[default]
+    Anywhere,
+    /// The origin is on this edge of the grid
+    Edge(Edge),
+    /// Both coordinates of the origin are even
+    EvenCoordinates,
+    /// The origin is one of these (x, y) cells
+    Cells(Vec<(isize, isize)>),
+}
+
+impl Placement {
+    /// Whether a match with its origin at `(x, y)` is allowed in a `width` x `height` grid
+    pub fn allows(&self, (x, y): (isize, isize), width: usize, height: usize) -> bool {
+        let right = width as isize - 1;
+        let bottom = height as isize - 1;
+        match self {
+            Placement::Anywhere => true,
+            Placement::Edge(Edge::Top) => y == 0,
+            Placement::Edge(Edge::Bottom) => y == bottom,
+            Placement::Edge(Edge::Left) => x == 0,
+            Placement::Edge(Edge::Right) => x == right,
+            Placement::Edge(Edge::Any) => x == 0 || y == 0 || x == right || y == bottom,
+            Placement::EvenCoordinates => x % 2 == 0 && y % 2 == 0,
+            Placement::Cells(cells) => cells.contains(&(x, y)),
+        }
+    }
+}
+
+/// Where the top left cell of an S x S patch ends up within the patch after orienting it
+pub fn origin_in_patch(rotation_times: usize, reflected: bool, size: usize) -> (isize, isize) {
+    let (x, y) = rewrite::orient_position((0, 0), rotation_times, reflected, size);
+    (x as isize, y as isize)
+}
+
+/// Grid cell the patch's origin lands on for a match
+pub fn origin(orientation: &PatchOrientation, size: usize) -> (isize, isize) {
+    let (dx, dy) = origin_in_patch(orientation.rotation_times, orientation.reflected, size);
+    (orientation.position.0 + dx, orientation.position.1 + dy)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rewrite::Grid;
+
+    #[test]
+    fn origin_follows_orientation() {
+        let mut patch: Grid<u8, 3, 3> = Default::default();
+        patch.items[0][0] = 1;
+        for rotation_times in 0..4 {
+            for reflected in [false, true] {
+                let oriented = patch.orient(rotation_times, reflected);
+                let (x, y) = origin_in_patch(rotation_times, reflected, 3);
+                assert_eq!(oriented.items[y as usize][x as usize], 1);
+            }
+        }
+    }
+
+    #[test]
+    fn allows() {
+        assert!(Placement::Edge(Edge::Top).allows((5, 0), 8, 8));
+        assert!(!Placement::Edge(Edge::Top).allows((5, 1), 8, 8));
+        assert!(Placement::Edge(Edge::Any).allows((7, 3), 8, 8));
+        assert!(!Placement::Edge(Edge::Any).allows((6, 3), 8, 8));
+        assert!(Placement::EvenCoordinates.allows((2, 4), 8, 8));
+        assert!(!Placement::EvenCoordinates.allows((2, 3), 8, 8));
+        assert!(Placement::Cells(vec![(1, 2)]).allows((1, 2), 8, 8));
+        assert!(!Placement::Cells(vec![(1, 2)]).allows((2, 1), 8, 8));
+    }
+}
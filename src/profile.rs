@@ -0,0 +1,118 @@
+//! Time spent on each rule, for finding the rule that dominates a step. See
+//! Simulation::set_profiling. Timings use std::time::Instant, which panics on wasm32, so
+//! profiling is off unless asked for.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Totals for one rule since profiling was turned on
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleTimings {
+    /// Time spent finding the rule's matches
+    pub scan: Duration,
+    /// Time spent writing a chosen match into the grid
+    pub apply: Duration,
+    /// Number of times the rule's matches were found
+    pub scans: usize,
+    /// Number of times the rule was applied. Applications the scheduler rejected, see
+    /// Scheduler::accept, aren't counted, nor is the time spent on them.
+    pub applications: usize,
+}
+
+impl RuleTimings {
+    pub fn total(&self) -> Duration {
+        self.scan + self.apply
+    }
+}
+
+/// Timings of every rule, indexed by rule id
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub rules: Vec<RuleTimings>,
+}
+
+impl Profile {
+    /// Timings of a rule, added if the rule list has grown since profiling started
+    pub fn rule_mut(&mut self, rule_id: usize) -> &mut RuleTimings {
+        if rule_id >= self.rules.len() {
+            self.rules.resize(rule_id + 1, RuleTimings::default());
+        }
+        &mut self.rules[rule_id]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.rules.iter().map(RuleTimings::total).sum()
+    }
+
+    /// Add the timings in `other` to these
+    pub fn add(&mut self, other: &Profile) {
+        for (rule_id, timings) in other.rules.iter().enumerate() {
+            let totals = self.rule_mut(rule_id);
+            totals.scan += timings.scan;
+            totals.apply += timings.apply;
+            totals.scans += timings.scans;
+            totals.applications += timings.applications;
+        }
+    }
+
+    /// Rule ids of rules which were tried at least once, slowest first
+    pub fn slowest(&self) -> Vec<usize> {
+        let mut ids = (0..self.rules.len())
+            .filter(|&id| self.rules[id].scans > 0)
+            .collect::<Vec<_>>();
+        ids.sort_by(|&a, &b| self.rules[b].total().cmp(&self.rules[a].total()));
+        ids
+    }
+}
+
+/// One line per rule, slowest first: its share of the total time, then the time spent scanning
+/// and applying with the number of scans and applications in brackets
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        for rule_id in self.slowest() {
+            let rule = &self.rules[rule_id];
+            let share = if total > 0.0 {
+                100.0 * rule.total().as_secs_f64() / total
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "rule {:>2} {:>5.1}%  scan {:.3}ms ({})  apply {:.3}ms ({})",
+                rule_id,
+                share,
+                rule.scan.as_secs_f64() * 1000.0,
+                rule.scans,
+                rule.apply.as_secs_f64() * 1000.0,
+                rule.applications,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slowest_first() {
+        let mut profile = Profile::default();
+        profile.rule_mut(2).scan = Duration::from_millis(3);
+        profile.rule_mut(2).scans = 1;
+        profile.rule_mut(0).scan = Duration::from_millis(1);
+        profile.rule_mut(0).apply = Duration::from_millis(1);
+        profile.rule_mut(0).scans = 1;
+        // never tried
+        assert_eq!(profile.rules[1], RuleTimings::default());
+
+        assert_eq!(profile.total(), Duration::from_millis(5));
+        assert_eq!(profile.slowest(), vec![2, 0]);
+        let report = profile.to_string();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("rule  2  60.0%"), "{}", lines[0]);
+        assert!(lines[1].starts_with("rule  0  40.0%"), "{}", lines[1]);
+    }
+}
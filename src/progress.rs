@@ -0,0 +1,129 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::cli::Options;
+
+/// Time between progress reports
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far a headless run has got
+pub struct Status {
+    /// "step" or "run"
+    pub unit: &'static str,
+    pub done: usize,
+    /// Number of steps or runs which will be done at most, if limited
+    pub total: Option<usize>,
+    /// Matches of every rule in the current grid
+    pub matches: Option<usize>,
+    pub elapsed: Duration,
+}
+
+impl Status {
+    fn rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.done as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Time until `total` is reached at the current rate
+    fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.done);
+        let rate = self.rate();
+        (rate > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+/// eg. "step 1200/5000, 350.0 steps/s, 42 matches, ETA 11s"
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.unit, self.done)?;
+        if let Some(total) = self.total {
+            write!(f, "/{}", total)?;
+        }
+        write!(f, ", {:.1} {}s/s", self.rate(), self.unit)?;
+        if let Some(matches) = self.matches {
+            write!(f, ", {} matches", matches)?;
+        }
+        if let Some(eta) = self.eta() {
+            write!(f, ", ETA {}s", eta.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the status of a headless run every INTERVAL to stderr, unless --quiet, and to the
+/// --status-file if given, replacing the previous status.
+pub struct Progress {
+    started: Instant,
+    last_report: Instant,
+    quiet: bool,
+    status_file: Option<PathBuf>,
+}
+
+impl Progress {
+    pub fn new(options: &Options) -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last_report: now,
+            quiet: options.quiet,
+            status_file: options.status_file.clone(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Whether a report should be made now. Callers can skip working out an expensive status
+    /// when it isn't.
+    pub fn due(&self) -> bool {
+        (!self.quiet || self.status_file.is_some()) && self.last_report.elapsed() >= INTERVAL
+    }
+
+    pub fn report(&mut self, status: &Status) {
+        self.last_report = Instant::now();
+        if !self.quiet {
+            eprintln!("{}", status);
+        }
+        if let Some(path) = &self.status_file {
+            // progress is best effort, a failed write shouldn't stop the run
+            if let Err(e) = fs::write(path, format!("{}\n", status)) {
+                eprintln!("failed to write {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_line() {
+        let status = Status {
+            unit: "step",
+            done: 1000,
+            total: Some(5000),
+            matches: Some(42),
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(
+            status.to_string(),
+            "step 1000/5000, 500.0 steps/s, 42 matches, ETA 8s"
+        );
+        let status = Status {
+            unit: "run",
+            done: 3,
+            total: None,
+            matches: None,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(status.to_string(), "run 3, 0.0 runs/s");
+    }
+}
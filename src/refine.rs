@@ -0,0 +1,166 @@
+//! Structure then detail: run a model on a small coarse grid, then blow each coarse cell up into
+//! an N x N block of the full size grid. The simulation the node is applied to then carries on
+//! with its own rules at full resolution. Model files refine with the refine directive, see
+//! model_file.
+
+use rand::RngCore;
+
+use crate::node::Node;
+use crate::rewrite::{Grid, ReplacementRule};
+use crate::simulation::Simulation;
+use crate::upscale::Upscale;
+
+/// Replaces the whole grid, which must be N times the size of the coarse grid in both
+/// directions
+pub struct Refine<T, const CW: usize, const CH: usize, const S: usize, const N: usize> {
+    /// Starting grid of the coarse model
+    pub coarse: Grid<T, CW, CH>,
+    pub rules: Vec<ReplacementRule<T, S>>,
+    /// The coarse model stops after this many steps if it hasn't converged
+    pub max_steps: usize,
+    /// The block each coarse tile becomes
+    pub upscale: Upscale<T, N>,
+    /// The coarse grid the last apply ended with
+    pub result: Option<Grid<T, CW, CH>>,
+}
+
+impl<T, const CW: usize, const CH: usize, const S: usize, const N: usize> Refine<T, CW, CH, S, N>
+where
+    T: PartialEq + Copy + Default,
+{
+    pub fn new(
+        coarse: Grid<T, CW, CH>,
+        rules: Vec<ReplacementRule<T, S>>,
+        max_steps: usize,
+    ) -> Self {
+        Self {
+            coarse,
+            rules,
+            max_steps,
+            upscale: Upscale::default(),
+            result: None,
+        }
+    }
+
+    pub fn with_block(mut self, tile: T, block: Grid<T, N, N>) -> Self {
+        self.upscale = self.upscale.with_template(tile, block);
+        self
+    }
+
+    pub fn with_upscale(mut self, upscale: Upscale<T, N>) -> Self {
+        self.upscale = upscale;
+        self
+    }
+}
+
+impl<
+        T,
+        const W: usize,
+        const H: usize,
+        const CW: usize,
+        const CH: usize,
+        const S: usize,
+        const N: usize,
+    > Node<T, W, H> for Refine<T, CW, CH, S, N>
+where
+    T: Eq + Copy + Default + 'static,
+{
+    fn apply(&mut self, grid: &mut Grid<T, W, H>, rng: &mut dyn RngCore) -> bool {
+        // the coarse model borrows the rules for the run
+        let rules = std::mem::take(&mut self.rules);
+        let mut coarse = Simulation::new(self.coarse.clone(), rules, rng.next_u64());
+        coarse.run(self.max_steps);
+        self.rules = std::mem::take(&mut coarse.rules);
+
+        let fine = self.upscale.upscale(&coarse.grid, rng);
+        self.result = Some(coarse.grid);
+        let changed = *grid != fine;
+        *grid = fine;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile;
+
+    const K: Option<Tile> = Some(Tile::Black);
+    const R: Option<Tile> = Some(Tile::Red);
+    const W: Option<Tile> = Some(Tile::White);
+    const G: Option<Tile> = Some(Tile::Green);
+
+    #[test]
+    fn coarse_cells_become_blocks() {
+        let coarse = Grid {
+            items: [[Tile::Black, Tile::Black, Tile::Blue]],
+        };
+        let rules = vec![ReplacementRule::new(
+            Grid { items: [[K]] },
+            Grid { items: [[R]] },
+        )];
+        // one step, so one Black turns Red
+        let mut refine = Refine::new(coarse, rules, 1).with_block(
+            Tile::Red,
+            Grid {
+                items: [[Tile::Red, Tile::White], [Tile::White, Tile::Red]],
+            },
+        );
+        let mut grid: Grid<Tile, 6, 2> = Default::default();
+        let mut sim = Simulation::new(grid.clone(), Vec::<ReplacementRule<Tile, 1>>::new(), 0);
+        assert!(sim.apply_node(&mut refine));
+        grid = sim.grid;
+
+        let result = refine.result.unwrap();
+        let red = result.items[0]
+            .iter()
+            .position(|&tile| tile == Tile::Red)
+            .unwrap();
+        assert!(red < 2);
+        let black = 1 - red;
+        assert_eq!(
+            grid.items[0][red * 2..red * 2 + 2],
+            [Tile::Red, Tile::White]
+        );
+        assert_eq!(
+            grid.items[1][red * 2..red * 2 + 2],
+            [Tile::White, Tile::Red]
+        );
+        // no block for Black or Blue, so they fill theirs
+        assert_eq!(grid.items[1][black * 2..black * 2 + 2], [Tile::Black; 2]);
+        assert_eq!(grid.items[0][4..], [Tile::Blue; 2]);
+        // the rules are back for the next apply
+        assert_eq!(refine.rules.len(), 1);
+    }
+
+    #[test]
+    fn fine_rules_carry_on() {
+        let coarse: Grid<Tile, 2, 2> = Default::default();
+        let coarse_rules = vec![ReplacementRule::new(
+            Grid { items: [[K]] },
+            Grid { items: [[R]] },
+        )];
+        let mut refine = Refine::new(coarse, coarse_rules, 100).with_block(
+            Tile::Red,
+            Grid {
+                items: [[Tile::White; 3]; 3],
+            },
+        );
+        let fine_rules = vec![ReplacementRule::new(
+            Grid { items: [[W]] },
+            Grid { items: [[G]] },
+        )];
+        let mut sim = Simulation::new(Grid::<Tile, 6, 6>::default(), fine_rules, 0);
+        sim.apply_node(&mut refine);
+        assert_eq!(sim.run(100), 36);
+        assert_eq!(sim.grid.items, [[Tile::Green; 6]; 6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fill")]
+    fn sizes_must_agree() {
+        let mut refine: Refine<Tile, 2, 2, 1, 2> = Refine::new(Default::default(), Vec::new(), 0);
+        let mut grid: Grid<Tile, 5, 4> = Default::default();
+        refine.apply(&mut grid, &mut rand::rngs::mock::StepRng::new(0, 1));
+    }
+}
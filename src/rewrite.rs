@@ -0,0 +1,1222 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use tracing::{trace, trace_span};
+
+use crate::boundary::Boundaries;
+use crate::condition::{Condition, Context};
+use crate::constraint::Constraint;
+use crate::coord::Coord;
+use crate::counters::{Comparison, Counters, Effect, Guard};
+use crate::determinism;
+use crate::field::{FieldGuard, Fields};
+use crate::grid::GridView;
+use crate::layers::CellLayers;
+use crate::matcher::MatchStrategy;
+use crate::morphology::Neighbourhood;
+use crate::placement::{self, Placement};
+use crate::profile::{Profile, RuleTimings};
+use crate::scheduler::{Priority, Scheduler};
+use crate::symmetry::Symmetry;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Grid<T, const W: usize, const H: usize> {
+    pub items: [[T; W]; H],
+}
+
+/// Grid of "T: Default" itself also defined default, filling the entire grid
+impl<T: Default + Copy, const W: usize, const H: usize> Default for Grid<T, W, H> {
+    fn default() -> Self {
+        Self {
+            items: [[Default::default(); W]; H],
+        }
+    }
+}
+
+impl<T, const W: usize, const H: usize> Grid<T, W, H> {
+    /// (width, height) in tiles
+    pub fn size(&self) -> (usize, usize) {
+        (W, H)
+    }
+}
+
+/// Where and how a patch was placed: mirrored left to right if `reflected`, then rotated, then
+/// moved so its top left corner is at `position`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PatchOrientation {
+    pub rotation_times: usize,
+    pub reflected: bool,
+    pub position: (isize, isize),
+}
+
+impl PatchOrientation {
+    /// 0-3 for the rotations, 4-7 for the reflected rotations
+    pub fn index(&self) -> usize {
+        self.rotation_times % 4 + if self.reflected { 4 } else { 0 }
+    }
+}
+
+/// A replacement cell which is sampled from weighted tiles every time its rule is applied, eg.
+/// 80% Green and 20% DarkGreen for some texture. Only used when applying with an RNG, otherwise
+/// (eg. in searches and replays) the replace patch's own cell is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomCell<T> {
+    /// (x, y) in the replace patch, before rotation
+    pub position: (usize, usize),
+    /// (tile, weight) pairs, weights don't need to add up to anything in particular
+    pub choices: Vec<(T, f64)>,
+}
+
+/// Extra condition on one cell of a find patch: the number of its neighbours in the grid holding
+/// `tile`, eg. "at least 3 Red Moore neighbours". Neighbours beyond the edge of the grid aren't
+/// counted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeighbourCount<T> {
+    /// (x, y) in the find patch, before rotation
+    pub position: (usize, usize),
+    pub tile: T,
+    pub neighbourhood: Neighbourhood,
+    pub comparison: Comparison,
+    pub count: usize,
+}
+
+impl<T: PartialEq> NeighbourCount<T> {
+    pub fn new(
+        position: (usize, usize),
+        tile: T,
+        neighbourhood: Neighbourhood,
+        comparison: Comparison,
+        count: usize,
+    ) -> Self {
+        Self {
+            position,
+            tile,
+            neighbourhood,
+            comparison,
+            count,
+        }
+    }
+
+    /// Whether the count holds with the patch placed at `orientation`. Cells beyond the edges
+    /// are found through the boundaries, a cell with no cell to read has no neighbours.
+    pub fn holds<const W: usize, const H: usize>(
+        &self,
+        grid: &Grid<T, W, H>,
+        size: usize,
+        orientation: &PatchOrientation,
+        boundaries: &Boundaries,
+    ) -> bool {
+        let (x, y) = orient_position(
+            self.position,
+            orientation.rotation_times,
+            orientation.reflected,
+            size,
+        );
+        let at = (
+            x as isize + orientation.position.0,
+            y as isize + orientation.position.1,
+        );
+        let neighbours = match boundaries.resolve(at, W, H) {
+            Some(at) => self
+                .neighbourhood
+                .neighbours_within::<W, H>(at, boundaries)
+                .filter(|&(nx, ny)| grid.items[ny][nx] == self.tile)
+                .count(),
+            None => 0,
+        };
+        self.comparison.compare(neighbours, self.count)
+    }
+}
+
+pub struct ReplacementRule<T, const S: usize> {
+    pub find: Grid<Option<T>, S, S>,
+    pub replace: Grid<Option<T>, S, S>,
+    /// The rule is only considered while every guard holds
+    pub guards: Vec<Guard>,
+    /// Applied to the counters every time the rule is applied
+    pub effects: Vec<Effect>,
+    /// Like guards, but over the whole grid
+    pub conditions: Vec<Box<dyn Condition<T>>>,
+    /// Where in the grid the rule may match
+    pub placement: Placement,
+    /// Which orientations of the patches are tried, every rotation by default
+    pub symmetry: Symmetry,
+    /// What the patches see and write beyond the edges of the grid, nothing by default
+    pub boundaries: Boundaries,
+    /// Written over the replace patch after it is applied
+    pub random_cells: Vec<RandomCell<T>>,
+    /// Checked on top of the find patch at every match
+    pub neighbour_counts: Vec<NeighbourCount<T>>,
+    /// Checked on top of the find patch at every match, against the simulation's fields
+    pub field_guards: Vec<FieldGuard>,
+    /// Which layer each cell of the patches is matched on and written to, the simulation's grid
+    /// by default, see layers
+    pub layers: Grid<CellLayers, S, S>,
+    /// Inactive rules are never considered, eg. while switched off by hand in the window
+    pub active: bool,
+}
+
+impl<T, const S: usize> ReplacementRule<T, S> {
+    pub fn new(find: Grid<Option<T>, S, S>, replace: Grid<Option<T>, S, S>) -> Self {
+        Self {
+            find,
+            replace,
+            guards: Vec::new(),
+            effects: Vec::new(),
+            conditions: Vec::new(),
+            placement: Placement::Anywhere,
+            symmetry: Symmetry::Rotations,
+            boundaries: Boundaries::CLAMP,
+            random_cells: Vec::new(),
+            neighbour_counts: Vec::new(),
+            field_guards: Vec::new(),
+            layers: Grid {
+                items: [[CellLayers::GRID; S]; S],
+            },
+            active: true,
+        }
+    }
+
+    /// Match the cell at (x, y) in the patches, before rotation, on `layers.read` and write it
+    /// to `layers.write`. Random cells are always written to the grid.
+    pub fn with_cell_layers(mut self, (x, y): (usize, usize), layers: CellLayers) -> Self {
+        self.layers.items[y][x] = layers;
+        self
+    }
+
+    pub fn with_neighbour_count(mut self, neighbour_count: NeighbourCount<T>) -> Self {
+        self.neighbour_counts.push(neighbour_count);
+        self
+    }
+
+    pub fn with_field_guard(mut self, field_guard: FieldGuard) -> Self {
+        self.field_guards.push(field_guard);
+        self
+    }
+
+    pub fn with_random_cell(mut self, position: (usize, usize), choices: Vec<(T, f64)>) -> Self {
+        self.random_cells.push(RandomCell { position, choices });
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn with_symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    pub fn with_boundaries(mut self, boundaries: Boundaries) -> Self {
+        self.boundaries = boundaries;
+        self
+    }
+
+    pub fn with_guard(mut self, guard: Guard) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    pub fn with_effect(mut self, effect: Effect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn with_condition<C: Condition<T> + 'static>(mut self, condition: C) -> Self {
+        self.conditions.push(Box::new(condition));
+        self
+    }
+
+    /// The same rule with its patches in the top left corner of P x P patches, padded with
+    /// don't-cares, so it can share a rule set with larger rules, eg. a 1x2 rule alongside 3x3
+    /// ones. It matches and writes the same cells as before, in the same places: the top left
+    /// cell stays the origin for placements, and orientations which only move the rule around
+    /// the larger patch aren't counted twice.
+    pub fn embed<const P: usize>(self) -> ReplacementRule<T, P>
+    where
+        T: Copy,
+    {
+        assert!(
+            P >= S,
+            "can't embed a {0}x{0} rule in {1}x{1} patches",
+            S,
+            P
+        );
+        let pad = |patch: &Grid<Option<T>, S, S>| {
+            let mut padded = Grid {
+                items: [[None; P]; P],
+            };
+            for (row, patch_row) in padded.items.iter_mut().zip(patch.items.iter()) {
+                row[..S].copy_from_slice(patch_row);
+            }
+            padded
+        };
+        ReplacementRule {
+            find: pad(&self.find),
+            replace: pad(&self.replace),
+            guards: self.guards,
+            effects: self.effects,
+            conditions: self.conditions,
+            placement: self.placement,
+            symmetry: self.symmetry,
+            boundaries: self.boundaries,
+            random_cells: self.random_cells,
+            neighbour_counts: self.neighbour_counts,
+            field_guards: self.field_guards,
+            layers: {
+                let mut layers = Grid {
+                    items: [[CellLayers::GRID; P]; P],
+                };
+                for (row, patch_row) in layers.items.iter_mut().zip(self.layers.items.iter()) {
+                    row[..S].copy_from_slice(patch_row);
+                }
+                layers
+            },
+            active: self.active,
+        }
+    }
+
+    /// Whether any cell is matched on or written to another layer than the simulation's grid
+    pub fn is_layered(&self) -> bool {
+        self.layers
+            .items
+            .iter()
+            .flatten()
+            .any(|&layers| layers != CellLayers::GRID)
+    }
+
+    /// The cells of `patch` whose layer, picked from their CellLayers by `layer_of`, is `layer`.
+    /// The others are don't-cares.
+    fn patch_on(
+        &self,
+        patch: &Grid<Option<T>, S, S>,
+        layer: usize,
+        layer_of: fn(&CellLayers) -> usize,
+    ) -> Grid<Option<T>, S, S>
+    where
+        T: Copy,
+    {
+        let mut on_layer = Grid {
+            items: [[None; S]; S],
+        };
+        for (y, row) in patch.items.iter().enumerate() {
+            for (x, item) in row.iter().enumerate() {
+                if layer_of(&self.layers.items[y][x]) == layer {
+                    on_layer.items[y][x] = *item;
+                }
+            }
+        }
+        on_layer
+    }
+
+    /// The find patch's cells matched on `layer`
+    pub fn find_on(&self, layer: usize) -> Grid<Option<T>, S, S>
+    where
+        T: Copy,
+    {
+        self.patch_on(&self.find, layer, |layers| layers.read)
+    }
+
+    /// The replace patch's cells written to `layer`
+    pub fn replace_on(&self, layer: usize) -> Grid<Option<T>, S, S>
+    where
+        T: Copy,
+    {
+        self.patch_on(&self.replace, layer, |layers| layers.write)
+    }
+
+    /// find_on the simulation's grid, borrowing the find patch when the rule has no other layers
+    pub fn grid_find(&self) -> Cow<'_, Grid<Option<T>, S, S>>
+    where
+        T: Copy,
+    {
+        if self.is_layered() {
+            Cow::Owned(self.find_on(0))
+        } else {
+            Cow::Borrowed(&self.find)
+        }
+    }
+
+    /// replace_on the simulation's grid, see grid_find
+    pub fn grid_replace(&self) -> Cow<'_, Grid<Option<T>, S, S>>
+    where
+        T: Copy,
+    {
+        if self.is_layered() {
+            Cow::Owned(self.replace_on(0))
+        } else {
+            Cow::Borrowed(&self.replace)
+        }
+    }
+
+    /// Layers other than the simulation's grid which the rule reads or writes
+    fn other_layers(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut layers = self
+            .layers
+            .items
+            .iter()
+            .flatten()
+            .flat_map(|layers| [layers.read, layers.write])
+            .filter(|&layer| layer != 0)
+            .collect::<Vec<_>>();
+        layers.sort_unstable();
+        layers.dedup();
+        layers.into_iter()
+    }
+
+    /// Whether the cells matched on other layers than the grid match at `orientation`. `layers`
+    /// are layers 1 and up, a rule reading or writing a layer which isn't there never matches.
+    pub fn layers_match_at<const W: usize, const H: usize>(
+        &self,
+        layers: &[Grid<T, W, H>],
+        orientation: &PatchOrientation,
+    ) -> bool
+    where
+        T: Eq + Copy,
+    {
+        let (x, y) = orientation.position;
+        self.other_layers().all(|layer| {
+            layers.get(layer - 1).is_some_and(|grid| {
+                let find = self
+                    .find_on(layer)
+                    .orient(orientation.rotation_times, orientation.reflected);
+                grid.check_bounded_patch_at(&find, x, y, &self.boundaries)
+            })
+        })
+    }
+
+    /// Write the cells written to other layers than the grid at `orientation`, see
+    /// layers_match_at
+    pub fn replace_layers_at<const W: usize, const H: usize>(
+        &self,
+        layers: &mut [Grid<T, W, H>],
+        orientation: &PatchOrientation,
+    ) where
+        T: Eq + Copy,
+    {
+        for layer in self.other_layers() {
+            if let Some(grid) = layers.get_mut(layer - 1) {
+                grid.replace_bounded_at(&self.replace_on(layer), orientation, &self.boundaries);
+            }
+        }
+    }
+
+    /// Whether the find patch's cells on the grid match at `orientation`, including the
+    /// neighbour counts. Cells on other layers are checked by layers_match_at.
+    pub fn matches_at<const W: usize, const H: usize>(
+        &self,
+        grid: &Grid<T, W, H>,
+        orientation: &PatchOrientation,
+    ) -> bool
+    where
+        T: Eq + Copy,
+    {
+        let (x, y) = orientation.position;
+        let find = self
+            .grid_find()
+            .orient(orientation.rotation_times, orientation.reflected);
+        grid.check_bounded_patch_at(&find, x, y, &self.boundaries)
+            && self.neighbour_counts_hold(grid, orientation)
+    }
+
+    fn neighbour_counts_hold<const W: usize, const H: usize>(
+        &self,
+        grid: &Grid<T, W, H>,
+        orientation: &PatchOrientation,
+    ) -> bool
+    where
+        T: PartialEq,
+    {
+        self.neighbour_counts
+            .iter()
+            .all(|neighbour_count| neighbour_count.holds(grid, S, orientation, &self.boundaries))
+    }
+
+    /// Whether every field guard holds at `orientation`
+    pub fn field_guards_hold<const W: usize, const H: usize>(
+        &self,
+        fields: &Fields<W, H>,
+        orientation: &PatchOrientation,
+    ) -> bool {
+        self.field_guards
+            .iter()
+            .all(|field_guard| field_guard.holds(fields, S, orientation, &self.boundaries))
+    }
+
+    /// For each orientation (by PatchOrientation::index), the first orientation which has
+    /// exactly the same effect, ie. the same oriented find and replace patches and cell layers,
+    /// random cells, neighbour counts and field guards. Symmetric rules would otherwise match the
+    /// same place several times, making them more likely to be picked.
+    pub fn equivalent_orientations(&self) -> [usize; 8]
+    where
+        T: Eq + Copy,
+    {
+        self.orientation_classes().map(|(class, _)| class)
+    }
+
+    /// equivalent_orientations, plus where each orientation's cells start within the patch.
+    /// Patches are compared with their don't-care margins trimmed, so a small rule embedded in
+    /// a larger patch (see embed) is the same in every orientation which only moves it around
+    /// the patch; a match at `position` then affects the cells from `position + offset`.
+    fn orientation_classes(&self) -> [(usize, (isize, isize)); 8]
+    where
+        T: Eq + Copy,
+    {
+        let trimmed = |index: usize| {
+            let (rotation_times, reflected) = (index % 4, index >= 4);
+            let find = self.find.orient(rotation_times, reflected);
+            let replace = self.replace.orient(rotation_times, reflected);
+            let layers = self.layers.orient(rotation_times, reflected);
+            let mut cells = Vec::new();
+            for y in 0..S {
+                for x in 0..S {
+                    let (find, replace) = (find.items[y][x], replace.items[y][x]);
+                    if find.is_some() || replace.is_some() {
+                        cells.push(((x, y), (find, replace, layers.items[y][x])));
+                    }
+                }
+            }
+            let positions = self
+                .random_cells
+                .iter()
+                .map(|cell| cell.position)
+                .chain(self.neighbour_counts.iter().map(|count| count.position))
+                .chain(self.field_guards.iter().map(|guard| guard.position))
+                .map(|position| orient_position(position, rotation_times, reflected, S))
+                .collect::<Vec<_>>();
+            let corner = cells
+                .iter()
+                .map(|(position, _)| *position)
+                .chain(positions.iter().copied())
+                .fold(None, |corner: Option<(usize, usize)>, (x, y)| {
+                    Some(corner.map_or((x, y), |(cx, cy)| (cx.min(x), cy.min(y))))
+                })
+                .unwrap_or((0, 0));
+            let shift = |(x, y): (usize, usize)| (x - corner.0, y - corner.1);
+            let cells = cells
+                .into_iter()
+                .map(|(position, cell)| (shift(position), cell))
+                .collect::<Vec<_>>();
+            let positions = positions.into_iter().map(shift).collect::<Vec<_>>();
+            ((cells, positions), (corner.0 as isize, corner.1 as isize))
+        };
+        let orientations = [0, 1, 2, 3, 4, 5, 6, 7].map(trimmed);
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|index| {
+            let class = (0..index)
+                .find(|&earlier| orientations[earlier].0 == orientations[index].0)
+                .unwrap_or(index);
+            (class, orientations[index].1)
+        })
+    }
+
+    /// Whether the rule is active and its guards and conditions all hold, so its matches should
+    /// be considered
+    pub fn enabled(&self, context: &Context<T>) -> bool {
+        self.active
+            && self
+                .guards
+                .iter()
+                .all(|guard| guard.holds(context.counters))
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.holds(context))
+    }
+}
+
+impl<T: Eq + Copy, const W: usize, const H: usize> Grid<T, W, H> {
+    pub fn check_patch_at<const S: usize>(
+        &self,
+        patch: &Grid<Option<T>, S, S>,
+        offset_x: isize,
+        offset_y: isize,
+    ) -> bool {
+        self.check_bounded_patch_at(patch, offset_x, offset_y, &Boundaries::CLAMP)
+    }
+
+    /// Like check_patch_at, but cells beyond the edges are read through the boundaries
+    pub fn check_bounded_patch_at<const S: usize>(
+        &self,
+        patch: &Grid<Option<T>, S, S>,
+        offset_x: isize,
+        offset_y: isize,
+        boundaries: &Boundaries,
+    ) -> bool {
+        // a patch inside the grid is compared through a view of the grid under it, without
+        // resolving every cell
+        let inside = offset_x >= 0
+            && offset_y >= 0
+            && offset_x as usize + S <= W
+            && offset_y as usize + S <= H;
+        if inside {
+            return self
+                .sub_view((offset_x as usize, offset_y as usize), (S, S))
+                .fits(patch);
+        }
+        for (patch_y, row) in patch.items.iter().enumerate() {
+            'inner: for (patch_x, item) in row.iter().enumerate() {
+                match item {
+                    // None is a 'dont care' value and matches anything
+                    None => continue 'inner,
+                    Some(item) => {
+                        let grid_x = patch_x as isize + offset_x;
+                        let grid_y = patch_y as isize + offset_y;
+                        // patch has a value but there is no cell there, BAD!
+                        let Some((grid_x, grid_y)) = boundaries.resolve((grid_x, grid_y), W, H)
+                        else {
+                            return false;
+                        };
+                        let grid_item = &self.items[grid_y][grid_x];
+                        // if _any_ items fail to match, the whole patch fails
+                        if grid_item != item {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Matches in every rotation, anywhere in the grid
+    pub fn get_patch_matches<const S: usize>(
+        &self,
+        patch: &Grid<Option<T>, S, S>,
+    ) -> Vec<PatchOrientation> {
+        self.get_placed_matches(
+            patch,
+            &Placement::Anywhere,
+            Symmetry::Rotations,
+            &Boundaries::CLAMP,
+        )
+    }
+
+    /// Matches in each of the symmetry's orientations whose origin (see `placement`) is allowed
+    /// by the placement
+    pub fn get_placed_matches<const S: usize>(
+        &self,
+        patch: &Grid<Option<T>, S, S>,
+        placement: &Placement,
+        symmetry: Symmetry,
+        boundaries: &Boundaries,
+    ) -> Vec<PatchOrientation> {
+        let mut matches = Vec::new();
+        for &(rotation_times, reflected) in symmetry.orientations() {
+            self.push_oriented_matches(
+                &patch.orient(rotation_times, reflected),
+                (rotation_times, reflected),
+                placement,
+                boundaries,
+                &mut matches,
+            );
+        }
+        matches
+    }
+
+    /// Check an already oriented patch at every offset where it overlaps the grid and the
+    /// placement allows it. The placement is checked first since it is much cheaper than the
+    /// patch. `orientation` is (rotation_times, reflected). Positions on wrapped axes are always
+    /// inside the grid.
+    pub fn push_oriented_matches<const S: usize>(
+        &self,
+        oriented_patch: &Grid<Option<T>, S, S>,
+        orientation: (usize, bool),
+        placement: &Placement,
+        boundaries: &Boundaries,
+        matches: &mut Vec<PatchOrientation>,
+    ) {
+        self.push_matches_where::<S>(orientation, placement, boundaries, matches, |x, y| {
+            self.check_bounded_patch_at(oriented_patch, x, y, boundaries)
+        });
+    }
+
+    /// Like push_oriented_matches, but an S x S patch's offsets are checked by `check`, for
+    /// strategies which compare the cells their own way. Offsets passed to it are normalized.
+    pub fn push_matches_where<const S: usize>(
+        &self,
+        (rotation_times, reflected): (usize, bool),
+        placement: &Placement,
+        boundaries: &Boundaries,
+        matches: &mut Vec<PatchOrientation>,
+        mut is_match: impl FnMut(isize, isize) -> bool,
+    ) {
+        let (origin_x, origin_y) = placement::origin_in_patch(rotation_times, reflected, S);
+        let mut check = |offset_x: isize, offset_y: isize| {
+            let (offset_x, offset_y) = boundaries.normalize((offset_x, offset_y), W, H);
+            if is_match(offset_x, offset_y) {
+                matches.push(PatchOrientation {
+                    rotation_times,
+                    reflected,
+                    position: (offset_x, offset_y),
+                });
+            }
+        };
+        match placement {
+            // only visit the listed cells rather than filtering every offset
+            Placement::Cells(cells) => {
+                for &(x, y) in cells {
+                    check(x - origin_x, y - origin_y);
+                }
+            }
+            _ => {
+                for offset_x in boundaries.x.offsets(W, S) {
+                    for offset_y in boundaries.y.offsets(H, S) {
+                        let origin =
+                            boundaries.normalize((offset_x + origin_x, offset_y + origin_y), W, H);
+                        if placement.allows(origin, W, H) {
+                            check(offset_x, offset_y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn replace_at<const S: usize>(
+        &mut self,
+        replacement_patch: &Grid<Option<T>, S, S>,
+        orientation: &PatchOrientation,
+    ) {
+        self.replace_bounded_at(replacement_patch, orientation, &Boundaries::CLAMP);
+    }
+
+    /// Like replace_at, but cells beyond the edges are written through the boundaries. Cells
+    /// with nowhere to go are dropped.
+    pub fn replace_bounded_at<const S: usize>(
+        &mut self,
+        replacement_patch: &Grid<Option<T>, S, S>,
+        orientation: &PatchOrientation,
+        boundaries: &Boundaries,
+    ) {
+        let oriented = replacement_patch.orient(orientation.rotation_times, orientation.reflected);
+        // TODO abstract 2d iteration out of Grid
+        for (y, row) in oriented.items.iter().enumerate() {
+            for (x, item) in row.iter().enumerate() {
+                let at = (
+                    x as isize + orientation.position.0,
+                    y as isize + orientation.position.1,
+                );
+                if let (Some(item), Some((x, y))) = (item, boundaries.resolve_write(at, W, H)) {
+                    self.items[y][x] = *item;
+                }
+            }
+        }
+    }
+
+    /// Every match of the rule on the grid: the matcher's matches of the find patch's grid cells
+    /// in the rule's symmetry, filtered by the rule's neighbour counts. Orientations of a
+    /// symmetric rule which land in the same place only count once.
+    pub fn get_rule_matches<M, const S: usize>(
+        &self,
+        rule: &ReplacementRule<T, S>,
+        matcher: &mut M,
+    ) -> Vec<PatchOrientation>
+    where
+        M: MatchStrategy<T, W, H, S> + ?Sized,
+    {
+        let mut matches = matcher.find_matches(
+            self,
+            &rule.grid_find(),
+            &rule.placement,
+            rule.symmetry,
+            &rule.boundaries,
+        );
+        if !rule.neighbour_counts.is_empty() {
+            matches.retain(|orientation| rule.neighbour_counts_hold(self, orientation));
+        }
+        let classes = rule.orientation_classes();
+        if classes
+            .iter()
+            .enumerate()
+            .any(|(index, (class, _))| *class != index)
+        {
+            // count each distinct placement once, whichever orientation found it first
+            let mut seen = HashSet::new();
+            matches.retain(|orientation| {
+                let (class, (dx, dy)) = classes[orientation.index()];
+                let (x, y) = orientation.position;
+                let start = rule.boundaries.normalize((x + dx, y + dy), W, H);
+                seen.insert((class, start))
+            });
+        }
+        matches
+    }
+
+    /// Apply the rule at one of its matches, chosen at random. Returns where it was applied, or
+    /// None if there were no matches. Field guards see every field as 0.
+    pub fn single_random_replace<M, R, const S: usize>(
+        &mut self,
+        rule: &ReplacementRule<T, S>,
+        matcher: &mut M,
+        rng: &mut R,
+    ) -> Option<PatchOrientation>
+    where
+        M: MatchStrategy<T, W, H, S> + ?Sized,
+        R: Rng + ?Sized,
+    {
+        self.timed_random_replace(rule, &Fields::default(), &mut [], &[], matcher, rng, None)
+    }
+
+    /// single_random_replace with field guards checked against `fields`, cells on other layers
+    /// matched on and written to `layers` (see layers_match_at) and matches skipped unless every
+    /// constraint allows them, adding the time spent finding and applying matches to `timings`.
+    /// Constraints only see the changes to the grid.
+    #[allow(clippy::too_many_arguments)]
+    fn timed_random_replace<M, R, const S: usize>(
+        &mut self,
+        rule: &ReplacementRule<T, S>,
+        fields: &Fields<W, H>,
+        layers: &mut [Grid<T, W, H>],
+        constraints: &[Box<dyn Constraint<T, W, H>>],
+        matcher: &mut M,
+        rng: &mut R,
+        mut timings: Option<&mut RuleTimings>,
+    ) -> Option<PatchOrientation>
+    where
+        M: MatchStrategy<T, W, H, S> + ?Sized,
+        R: Rng + ?Sized,
+    {
+        let started = timings.is_some().then(Instant::now);
+        let matches = {
+            let _span = trace_span!("match").entered();
+            let mut matches = self.get_rule_matches(rule, matcher);
+            if !rule.field_guards.is_empty() {
+                matches.retain(|orientation| rule.field_guards_hold(fields, orientation));
+            }
+            if rule.is_layered() {
+                matches.retain(|orientation| rule.layers_match_at(layers, orientation));
+            }
+            matches
+        };
+        trace!(matches = matches.len());
+        if let (Some(timings), Some(started)) = (timings.as_deref_mut(), started) {
+            timings.scan += started.elapsed();
+            timings.scans += 1;
+        }
+        if matches.is_empty() {
+            return None;
+        }
+        let started = timings.is_some().then(Instant::now);
+        let _span = trace_span!("apply").entered();
+        let chosen_match = if constraints.is_empty() {
+            apply_random_match(&matches, rng, |chosen_match, rng| {
+                self.replace_bounded_at(&rule.grid_replace(), chosen_match, &rule.boundaries);
+                for ((x, y), tile) in Self::sample_random_cells(rule, chosen_match, rng) {
+                    self.items[y][x] = tile;
+                }
+            })?
+        } else {
+            self.constrained_replace(rule, matches, constraints, rng)?
+        };
+        rule.replace_layers_at(layers, &chosen_match);
+        matcher.cells_changed(self, &Self::footprint::<S>(&rule.boundaries, &chosen_match));
+        if let (Some(timings), Some(started)) = (timings, started) {
+            timings.apply += started.elapsed();
+            timings.applications += 1;
+        }
+        Some(chosen_match)
+    }
+
+    /// Apply the rule at one of `matches` whose changes, random cells included, every constraint
+    /// allows, trying them in random order. Returns None if every match was rejected.
+    fn constrained_replace<R: Rng + ?Sized, const S: usize>(
+        &mut self,
+        rule: &ReplacementRule<T, S>,
+        mut matches: Vec<PatchOrientation>,
+        constraints: &[Box<dyn Constraint<T, W, H>>],
+        rng: &mut R,
+    ) -> Option<PatchOrientation> {
+        while !matches.is_empty() {
+            let chosen_match = matches.swap_remove(determinism::index(rng, matches.len()));
+            let mut changes = Self::replace_changes(rule, &chosen_match);
+            changes.extend(Self::sample_random_cells(rule, &chosen_match, rng));
+            if constraints
+                .iter()
+                .all(|constraint| constraint.allows(self, &changes))
+            {
+                for ((x, y), tile) in changes {
+                    self.items[y][x] = tile;
+                }
+                return Some(chosen_match);
+            }
+            trace!(?chosen_match, "rejected by a constraint");
+        }
+        None
+    }
+
+    /// Every cell an S x S patch at `orientation` may write, ie. the cells it covers inside the
+    /// grid once the boundaries are applied
+    pub fn footprint<const S: usize>(
+        boundaries: &Boundaries,
+        orientation: &PatchOrientation,
+    ) -> Vec<(usize, usize)> {
+        let (x, y) = orientation.position;
+        (0..S as isize)
+            .flat_map(|dy| {
+                (0..S as isize)
+                    .filter_map(move |dx| boundaries.resolve_write((x + dx, y + dy), W, H))
+            })
+            .collect()
+    }
+
+    /// Cells of the grid written by the rule's replace patch at `orientation`, with their new
+    /// tiles, in the order replace_bounded_at writes them. Random cells are left out.
+    pub fn replace_changes<const S: usize>(
+        rule: &ReplacementRule<T, S>,
+        orientation: &PatchOrientation,
+    ) -> Vec<((usize, usize), T)> {
+        let oriented = rule
+            .grid_replace()
+            .orient(orientation.rotation_times, orientation.reflected);
+        let mut changes = Vec::new();
+        for (y, row) in oriented.items.iter().enumerate() {
+            for (x, item) in row.iter().enumerate() {
+                let at = (
+                    x as isize + orientation.position.0,
+                    y as isize + orientation.position.1,
+                );
+                if let (Some(item), Some(at)) = (item, rule.boundaries.resolve_write(at, W, H)) {
+                    changes.push((at, *item));
+                }
+            }
+        }
+        changes
+    }
+
+    /// Cells written by the rule's random cells at `orientation`, with their sampled tiles
+    fn sample_random_cells<R: Rng + ?Sized, const S: usize>(
+        rule: &ReplacementRule<T, S>,
+        orientation: &PatchOrientation,
+        rng: &mut R,
+    ) -> Vec<((usize, usize), T)> {
+        let mut cells = Vec::new();
+        for cell in rule.random_cells.iter() {
+            let (x, y) = orient_position(
+                cell.position,
+                orientation.rotation_times,
+                orientation.reflected,
+                S,
+            );
+            let at = (
+                x as isize + orientation.position.0,
+                y as isize + orientation.position.1,
+            );
+            let Some((x, y)) = rule.boundaries.resolve_write(at, W, H) else {
+                continue;
+            };
+            // all zero weights leave the replace patch's cell
+            if let Ok((tile, _)) = cell.choices.choose_weighted(rng, |&(_, weight)| weight) {
+                cells.push(((x, y), *tile));
+            }
+        }
+        cells
+    }
+
+    /// Apply the first rule in the list which has any matches. Returns the index of the rule and
+    /// where it was applied, or None if no rule matched. Field guards see every field as 0.
+    pub fn priority_random_repace<M, R, const S: usize>(
+        &mut self,
+        rules: &[ReplacementRule<T, S>],
+        counters: &mut Counters,
+        steps: usize,
+        matcher: &mut M,
+        rng: &mut R,
+    ) -> Option<(usize, PatchOrientation)>
+    where
+        M: MatchStrategy<T, W, H, S> + ?Sized,
+        R: Rng,
+    {
+        self.scheduled_random_replace(
+            rules,
+            counters,
+            &Fields::default(),
+            &mut [],
+            &[],
+            steps,
+            &mut Priority,
+            matcher,
+            rng,
+            None,
+        )
+    }
+
+    /// Apply the first rule, in the order given by the scheduler, which has any matches and whose
+    /// guards and conditions hold. The applied rule's effects are applied to the counters, its
+    /// field guards are checked against `fields` and its cells on other layers against `layers`,
+    /// layer 1 first. Matches are skipped unless every constraint allows them. `steps` is the
+    /// number of steps so far, as seen by conditions. Returns the index of the rule and where it
+    /// was applied, or None if no rule matched. Time spent on each rule is added to `profile` if
+    /// given.
+    #[allow(clippy::too_many_arguments)]
+    pub fn scheduled_random_replace<C, M, R, const S: usize>(
+        &mut self,
+        rules: &[ReplacementRule<T, S>],
+        counters: &mut Counters,
+        fields: &Fields<W, H>,
+        layers: &mut [Grid<T, W, H>],
+        constraints: &[Box<dyn Constraint<T, W, H>>],
+        steps: usize,
+        scheduler: &mut C,
+        matcher: &mut M,
+        rng: &mut R,
+        mut profile: Option<&mut Profile>,
+    ) -> Option<(usize, PatchOrientation)>
+    where
+        C: Scheduler + ?Sized,
+        M: MatchStrategy<T, W, H, S> + ?Sized,
+        R: Rng,
+    {
+        let order = scheduler.order(rules.len(), rng);
+        let applied = order.into_iter().find_map(|rule_id| {
+            // one span per rule tried, so timings can be broken down by rule
+            let _span = trace_span!("rule", rule_id).entered();
+            if !rules[rule_id].enabled(&Context::new(self, steps, counters)) {
+                return None;
+            }
+            let timings = profile
+                .as_deref_mut()
+                .map(|profile| profile.rule_mut(rule_id));
+            self.timed_random_replace(
+                &rules[rule_id],
+                fields,
+                layers,
+                constraints,
+                matcher,
+                rng,
+                timings,
+            )
+            .map(|orientation| (rule_id, orientation))
+        });
+        if let Some((rule_id, _)) = applied {
+            for effect in rules[rule_id].effects.iter() {
+                effect.apply(counters);
+            }
+            scheduler.applied(rule_id);
+        }
+        applied
+    }
+}
+
+/// Apply one of `matches`, chosen at random, with `apply`. Returns the chosen match, or None if
+/// there were no matches. Shared by every lattice, so they all choose matches the same way.
+pub fn apply_random_match<M: Copy, R: Rng + ?Sized>(
+    matches: &[M],
+    rng: &mut R,
+    apply: impl FnOnce(&M, &mut R),
+) -> Option<M> {
+    if matches.is_empty() {
+        return None;
+    }
+    let chosen_match = matches[determinism::index(rng, matches.len())];
+    apply(&chosen_match, rng);
+    Some(chosen_match)
+}
+
+/// Rules on lattices other than the square grid, eg. hex::HexGrid. A lattice only finds and
+/// applies matches, choosing one at random and trying rules in order is shared.
+pub trait RandomReplace<Rule> {
+    /// Where a rule was placed
+    type Match: Copy;
+
+    fn rule_matches(&self, rule: &Rule) -> Vec<Self::Match>;
+
+    fn apply_match(&mut self, rule: &Rule, at: &Self::Match);
+
+    /// Apply the rule at one of its matches, chosen at random. Returns where it was applied, or
+    /// None if there were no matches.
+    fn single_random_replace<R: Rng + ?Sized>(
+        &mut self,
+        rule: &Rule,
+        rng: &mut R,
+    ) -> Option<Self::Match> {
+        let matches = self.rule_matches(rule);
+        apply_random_match(&matches, rng, |at, _| self.apply_match(rule, at))
+    }
+
+    /// Apply the first rule in the list which has any matches. Returns the index of the rule and
+    /// where it was applied, or None if no rule matched.
+    fn priority_random_replace<R: Rng + ?Sized>(
+        &mut self,
+        rules: &[Rule],
+        rng: &mut R,
+    ) -> Option<(usize, Self::Match)> {
+        rules.iter().enumerate().find_map(|(rule_id, rule)| {
+            self.single_random_replace(rule, rng)
+                .map(|at| (rule_id, at))
+        })
+    }
+}
+
+/// Where (x, y) in an S x S patch ends up after rotating the patch, see Grid::rotate
+pub fn rotate_position((x, y): (usize, usize), times: usize, size: usize) -> (usize, usize) {
+    (x, y).rotated(times, (size, size))
+}
+
+/// Where (x, y) in an S x S patch ends up after orienting the patch, see Grid::orient
+pub fn orient_position(
+    (x, y): (usize, usize),
+    rotation_times: usize,
+    reflected: bool,
+    size: usize,
+) -> (usize, usize) {
+    let x = if reflected { size - 1 - x } else { x };
+    rotate_position((x, y), rotation_times, size)
+}
+
+/// Rotation only implemented for square grids (W==H)
+impl<T: Default + Copy, const S: usize> Grid<T, S, S> {
+    /// x_transform: lambda of (old_x, old_y, size) -> new_x
+    /// y_transform: lambda of (old_x, old_y, size) -> new_y
+    fn transform_indices<R1, R2>(&self, x_transform: R1, y_transform: R2) -> Self
+    where
+        R1: Fn(usize, usize, usize) -> usize,
+        R2: Fn(usize, usize, usize) -> usize,
+    {
+        let mut ret: Self = Default::default();
+        self.items.iter().enumerate().for_each(|(y, row)| {
+            row.iter().enumerate().for_each(|(x, item)| {
+                let new_x = x_transform(x, y, S);
+                let new_y = y_transform(x, y, S);
+                ret.items[new_y][new_x] = *item;
+            })
+        });
+        ret
+    }
+
+    pub fn rotate(&self, times: usize) -> Self {
+        match times {
+            // 0 degrees (no-op)
+            0 => self.transform_indices(|x, _, _| x, |_, y, _| y),
+            // 90 degrees
+            1 => self.transform_indices(|_, y, size| size - 1 - y, |x, _, _| x),
+            // 180 degrees
+            2 => self.transform_indices(|x, _, size| size - 1 - x, |_, y, size| size - 1 - y),
+            // 270 degrees
+            3 => self.transform_indices(|_, y, _| y, |x, _, size| size - 1 - x),
+            // else
+            n => self.rotate(n % 4),
+        }
+    }
+
+    /// Mirror left to right
+    pub fn reflect(&self) -> Self {
+        self.transform_indices(|x, _, size| size - 1 - x, |_, y, _| y)
+    }
+
+    /// Mirror left to right if `reflected`, then rotate
+    pub fn orient(&self, rotation_times: usize, reflected: bool) -> Self {
+        if reflected {
+            self.reflect().rotate(rotation_times)
+        } else {
+            self.rotate(rotation_times)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::boundary::Boundary;
+
+    fn grid<T: Strategy, const W: usize, const H: usize>(
+        item: T,
+    ) -> impl Strategy<Value = Grid<T::Value, W, H>>
+    where
+        T::Value: Copy,
+    {
+        prop::array::uniform(prop::array::uniform(item)).prop_map(|items| Grid { items })
+    }
+
+    fn boundaries() -> impl Strategy<Value = Boundaries> {
+        let boundary =
+            || prop::sample::select(vec![Boundary::Clamp, Boundary::Wrap, Boundary::Mirror]);
+        (boundary(), boundary()).prop_map(|(x, y)| Boundaries::new(x, y))
+    }
+
+    fn orientations_cycle<const S: usize>(grid: &Grid<u8, S, S>) -> Result<(), TestCaseError> {
+        prop_assert_eq!(&grid.rotate(4), grid);
+        prop_assert_eq!(&grid.rotate(1).rotate(3), grid);
+        prop_assert_eq!(&grid.reflect().reflect(), grid);
+        for (rotation_times, reflected) in Symmetry::All.orientations().iter().copied() {
+            let oriented = grid.orient(rotation_times, reflected);
+            for (y, row) in grid.items.iter().enumerate() {
+                for (x, item) in row.iter().enumerate() {
+                    let (to_x, to_y) = orient_position((x, y), rotation_times, reflected, S);
+                    prop_assert_eq!(oriented.items[to_y][to_x], *item);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn orientations_cycle_1(grid in grid::<_, 1, 1>(0..4u8)) {
+            orientations_cycle(&grid)?;
+        }
+
+        #[test]
+        fn orientations_cycle_2(grid in grid::<_, 2, 2>(0..4u8)) {
+            orientations_cycle(&grid)?;
+        }
+
+        #[test]
+        fn orientations_cycle_3(grid in grid::<_, 3, 3>(0..4u8)) {
+            orientations_cycle(&grid)?;
+        }
+
+        #[test]
+        fn orientations_cycle_6(grid in grid::<_, 6, 6>(0..4u8)) {
+            orientations_cycle(&grid)?;
+        }
+
+        #[test]
+        fn replace_after_match_stays_in_bounds(
+            mut target in grid::<_, 7, 5>(0..3u8),
+            mask in grid::<_, 3, 3>(any::<bool>()),
+            replace in grid::<_, 3, 3>(prop::option::of(3..6u8)),
+            position in (-4..9isize, -4..7isize),
+            rotation_times in 0..4usize,
+            reflected in any::<bool>(),
+            boundaries in boundaries(),
+        ) {
+            // build a find patch matching the target at position: masked cells take the tile
+            // under them, if there is one
+            let mut find = Grid::<Option<u8>, 3, 3>::default();
+            for (y, row) in find.items.iter_mut().enumerate() {
+                for (x, item) in row.iter_mut().enumerate() {
+                    let at = (x as isize + position.0, y as isize + position.1);
+                    let resolved = boundaries.resolve(at, 7, 5);
+                    if let (true, Some((grid_x, grid_y))) = (mask.items[y][x], resolved) {
+                        *item = Some(target.items[grid_y][grid_x]);
+                    }
+                }
+            }
+            prop_assert!(target.check_bounded_patch_at(&find, position.0, position.1, &boundaries));
+
+            let orientation = PatchOrientation { rotation_times, reflected, position };
+            target.replace_bounded_at(&replace, &orientation, &boundaries);
+
+            let oriented = replace.orient(rotation_times, reflected);
+            let fully_specified = find.items.iter().flatten().all(Option::is_some);
+            for (y, row) in oriented.items.iter().enumerate() {
+                for (x, item) in row.iter().enumerate() {
+                    let Some(item) = item else { continue };
+                    let at = (x as isize + position.0, y as isize + position.1);
+                    match boundaries.resolve_write(at, 7, 5) {
+                        Some((x, y)) => prop_assert_eq!(target.items[y][x], *item),
+                        // a fully specified find patch only matches with every cell on the grid
+                        None => prop_assert!(!fully_specified || boundaries != Boundaries::CLAMP),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -7,8 +7,9 @@ type AxisId = usize;
 /// A transformed axis is one that is derived from another axis (input_axis) and is optionally
 /// negated
 pub struct TransformedAxis {
-    input_axis: usize,
-    negated: bool,
+    pub input_axis: usize,
+    /// Coordinates along the axis count down from the far side instead of up
+    pub negated: bool,
 }
 
 #[derive(Debug)]
@@ -47,7 +48,7 @@ pub fn rotation_permutations(dimension: usize) -> Vec<RotationConfiguration> {
             }
         })
         // Expand each permutation to every possible axis negation scenario
-        .flat_map(|permutation| enumerate_negations(permutation))
+        .flat_map(enumerate_negations)
         .collect()
 }
 
@@ -64,25 +65,19 @@ fn is_permutation(arr: &[AxisId]) -> bool {
 pub fn parity(arr: &[AxisId]) -> bool {
     let mut parity = false;
     let mut visited = vec![false; arr.len()];
-    loop {
-        // first non-visited node
-        match visited.iter().position(|&x| !x) {
-            Some(first) => {
-                let mut idx = first;
-
-                let mut cycle_count = 0;
-                // mark first in cycle as visited
-                while !visited[idx] {
-                    visited[idx] = true;
-                    idx = arr[idx];
-                    cycle_count += 1;
-                }
-                // finshed a cycle, factor in parity
-                parity ^= (cycle_count - 1) % 2 == 1;
-            }
-            // have visited every one
-            None => break,
+    // first non-visited node, until every one has been visited
+    while let Some(first) = visited.iter().position(|&x| !x) {
+        let mut idx = first;
+
+        let mut cycle_count = 0;
+        // mark first in cycle as visited
+        while !visited[idx] {
+            visited[idx] = true;
+            idx = arr[idx];
+            cycle_count += 1;
         }
+        // finshed a cycle, factor in parity
+        parity ^= (cycle_count - 1) % 2 == 1;
     }
     parity
 }
@@ -129,3 +124,68 @@ fn enumerate_negations(permutation: AxisPermutation) -> Vec<RotationConfiguratio
 fn bit(n: u32, index: u32) -> bool {
     n & (1 << index) != 0
 }
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::coord::Coord as _;
+    use crate::ndcoord::Coord;
+
+    /// Rotate `at` inside a hypercube with sides of length `size`
+    fn rotate<const D: usize>(
+        at: &Coord<D>,
+        configuration: &RotationConfiguration,
+        size: isize,
+    ) -> Coord<D> {
+        let mut axes = [0; D];
+        for (axis, transformed) in axes.iter_mut().zip(configuration) {
+            let value = at.axes()[transformed.input_axis];
+            *axis = if transformed.negated {
+                size - 1 - value
+            } else {
+                value
+            };
+        }
+        Coord::new(axes)
+    }
+
+    #[test]
+    fn rotation_counts() {
+        assert_eq!(rotation_permutations(1).len(), 1);
+        assert_eq!(rotation_permutations(2).len(), 4);
+        assert_eq!(rotation_permutations(3).len(), 24);
+        assert_eq!(rotation_permutations(4).len(), 192);
+    }
+
+    proptest! {
+        #[test]
+        fn square_rotations_agree_with_coord(size in 1..16usize, x in 0..16usize, y in 0..16usize) {
+            let (x, y) = (x % size, y % size);
+            let at = Coord::new_2d(x as isize, y as isize);
+            let mut nd = rotation_permutations(2)
+                .iter()
+                .map(|configuration| {
+                    let [x, y] = *rotate(&at, configuration, size as isize).axes();
+                    (x as usize, y as usize)
+                })
+                .collect::<Vec<_>>();
+            let mut planar = (0..4)
+                .map(|times| (x, y).rotated(times, (size, size)))
+                .collect::<Vec<_>>();
+            nd.sort();
+            planar.sort();
+            prop_assert_eq!(nd, planar);
+        }
+
+        #[test]
+        fn rotations_stay_inside(size in 1..8isize, axes in prop::array::uniform3(0..8isize)) {
+            let at = Coord::new(axes.map(|ax| ax % size));
+            let bounds = Coord::new([size; 3]);
+            for configuration in rotation_permutations(3) {
+                prop_assert!(rotate(&at, &configuration, size).is_within(&bounds));
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Filling a grid, or part of it, with tiles drawn from a weighted distribution. Most models
+//! start by scattering a few seeds for their rules to grow from, see the mix directive in
+//! model_file.
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+use crate::node::Node;
+use crate::rewrite::Grid;
+
+/// Where tiles are drawn from the distribution independently for every cell, or from smooth 2D
+/// value noise so that neighbouring cells tend to get the same tile
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sampling {
+    #[default]
+    Independent,
+    /// `scale` is the distance in cells between noise lattice points, larger gives bigger blobs.
+    /// Noise values bunch up around the middle of the distribution, so weights are only followed
+    /// roughly.
+    ValueNoise { scale: f64 },
+}
+
+/// Node which fills cells with tiles drawn from `choices`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scatter<T> {
+    /// (tile, weight) pairs, weights don't need to add up to anything in particular
+    pub choices: Vec<(T, f64)>,
+    /// (x, y, width, height) of the area to fill, clipped to the grid. None fills the whole grid.
+    pub region: Option<(usize, usize, usize, usize)>,
+    /// If set, only cells holding this tile are filled, eg. to scatter seeds over a background
+    pub only: Option<T>,
+    pub sampling: Sampling,
+}
+
+impl<T> Scatter<T> {
+    pub fn new(choices: Vec<(T, f64)>) -> Self {
+        Self {
+            choices,
+            region: None,
+            only: None,
+            sampling: Sampling::Independent,
+        }
+    }
+
+    pub fn with_region(mut self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        self.region = Some((x, y, width, height));
+        self
+    }
+
+    pub fn only_over(mut self, tile: T) -> Self {
+        self.only = Some(tile);
+        self
+    }
+
+    pub fn with_noise(mut self, scale: f64) -> Self {
+        self.sampling = Sampling::ValueNoise { scale };
+        self
+    }
+
+    /// Choice which `t` in [0, 1) falls into, after laying the weights end to end
+    fn choice_at(&self, t: f64) -> Option<&T> {
+        let total: f64 = self
+            .choices
+            .iter()
+            .map(|&(_, weight)| weight.max(0.0))
+            .sum();
+        let mut remaining = t * total;
+        self.choices
+            .iter()
+            .filter(|&&(_, weight)| weight > 0.0)
+            .find(|&&(_, weight)| {
+                remaining -= weight;
+                remaining < 0.0
+            })
+            .or_else(|| self.choices.iter().rev().find(|&&(_, weight)| weight > 0.0))
+            .map(|(tile, _)| tile)
+    }
+}
+
+impl<T: Copy + PartialEq, const W: usize, const H: usize> Node<T, W, H> for Scatter<T> {
+    fn apply(&mut self, grid: &mut Grid<T, W, H>, rng: &mut dyn RngCore) -> bool {
+        let (x0, y0, width, height) = self.region.unwrap_or((0, 0, W, H));
+        let noise = match self.sampling {
+            Sampling::Independent => None,
+            Sampling::ValueNoise { scale } => Some(ValueNoise {
+                seed: rng.gen(),
+                scale: scale.max(f64::MIN_POSITIVE),
+            }),
+        };
+
+        let mut changed = false;
+        for y in y0..(y0 + height).min(H) {
+            for x in x0..(x0 + width).min(W) {
+                if self.only.is_some_and(|only| grid.items[y][x] != only) {
+                    continue;
+                }
+                let tile = match &noise {
+                    None => self
+                        .choices
+                        .choose_weighted(&mut *rng, |&(_, weight)| weight)
+                        .ok()
+                        .map(|(tile, _)| tile),
+                    Some(noise) => self.choice_at(noise.at(x, y)),
+                };
+                // no choices, or all of them have zero weight
+                let Some(&tile) = tile else {
+                    return changed;
+                };
+                changed |= grid.items[y][x] != tile;
+                grid.items[y][x] = tile;
+            }
+        }
+        changed
+    }
+}
+
+/// Random values on a square lattice, smoothly interpolated in between
+struct ValueNoise {
+    seed: u64,
+    scale: f64,
+}
+
+impl ValueNoise {
+    /// Value in [0, 1) at cell (x, y)
+    fn at(&self, x: usize, y: usize) -> f64 {
+        let fx = x as f64 / self.scale;
+        let fy = y as f64 / self.scale;
+        let (ix, iy) = (fx.floor() as u64, fy.floor() as u64);
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(fx.fract()), smooth(fy.fract()));
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let top = lerp(self.lattice(ix, iy), self.lattice(ix + 1, iy), tx);
+        let bottom = lerp(self.lattice(ix, iy + 1), self.lattice(ix + 1, iy + 1), tx);
+        lerp(top, bottom, ty)
+    }
+
+    fn lattice(&self, x: u64, y: u64) -> f64 {
+        // splitmix64 finalizer
+        let mut z = self.seed
+            ^ x.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ y.wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // top 53 bits, so the result is below 1
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metrics::{components, count};
+    use crate::tile::Tile::{self, Black as B, Green as G, Red as R};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn independent_follows_weights() {
+        let mut grid = Grid::<Tile, 100, 100>::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(Scatter::new(vec![(B, 9.0), (R, 1.0)]).apply(&mut grid, &mut rng));
+        let red = count(&grid, &R);
+        assert!((800..1200).contains(&red), "{red}");
+    }
+
+    #[test]
+    fn region_and_only() {
+        let mut grid = Grid::<Tile, 6, 6>::default();
+        grid.items[1][1] = G;
+        let mut rng = StdRng::seed_from_u64(0);
+        Scatter::new(vec![(R, 1.0)])
+            .with_region(1, 1, 100, 2)
+            .only_over(B)
+            .apply(&mut grid, &mut rng);
+        assert_eq!(count(&grid, &R), 9);
+        assert_eq!(grid.items[1][1], G);
+        assert_eq!(grid.items[0], [B; 6]);
+        assert_eq!(grid.items[3], [B; 6]);
+    }
+
+    #[test]
+    fn zero_weights_change_nothing() {
+        let mut grid = Grid::<Tile, 4, 4>::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(!Scatter::new(vec![(R, 0.0)]).apply(&mut grid, &mut rng));
+        assert!(!Scatter::new(vec![(R, 1.0)])
+            .with_noise(4.0)
+            .only_over(G)
+            .apply(&mut grid, &mut rng));
+    }
+
+    #[test]
+    fn noise_is_coherent() {
+        let mut independent = Grid::<Tile, 64, 64>::default();
+        let mut noisy = Grid::<Tile, 64, 64>::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let choices = vec![(B, 1.0), (R, 1.0)];
+        Scatter::new(choices.clone()).apply(&mut independent, &mut rng);
+        Scatter::new(choices)
+            .with_noise(8.0)
+            .apply(&mut noisy, &mut rng);
+        assert!(count(&noisy, &R) > 0);
+        assert!(components(&noisy, &R) * 10 < components(&independent, &R));
+    }
+}
@@ -0,0 +1,200 @@
+//! Ways of choosing which rule to apply when several rules match. Rules are tried in the order
+//! a scheduler gives and the first one with any matches is applied, so a scheduler decides the
+//! semantics of a model without knowing anything about grids or patches.
+
+use rand::{Rng, RngCore};
+
+use crate::determinism;
+
+pub trait Scheduler {
+    /// Indices of the rules to try this step, in the order to try them. Rules left out are not
+    /// applied this step.
+    fn order(&mut self, rule_count: usize, rng: &mut dyn RngCore) -> Vec<usize>;
+
+    /// Called with the rule that was applied, after a step which applied one. With an energy
+    /// function, only once accept has kept the application.
+    fn applied(&mut self, _rule_id: usize) {}
+
+    /// Only called when the simulation has an energy function. Called after a rule was applied,
+    /// with the change in energy it caused. Returning false undoes the application, which then
+    /// isn't passed to applied or counted in the profile.
+    fn accept(&mut self, _energy_delta: f64, _rng: &mut dyn RngCore) -> bool {
+        true
+    }
+}
+
+/// Always try rules in list order, so a rule only fires once every rule before it has run out of
+/// matches
+#[derive(Default)]
+pub struct Priority;
+
+impl Scheduler for Priority {
+    fn order(&mut self, rule_count: usize, _rng: &mut dyn RngCore) -> Vec<usize> {
+        (0..rule_count).collect()
+    }
+}
+
+/// Every matching rule is equally likely to be applied
+#[derive(Default)]
+pub struct UniformRandom;
+
+impl Scheduler for UniformRandom {
+    fn order(&mut self, rule_count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut order = (0..rule_count).collect::<Vec<_>>();
+        determinism::shuffle(&mut order, rng);
+        order
+    }
+}
+
+/// Matching rules are applied with probability proportional to their weight. Rules without a
+/// weight have weight 1, rules with weight 0 are never applied.
+pub struct Weighted {
+    pub weights: Vec<f64>,
+}
+
+impl Scheduler for Weighted {
+    fn order(&mut self, rule_count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        // Efraimidis-Spirakis: sorting by u^(1/w) gives a weighted sample without replacement, so
+        // the first matching rule in the order is chosen in proportion to its weight
+        let mut keyed = (0..rule_count)
+            .map(|rule_id| (rule_id, self.weights.get(rule_id).copied().unwrap_or(1.0)))
+            .filter(|&(_, weight)| weight > 0.0)
+            .map(|(rule_id, weight)| (rule_id, rng.gen::<f64>().powf(1.0 / weight)))
+            .collect::<Vec<_>>();
+        keyed.sort_by(|a, b| b.1.total_cmp(&a.1));
+        keyed.into_iter().map(|(rule_id, _)| rule_id).collect()
+    }
+}
+
+/// Start from the rule after the one applied last, wrapping around, so every rule gets a turn
+#[derive(Default)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl Scheduler for RoundRobin {
+    fn order(&mut self, rule_count: usize, _rng: &mut dyn RngCore) -> Vec<usize> {
+        let start = self.next % rule_count.max(1);
+        (start..rule_count).chain(0..start).collect()
+    }
+
+    fn applied(&mut self, rule_id: usize) {
+        self.next = rule_id + 1;
+    }
+}
+
+/// Simulated annealing: rules are tried in random order like UniformRandom, and applications
+/// which raise the energy are only kept with probability exp(-delta / temperature)
+/// (the Metropolis criterion). Applications which lower the energy are always kept. The
+/// temperature is multiplied by `cooling` after every candidate, so the simulation gets
+/// greedier over time.
+pub struct Annealing {
+    pub temperature: f64,
+    pub cooling: f64,
+}
+
+impl Annealing {
+    pub fn new(temperature: f64, cooling: f64) -> Self {
+        Self {
+            temperature,
+            cooling,
+        }
+    }
+}
+
+impl Scheduler for Annealing {
+    fn order(&mut self, rule_count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        UniformRandom.order(rule_count, rng)
+    }
+
+    fn accept(&mut self, energy_delta: f64, rng: &mut dyn RngCore) -> bool {
+        let temperature = self.temperature;
+        self.temperature *= self.cooling;
+        energy_delta <= 0.0
+            || (temperature > 0.0 && rng.gen::<f64>() < (-energy_delta / temperature).exp())
+    }
+}
+
+/// Names accepted by by_name. Weighted and Annealing need parameters so can't be chosen
+/// by name.
+pub const NAMES: [&str; 3] = ["priority", "random", "round-robin"];
+
+/// Look up a scheduler by the name used on the command line
+pub fn by_name(name: &str) -> Option<Box<dyn Scheduler>> {
+    match name {
+        "priority" => Some(Box::new(Priority)),
+        "random" => Some(Box::new(UniformRandom)),
+        "round-robin" => Some(Box::new(RoundRobin::default())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn priority_is_list_order() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(Priority.order(3, &mut rng), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn uniform_random_tries_every_rule() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut firsts = [0; 3];
+        for _ in 0..300 {
+            let mut order = UniformRandom.order(3, &mut rng);
+            firsts[order[0]] += 1;
+            order.sort();
+            assert_eq!(order, vec![0, 1, 2]);
+        }
+        assert!(firsts.iter().all(|&n| n > 50));
+    }
+
+    #[test]
+    fn weighted_skips_zero_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut scheduler = Weighted {
+            weights: vec![0.0, 9.0],
+        };
+        let mut firsts = [0; 3];
+        for _ in 0..1000 {
+            let order = scheduler.order(3, &mut rng);
+            assert!(!order.contains(&0));
+            firsts[order[0]] += 1;
+        }
+        // rule 1 has weight 9, rule 2 the default of 1
+        assert!(firsts[1] > 800 && firsts[2] > 50);
+    }
+
+    #[test]
+    fn annealing_cools() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut scheduler = Annealing::new(10.0, 0.5);
+        assert!(scheduler.accept(-1.0, &mut rng));
+        assert_eq!(scheduler.temperature, 5.0);
+        // at temperature 0 only improvements are accepted
+        scheduler.temperature = 0.0;
+        assert!(!scheduler.accept(0.1, &mut rng));
+        assert!(scheduler.accept(0.0, &mut rng));
+
+        let mut hot = Annealing::new(1e9, 1.0);
+        assert!((0..100).all(|_| hot.accept(1.0, &mut rng)));
+    }
+
+    #[test]
+    fn round_robin_starts_after_last_applied() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut scheduler = RoundRobin::default();
+        assert_eq!(scheduler.order(3, &mut rng), vec![0, 1, 2]);
+        scheduler.applied(1);
+        assert_eq!(scheduler.order(3, &mut rng), vec![2, 0, 1]);
+        scheduler.applied(2);
+        assert_eq!(scheduler.order(3, &mut rng), vec![0, 1, 2]);
+        assert_eq!(scheduler.order(0, &mut rng), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,277 @@
+//! Lua scripted steps (feature `lua`). A script defines a global function
+//!
+//! ```lua
+//! function step(grid, n, rng)
+//!     -- n is the number of steps run so far, starting at 0
+//!     if n == 0 then
+//!         grid:set(rng:int(0, grid:width() - 1), 0, "Red")
+//!     end
+//!     -- return true to skip the rules for this step
+//!     return false
+//! end
+//! ```
+//!
+//! which is called once per step, before the rules. The grid passed in has the methods
+//! `width()`, `height()`, `get(x, y)` and `set(x, y, name)`. Coordinates start at 0 and tiles are
+//! referred to by their name. `get` returns nil outside of the grid.
+//!
+//! `rng` is the simulation's own RNG, with the methods `int(low, high)`, a whole number from low
+//! to high inclusive, and `float()`, from 0 up to 1. Scripts should use it rather than
+//! `math.random` so runs stay reproducible from their seed, see determinism.
+
+use std::fs;
+use std::path::Path;
+
+use mlua::{Function, Lua, UserData, UserDataMethods};
+use rand::Rng;
+
+use crate::determinism;
+use crate::error::{BimpError, Result};
+use crate::rewrite::Grid;
+use crate::tile::Named;
+
+pub struct LuaScript {
+    lua: Lua,
+}
+
+impl LuaScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (source, name) = read_source(path.as_ref())?;
+        Self::from_source(&source, &name)
+    }
+
+    /// `name` is used to identify the script in error messages
+    pub fn from_source(source: &str, name: &str) -> Result<Self> {
+        Ok(Self {
+            lua: new_lua(source, name, "step")?,
+        })
+    }
+
+    /// Run the script's step function, drawing from `rng`. Returns true if the script asked for
+    /// the rules to be skipped this step.
+    pub fn step<T, R, const W: usize, const H: usize>(
+        &self,
+        grid: &mut Grid<T, W, H>,
+        n: usize,
+        rng: &mut R,
+    ) -> Result<bool>
+    where
+        T: Named + Copy,
+        R: Rng,
+    {
+        let step: Function = self.lua.globals().get("step")?;
+        let skip_rules = self.lua.scope(|scope| {
+            let grid = scope.create_nonstatic_userdata(LuaGrid(grid))?;
+            let rng = scope.create_nonstatic_userdata(LuaRng(rng))?;
+            let skip_rules: Option<bool> = step.call((grid, n, rng))?;
+            Ok(skip_rules.unwrap_or(false))
+        })?;
+        Ok(skip_rules)
+    }
+}
+
+/// A script defining a global function `score(grid)`, which returns a number rating the grid.
+/// Higher is better. The grid has the same methods as for `step`, changes made by the script are
+/// discarded.
+pub struct LuaScore {
+    lua: Lua,
+}
+
+impl LuaScore {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (source, name) = read_source(path.as_ref())?;
+        Self::from_source(&source, &name)
+    }
+
+    pub fn from_source(source: &str, name: &str) -> Result<Self> {
+        Ok(Self {
+            lua: new_lua(source, name, "score")?,
+        })
+    }
+
+    pub fn score<T, const W: usize, const H: usize>(&self, grid: &Grid<T, W, H>) -> Result<f64>
+    where
+        T: Named + Copy,
+    {
+        let score: Function = self.lua.globals().get("score")?;
+        let mut grid = grid.clone();
+        let score = self.lua.scope(|scope| {
+            let grid = scope.create_nonstatic_userdata(LuaGrid(&mut grid))?;
+            score.call::<_, f64>(grid)
+        })?;
+        Ok(score)
+    }
+}
+
+/// (source, name for error messages)
+fn read_source(path: &Path) -> Result<(String, String)> {
+    let source = fs::read_to_string(path).map_err(|source| BimpError::File {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok((source, path.display().to_string()))
+}
+
+/// Run the script's top level and check it defines the global function `entry_point`
+fn new_lua(source: &str, name: &str, entry_point: &str) -> Result<Lua> {
+    let lua = Lua::new();
+    lua.load(source).set_name(name).exec()?;
+    // fail early rather than on the first call
+    lua.globals().get::<_, Function>(entry_point)?;
+    Ok(lua)
+}
+
+/// The grid as seen by a script, only alive for the duration of one step call
+struct LuaGrid<'g, T, const W: usize, const H: usize>(&'g mut Grid<T, W, H>);
+
+impl<'g, T: Named + Copy, const W: usize, const H: usize> LuaGrid<'g, T, W, H> {
+    fn in_bounds(x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < W && (y as usize) < H
+    }
+}
+
+impl<'g, T: Named + Copy, const W: usize, const H: usize> UserData for LuaGrid<'g, T, W, H> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("width", |_, _, ()| Ok(W));
+        methods.add_method("height", |_, _, ()| Ok(H));
+        methods.add_method("get", |_, this, (x, y): (isize, isize)| {
+            Ok(Self::in_bounds(x, y).then(|| this.0.items[y as usize][x as usize].name()))
+        });
+        methods.add_method_mut("set", |_, this, (x, y, name): (isize, isize, String)| {
+            if !Self::in_bounds(x, y) {
+                return Err(mlua::Error::external(format!(
+                    "({}, {}) is outside of the {}x{} grid",
+                    x, y, W, H
+                )));
+            }
+            let tile = T::from_name(&name)
+                .ok_or_else(|| mlua::Error::external(format!("no tile named '{}'", name)))?;
+            this.0.items[y as usize][x as usize] = tile;
+            Ok(())
+        });
+    }
+}
+
+/// The simulation's RNG as seen by a script, only alive for the duration of one step call
+struct LuaRng<'r, R>(&'r mut R);
+
+impl<'r, R: Rng> UserData for LuaRng<'r, R> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("int", |_, this, (low, high): (i64, i64)| {
+            if high < low {
+                return Err(mlua::Error::external(format!(
+                    "rng:int({}, {}) has an empty range",
+                    low, high
+                )));
+            }
+            let len = (high - low) as usize + 1;
+            Ok(low + determinism::index(this.0, len) as i64)
+        });
+        methods.add_method_mut("float", |_, this, ()| Ok(this.0.gen::<f64>()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::determinism::SimRng;
+    use crate::tile::Tile;
+
+    fn rng() -> SimRng {
+        SimRng::seed_from_u64(0)
+    }
+
+    #[test]
+    fn read_and_write() {
+        let script = LuaScript::from_source(
+            r#"
+            function step(grid, n)
+                assert(grid:width() == 3 and grid:height() == 2)
+                assert(grid:get(5, 5) == nil)
+                grid:set(n, 1, grid:get(0, 0))
+                return n > 0
+            end
+            "#,
+            "test",
+        )
+        .unwrap();
+        let mut grid: Grid<Tile, 3, 2> = Default::default();
+        grid.items[0][0] = Tile::Red;
+
+        assert!(!script.step(&mut grid, 0, &mut rng()).unwrap());
+        assert!(script.step(&mut grid, 2, &mut rng()).unwrap());
+        assert_eq!(grid.items[1], [Tile::Red, Tile::Black, Tile::Red]);
+    }
+
+    #[test]
+    fn bad_tile_name() {
+        let script =
+            LuaScript::from_source(r#"function step(grid) grid:set(0, 0, "Nope") end"#, "test")
+                .unwrap();
+        let mut grid: Grid<Tile, 1, 1> = Default::default();
+        assert!(script.step(&mut grid, 0, &mut rng()).is_err());
+    }
+
+    #[test]
+    fn random_cells_follow_the_seed() {
+        let script = LuaScript::from_source(
+            r#"
+            function step(grid, n, rng)
+                assert(rng:float() < 1)
+                grid:set(rng:int(0, grid:width() - 1), rng:int(0, 0), "Red")
+                return false
+            end
+            "#,
+            "test",
+        )
+        .unwrap();
+        let run = |seed| {
+            let mut grid: Grid<Tile, 16, 1> = Default::default();
+            let mut rng = SimRng::seed_from_u64(seed);
+            for n in 0..4 {
+                script.step(&mut grid, n, &mut rng).unwrap();
+            }
+            grid
+        };
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+
+        let empty = LuaScript::from_source("function step(g, n, rng) rng:int(2, 1) end", "test");
+        let mut grid: Grid<Tile, 1, 1> = Default::default();
+        assert!(empty.unwrap().step(&mut grid, 0, &mut rng()).is_err());
+    }
+
+    #[test]
+    fn missing_step_function() {
+        assert!(LuaScript::from_source("x = 1", "test").is_err());
+    }
+
+    #[test]
+    fn score() {
+        let script = LuaScore::from_source(
+            r#"
+            function score(grid)
+                local reds = 0
+                for y = 0, grid:height() - 1 do
+                    for x = 0, grid:width() - 1 do
+                        if grid:get(x, y) == "Red" then reds = reds + 1 end
+                    end
+                end
+                grid:set(0, 0, "Blue")
+                return reds / 2
+            end
+            "#,
+            "test",
+        )
+        .unwrap();
+        let mut grid: Grid<Tile, 3, 2> = Default::default();
+        grid.items[0][0] = Tile::Red;
+        grid.items[1][2] = Tile::Red;
+
+        assert_eq!(script.score(&grid).unwrap(), 1.0);
+        assert_eq!(grid.items[0][0], Tile::Red);
+        assert!(LuaScore::from_source("function step() end", "test").is_err());
+    }
+}
@@ -0,0 +1,631 @@
+//! Search over rule applications for a sequence reaching a goal. Every match of every rule is a
+//! possible move, so unlike a normal run, which commits to one random match per step, a search
+//! can back out of applications which lead nowhere. Moves go through the same checks as a
+//! normal step: inactive rules and rules whose conditions don't hold have no moves, and moves a
+//! constraint rejects are left out. Conditions see the number of moves so far as the step
+//! count. Counters, fields and random cells aren't part of the searched state, so rules using
+//! them are rejected rather than searched differently from how they would run.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::io::{self, BufRead, Write};
+
+use rand::Rng;
+use tracing::debug;
+
+use crate::condition::Context;
+use crate::constraint::Constraint;
+use crate::counters::Counters;
+use crate::determinism;
+use crate::error::{BimpError, Result};
+use crate::matcher::MatchStrategy;
+use crate::placement;
+use crate::rewrite::{Grid, PatchOrientation, ReplacementRule};
+
+/// One rule application
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub rule_id: usize,
+    pub orientation: PatchOrientation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Follow one sequence of moves, in random order, as deep as allowed before backtracking.
+    /// Finds long sequences quickly but they aren't the shortest.
+    DepthFirst,
+    /// Try every sequence of one move, then every sequence of two, and so on. Finds a shortest
+    /// sequence but only reaches shallow depths.
+    BreadthFirst,
+}
+
+pub struct Limits {
+    /// Longest sequence of moves tried
+    pub max_depth: usize,
+    /// Give up after visiting this many distinct grids
+    pub max_states: usize,
+}
+
+/// Every move available from `grid` after `steps` moves
+pub fn moves<T, M, const W: usize, const H: usize, const S: usize>(
+    grid: &Grid<T, W, H>,
+    steps: usize,
+    rules: &[ReplacementRule<T, S>],
+    constraints: &[Box<dyn Constraint<T, W, H>>],
+    matcher: &mut M,
+) -> Vec<Move>
+where
+    T: Eq + Copy,
+    M: MatchStrategy<T, W, H, S> + ?Sized,
+{
+    // each state searched is a different grid
+    matcher.grid_changed();
+    let counters = Counters::default();
+    let context = Context::new(grid, steps, &counters);
+    rules
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| rule.enabled(&context))
+        .flat_map(|(rule_id, rule)| {
+            grid.get_rule_matches(rule, matcher)
+                .into_iter()
+                .filter(move |orientation| {
+                    let changes = Grid::<T, W, H>::replace_changes(rule, orientation);
+                    constraints
+                        .iter()
+                        .all(|constraint| constraint.allows(grid, &changes))
+                })
+                .map(move |orientation| Move {
+                    rule_id,
+                    orientation,
+                })
+        })
+        .collect()
+}
+
+/// Check that every rule can be searched: it mustn't use counters, fields or random cells,
+/// which the search doesn't follow
+pub fn check_searchable<T, const S: usize>(rules: &[ReplacementRule<T, S>]) -> Result<()> {
+    for (rule_id, rule) in rules.iter().enumerate() {
+        let reason = if !rule.guards.is_empty() || !rule.effects.is_empty() {
+            "it uses counters"
+        } else if !rule.field_guards.is_empty() {
+            "it has field guards"
+        } else if !rule.random_cells.is_empty() {
+            "it has random cells"
+        } else if rule.is_layered() {
+            "it reads or writes other layers"
+        } else {
+            continue;
+        };
+        return Err(BimpError::Unsearchable {
+            rule_id,
+            reason: reason.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Find a sequence of moves turning `start` into a grid where `goal` is true. Returns None if
+/// there is none within the limits, or an error if a rule can't be searched, see
+/// check_searchable. A grid is only explored again when it is reached in fewer moves than
+/// before, so different sequences reaching the same grid are explored once unless a shorter one
+/// can get further within max_depth. With a max_depth of 0 only `start` is checked.
+#[allow(clippy::too_many_arguments)]
+pub fn search<T, M, R, G, const W: usize, const H: usize, const S: usize>(
+    start: &Grid<T, W, H>,
+    rules: &[ReplacementRule<T, S>],
+    constraints: &[Box<dyn Constraint<T, W, H>>],
+    strategy: Strategy,
+    limits: &Limits,
+    matcher: &mut M,
+    rng: &mut R,
+    mut goal: G,
+) -> Result<Option<Vec<Move>>>
+where
+    T: Eq + Copy + Hash,
+    M: MatchStrategy<T, W, H, S> + ?Sized,
+    R: Rng + ?Sized,
+    G: FnMut(&Grid<T, W, H>) -> bool,
+{
+    check_searchable(rules)?;
+    if goal(start) {
+        return Ok(Some(Vec::new()));
+    }
+    if limits.max_depth == 0 {
+        return Ok(None);
+    }
+    // the fewest moves each grid has been reached in
+    let mut visited = HashMap::new();
+    visited.insert(start.clone(), 0);
+
+    let apply = |grid: &Grid<T, W, H>, mv: &Move| {
+        let rule = &rules[mv.rule_id];
+        let mut next = grid.clone();
+        next.replace_bounded_at(&rule.replace, &mv.orientation, &rule.boundaries);
+        next
+    };
+
+    let found = match strategy {
+        Strategy::DepthFirst => {
+            let shuffled = |grid: &Grid<T, W, H>, steps, matcher: &mut M, rng: &mut R| {
+                let mut moves = moves(grid, steps, rules, constraints, matcher);
+                determinism::shuffle(&mut moves, rng);
+                moves
+            };
+            // one frame per grid on the current path, holding the moves not yet tried from it
+            let mut stack = vec![(start.clone(), shuffled(start, 0, matcher, rng))];
+            let mut trace = Vec::new();
+            loop {
+                let Some((grid, untried)) = stack.last_mut() else {
+                    break None;
+                };
+                let Some(mv) = untried.pop() else {
+                    stack.pop();
+                    trace.pop();
+                    continue;
+                };
+                let next = apply(grid, &mv);
+                let depth = trace.len() + 1;
+                // a grid reached before in as few moves has been explored at least as deep
+                if visited.get(&next).is_some_and(|&seen| seen <= depth) {
+                    continue;
+                }
+                visited.insert(next.clone(), depth);
+                if visited.len() > limits.max_states {
+                    break None;
+                }
+                if goal(&next) {
+                    trace.push(mv);
+                    break Some(trace);
+                }
+                if depth < limits.max_depth {
+                    let next_moves = shuffled(&next, depth, matcher, rng);
+                    stack.push((next, next_moves));
+                    trace.push(mv);
+                }
+            }
+        }
+        Strategy::BreadthFirst => {
+            // every grid reached so far, with the move that reached it and the index of the grid
+            // it was reached from, so a sequence can be read back from any of them
+            let mut reached: Vec<(Move, Option<usize>)> = Vec::new();
+            let mut queue = VecDeque::from([(start.clone(), None, 0)]);
+            let trace_to = |reached: &[(Move, Option<usize>)], mut at: Option<usize>| {
+                let mut trace = Vec::new();
+                while let Some(i) = at {
+                    trace.push(reached[i].0);
+                    at = reached[i].1;
+                }
+                trace.reverse();
+                trace
+            };
+            loop {
+                let Some((grid, at, depth)) = queue.pop_front() else {
+                    break None;
+                };
+                if depth >= limits.max_depth {
+                    continue;
+                }
+                let mut found = None;
+                for mv in moves(&grid, depth, rules, constraints, matcher) {
+                    let next = apply(&grid, &mv);
+                    // grids are reached in order of depth, so the first time is the shallowest
+                    if visited.contains_key(&next) {
+                        continue;
+                    }
+                    visited.insert(next.clone(), depth + 1);
+                    reached.push((mv, at));
+                    let index = reached.len() - 1;
+                    if goal(&next) {
+                        found = Some(trace_to(&reached, Some(index)));
+                        break;
+                    }
+                    queue.push_back((next, Some(index), depth + 1));
+                }
+                if found.is_some() || visited.len() > limits.max_states {
+                    break found;
+                }
+            }
+        }
+    };
+    debug!(
+        states = visited.len(),
+        found = found.is_some(),
+        "search finished"
+    );
+    Ok(found)
+}
+
+/// Apply a sequence of moves, eg. one found by search. Every move must be one search could have
+/// made from the grid as it is when the move is reached: its rule searchable, active and with its
+/// conditions holding after the moves before it, matching there, placed where the rule's
+/// placement allows and allowed by every constraint. Otherwise the grid is left part way through
+/// the sequence.
+pub fn replay<T, const W: usize, const H: usize, const S: usize>(
+    grid: &mut Grid<T, W, H>,
+    rules: &[ReplacementRule<T, S>],
+    constraints: &[Box<dyn Constraint<T, W, H>>],
+    trace: &[Move],
+) -> Result<()>
+where
+    T: Eq + Copy,
+{
+    check_searchable(rules)?;
+    let counters = Counters::default();
+    for (i, mv) in trace.iter().enumerate() {
+        let invalid = |reason: String| BimpError::InvalidMove {
+            index: i + 1,
+            reason,
+        };
+        let rule = rules
+            .get(mv.rule_id)
+            .ok_or_else(|| invalid(format!("there is no rule {}", mv.rule_id)))?;
+        let (x, y) = mv.orientation.position;
+        if !rule.enabled(&Context::new(grid, i, &counters)) {
+            return Err(invalid(format!(
+                "rule {} is inactive or its conditions don't hold",
+                mv.rule_id
+            )));
+        }
+        let origin = rule
+            .boundaries
+            .normalize(placement::origin(&mv.orientation, S), W, H);
+        if !rule.placement.allows(origin, W, H) || !rule.matches_at(grid, &mv.orientation) {
+            return Err(invalid(format!(
+                "rule {} doesn't match at ({}, {})",
+                mv.rule_id, x, y
+            )));
+        }
+        let changes = Grid::<T, W, H>::replace_changes(rule, &mv.orientation);
+        if !constraints
+            .iter()
+            .all(|constraint| constraint.allows(grid, &changes))
+        {
+            return Err(invalid(format!(
+                "a constraint doesn't allow rule {} at ({}, {})",
+                mv.rule_id, x, y
+            )));
+        }
+        for ((x, y), tile) in changes {
+            grid.items[y][x] = tile;
+        }
+    }
+    Ok(())
+}
+
+/// One move per line: rule index, orientation, x offset and y offset separated by spaces. The
+/// orientation is PatchOrientation::index, so just the rotation for moves which aren't reflected.
+pub fn write_trace<O: Write>(trace: &[Move], out: &mut O) -> io::Result<()> {
+    for mv in trace {
+        let (x, y) = mv.orientation.position;
+        writeln!(out, "{} {} {} {}", mv.rule_id, mv.orientation.index(), x, y)?;
+    }
+    Ok(())
+}
+
+/// Read the format written by write_trace. Blank lines are skipped.
+pub fn read_trace<I: BufRead>(input: &mut I) -> Result<Vec<Move>> {
+    let mut trace = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parse_error = || BimpError::Parse {
+            line: i + 1,
+            message: format!("expected 'rule orientation x y', got '{}'", line),
+        };
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [rule_id, orientation, x, y] = fields[..] else {
+            return Err(parse_error());
+        };
+        let orientation = orientation
+            .parse::<usize>()
+            .ok()
+            .filter(|&index| index < 8)
+            .ok_or_else(parse_error)?;
+        trace.push(Move {
+            rule_id: rule_id.parse().map_err(|_| parse_error())?,
+            orientation: PatchOrientation {
+                rotation_times: orientation % 4,
+                reflected: orientation >= 4,
+                position: (
+                    x.parse().map_err(|_| parse_error())?,
+                    y.parse().map_err(|_| parse_error())?,
+                ),
+            },
+        });
+    }
+    Ok(trace)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::boundary::{Boundaries, Boundary};
+    use crate::condition::StepPeriod;
+    use crate::constraint::Connected;
+    use crate::counters::Effect;
+    use crate::matcher::NaiveScan;
+    use crate::metrics;
+    use crate::symmetry::Symmetry;
+    use crate::tile::Tile;
+
+    const R: Option<Tile> = Some(Tile::Red);
+    const K: Option<Tile> = Some(Tile::Black);
+    const G: Option<Tile> = Some(Tile::Green);
+
+    /// Red grows into Black, and can also turn Green, after which it is stuck
+    fn rules() -> Vec<ReplacementRule<Tile, 2>> {
+        vec![
+            ReplacementRule::new(
+                Grid {
+                    items: [[R, K], [None, None]],
+                },
+                Grid {
+                    items: [[R, R], [None, None]],
+                },
+            ),
+            ReplacementRule::new(
+                Grid {
+                    items: [[R, None], [None, None]],
+                },
+                Grid {
+                    items: [[G, None], [None, None]],
+                },
+            ),
+        ]
+    }
+
+    fn start() -> Grid<Tile, 4, 1> {
+        let mut grid: Grid<Tile, 4, 1> = Default::default();
+        grid.items[0][0] = Tile::Red;
+        grid
+    }
+
+    fn solve(strategy: Strategy, max_depth: usize) -> Option<Vec<Move>> {
+        let limits = Limits {
+            max_depth,
+            max_states: 10_000,
+        };
+        let goal = |grid: &Grid<Tile, 4, 1>| metrics::count(grid, &Tile::Green) == 4;
+        search(
+            &start(),
+            &rules(),
+            &[],
+            strategy,
+            &limits,
+            &mut NaiveScan,
+            &mut StdRng::seed_from_u64(0),
+            goal,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn trace_replays_to_goal() {
+        for strategy in [Strategy::DepthFirst, Strategy::BreadthFirst] {
+            let trace = solve(strategy, 10).unwrap();
+            let mut grid = start();
+            replay(&mut grid, &rules(), &[], &trace).unwrap();
+            assert_eq!(grid.items, [[Tile::Green; 4]]);
+        }
+    }
+
+    #[test]
+    fn replay_checks_moves() {
+        let trace = solve(Strategy::BreadthFirst, 10).unwrap();
+        // replaying twice fails as soon as a move no longer matches
+        let mut grid = start();
+        replay(&mut grid, &rules(), &[], &trace).unwrap();
+        assert!(matches!(
+            replay(&mut grid, &rules(), &[], &trace),
+            Err(BimpError::InvalidMove { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn breadth_first_is_shortest() {
+        // 3 grows and 4 recolors
+        assert_eq!(solve(Strategy::BreadthFirst, 10).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn depth_limit() {
+        assert!(solve(Strategy::BreadthFirst, 6).is_none());
+        assert!(solve(Strategy::DepthFirst, 6).is_none());
+        assert!(solve(Strategy::DepthFirst, 7).is_some());
+    }
+
+    #[test]
+    fn no_moves_at_depth_0() {
+        assert!(solve(Strategy::BreadthFirst, 0).is_none());
+        assert!(solve(Strategy::DepthFirst, 0).is_none());
+    }
+
+    #[test]
+    fn grids_first_reached_at_the_depth_limit() {
+        let one = |from: Tile, to: Tile| {
+            ReplacementRule::new(
+                Grid {
+                    items: [[Some(from)]],
+                },
+                Grid {
+                    items: [[Some(to)]],
+                },
+            )
+        };
+        // Red is one move away, or three through Blue and Green. From Red, White is two moves
+        // away, so the only way there within 3 moves is the short way to Red.
+        let rules = vec![
+            one(Tile::Black, Tile::Red),
+            one(Tile::Black, Tile::Blue),
+            one(Tile::Blue, Tile::Green),
+            one(Tile::Green, Tile::Red),
+            one(Tile::Red, Tile::Yellow),
+            one(Tile::Yellow, Tile::White),
+        ];
+        let limits = Limits {
+            max_depth: 3,
+            max_states: 100,
+        };
+        // whichever way round depth first goes
+        for seed in 0..16 {
+            let trace = search(
+                &Grid::<Tile, 1, 1>::default(),
+                &rules,
+                &[],
+                Strategy::DepthFirst,
+                &limits,
+                &mut NaiveScan,
+                &mut StdRng::seed_from_u64(seed),
+                |grid| grid.items[0][0] == Tile::White,
+            )
+            .unwrap();
+            let rule_ids = trace
+                .unwrap()
+                .iter()
+                .map(|mv| mv.rule_id)
+                .collect::<Vec<_>>();
+            assert_eq!(rule_ids, [0, 4, 5], "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn replay_checks_rules_and_constraints() {
+        let trace = solve(Strategy::BreadthFirst, 10).unwrap();
+        let mut rules = rules();
+        rules[1].active = false;
+        assert!(matches!(
+            replay(&mut start(), &rules, &[], &trace),
+            Err(BimpError::InvalidMove { .. })
+        ));
+        rules[1].active = true;
+        rules[0].placement = crate::placement::Placement::EvenCoordinates;
+        assert!(replay(&mut start(), &rules, &[], &trace).is_err());
+        rules[1].effects.push(Effect::Add("grown".to_string(), 1));
+        assert!(matches!(
+            replay(&mut start(), &rules, &[], &trace),
+            Err(BimpError::Unsearchable { .. })
+        ));
+
+        // Red may not split the Black region, so it can't turn the middle cell
+        let mut grid = Grid {
+            items: [[Tile::Black, Tile::Red, Tile::Black]],
+        };
+        let constraints: Vec<Box<dyn Constraint<Tile, 3, 1>>> =
+            vec![Box::new(Connected::new(vec![Tile::Black, Tile::Red]))];
+        let recolor = Move {
+            rule_id: 1,
+            orientation: PatchOrientation {
+                rotation_times: 0,
+                reflected: false,
+                position: (1, 0),
+            },
+        };
+        assert!(replay(&mut grid.clone(), &self::rules(), &constraints, &[recolor]).is_err());
+        replay(&mut grid, &self::rules(), &[], &[recolor]).unwrap();
+        assert_eq!(grid.items[0][1], Tile::Green);
+    }
+
+    #[test]
+    fn wrapped_search_replays_to_goal() {
+        // Red only grows to the right, so from the last cell it has to wrap around the edge
+        let rules = vec![ReplacementRule::new(
+            Grid {
+                items: [[R, K], [None, None]],
+            },
+            Grid {
+                items: [[R, R], [None, None]],
+            },
+        )
+        .with_symmetry(Symmetry::Identity)
+        .with_boundaries(Boundaries::new(Boundary::Wrap, Boundary::Clamp))];
+        let mut start: Grid<Tile, 4, 1> = Default::default();
+        start.items[0][3] = Tile::Red;
+        let limits = Limits {
+            max_depth: 10,
+            max_states: 10_000,
+        };
+        for strategy in [Strategy::DepthFirst, Strategy::BreadthFirst] {
+            let mut reached = None;
+            let trace = search(
+                &start,
+                &rules,
+                &[],
+                strategy,
+                &limits,
+                &mut NaiveScan,
+                &mut StdRng::seed_from_u64(0),
+                |grid: &Grid<Tile, 4, 1>| {
+                    let done = metrics::count(grid, &Tile::Red) == 4;
+                    if done {
+                        reached = Some(grid.clone());
+                    }
+                    done
+                },
+            )
+            .unwrap()
+            .unwrap();
+            let mut grid = start.clone();
+            replay(&mut grid, &rules, &[], &trace).unwrap();
+            assert_eq!(Some(grid), reached);
+        }
+    }
+
+    #[test]
+    fn moves_pass_the_same_checks_as_steps() {
+        let grid = start();
+        let all = |rules: &[ReplacementRule<Tile, 2>], steps| {
+            moves(&grid, steps, rules, &[], &mut NaiveScan)
+                .iter()
+                .map(|mv| mv.rule_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(all(&rules(), 0), [0, 1]);
+        let mut rules = rules();
+        rules[1].active = false;
+        assert_eq!(all(&rules, 0), [0]);
+        // conditions see the number of moves so far
+        rules[0].conditions.push(Box::new(StepPeriod {
+            period: 2,
+            phase: 1,
+        }));
+        assert_eq!(all(&rules, 0), []);
+        assert_eq!(all(&rules, 1), [0]);
+
+        // Red may not split the Black region, so it can't turn the middle cell
+        let grid = Grid {
+            items: [[Tile::Black, Tile::Red, Tile::Black]],
+        };
+        let constraints: Vec<Box<dyn Constraint<Tile, 3, 1>>> =
+            vec![Box::new(Connected::new(vec![Tile::Black, Tile::Red]))];
+        let recolor = &self::rules()[1..];
+        assert!(!moves(&grid, 0, recolor, &[], &mut NaiveScan).is_empty());
+        assert!(moves(&grid, 0, recolor, &constraints, &mut NaiveScan).is_empty());
+    }
+
+    #[test]
+    fn unsearchable_rules() {
+        let mut rules = rules();
+        assert!(check_searchable(&rules).is_ok());
+        rules[1].effects.push(Effect::Add("grown".to_string(), 1));
+        assert!(matches!(
+            check_searchable(&rules),
+            Err(BimpError::Unsearchable { rule_id: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn trace_round_trip() {
+        let trace = solve(Strategy::BreadthFirst, 10).unwrap();
+        let mut text = Vec::new();
+        write_trace(&trace, &mut text).unwrap();
+        assert_eq!(read_trace(&mut text.as_slice()).unwrap(), trace);
+        assert!(read_trace(&mut "0 1 2\n".as_bytes()).is_err());
+    }
+}
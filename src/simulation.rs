@@ -0,0 +1,993 @@
+use std::hash::Hash;
+use std::time::Duration;
+
+use rand::{RngCore, SeedableRng};
+use tracing::{debug, debug_span};
+
+use crate::constraint::Constraint;
+use crate::counters::Counters;
+use crate::cycle::{self, CycleDetector};
+use crate::determinism::{SimRng, Sorted};
+use crate::field::{FieldNode, Fields};
+use crate::matcher::{MatchStrategy, NaiveScan};
+use crate::metadata::Metadata;
+use crate::node::Node;
+use crate::profile::Profile;
+use crate::rewrite::{Grid, PatchOrientation, ReplacementRule};
+use crate::scheduler::{Priority, Scheduler};
+use crate::stats::RuleStats;
+use crate::tile::AsciiSymbol;
+
+/// Callbacks for things happening in a simulation, eg. for logging, statistics or driving
+/// external visuals. Every method does nothing by default.
+pub trait SimObserver {
+    /// `rule_id` is the index of the rule in the simulation's rule list
+    fn rule_applied(&mut self, _rule_id: usize, _orientation: &PatchOrientation) {}
+
+    /// With the Priority scheduler a run goes through phases where the same rule keeps
+    /// firing until it runs out of matches. Called when the rule that fired differs from the one
+    /// that fired on the previous step. `from` is None on the first step.
+    fn phase_changed(&mut self, _from: Option<usize>, _to: usize) {}
+
+    /// Called once, on the first step where no rule matched
+    fn converged(&mut self, _steps: usize) {}
+
+    /// Called once, on the step which brought back a grid seen `length` steps before. Only with
+    /// cycle detection on, see Simulation::set_cycle_detection.
+    fn cycle_detected(&mut self, _length: usize, _steps: usize) {}
+}
+
+/// Passes a scheduler everything but the applications, for when an application may still be
+/// rejected by the scheduler's accept
+struct Unaccepted<'s>(&'s mut dyn Scheduler);
+
+impl Scheduler for Unaccepted<'_> {
+    fn order(&mut self, rule_count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+        self.0.order(rule_count, rng)
+    }
+}
+
+/// Energy of a grid, see Simulation::set_energy
+pub type EnergyFn<T, const W: usize, const H: usize> = Box<dyn Fn(&Grid<T, W, H>) -> f64>;
+
+/// A grid, the rules that rewrite it and the RNG used to pick between matches. Runs with the
+/// same seed, grid, rules and match strategy always produce the same results, on any platform.
+/// In deterministic mode they don't depend on the match strategy either.
+pub struct Simulation<T, const W: usize, const H: usize, const S: usize> {
+    pub grid: Grid<T, W, H>,
+    /// The grid the simulation started with, for metadata
+    initial: Grid<T, W, H>,
+    seed: u64,
+    pub rules: Vec<ReplacementRule<T, S>>,
+    pub rng: SimRng,
+    /// Number of steps which applied a rule
+    pub steps: usize,
+    /// Read and written by rule guards and effects
+    pub counters: Counters,
+    /// Read by rule field guards, and changed by the field nodes after every step
+    pub fields: Fields<W, H>,
+    /// Layers 1 and up, read and written by rules with cells on other layers than the grid, see
+    /// add_layer
+    pub layers: Vec<Grid<T, W, H>>,
+    field_nodes: Vec<Box<dyn FieldNode<T, W, H>>>,
+    /// Matches are skipped unless every constraint allows them, see add_constraint
+    constraints: Vec<Box<dyn Constraint<T, W, H>>>,
+    /// How matches are found, NaiveScan unless changed with set_matcher
+    matcher: Box<dyn MatchStrategy<T, W, H, S>>,
+    /// Matches are sorted before one is picked, see set_deterministic
+    deterministic: bool,
+    /// Which rule is applied when several match, Priority unless changed with set_scheduler
+    scheduler: Box<dyn Scheduler>,
+    /// If set, every application is offered to the scheduler's accept with the change in energy
+    energy: Option<EnergyFn<T, W, H>>,
+    observers: Vec<Box<dyn SimObserver>>,
+    /// Time spent on each rule, if profiling is on
+    profile: Option<Profile>,
+    /// Firing counts by rule id, see rule_stats. Match counts are found when asked for.
+    stats: Vec<RuleStats>,
+    /// Hashes of recent grids, if cycle detection is on
+    cycles: Option<CycleDetector<T, W, H>>,
+    /// Length of the cycle the model is in, once one is found
+    cycle: Option<usize>,
+    last_rule: Option<usize>,
+    converged: bool,
+}
+
+impl<T: Eq + Copy + Default + 'static, const W: usize, const H: usize, const S: usize>
+    Simulation<T, W, H, S>
+{
+    pub fn new(grid: Grid<T, W, H>, rules: Vec<ReplacementRule<T, S>>, seed: u64) -> Self {
+        Self {
+            initial: grid.clone(),
+            grid,
+            seed,
+            rules,
+            rng: SimRng::seed_from_u64(seed),
+            steps: 0,
+            counters: Counters::default(),
+            fields: Fields::default(),
+            layers: Vec::new(),
+            field_nodes: Vec::new(),
+            constraints: Vec::new(),
+            matcher: Box::new(NaiveScan),
+            deterministic: false,
+            scheduler: Box::new(Priority),
+            energy: None,
+            observers: Vec::new(),
+            profile: None,
+            stats: Vec::new(),
+            cycles: None,
+            cycle: None,
+            last_rule: None,
+            converged: false,
+        }
+    }
+
+    pub fn set_matcher(&mut self, matcher: Box<dyn MatchStrategy<T, W, H, S>>) {
+        self.matcher = if self.deterministic {
+            Box::new(Sorted(matcher))
+        } else {
+            matcher
+        };
+    }
+
+    /// Strict deterministic mode: matches are put in a fixed order before one is picked, so every
+    /// match strategy gives the same results. Costs a sort per rule tried.
+    pub fn set_deterministic(&mut self) {
+        if !self.deterministic {
+            self.deterministic = true;
+            let matcher = std::mem::replace(&mut self.matcher, Box::new(NaiveScan));
+            self.matcher = Box::new(Sorted(matcher));
+        }
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Box<dyn Scheduler>) {
+        self.scheduler = scheduler;
+    }
+
+    /// Energy of a grid, lower is better. Only has an effect with a scheduler which can reject
+    /// applications, eg. Annealing.
+    pub fn set_energy<E: Fn(&Grid<T, W, H>) -> f64 + 'static>(&mut self, energy: E) {
+        self.energy = Some(Box::new(energy));
+    }
+
+    /// Start timing each rule, or stop and discard the timings so far. Not available on wasm32.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = enabled.then(|| self.profile.take().unwrap_or_default());
+    }
+
+    /// Time spent on each rule since profiling was turned on, None if it's off
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Remember the hashes of the last `window` grids, and stop `run` when a grid comes back.
+    /// Catches models which oscillate forever, eg. two rules undoing each other. Random models
+    /// can revisit a grid by chance too, so keep the window small.
+    pub fn set_cycle_detection(&mut self, window: usize)
+    where
+        T: Hash,
+    {
+        self.cycles = Some(CycleDetector::new(
+            &self.grid,
+            window,
+            cycle::tile_hash::<T>,
+        ));
+        self.cycle = None;
+    }
+
+    /// Length of the cycle the model is in, if cycle detection is on and found one. Cleared when
+    /// the grid is changed from outside the rules, see grid_changed.
+    pub fn cycle(&self) -> Option<usize> {
+        self.cycle
+    }
+
+    /// Switch a rule on or off. Switching rules can unstick a converged or cycling model, so both
+    /// are cleared.
+    pub fn set_rule_active(&mut self, rule_id: usize, active: bool) {
+        self.rules[rule_id].active = active;
+        self.converged = false;
+        self.cycle = None;
+    }
+
+    /// What it takes to reproduce the run so far, see metadata
+    pub fn metadata(&self) -> Metadata
+    where
+        T: AsciiSymbol,
+    {
+        Metadata::new(self.seed, &self.initial, &self.rules, self.steps)
+    }
+
+    /// Run `node` on the fields after every step, whether or not a rule was applied, after the
+    /// nodes added before it. The cycle detector only sees the grid, so a model driven by its
+    /// fields may be stopped as cycling when its tiles repeat.
+    pub fn add_field_node<N: FieldNode<T, W, H> + 'static>(&mut self, node: N) {
+        self.field_nodes.push(Box::new(node));
+    }
+
+    /// Only apply rules where `constraint` allows the change, eg. Connected to never cut off
+    /// part of the open area. Rejected matches are skipped, so a rule with no allowed matches
+    /// doesn't fire and the next one is tried.
+    pub fn add_constraint<C: Constraint<T, W, H> + 'static>(&mut self, constraint: C) {
+        self.constraints.push(Box::new(constraint));
+    }
+
+    /// Add a layer of tiles for rules to read and write, see layers. Returns its layer number,
+    /// counting from 1 since the grid is layer 0.
+    pub fn add_layer(&mut self, layer: Grid<T, W, H>) -> usize {
+        self.layers.push(layer);
+        self.layers.len()
+    }
+
+    fn update_fields(&mut self) {
+        for node in self.field_nodes.iter_mut() {
+            node.update(&mut self.fields, &self.grid);
+        }
+    }
+
+    pub fn add_observer<O: SimObserver + 'static>(&mut self, observer: O) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Apply the rule the scheduler picks from those with any matches. Returns false if no rule
+    /// matched, ie. the simulation has finished. With an energy function the scheduler may reject
+    /// the application, in which case the grid is unchanged but this still returns true.
+    pub fn step(&mut self) -> bool {
+        let _span = debug_span!("step", step = self.steps).entered();
+        let before = self
+            .energy
+            .as_ref()
+            .map(|energy| (self.grid.clone(), energy(&self.grid)));
+        let counters_before = before.as_ref().map(|_| self.counters.clone());
+        let layers_before = before.as_ref().map(|_| self.layers.clone());
+        // until the scheduler accepts the application, it isn't told about it and its timings
+        // are kept aside
+        let mut pending = (before.is_some() && self.profile.is_some()).then(Profile::default);
+        let mut unaccepted = Unaccepted(self.scheduler.as_mut());
+        let (scheduler, profile): (&mut dyn Scheduler, _) = if before.is_some() {
+            (&mut unaccepted, pending.as_mut())
+        } else {
+            (unaccepted.0, self.profile.as_mut())
+        };
+        let applied = self.grid.scheduled_random_replace(
+            &self.rules,
+            &mut self.counters,
+            &self.fields,
+            &mut self.layers,
+            &self.constraints,
+            self.steps,
+            scheduler,
+            self.matcher.as_mut(),
+            &mut self.rng,
+            profile,
+        );
+        if let (Some((rule_id, orientation)), Some((grid_before, energy_before)), Some(energy)) =
+            (&applied, before, &self.energy)
+        {
+            let delta = energy(&self.grid) - energy_before;
+            let accepted = self.scheduler.accept(delta, &mut self.rng);
+            if let (Some(profile), Some(mut pending)) = (self.profile.as_mut(), pending) {
+                if !accepted {
+                    let timings = pending.rule_mut(*rule_id);
+                    timings.apply = Duration::ZERO;
+                    timings.applications = 0;
+                }
+                profile.add(&pending);
+            }
+            if !accepted {
+                self.grid = grid_before;
+                let footprint = self.footprint(*rule_id, orientation);
+                self.matcher.cells_changed(&self.grid, &footprint);
+                self.counters = counters_before.expect("saved along with the grid");
+                self.layers = layers_before.expect("saved along with the grid");
+                self.update_fields();
+                return true;
+            }
+            self.scheduler.applied(*rule_id);
+        }
+        let running = match applied {
+            Some((rule_id, orientation)) => {
+                self.steps += 1;
+                self.converged = false;
+                if rule_id >= self.stats.len() {
+                    self.stats.resize(rule_id + 1, RuleStats::default());
+                }
+                self.stats[rule_id].fired += 1;
+                self.stats[rule_id].last_fired = Some(self.steps);
+                for observer in self.observers.iter_mut() {
+                    if self.last_rule != Some(rule_id) {
+                        observer.phase_changed(self.last_rule, rule_id);
+                    }
+                    observer.rule_applied(rule_id, &orientation);
+                }
+                self.last_rule = Some(rule_id);
+                self.detect_cycle(rule_id, &orientation);
+                true
+            }
+            None => {
+                if !self.converged {
+                    debug!(steps = self.steps, "converged");
+                    self.converged = true;
+                    for observer in self.observers.iter_mut() {
+                        observer.converged(self.steps);
+                    }
+                }
+                false
+            }
+        };
+        self.update_fields();
+        running
+    }
+
+    /// Rehash the cells the rule may have written and check whether the grid was seen recently
+    fn detect_cycle(&mut self, rule_id: usize, orientation: &PatchOrientation) {
+        let Some(cycles) = &mut self.cycles else {
+            return;
+        };
+        let footprint =
+            Grid::<T, W, H>::footprint::<S>(&self.rules[rule_id].boundaries, orientation);
+        cycles.update(&self.grid, footprint);
+        if let (Some(length), None) = (cycles.record(), self.cycle) {
+            debug!(length, steps = self.steps, "cycle");
+            self.cycle = Some(length);
+            for observer in self.observers.iter_mut() {
+                observer.cycle_detected(length, self.steps);
+            }
+        }
+    }
+
+    /// Cells the rule may have written when applied at `orientation`
+    fn footprint(&self, rule_id: usize, orientation: &PatchOrientation) -> Vec<(usize, usize)> {
+        Grid::<T, W, H>::footprint::<S>(&self.rules[rule_id].boundaries, orientation)
+    }
+
+    /// Call after changing `grid` directly, eg. from a script, so rules which may match again
+    /// are tried and the matcher and cycle detector see the whole new grid
+    pub fn grid_changed(&mut self) {
+        self.converged = false;
+        self.cycle = None;
+        self.matcher.grid_changed();
+        if let Some(cycles) = &mut self.cycles {
+            cycles.rehash(&self.grid);
+        }
+    }
+
+    /// Set the cell at (x, y) from outside the rules, eg. when embedded, see grid_changed
+    pub fn set(&mut self, (x, y): (usize, usize), tile: T) {
+        self.grid.items[y][x] = tile;
+        self.grid_changed();
+    }
+
+    /// How often each rule has fired and how many matches it has in the current grid, by rule
+    /// id. Finds every rule's matches, so costs about as much as a step.
+    pub fn rule_stats(&mut self) -> Vec<RuleStats> {
+        (0..self.rules.len())
+            .map(|rule_id| RuleStats {
+                matches: self.rule_matches(rule_id).len(),
+                ..self.stats.get(rule_id).cloned().unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Every match of the rule in the current grid, with its field guards and other layers
+    /// checked. Constraints
+    /// are only checked when applying, so some of these may be skipped then.
+    pub fn rule_matches(&mut self, rule_id: usize) -> Vec<PatchOrientation> {
+        let rule = &self.rules[rule_id];
+        let mut matches = self.grid.get_rule_matches(rule, self.matcher.as_mut());
+        matches.retain(|orientation| rule.field_guards_hold(&self.fields, orientation));
+        if rule.is_layered() {
+            matches.retain(|orientation| rule.layers_match_at(&self.layers, orientation));
+        }
+        matches
+    }
+
+    /// Run a whole-grid node once, using the simulation's RNG. Doesn't count as a step. Returns
+    /// true if any cell changed.
+    pub fn apply_node<N: Node<T, W, H> + ?Sized>(&mut self, node: &mut N) -> bool {
+        let _span = debug_span!("node").entered();
+        let changed = node.apply(&mut self.grid, &mut self.rng);
+        if changed {
+            self.grid_changed();
+        }
+        changed
+    }
+
+    /// Step up to `max_steps` times, stopping early if no rule matches or a cycle is found.
+    /// Steps where annealing rejected the application count towards `max_steps`. Returns the
+    /// number of steps which applied a rule.
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        let before = self.steps;
+        for _ in 0..max_steps {
+            if self.cycle.is_some() || !self.step() {
+                break;
+            }
+        }
+        self.steps - before
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::condition::{StepPeriod, TileCount};
+    use crate::counters::{Comparison, Effect, Guard};
+    use crate::symmetry::Symmetry;
+    use crate::tile::Tile;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Applied(usize),
+        Phase(Option<usize>, usize),
+        Converged(usize),
+        Cycle(usize, usize),
+    }
+
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl SimObserver for Recorder {
+        fn rule_applied(&mut self, rule_id: usize, _: &PatchOrientation) {
+            self.0.borrow_mut().push(Event::Applied(rule_id));
+        }
+        fn phase_changed(&mut self, from: Option<usize>, to: usize) {
+            self.0.borrow_mut().push(Event::Phase(from, to));
+        }
+        fn converged(&mut self, steps: usize) {
+            self.0.borrow_mut().push(Event::Converged(steps));
+        }
+        fn cycle_detected(&mut self, length: usize, steps: usize) {
+            self.0.borrow_mut().push(Event::Cycle(length, steps));
+        }
+    }
+
+    #[test]
+    fn observer_events() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        // Red -> Green, then Green -> Blue once there is no Red left
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[B]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Red]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        sim.add_observer(Recorder(events.clone()));
+
+        assert_eq!(sim.run(10), 4);
+        assert!(!sim.step());
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                Event::Phase(None, 0),
+                Event::Applied(0),
+                Event::Applied(0),
+                Event::Phase(Some(0), 1),
+                Event::Applied(1),
+                Event::Applied(1),
+                Event::Converged(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_counts_scans_and_applications() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[B]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Red]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        assert!(sim.profile().is_none());
+        sim.set_profiling(true);
+
+        // rule 0 is tried on every step, rule 1 only once there is no Red left
+        assert_eq!(sim.run(10), 4);
+        let profile = sim.profile().unwrap();
+        let counts = profile
+            .rules
+            .iter()
+            .map(|rule| (rule.scans, rule.applications))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(5, 2), (3, 2)]);
+
+        sim.set_profiling(false);
+        assert!(sim.profile().is_none());
+    }
+
+    #[test]
+    fn rule_stats_count_firings() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        const Y: Option<Tile> = Some(Tile::Yellow);
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[B]] }),
+            // dead rule, there is no Yellow
+            ReplacementRule::new(Grid { items: [[Y]] }, Grid { items: [[B]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Red, Tile::Green]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        assert_eq!(sim.run(2), 2);
+        let stats = sim.rule_stats();
+        assert_eq!(
+            stats,
+            vec![
+                RuleStats {
+                    fired: 2,
+                    last_fired: Some(2),
+                    matches: 0,
+                },
+                RuleStats {
+                    fired: 0,
+                    last_fired: None,
+                    matches: 3,
+                },
+                RuleStats::default(),
+            ]
+        );
+
+        sim.run(10);
+        let stats = sim.rule_stats();
+        assert_eq!(stats[1].fired, 3);
+        assert_eq!(stats[1].last_fired, Some(5));
+        assert_eq!(stats[2], RuleStats::default());
+    }
+
+    #[test]
+    fn cycle_stops_run() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        // flips between Red and Green forever
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[R]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Blue]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        sim.add_observer(Recorder(events.clone()));
+        sim.set_cycle_detection(8);
+
+        assert_eq!(sim.run(100), 2);
+        assert_eq!(sim.cycle(), Some(2));
+        assert!(events.borrow().contains(&Event::Cycle(2, 2)));
+        // stays stopped until the grid is changed from outside
+        assert_eq!(sim.run(100), 0);
+        sim.grid.items[0][1] = Tile::Red;
+        sim.grid_changed();
+        assert_eq!(sim.cycle(), None);
+        assert!(sim.run(100) > 0);
+        assert!(sim.cycle().is_some());
+        sim.set((1, 0), Tile::Blue);
+        assert_eq!(sim.cycle(), None);
+        assert!(sim.run(100) > 0);
+    }
+
+    #[test]
+    fn inactive_rules_are_skipped() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[B]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Red]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        sim.set_rule_active(0, false);
+        assert!(sim.step());
+        assert!(sim.grid.items[0].contains(&Tile::Blue));
+        sim.set_rule_active(1, false);
+        assert!(!sim.step());
+        sim.set_rule_active(0, true);
+        assert!(sim.step());
+        assert!(sim.grid.items[0].contains(&Tile::Green));
+    }
+
+    #[test]
+    fn round_robin_alternates_rules() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        // with priority, rule 0 would turn both Reds Green before rule 1 fires
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[B]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Red]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        sim.set_scheduler(Box::new(crate::scheduler::RoundRobin::default()));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        sim.add_observer(Recorder(events.clone()));
+
+        assert_eq!(sim.run(10), 4);
+        let applied = events
+            .borrow()
+            .iter()
+            .filter_map(|e| match e {
+                Event::Applied(rule_id) => Some(*rule_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(applied, vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn counter_quota() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const K: Option<Tile> = Some(Tile::Black);
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[K]] }, Grid { items: [[R]] })
+                .with_guard(Guard::new("rooms", Comparison::Less, 3))
+                .with_effect(Effect::Add("rooms".to_string(), 1)),
+        ];
+        let mut sim = Simulation::new(Grid::<Tile, 8, 1>::default(), rules, 0);
+
+        assert_eq!(sim.run(100), 3);
+        assert_eq!(sim.counters.get("rooms"), 3);
+        assert_eq!(crate::metrics::count(&sim.grid, &Tile::Red), 3);
+    }
+
+    #[test]
+    fn conditions_see_steps_and_grid() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const K: Option<Tile> = Some(Tile::Black);
+        let rules = vec![
+            // only on even steps, and only while there are fewer than 2 Reds
+            ReplacementRule::new(Grid { items: [[K]] }, Grid { items: [[R]] })
+                .with_condition(StepPeriod {
+                    period: 2,
+                    phase: 0,
+                })
+                .with_condition(TileCount::at_most(Tile::Red, 1)),
+            ReplacementRule::new(Grid { items: [[K]] }, Grid { items: [[G]] }),
+        ];
+        let mut sim = Simulation::new(Grid::<Tile, 6, 1>::default(), rules, 0);
+
+        assert_eq!(sim.run(100), 6);
+        assert_eq!(crate::metrics::count(&sim.grid, &Tile::Red), 2);
+        assert_eq!(crate::metrics::count(&sim.grid, &Tile::Green), 4);
+    }
+
+    #[test]
+    fn random_cells_sample_weights() {
+        const K: Option<Tile> = Some(Tile::Black);
+        const G: Option<Tile> = Some(Tile::Green);
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[K]] }, Grid { items: [[G]] }).with_random_cell(
+                (0, 0),
+                vec![(Tile::Green, 4.0), (Tile::DarkGreen, 1.0), (Tile::Red, 0.0)],
+            ),
+        ];
+        let mut sim = Simulation::new(Grid::<Tile, 50, 20>::default(), rules, 0);
+
+        assert_eq!(sim.run(2000), 1000);
+        let green = crate::metrics::count(&sim.grid, &Tile::Green);
+        let dark_green = crate::metrics::count(&sim.grid, &Tile::DarkGreen);
+        assert_eq!(green + dark_green, 1000);
+        assert!((700..900).contains(&green));
+    }
+
+    #[test]
+    fn neighbour_counts_filter_matches() {
+        use crate::morphology::Neighbourhood;
+        use crate::rewrite::NeighbourCount;
+        use Tile::{Black as B, Green as G, Red as R};
+
+        let rules =
+            vec![
+                ReplacementRule::new(Grid { items: [[Some(B)]] }, Grid { items: [[Some(G)]] })
+                    .with_neighbour_count(NeighbourCount::new(
+                        (0, 0),
+                        R,
+                        Neighbourhood::Moore,
+                        Comparison::GreaterOrEqual,
+                        3,
+                    )),
+            ];
+        let grid = Grid {
+            items: [[R, R, B, B], [B, R, B, B], [B, B, B, R]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+
+        assert_eq!(sim.run(100), 2);
+        assert_eq!(sim.grid.items, [[R, R, B, B], [G, R, G, B], [B, B, B, R]]);
+    }
+
+    #[test]
+    fn field_guards_follow_the_field() {
+        use crate::field::{Decay, Diffuse, FieldGuard, Source};
+        use Tile::{Black as K, Blue as B, Red as R};
+
+        // ice melts where the fire's heat has spread far enough
+        let melt = ReplacementRule::new(Grid { items: [[Some(B)]] }, Grid { items: [[Some(K)]] })
+            .with_field_guard(FieldGuard::new("heat", (0, 0), Comparison::Greater, 0.1));
+        let grid = Grid {
+            items: [[R, B, B, B, B, B]],
+        };
+        let mut sim = Simulation::new(grid, vec![melt], 0);
+        sim.add_field_node(Source::new("heat", R, 1.0));
+        sim.add_field_node(Diffuse::new("heat", 0.5));
+        sim.add_field_node(Decay::new("heat", 0.1));
+
+        // nothing is warm before the fields are first updated
+        assert!(!sim.step());
+        assert_eq!(sim.steps, 0);
+        assert!(sim.fields.value("heat", 1, 0) > 0.0);
+        for _ in 0..100 {
+            sim.step();
+        }
+        // the heat falls off with distance, past the threshold two cells away
+        assert_eq!(sim.grid.items, [[R, K, K, B, B, B]]);
+    }
+
+    #[test]
+    fn constraints_skip_rejected_matches() {
+        use crate::constraint::Connected;
+        use crate::metrics;
+        use Tile::{Black as B, Red as D, White as O};
+
+        // walls fill the floor as far as they can without sealing off either door
+        let wall = ReplacementRule::new(Grid { items: [[Some(O)]] }, Grid { items: [[Some(B)]] });
+        let mut grid = Grid { items: [[O; 6]; 6] };
+        grid.items[0][0] = D;
+        grid.items[5][5] = D;
+        let mut sim = Simulation::new(grid, vec![wall], 7);
+        sim.add_constraint(Connected::new(vec![O, D]));
+        let steps = sim.run(1000);
+        assert!(steps > 0);
+        let mut open = sim.grid.clone();
+        open.items.iter_mut().flatten().for_each(|tile| {
+            if *tile == D {
+                *tile = O;
+            }
+        });
+        assert_eq!(metrics::components(&open, &O), 1);
+        // a path between the doors is all that's left, no floor cell can go without cutting it
+        assert_eq!(metrics::count(&sim.grid, &O) + steps, 34);
+        assert!(metrics::count(&sim.grid, &O) >= 9);
+        assert!(!sim.step());
+    }
+
+    #[test]
+    fn symmetric_rules_match_once_per_placement() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const X: Option<Tile> = None;
+        let grid = Grid {
+            items: [[Tile::Red; 3]; 3],
+        };
+        let mut matcher = NaiveScan;
+        let count = |rule: &ReplacementRule<Tile, 2>, matcher: &mut NaiveScan| {
+            grid.get_rule_matches(rule, matcher).len()
+        };
+
+        let symmetric = ReplacementRule::new(
+            Grid {
+                items: [[R, R], [R, R]],
+            },
+            Grid {
+                items: [[G, G], [G, G]],
+            },
+        );
+        assert_eq!(symmetric.equivalent_orientations(), [0; 8]);
+        assert_eq!(count(&symmetric, &mut matcher), 4);
+
+        let half_turn = ReplacementRule::new(
+            Grid {
+                items: [[R, X], [X, R]],
+            },
+            Grid {
+                items: [[G, X], [X, G]],
+            },
+        );
+        assert_eq!(
+            half_turn.equivalent_orientations(),
+            [0, 1, 0, 1, 1, 0, 1, 0]
+        );
+        assert_eq!(count(&half_turn, &mut matcher), 8);
+
+        // same find patch, but the replacement tells rotations apart
+        let asymmetric = ReplacementRule::new(
+            Grid {
+                items: [[R, R], [R, R]],
+            },
+            Grid {
+                items: [[G, X], [X, X]],
+            },
+        );
+        assert_eq!(
+            asymmetric.equivalent_orientations(),
+            [0, 1, 2, 3, 1, 2, 3, 0]
+        );
+        assert_eq!(count(&asymmetric, &mut matcher), 16);
+        // mirrored placements are the same as rotated ones
+        let asymmetric = asymmetric.with_symmetry(Symmetry::All);
+        assert_eq!(count(&asymmetric, &mut matcher), 16);
+    }
+
+    #[test]
+    fn mixed_patch_sizes() {
+        use crate::placement::{Edge, Placement};
+
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        const X: Option<Tile> = None;
+        let grid = Grid {
+            items: [[Tile::Red; 4]; 3],
+        };
+        let mut matcher = NaiveScan;
+
+        // embedding doesn't change where a rule matches
+        let single = ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] });
+        assert_eq!(grid.get_rule_matches(&single, &mut matcher).len(), 12);
+        let embedded = single.embed::<3>();
+        assert_eq!(embedded.equivalent_orientations(), [0; 8]);
+        assert_eq!(grid.get_rule_matches(&embedded, &mut matcher).len(), 12);
+        let domino = || {
+            ReplacementRule::new(
+                Grid {
+                    items: [[R, R], [X, X]],
+                },
+                Grid {
+                    items: [[G, B], [X, X]],
+                },
+            )
+        };
+        let matches = grid.get_rule_matches(&domino(), &mut matcher).len();
+        assert_eq!(matches, 34);
+        let embedded = domino().embed::<3>();
+        assert_eq!(
+            grid.get_rule_matches(&embedded, &mut matcher).len(),
+            matches
+        );
+        // the origin is still the rule's own top left cell
+        let top = domino()
+            .with_symmetry(Symmetry::Identity)
+            .with_placement(Placement::Edge(Edge::Top))
+            .embed::<3>();
+        assert_eq!(grid.get_rule_matches(&top, &mut matcher).len(), 3);
+
+        // a 1x1 rule in the same set as a 3x3 one
+        let rules = vec![
+            ReplacementRule::new(
+                Grid {
+                    items: [[R, R, R], [R, R, R], [R, R, R]],
+                },
+                Grid {
+                    items: [[B, B, B], [B, B, B], [B, B, B]],
+                },
+            ),
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }).embed(),
+        ];
+        let mut sim = Simulation::new(grid, rules, 0);
+        assert_eq!(sim.run(100), 4);
+        let blue = crate::metrics::count(&sim.grid, &Tile::Blue);
+        let green = crate::metrics::count(&sim.grid, &Tile::Green);
+        assert_eq!((blue, green), (9, 3));
+    }
+
+    #[test]
+    fn deterministic_golden_run() {
+        let run = |matcher: &str| {
+            let mut sim = crate::models::simulation(1234);
+            sim.set_deterministic();
+            sim.set_matcher(crate::matcher::by_name(matcher).unwrap());
+            let steps = sim.run(300);
+            let mut ascii = Vec::new();
+            crate::ascii::write_grid(&sim.grid, &mut ascii).unwrap();
+            (steps, crate::determinism::fnv1a(&ascii))
+        };
+        // if this changes, every saved seed gives different results than before
+        assert_eq!(run("naive"), (300, 12049663169466759330));
+        assert_eq!(run("anchor"), run("naive"));
+        assert_eq!(run("packed"), run("naive"));
+    }
+
+    #[test]
+    fn annealing_rejects_energy_increases_when_cold() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        // Red <-> Green forever, but Green costs energy
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[R]] }),
+        ];
+        let grid = Grid {
+            items: [[Tile::Green; 8]],
+        };
+        let mut sim = Simulation::new(grid, rules, 0);
+        // rejected applications are undone in the packed grid too
+        sim.set_matcher(Box::new(crate::matcher::PackedScan::default()));
+        sim.set_scheduler(Box::new(crate::scheduler::Annealing::new(0.0, 1.0)));
+        sim.set_energy(|grid| crate::metrics::count(grid, &Tile::Green) as f64);
+
+        // only the 8 Green -> Red applications are kept, every other step is rejected
+        assert_eq!(sim.run(200), 8);
+        assert_eq!(sim.steps, 8);
+        assert_eq!(sim.grid.items, [[Tile::Red; 8]]);
+    }
+
+    /// Tries rules in order and accepts applications or not as told, recording what it was told
+    /// was applied
+    struct Judge {
+        accept: bool,
+        applied: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl Scheduler for Judge {
+        fn order(&mut self, rule_count: usize, _: &mut dyn RngCore) -> Vec<usize> {
+            (0..rule_count).collect()
+        }
+        fn applied(&mut self, rule_id: usize) {
+            self.applied.borrow_mut().push(rule_id);
+        }
+        fn accept(&mut self, _: f64, _: &mut dyn RngCore) -> bool {
+            self.accept
+        }
+    }
+
+    #[test]
+    fn rejected_applications_are_not_scheduled_or_profiled() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        let rules = vec![ReplacementRule::new(
+            Grid { items: [[R]] },
+            Grid { items: [[G]] },
+        )];
+        let applied = Rc::new(RefCell::new(Vec::new()));
+        let mut sim = Simulation::new(
+            Grid {
+                items: [[Tile::Red]],
+            },
+            rules,
+            0,
+        );
+        sim.set_scheduler(Box::new(Judge {
+            accept: false,
+            applied: applied.clone(),
+        }));
+        sim.set_energy(|_| 0.0);
+        sim.set_profiling(true);
+
+        assert!(sim.step());
+        assert_eq!(sim.grid.items, [[Tile::Red]]);
+        assert!(applied.borrow().is_empty());
+        let timings = &sim.profile().unwrap().rules[0];
+        assert_eq!((timings.scans, timings.applications), (1, 0));
+        assert_eq!(timings.apply, Duration::ZERO);
+
+        sim.set_scheduler(Box::new(Judge {
+            accept: true,
+            applied: applied.clone(),
+        }));
+        assert!(sim.step());
+        assert_eq!(sim.grid.items, [[Tile::Green]]);
+        assert_eq!(*applied.borrow(), [0]);
+        let timings = &sim.profile().unwrap().rules[0];
+        assert_eq!((timings.scans, timings.applications), (2, 1));
+    }
+}
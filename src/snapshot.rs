@@ -0,0 +1,129 @@
+//! Golden tests: the bundled models are run with fixed seeds until they converge and their final
+//! grids compared against checked-in ascii snapshots, so changes to matching or scheduling which
+//! change results don't go unnoticed. Set BIMP_UPDATE_SNAPSHOTS=1 to rewrite the snapshots after
+//! an intended change.
+
+use std::fmt::Write;
+
+use crate::rewrite::Grid;
+use crate::tile::AsciiSymbol;
+
+/// A human readable description of where two grids differ, or None if they are equal. Rows
+/// which differ are shown from both grids, with the differing columns marked underneath.
+fn diff<T: AsciiSymbol + PartialEq, const W: usize, const H: usize>(
+    expected: &Grid<T, W, H>,
+    actual: &Grid<T, W, H>,
+) -> Option<String> {
+    let line = |row: &[T; W]| row.iter().map(AsciiSymbol::to_char).collect::<String>();
+    let mut out = String::new();
+    let mut cells = 0;
+    for (y, (expected, actual)) in expected.items.iter().zip(actual.items.iter()).enumerate() {
+        if expected == actual {
+            continue;
+        }
+        let marks = expected
+            .iter()
+            .zip(actual.iter())
+            .map(|(e, a)| if e == a { ' ' } else { '^' })
+            .collect::<String>();
+        cells += marks.matches('^').count();
+        // writing to a String can't fail
+        let _ = writeln!(out, "row {:>3} expected {}", y, line(expected));
+        let _ = writeln!(out, "          actual {}", line(actual));
+        let _ = writeln!(out, "                 {}", marks.trim_end());
+    }
+    (cells > 0).then(|| format!("{} cells differ\n{}", cells, out))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::ascii;
+    use crate::determinism::SimRng;
+    use crate::model_file::{self, ModelFile};
+    use crate::models;
+    use crate::rewrite::RandomReplace;
+    use crate::simulation::Simulation;
+    use crate::tile::Tile;
+
+    /// Give up on runs which haven't converged after this many steps
+    const MAX_STEPS: usize = 100_000;
+
+    /// Compare `grid` with snapshots/`name`.txt, or write it there if BIMP_UPDATE_SNAPSHOTS is set
+    fn check<const W: usize, const H: usize>(name: &str, grid: &Grid<Tile, W, H>) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("snapshots")
+            .join(format!("{}.txt", name));
+        if env::var_os("BIMP_UPDATE_SNAPSHOTS").is_some() {
+            let mut out = Vec::new();
+            ascii::write_grid(grid, &mut out).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, out).unwrap();
+            return;
+        }
+        let file = fs::File::open(&path).unwrap_or_else(|e| {
+            panic!(
+                "can't open {} ({}), run with BIMP_UPDATE_SNAPSHOTS=1 to create it",
+                path.display(),
+                e
+            )
+        });
+        let expected: Grid<Tile, W, H> = ascii::read_grid(&mut BufReader::new(file)).unwrap();
+        if let Some(diff) = diff(&expected, grid) {
+            panic!("{} doesn't match its snapshot, {}", name, diff);
+        }
+    }
+
+    /// The built in square model's walk never converges, so the square grid is checked with the
+    /// backtracker from models/ instead
+    fn backtracker(seed: u64) {
+        let text = include_str!("../models/backtracker.bimp");
+        let model: ModelFile<Tile, 3> = model_file::read_model(&mut text.as_bytes()).unwrap();
+        let mut sim = Simulation::new(model.initial_grid::<24, 24>(seed), model.rules, seed);
+        sim.set_deterministic();
+        assert!(sim.run(MAX_STEPS) < MAX_STEPS, "didn't converge");
+        check(&format!("backtracker-seed-{}", seed), &sim.grid);
+    }
+
+    #[test]
+    fn backtracker_seed_1() {
+        backtracker(1);
+    }
+
+    #[test]
+    fn backtracker_seed_2() {
+        backtracker(2);
+    }
+
+    #[test]
+    fn hex_seed_1() {
+        let mut grid = models::hex_initial_grid();
+        let rules = models::hex_rules();
+        let mut rng = SimRng::seed_from_u64(1);
+        let steps = (0..MAX_STEPS)
+            .take_while(|_| grid.priority_random_replace(&rules, &mut rng).is_some())
+            .count();
+        assert!(steps < MAX_STEPS, "didn't converge");
+        check("hex-seed-1", &Grid { items: grid.items });
+    }
+
+    #[test]
+    fn diff_marks_cells() {
+        let expected = Grid {
+            items: [[Tile::Red, Tile::Black], [Tile::White, Tile::White]],
+        };
+        assert_eq!(diff(&expected, &expected), None);
+        let mut actual = expected.clone();
+        actual.items[1][0] = Tile::Blue;
+        let diff = diff(&expected, &actual).unwrap();
+        assert!(diff.starts_with("1 cells differ\nrow   1 "), "{}", diff);
+        assert!(diff.ends_with("                 ^\n"), "{}", diff);
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use bimp::constraint::Constraint;
+use bimp::determinism::SimRng;
+use bimp::error::{BimpError, Result};
+use bimp::matcher;
+use bimp::models;
+use bimp::search::{self, Limits};
+use bimp::tile::Tile;
+use rand::SeedableRng;
+
+use crate::batch::{Metric, Scorer};
+use crate::cli::Options;
+use crate::headless;
+
+/// A grid is a goal when its metric is at least `min`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Goal {
+    pub metric: Metric,
+    pub min: f64,
+}
+
+impl Goal {
+    /// Parse "METRIC>=N", eg. "components:White>=3"
+    pub fn parse(s: &str) -> std::result::Result<Self, String> {
+        let (metric, min) = s
+            .split_once(">=")
+            .ok_or_else(|| format!("expected a goal like count:Red>=10, got '{}'", s))?;
+        Ok(Self {
+            metric: Metric::parse(metric)?,
+            min: min
+                .parse()
+                .map_err(|_| format!("expected a number after >=, got '{}'", min))?,
+        })
+    }
+}
+
+/// Search for rule applications turning the initial grid into a goal grid, and write them to
+/// stdout in the format read by --replay
+pub fn run_search(options: &Options, seed: u64) -> Result<()> {
+    let strategy = options.search.expect("checked by caller");
+    let goal = options.goal.as_ref().expect("checked by cli");
+    let scorer = Scorer::new(&goal.metric)?;
+    let limits = Limits {
+        max_depth: options.max_depth,
+        max_states: options.max_states,
+    };
+    let mut matcher = matcher::by_name(&options.matcher).expect("checked by cli");
+    let mut score_error = None;
+    let constraints = constraints(options);
+
+    let trace = search::search(
+        &headless::initial_grid(options, seed)?,
+        &options.rules(),
+        &constraints,
+        strategy,
+        &limits,
+        matcher.as_mut(),
+        &mut SimRng::seed_from_u64(seed),
+        |grid| match scorer.score(grid) {
+            Ok(score) => score >= goal.min,
+            Err(e) => {
+                // stop the search on the first error
+                score_error.get_or_insert(e);
+                true
+            }
+        },
+    )?;
+    if let Some(e) = score_error {
+        return Err(e);
+    }
+    let trace = trace.ok_or_else(|| {
+        io::Error::other(format!(
+            "no sequence reaching the goal within {} steps and {} states",
+            limits.max_depth, limits.max_states
+        ))
+    })?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    headless::ignore_broken_pipe(search::write_trace(&trace, &mut out).and_then(|()| out.flush()))
+}
+
+/// The constraints chosen on the command line, see Options::connected
+fn constraints(
+    options: &Options,
+) -> Vec<Box<dyn Constraint<Tile, { models::WIDTH }, { models::HEIGHT }>>> {
+    options
+        .connected()
+        .into_iter()
+        .map(|connected| Box::new(connected) as Box<dyn Constraint<_, _, _>>)
+        .collect()
+}
+
+/// Apply the rule applications from options.replay to the initial grid and write the result.
+/// Models which draw their initial grid randomly need the seed the search was run with.
+pub fn run_replay(options: &Options, seed: u64) -> Result<()> {
+    let path = options.replay.as_ref().expect("checked by caller");
+    let file = File::open(path).map_err(|source| BimpError::File {
+        path: path.clone(),
+        source,
+    })?;
+    let trace = search::read_trace(&mut BufReader::new(file))?;
+    let mut grid = headless::initial_grid(options, seed)?;
+    let constraints = constraints(options);
+    search::replay(&mut grid, &options.rules(), &constraints, &trace)?;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    headless::ignore_broken_pipe(
+        bimp::ascii::write_grid(&grid, &mut out).and_then(|()| out.flush()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bimp::tile::Tile;
+
+    #[test]
+    fn parse_goal() {
+        assert_eq!(
+            Goal::parse("count:Red>=2.5"),
+            Ok(Goal {
+                metric: Metric::Count(Tile::Red),
+                min: 2.5
+            })
+        );
+        assert!(Goal::parse("count:Red").is_err());
+        assert!(Goal::parse("count:Red>=lots").is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Turning rule applications into notes. A Sonifier observes a simulation and hands a Note to
+//! its sink every time a rule with a sound fires, pitched by how high up the grid the match was
+//! and panned by how far across. Sinks can drive any audio backend; `render` and `write_wav` are
+//! a small built-in synth for writing a run out as a sound file.
+
+use std::io::{self, Write};
+
+use crate::rewrite::PatchOrientation;
+use crate::simulation::SimObserver;
+
+/// Semitones of the major pentatonic scale, which sounds fine whichever notes overlap
+const PENTATONIC: [i32; 5] = [0, 2, 4, 7, 9];
+
+/// The sound a rule makes when it fires
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuleSound {
+    /// MIDI note number when the rule fires at the bottom of the grid, eg. 60 for middle C
+    pub note: i32,
+    /// 0 to 1
+    pub velocity: f32,
+    /// Seconds
+    pub duration: f32,
+}
+
+impl RuleSound {
+    pub fn new(note: i32) -> Self {
+        Self {
+            note,
+            velocity: 0.5,
+            duration: 0.3,
+        }
+    }
+}
+
+/// One rule application as a note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub rule_id: usize,
+    /// Number of applications before this one, used as the note's time
+    pub step: usize,
+    /// Hz
+    pub frequency: f32,
+    /// -1 for the left edge of the grid to 1 for the right
+    pub pan: f32,
+    pub velocity: f32,
+    pub duration: f32,
+}
+
+/// Receives notes as rules fire
+pub trait AudioSink {
+    fn play(&mut self, note: Note);
+}
+
+impl<F: FnMut(Note)> AudioSink for F {
+    fn play(&mut self, note: Note) {
+        self(note)
+    }
+}
+
+/// A sound for each of `rules` rules, going up the scale from C3 in rule order
+pub fn default_sounds(rules: usize) -> Vec<Option<RuleSound>> {
+    (0..rules as i32)
+        .map(|rule_id| {
+            let octave = rule_id / PENTATONIC.len() as i32;
+            let degree = PENTATONIC[(rule_id % PENTATONIC.len() as i32) as usize];
+            Some(RuleSound::new(48 + 12 * octave + degree))
+        })
+        .collect()
+}
+
+/// Frequency of a MIDI note number, A4 (69) being 440Hz
+pub fn frequency(note: i32) -> f32 {
+    440.0 * 2f32.powf((note - 69) as f32 / 12.0)
+}
+
+/// Observer playing the sound of each rule application into a sink, see the module docs
+pub struct Sonifier<A, const W: usize, const H: usize> {
+    /// Indexed by rule, None for a silent rule
+    pub sounds: Vec<Option<RuleSound>>,
+    /// Octaves of the pentatonic scale the grid's rows are spread over, bottom to top
+    pub octaves: i32,
+    sink: A,
+    step: usize,
+}
+
+impl<A: AudioSink, const W: usize, const H: usize> Sonifier<A, W, H> {
+    pub fn new(sounds: Vec<Option<RuleSound>>, sink: A) -> Self {
+        Self {
+            sounds,
+            octaves: 2,
+            sink,
+            step: 0,
+        }
+    }
+
+    pub fn with_octaves(mut self, octaves: i32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    /// The note `sound` makes when its rule matches with its top left corner at `position`
+    pub fn note(&self, rule_id: usize, sound: &RuleSound, (x, y): (isize, isize)) -> Note {
+        let x = x.clamp(0, W as isize - 1) as f32;
+        let y = y.clamp(0, H as isize - 1) as i32;
+        let degree = (H as i32 - 1 - y) * self.octaves * PENTATONIC.len() as i32 / H as i32;
+        let semitones = 12 * (degree / PENTATONIC.len() as i32)
+            + PENTATONIC[(degree % PENTATONIC.len() as i32) as usize];
+        let pan = if W > 1 {
+            x / (W - 1) as f32 * 2.0 - 1.0
+        } else {
+            0.0
+        };
+        Note {
+            rule_id,
+            step: self.step,
+            frequency: frequency(sound.note + semitones),
+            pan,
+            velocity: sound.velocity,
+            duration: sound.duration,
+        }
+    }
+}
+
+impl<A: AudioSink, const W: usize, const H: usize> SimObserver for Sonifier<A, W, H> {
+    fn rule_applied(&mut self, rule_id: usize, orientation: &PatchOrientation) {
+        if let Some(Some(sound)) = self.sounds.get(rule_id) {
+            let note = self.note(rule_id, sound, orientation.position);
+            self.sink.play(note);
+        }
+        self.step += 1;
+    }
+}
+
+/// Synthesize `notes` as decaying sine tones, with `step_seconds` between steps. Returns
+/// interleaved left and right samples.
+pub fn render(notes: &[Note], sample_rate: u32, step_seconds: f32) -> Vec<f32> {
+    let end = notes
+        .iter()
+        .map(|note| note.step as f32 * step_seconds + note.duration)
+        .fold(0.0, f32::max);
+    let mut samples = vec![0.0; (end * sample_rate as f32).ceil() as usize * 2];
+    for note in notes {
+        let start = (note.step as f32 * step_seconds * sample_rate as f32) as usize;
+        let length = (note.duration * sample_rate as f32) as usize;
+        let (left, right) = ((1.0 - note.pan) / 2.0, (1.0 + note.pan) / 2.0);
+        for i in 0..length {
+            let t = i as f32 / sample_rate as f32;
+            // exponential decay, down to 1% by the end of the note
+            let envelope = note.velocity * (-4.6 * t / note.duration).exp();
+            let sample = (std::f32::consts::TAU * note.frequency * t).sin() * envelope;
+            let frame = (start + i) * 2;
+            if frame + 1 < samples.len() {
+                samples[frame] += sample * left;
+                samples[frame + 1] += sample * right;
+            }
+        }
+    }
+    // scale down rather than clip when notes pile up
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak > 1.0 {
+        samples.iter_mut().for_each(|s| *s /= peak);
+    }
+    samples
+}
+
+/// Write interleaved stereo samples as a 16 bit PCM WAV file
+pub fn write_wav<O: Write>(samples: &[f32], sample_rate: u32, out: &mut O) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM, 2 channels
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    // bytes per second, bytes per frame, bits per sample
+    out.write_all(&(sample_rate * 4).to_le_bytes())?;
+    out.write_all(&4u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::rewrite::{Grid, ReplacementRule};
+    use crate::simulation::Simulation;
+    use crate::tile::Tile;
+
+    #[test]
+    fn pitch_follows_height() {
+        let sonifier: Sonifier<_, 4, 10> = Sonifier::new(vec![], |_| {});
+        let sound = RuleSound::new(60);
+        let bottom = sonifier.note(0, &sound, (0, 9));
+        assert_eq!(bottom.frequency, frequency(60));
+        assert_eq!(bottom.pan, -1.0);
+        // two octaves of the scale over the rows, so the top row is a scale step short of 84
+        let top = sonifier.note(0, &sound, (3, 0));
+        assert_eq!(top.frequency, frequency(60 + 12 + 9));
+        assert_eq!(top.pan, 1.0);
+        assert!((frequency(69) - 440.0).abs() < 1e-3);
+        assert!((frequency(81) - 880.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn notes_for_sounding_rules() {
+        const R: Option<Tile> = Some(Tile::Red);
+        const G: Option<Tile> = Some(Tile::Green);
+        const B: Option<Tile> = Some(Tile::Blue);
+        let rules = vec![
+            ReplacementRule::new(Grid { items: [[R]] }, Grid { items: [[G]] }),
+            ReplacementRule::new(Grid { items: [[G]] }, Grid { items: [[B]] }),
+        ];
+        let mut sim = Simulation::new(
+            Grid {
+                items: [[Tile::Red, Tile::Red]],
+            },
+            rules,
+            0,
+        );
+        let notes = Rc::new(RefCell::new(Vec::new()));
+        let sink = {
+            let notes = notes.clone();
+            move |note| notes.borrow_mut().push(note)
+        };
+        // only the second rule makes a sound
+        sim.add_observer(Sonifier::<_, 2, 1>::new(
+            vec![None, Some(RuleSound::new(60))],
+            sink,
+        ));
+        sim.run(10);
+        let notes = notes.borrow();
+        assert_eq!(
+            notes
+                .iter()
+                .map(|n| (n.rule_id, n.step))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (1, 3)]
+        );
+    }
+
+    #[test]
+    fn wav_output() {
+        let note = Note {
+            rule_id: 0,
+            step: 1,
+            frequency: 440.0,
+            pan: 0.0,
+            velocity: 1.0,
+            duration: 0.5,
+        };
+        let samples = render(&[note], 1000, 0.5);
+        // a second of stereo, silent until the note starts
+        assert_eq!(samples.len(), 2000);
+        assert!(samples[..1000].iter().all(|&s| s == 0.0));
+        assert!(samples[1000..].iter().any(|&s| s != 0.0));
+        let mut wav = Vec::new();
+        write_wav(&samples, 1000, &mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + 4000);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+    }
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use nannou::image;
+use nannou::prelude::*;
+
+/// A single texture split into a regular grid of equally sized sprites
+pub struct SpriteSheet {
+    texture: wgpu::Texture,
+    columns: usize,
+    rows: usize,
+}
+
+impl SpriteSheet {
+    pub fn load<P: AsRef<Path>>(
+        app: &App,
+        path: P,
+        columns: usize,
+        rows: usize,
+    ) -> image::ImageResult<Self> {
+        let texture = wgpu::Texture::from_path(app, path)?;
+        Ok(Self {
+            texture,
+            columns,
+            rows,
+        })
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Number of sprites in the sheet
+    pub fn sprite_count(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// Area of the sprite at index in texture coordinates, where (0, 0) is the bottom left of the
+    /// texture and (1, 1) is the top right
+    pub fn area(&self, index: usize) -> Rect {
+        let col = (index % self.columns) as f32;
+        let row = (index / self.columns) as f32;
+        let w = 1.0 / self.columns as f32;
+        let h = 1.0 / self.rows as f32;
+        Rect::from_corner_points(
+            [col * w, 1.0 - row * h],
+            [(col + 1.0) * w, 1.0 - (row + 1.0) * h],
+        )
+    }
+
+    /// Nearest neighbour sampling so that pixel art sprites stay crisp when scaled up
+    pub fn sampler() -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerBuilder::new()
+            .min_filter(wgpu::FilterMode::Nearest)
+            .mag_filter(wgpu::FilterMode::Nearest)
+            .into_descriptor()
+    }
+}
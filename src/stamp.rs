@@ -0,0 +1,167 @@
+//! Drawing into a grid, for building initial grids without setting cells one by one, eg.
+//!
+//! ```
+//! # use bimp::rewrite::Grid;
+//! # use bimp::tile::Tile;
+//! let mut grid: Grid<Tile, 16, 16> = Default::default();
+//! grid.draw_rect((0, 0), (16, 2), Tile::Blue);
+//! grid.draw_circle((8, 8), 3, Tile::Green);
+//! grid.draw_line((0, 15), (15, 15), Tile::Red);
+//! ```
+//!
+//! Positions are (x, y) and may be outside the grid: anything drawn there is clipped.
+
+use rand::Rng;
+
+use crate::rewrite::Grid;
+
+impl<T: Copy, const W: usize, const H: usize> Grid<T, W, H> {
+    /// Set the cell at (x, y), if there is one
+    pub fn draw_point(&mut self, (x, y): (isize, isize), tile: T) {
+        if (0..W as isize).contains(&x) && (0..H as isize).contains(&y) {
+            self.items[y as usize][x as usize] = tile;
+        }
+    }
+
+    /// Every cell on the line from `from` to `to`, both ends included, each touching the next at
+    /// least at a corner
+    pub fn draw_line(&mut self, from: (isize, isize), to: (isize, isize), tile: T) {
+        // Bresenham
+        let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+        let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let (mut x, mut y) = from;
+        let mut error = dx + dy;
+        loop {
+            self.draw_point((x, y), tile);
+            if (x, y) == to {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Fill the `size` = (width, height) rectangle with its top left corner at `at`
+    pub fn draw_rect(&mut self, at: (isize, isize), size: (usize, usize), tile: T) {
+        for y in at.1..at.1 + size.1 as isize {
+            for x in at.0..at.0 + size.0 as isize {
+                self.draw_point((x, y), tile);
+            }
+        }
+    }
+
+    /// Fill every cell whose distance from `center` is at most `radius`, so radius 0 is the
+    /// center cell alone
+    pub fn draw_circle(&mut self, center: (isize, isize), radius: usize, tile: T) {
+        let r = radius as isize;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if dx * dx + dy * dy <= r * r {
+                    self.draw_point((center.0 + dx, center.1 + dy), tile);
+                }
+            }
+        }
+    }
+
+    /// Set each cell to `tile` with probability `density`, eg. 0.01 for a seed in every
+    /// hundred cells. See scatter::Scatter for weighted mixes of tiles and smoother noise.
+    pub fn scatter<R: Rng + ?Sized>(&mut self, tile: T, density: f64, rng: &mut R) {
+        for item in self.items.iter_mut().flatten() {
+            // drawn for every cell whatever the density, so the RNG is left the same either way
+            if rng.gen::<f64>() < density {
+                *item = tile;
+            }
+        }
+    }
+
+    /// Write the cells of `patch` which aren't None with its top left corner at `at`. Unlike
+    /// rule patches it doesn't need to be square.
+    pub fn stamp<const PW: usize, const PH: usize>(
+        &mut self,
+        patch: &Grid<Option<T>, PW, PH>,
+        at: (isize, isize),
+    ) {
+        for (y, row) in patch.items.iter().enumerate() {
+            for (x, item) in row.iter().enumerate() {
+                if let Some(tile) = item {
+                    self.draw_point((at.0 + x as isize, at.1 + y as isize), *tile);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use crate::determinism::SimRng;
+    use crate::metrics;
+    use crate::rewrite::Grid;
+    use crate::tile::Tile::{self, Black as K, Red as R};
+
+    fn rows<const W: usize, const H: usize>(grid: &Grid<Tile, W, H>) -> Vec<String> {
+        grid.items
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&tile| if tile == R { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lines() {
+        let mut grid: Grid<Tile, 5, 3> = Default::default();
+        grid.draw_line((0, 0), (4, 2), R);
+        assert_eq!(rows(&grid), ["#....", ".##..", "...##"]);
+        // the same cells either way round, and clipped at the edges
+        let mut reversed: Grid<Tile, 5, 3> = Default::default();
+        reversed.draw_line((4, 2), (0, 0), R);
+        assert_eq!(metrics::count(&reversed, &R), 5);
+        let mut clipped: Grid<Tile, 5, 3> = Default::default();
+        clipped.draw_line((-2, 1), (10, 1), R);
+        assert_eq!(rows(&clipped), [".....", "#####", "....."]);
+    }
+
+    #[test]
+    fn rects_and_circles() {
+        let mut grid: Grid<Tile, 5, 5> = Default::default();
+        grid.draw_rect((3, -1), (4, 2), R);
+        assert_eq!(rows(&grid)[..2], ["...##", "....."]);
+
+        let mut grid: Grid<Tile, 5, 5> = Default::default();
+        grid.draw_circle((2, 2), 2, R);
+        assert_eq!(rows(&grid), ["..#..", ".###.", "#####", ".###.", "..#.."]);
+        grid.draw_circle((0, 0), 0, K);
+        assert_eq!(grid.items[0][0], K);
+    }
+
+    #[test]
+    fn scatter_and_stamp() {
+        let mut grid: Grid<Tile, 32, 32> = Default::default();
+        let mut rng = SimRng::seed_from_u64(0);
+        grid.scatter(R, 0.25, &mut rng);
+        let count = metrics::count(&grid, &R);
+        assert!((200..312).contains(&count), "{}", count);
+        grid.scatter(K, 0.0, &mut rng);
+        assert_eq!(metrics::count(&grid, &R), count);
+
+        let mut grid: Grid<Tile, 3, 2> = Default::default();
+        grid.stamp(
+            &Grid {
+                items: [[Some(R), None, Some(R)]],
+            },
+            (1, 1),
+        );
+        assert_eq!(rows(&grid), ["...", ".#."]);
+    }
+}
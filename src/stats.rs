@@ -0,0 +1,52 @@
+//! How often each rule fires, for spotting rules which never fire or fire far more than the
+//! rest. See Simulation::rule_stats.
+
+use std::fmt;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleStats {
+    /// Number of steps which applied the rule
+    pub fired: usize,
+    /// Step number, counting from 1, of the last step which applied the rule
+    pub last_fired: Option<usize>,
+    /// Number of places the rule matches in the current grid
+    pub matches: usize,
+}
+
+/// One line per rule, in rule order, see Simulation::rule_stats
+pub struct Summary<'a>(pub &'a [RuleStats]);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (rule_id, stats) in self.0.iter().enumerate() {
+            write!(f, "rule {:>2} ", rule_id)?;
+            match stats.last_fired {
+                Some(step) => write!(f, "fired {} times, last on step {}", stats.fired, step)?,
+                None => write!(f, "never fired")?,
+            }
+            writeln!(f, ", {} matches", stats.matches)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summary_lines() {
+        let stats = [
+            RuleStats {
+                fired: 12,
+                last_fired: Some(40),
+                matches: 3,
+            },
+            RuleStats::default(),
+        ];
+        assert_eq!(
+            Summary(&stats).to_string(),
+            "rule  0 fired 12 times, last on step 40, 3 matches\nrule  1 never fired, 0 matches\n"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use bimp::ascii;
+use bimp::error::{BimpError, Result};
+use bimp::models;
+use bimp::rewrite::Grid;
+use bimp::simulation::Simulation;
+use bimp::tile::Tile;
+use tracing::info;
+
+use crate::cli::Options;
+use crate::export;
+use crate::headless::ignore_broken_pipe;
+
+/// Time between looks at the watched folder
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+type ModelGrid = Grid<Tile, { models::WIDTH }, { models::HEIGHT }>;
+
+/// Run the model from every grid read from stdin, writing each final grid to stdout as soon as
+/// it is done. Grids are in the ascii format separated by blank lines, both ways. The nth grid
+/// is run with seed + n.
+pub fn run_stdin(options: &Options, seed: u64) -> Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    stream(options, seed, &mut stdin.lock(), &mut out)
+}
+
+fn stream<I: io::BufRead, O: Write>(
+    options: &Options,
+    seed: u64,
+    input: &mut I,
+    out: &mut O,
+) -> Result<()> {
+    let mut index = 0u64;
+    while let Some(grid) = ascii::read_next_grid(input)? {
+        let sim = process(options, grid, seed.wrapping_add(index))?;
+        let result = ascii::write_grid(&sim.grid, out)
+            .and_then(|()| writeln!(out))
+            .and_then(|()| out.flush());
+        ignore_broken_pipe(result)?;
+        index += 1;
+    }
+    Ok(())
+}
+
+/// Watch `dir` for grid files, running the model from each one as it appears or changes and
+/// writing the final grid next to it as "<name>.out.txt", with a metadata sidecar. Runs until
+/// interrupted. Files which fail to parse, eg. because they are still being written, are tried
+/// again when they next change.
+pub fn run_watch(options: &Options, dir: &Path, seed: u64) -> Result<()> {
+    let file_error = |path: PathBuf| move |source| BimpError::File { path, source };
+    // modification time of each input when it was last processed
+    let mut seen: HashMap<PathBuf, SystemTime> = HashMap::new();
+    let mut runs = 0u64;
+    eprintln!("watching {}", dir.display());
+    loop {
+        let entries = fs::read_dir(dir).map_err(file_error(dir.to_path_buf()))?;
+        let mut inputs = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_input(path))
+            .collect::<Vec<_>>();
+        inputs.sort();
+        for path in inputs {
+            let Ok(modified) = fs::metadata(&path).and_then(|meta| meta.modified()) else {
+                continue;
+            };
+            if seen.get(&path) == Some(&modified) {
+                continue;
+            }
+            seen.insert(path.clone(), modified);
+            let output = output_path(&path);
+            match watch_one(options, &path, &output, seed.wrapping_add(runs)) {
+                Ok(steps) => eprintln!(
+                    "{} -> {} ({} steps)",
+                    path.display(),
+                    output.display(),
+                    steps
+                ),
+                Err(e) => eprintln!("skipped {}: {}", path.display(), e),
+            }
+            runs += 1;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Run the model from the grid in `input`, returning the number of steps
+fn watch_one(options: &Options, input: &Path, output: &Path, seed: u64) -> Result<usize> {
+    let file_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| BimpError::File { path, source }
+    };
+    let file = File::open(input).map_err(file_error(input))?;
+    let grid = ascii::read_grid(&mut BufReader::new(file))?;
+    let sim = process(options, grid, seed)?;
+    let mut out = BufWriter::new(File::create(output).map_err(file_error(output))?);
+    ascii::write_grid(&sim.grid, &mut out)?;
+    out.flush()?;
+    export::save_sidecar(&export::metadata(&sim), output);
+    Ok(sim.steps)
+}
+
+/// Plain text grids in the watched folder, leaving out the outputs written there
+fn is_input(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    name.ends_with(".txt") && !name.ends_with(".out.txt")
+}
+
+/// "level.txt" -> "level.out.txt"
+fn output_path(input: &Path) -> PathBuf {
+    input.with_extension("out.txt")
+}
+
+/// Run the model from `grid` until it converges, cycles or reaches --max-steps
+fn process(
+    options: &Options,
+    grid: ModelGrid,
+    seed: u64,
+) -> Result<Simulation<Tile, { models::WIDTH }, { models::HEIGHT }, 3>> {
+    let mut sim = Simulation::new(grid, options.rules(), seed);
+    options.configure(&mut sim)?;
+    sim.run(options.max_steps.unwrap_or(usize::MAX));
+    info!(seed, steps = sim.steps, "stream run finished");
+    Ok(sim)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grids_in_grids_out() {
+        let options = Options {
+            max_steps: Some(5),
+            ..Options::default()
+        };
+        let mut input = Vec::new();
+        for _ in 0..2 {
+            ascii::write_grid(&models::initial_grid(), &mut input).unwrap();
+            writeln!(input).unwrap();
+        }
+        let mut out = Vec::new();
+        stream(&options, 1, &mut input.as_slice(), &mut out).unwrap();
+        let mut out = out.as_slice();
+        let first: ModelGrid = ascii::read_grid(&mut out).unwrap();
+        let second: ModelGrid = ascii::read_grid(&mut out).unwrap();
+        assert_eq!(
+            first,
+            process(&options, models::initial_grid(), 1).unwrap().grid
+        );
+        assert_eq!(
+            second,
+            process(&options, models::initial_grid(), 2).unwrap().grid
+        );
+        assert_eq!(
+            ascii::read_next_grid::<Tile, _, 1, 1>(&mut out).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn watched_files() {
+        assert!(is_input(Path::new("in/level.txt")));
+        assert!(!is_input(Path::new("in/level.out.txt")));
+        assert!(!is_input(Path::new("in/level.out.txt.json")));
+        assert_eq!(
+            output_path(Path::new("in/level.txt")),
+            PathBuf::from("in/level.out.txt")
+        );
+    }
+}
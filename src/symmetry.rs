@@ -0,0 +1,140 @@
+//! Which orientations of a rule's patches are tried when matching. Uses the same notation as
+//! MarkovJunior for the subgroups of the square's 8 symmetries, eg. "(x)" for a rule which may be
+//! mirrored left to right but never rotated.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// (rotation_times, reflected) pairs, see PatchOrientation
+pub type Orientations = &'static [(usize, bool)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Symmetry {
+    /// "()": only as written
+    Identity,
+    /// "(x)": mirrored left to right
+    ReflectX,
+    /// "(y)": mirrored top to bottom
+    ReflectY,
+    /// "(x)(y)": mirrored either way, or both (which is a half turn)
+    ReflectXY,
+    /// "(xy+)": the 4 rotations, never mirrored
+    #[default]
+    Rotations,
+    /// "(xy)" or "all": every rotation, mirrored or not
+    All,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 6] = [
+        Symmetry::Identity,
+        Symmetry::ReflectX,
+        Symmetry::ReflectY,
+        Symmetry::ReflectXY,
+        Symmetry::Rotations,
+        Symmetry::All,
+    ];
+
+    /// Patches are mirrored left to right before they are rotated, so mirroring top to bottom is
+    /// a mirror then a half turn
+    pub fn orientations(&self) -> Orientations {
+        match self {
+            Symmetry::Identity => &[(0, false)],
+            Symmetry::ReflectX => &[(0, false), (0, true)],
+            Symmetry::ReflectY => &[(0, false), (2, true)],
+            Symmetry::ReflectXY => &[(0, false), (0, true), (2, true), (2, false)],
+            Symmetry::Rotations => &[(0, false), (1, false), (2, false), (3, false)],
+            Symmetry::All => &[
+                (0, false),
+                (1, false),
+                (2, false),
+                (3, false),
+                (0, true),
+                (1, true),
+                (2, true),
+                (3, true),
+            ],
+        }
+    }
+
+    /// MarkovJunior notation
+    pub fn name(&self) -> &'static str {
+        match self {
+            Symmetry::Identity => "()",
+            Symmetry::ReflectX => "(x)",
+            Symmetry::ReflectY => "(y)",
+            Symmetry::ReflectXY => "(x)(y)",
+            Symmetry::Rotations => "(xy+)",
+            Symmetry::All => "(xy)",
+        }
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => Ok(Symmetry::All),
+            name => Symmetry::ALL
+                .into_iter()
+                .find(|symmetry| symmetry.name() == name)
+                .ok_or_else(|| {
+                    let names = Symmetry::ALL.map(|symmetry| symmetry.name()).join(", ");
+                    format!("unknown symmetry {name:?}, expected one of {names} or all")
+                }),
+        }
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rewrite::Grid;
+
+    #[test]
+    fn names_round_trip() {
+        for symmetry in Symmetry::ALL {
+            assert_eq!(symmetry.name().parse(), Ok(symmetry));
+        }
+        assert_eq!("all".parse(), Ok(Symmetry::All));
+        assert!("(z)".parse::<Symmetry>().is_err());
+    }
+
+    #[test]
+    fn orientations_are_distinct() {
+        // no symmetries of its own, so every orientation looks different
+        let patch = Grid {
+            items: [[1, 2, 3], [4, 5, 6], [7, 8, 9]],
+        };
+        for symmetry in Symmetry::ALL {
+            let oriented = symmetry
+                .orientations()
+                .iter()
+                .map(|&(rotation_times, reflected)| patch.orient(rotation_times, reflected))
+                .collect::<Vec<_>>();
+            for (i, a) in oriented.iter().enumerate() {
+                assert!(oriented[i + 1..].iter().all(|b| a != b), "{symmetry}");
+            }
+        }
+    }
+
+    #[test]
+    fn reflections_mirror_the_named_axis() {
+        let patch = Grid {
+            items: [[1, 2], [3, 4]],
+        };
+        let mirrored = |symmetry: Symmetry| {
+            let (rotation_times, reflected) = symmetry.orientations()[1];
+            patch.orient(rotation_times, reflected).items
+        };
+        assert_eq!(mirrored(Symmetry::ReflectX), [[2, 1], [4, 3]]);
+        assert_eq!(mirrored(Symmetry::ReflectY), [[3, 4], [1, 2]]);
+    }
+}
@@ -0,0 +1,224 @@
+/// 8 bit per channel color, independent of any rendering backend
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// `t` of the way from self to `other`, clamped to 0..=1
+    pub fn lerp(self, other: Rgb, t: f32) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        let channel =
+            |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+        Rgb::new(
+            channel(self.red, other.red),
+            channel(self.green, other.green),
+            channel(self.blue, other.blue),
+        )
+    }
+}
+
+/// The full PICO-8 palette, not every color is used by every model
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Tile {
+    #[default]
+    Black,
+    DarkBlue,
+    DarkPurple,
+    DarkGreen,
+    Brown,
+    DarkGrey,
+    LightGrey,
+    White,
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Lavender,
+    Pink,
+    LightPeach,
+}
+
+impl Tile {
+    pub const ALL: [Tile; 16] = [
+        Tile::Black,
+        Tile::DarkBlue,
+        Tile::DarkPurple,
+        Tile::DarkGreen,
+        Tile::Brown,
+        Tile::DarkGrey,
+        Tile::LightGrey,
+        Tile::White,
+        Tile::Red,
+        Tile::Orange,
+        Tile::Yellow,
+        Tile::Green,
+        Tile::Blue,
+        Tile::Lavender,
+        Tile::Pink,
+        Tile::LightPeach,
+    ];
+}
+
+/// Symbols which have a name, so they can be referred to from outside of Rust (eg. scripts)
+pub trait Named: Sized {
+    fn name(&self) -> &'static str;
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+impl Named for Tile {
+    fn name(&self) -> &'static str {
+        match self {
+            Tile::Black => "Black",
+            Tile::DarkBlue => "DarkBlue",
+            Tile::DarkPurple => "DarkPurple",
+            Tile::DarkGreen => "DarkGreen",
+            Tile::Brown => "Brown",
+            Tile::DarkGrey => "DarkGrey",
+            Tile::LightGrey => "LightGrey",
+            Tile::White => "White",
+            Tile::Red => "Red",
+            Tile::Orange => "Orange",
+            Tile::Yellow => "Yellow",
+            Tile::Green => "Green",
+            Tile::Blue => "Blue",
+            Tile::Lavender => "Lavender",
+            Tile::Pink => "Pink",
+            Tile::LightPeach => "LightPeach",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Tile::ALL.iter().copied().find(|tile| tile.name() == name)
+    }
+}
+
+/// Tiles with a one byte representation, so rows of them can be compared several cells at a
+/// time in one u64, see matcher::PackedScan. Different tiles must have different bytes.
+pub trait PackedTile: Copy + Eq {
+    fn to_byte(self) -> u8;
+}
+
+impl PackedTile for Tile {
+    fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
+impl PackedTile for u8 {
+    fn to_byte(self) -> u8 {
+        self
+    }
+}
+
+/// Symbols with a single character representation, for plain text grids
+pub trait AsciiSymbol: Sized {
+    fn to_char(&self) -> char;
+    fn from_char(c: char) -> Option<Self>;
+}
+
+/// The same letters MarkovJunior uses for the PICO-8 palette
+impl AsciiSymbol for Tile {
+    fn to_char(&self) -> char {
+        match self {
+            Tile::Black => 'B',
+            Tile::DarkBlue => 'I',
+            Tile::DarkPurple => 'P',
+            Tile::DarkGreen => 'E',
+            Tile::Brown => 'N',
+            Tile::DarkGrey => 'D',
+            Tile::LightGrey => 'A',
+            Tile::White => 'W',
+            Tile::Red => 'R',
+            Tile::Orange => 'O',
+            Tile::Yellow => 'Y',
+            Tile::Green => 'G',
+            Tile::Blue => 'U',
+            Tile::Lavender => 'S',
+            Tile::Pink => 'K',
+            Tile::LightPeach => 'F',
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        Tile::ALL.iter().copied().find(|tile| tile.to_char() == c)
+    }
+}
+
+pub trait Colorable {
+    fn color(&self) -> Rgb;
+}
+
+/// If T implements Default, Option<T> implements Colorable with color = Default::default().color()
+impl<T: Colorable + Default> Colorable for Option<T> {
+    fn color(&self) -> Rgb {
+        match self {
+            Some(c) => c.color(),
+            None => {
+                let def: T = Default::default();
+                def.color()
+            }
+        }
+    }
+}
+
+impl Colorable for Tile {
+    fn color(&self) -> Rgb {
+        match self {
+            Tile::Black => Rgb::new(0, 0, 0),
+            Tile::DarkBlue => Rgb::new(29, 43, 83),
+            Tile::DarkPurple => Rgb::new(126, 37, 83),
+            Tile::DarkGreen => Rgb::new(0, 135, 81),
+            Tile::Brown => Rgb::new(171, 82, 54),
+            Tile::DarkGrey => Rgb::new(95, 87, 79),
+            Tile::LightGrey => Rgb::new(194, 195, 199),
+            Tile::White => Rgb::new(255, 241, 232),
+            Tile::Red => Rgb::new(255, 0, 77),
+            Tile::Orange => Rgb::new(255, 163, 0),
+            Tile::Yellow => Rgb::new(255, 236, 39),
+            Tile::Green => Rgb::new(0, 228, 54),
+            Tile::Blue => Rgb::new(41, 173, 255),
+            Tile::Lavender => Rgb::new(131, 118, 156),
+            Tile::Pink => Rgb::new(255, 119, 168),
+            Tile::LightPeach => Rgb::new(255, 204, 170),
+        }
+    }
+}
+
+/// Symbols which can be drawn with a cell of a sprite sheet instead of a flat color
+pub trait Sprite {
+    /// Index into the sprite sheet, counting left to right then top to bottom. None falls back to
+    /// drawing the symbol's flat color.
+    fn sprite_index(&self) -> Option<usize>;
+}
+
+/// Same as Colorable: if T implements Default, Option<T> uses the sprite of Default::default()
+impl<T: Sprite + Default> Sprite for Option<T> {
+    fn sprite_index(&self) -> Option<usize> {
+        match self {
+            Some(s) => s.sprite_index(),
+            None => {
+                let def: T = Default::default();
+                def.sprite_index()
+            }
+        }
+    }
+}
+
+/// Sprites are laid out in the same order as the palette, in a 4x4 sheet. Black is the background
+/// so is always left as a flat color.
+impl Sprite for Tile {
+    fn sprite_index(&self) -> Option<usize> {
+        match self {
+            Tile::Black => None,
+            other => Some(*other as usize),
+        }
+    }
+}
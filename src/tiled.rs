@@ -0,0 +1,130 @@
+use std::io::{self, Write};
+
+use crate::rewrite::Grid;
+
+/// Settings for exporting grids as Tiled (https://www.mapeditor.org) maps
+pub struct TiledExport<T> {
+    /// Path of the .tsx tileset the map refers to, relative to the exported map
+    pub tileset: String,
+    /// (width, height) of one tile in the tileset, in pixels
+    pub tile_size: (u32, u32),
+    /// Global tile ID for each symbol. GID 0 is an empty cell in Tiled and is used for any symbol
+    /// missing from the table. The tileset is referenced with firstgid=1, so the tile at index i
+    /// in the tileset has GID i + 1.
+    pub gids: Vec<(T, u32)>,
+}
+
+impl<T: PartialEq> TiledExport<T> {
+    pub fn gid(&self, symbol: &T) -> u32 {
+        self.gids
+            .iter()
+            .find(|(s, _)| s == symbol)
+            .map(|&(_, gid)| gid)
+            .unwrap_or(0)
+    }
+
+    /// A single CSV layer: one line per row, GIDs separated by commas
+    pub fn write_csv<W: Write, const GW: usize, const GH: usize>(
+        &self,
+        grid: &Grid<T, GW, GH>,
+        out: &mut W,
+    ) -> io::Result<()> {
+        for row in grid.items.iter() {
+            writeln!(out, "{}", self.csv_row(row))?;
+        }
+        Ok(())
+    }
+
+    /// An orthogonal TMX map with one CSV encoded tile layer
+    pub fn write_tmx<W: Write, const GW: usize, const GH: usize>(
+        &self,
+        grid: &Grid<T, GW, GH>,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let (tile_w, tile_h) = self.tile_size;
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            concat!(
+                r#"<map version="1.10" orientation="orthogonal" renderorder="right-down" "#,
+                r#"width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" "#,
+                r#"nextlayerid="2" nextobjectid="1">"#
+            ),
+            GW, GH, tile_w, tile_h
+        )?;
+        writeln!(
+            out,
+            r#" <tileset firstgid="1" source="{}"/>"#,
+            escape_attribute(&self.tileset)
+        )?;
+        writeln!(
+            out,
+            r#" <layer id="1" name="bimp" width="{}" height="{}">"#,
+            GW, GH
+        )?;
+        writeln!(out, r#"  <data encoding="csv">"#)?;
+        for (y, row) in grid.items.iter().enumerate() {
+            // every row but the last has a trailing comma
+            let separator = if y + 1 < GH { "," } else { "" };
+            writeln!(out, "{}{}", self.csv_row(row), separator)?;
+        }
+        writeln!(out, "  </data>")?;
+        writeln!(out, " </layer>")?;
+        writeln!(out, "</map>")?;
+        Ok(())
+    }
+
+    fn csv_row(&self, row: &[T]) -> String {
+        row.iter()
+            .map(|symbol| self.gid(symbol).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn escape_attribute(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tile::Tile;
+
+    fn export() -> TiledExport<Tile> {
+        TiledExport {
+            tileset: "tiles.tsx".to_string(),
+            tile_size: (16, 16),
+            gids: vec![(Tile::Red, 9), (Tile::White, 8)],
+        }
+    }
+
+    fn grid() -> Grid<Tile, 3, 2> {
+        Grid {
+            items: [
+                [Tile::Red, Tile::Black, Tile::White],
+                [Tile::White, Tile::Red, Tile::Green],
+            ],
+        }
+    }
+
+    #[test]
+    fn csv() {
+        let mut out = Vec::new();
+        export().write_csv(&grid(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "9,0,8\n8,9,0\n");
+    }
+
+    #[test]
+    fn tmx_layer_data() {
+        let mut out = Vec::new();
+        export().write_tmx(&grid(), &mut out).unwrap();
+        let tmx = String::from_utf8(out).unwrap();
+        assert!(tmx.contains(r#"width="3" height="2" tilewidth="16" tileheight="16""#));
+        assert!(tmx.contains(r#"<tileset firstgid="1" source="tiles.tsx"/>"#));
+        assert!(tmx.contains("9,0,8,\n8,9,0\n  </data>"));
+    }
+}
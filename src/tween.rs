@@ -0,0 +1,117 @@
+use bimp::rewrite::Grid;
+use bimp::tile::{Colorable, Rgb};
+
+/// A cell fading to the color of its current tile
+#[derive(Clone, Copy)]
+struct Fade {
+    from: Rgb,
+    /// Time the cell last changed, in seconds since the app started
+    started: f32,
+}
+
+/// Colors for drawing a grid which fade changed cells from their old color to their new one
+/// over `duration` seconds, instead of snapping. A cell which changes again while fading starts
+/// from the color it had got to.
+pub struct Tween<T, const W: usize, const H: usize> {
+    duration: f32,
+    /// The grid as of the last update
+    shown: Grid<T, W, H>,
+    /// Row major, one per cell
+    fades: Vec<Fade>,
+    now: f32,
+}
+
+impl<T: Colorable + Copy + PartialEq, const W: usize, const H: usize> Tween<T, W, H> {
+    pub fn new(grid: &Grid<T, W, H>, duration: f32, now: f32) -> Self {
+        let fades = grid
+            .items
+            .iter()
+            .flatten()
+            .map(|item| Fade {
+                from: item.color(),
+                started: f32::NEG_INFINITY,
+            })
+            .collect();
+        Self {
+            duration,
+            shown: grid.clone(),
+            fades,
+            now,
+        }
+    }
+
+    /// Start fading the cells which differ from the last update
+    pub fn update(&mut self, grid: &Grid<T, W, H>, now: f32) {
+        self.now = now;
+        for y in 0..H {
+            for x in 0..W {
+                let item = grid.items[y][x];
+                if item != self.shown.items[y][x] {
+                    let from = self.color(x, y);
+                    self.fades[y * W + x] = Fade { from, started: now };
+                    self.shown.items[y][x] = item;
+                }
+            }
+        }
+    }
+
+    /// Color of the cell at (x, y) as of the last update
+    pub fn color(&self, x: usize, y: usize) -> Rgb {
+        let fade = self.fades[y * W + x];
+        let to = self.shown.items[y][x].color();
+        if self.duration <= 0.0 {
+            return to;
+        }
+        fade.from
+            .lerp(to, (self.now - fade.started) / self.duration)
+    }
+
+    /// Whether the cell at (x, y) is still fading
+    pub fn fading(&self, x: usize, y: usize) -> bool {
+        self.now - self.fades[y * W + x].started < self.duration
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bimp::tile::Tile;
+
+    use super::*;
+
+    #[test]
+    fn changed_cells_fade() {
+        let mut grid = Grid {
+            items: [[Tile::Black, Tile::White]],
+        };
+        let mut tween = Tween::new(&grid, 1.0, 0.0);
+        assert_eq!(tween.color(0, 0), Tile::Black.color());
+        assert!(!tween.fading(0, 0));
+
+        grid.items[0][0] = Tile::White;
+        tween.update(&grid, 10.0);
+        assert_eq!(tween.color(0, 0), Tile::Black.color());
+        tween.update(&grid, 10.5);
+        assert!(tween.fading(0, 0));
+        assert_eq!(tween.color(0, 0), Rgb::new(128, 121, 116));
+        // untouched cells don't fade
+        assert!(!tween.fading(1, 0));
+        tween.update(&grid, 11.0);
+        assert_eq!(tween.color(0, 0), Tile::White.color());
+        assert!(!tween.fading(0, 0));
+    }
+
+    #[test]
+    fn changes_while_fading_start_from_the_blend() {
+        let mut grid = Grid {
+            items: [[Tile::Black]],
+        };
+        let mut tween = Tween::new(&grid, 1.0, 0.0);
+        grid.items[0][0] = Tile::White;
+        tween.update(&grid, 1.0);
+        tween.update(&grid, 1.5);
+        let halfway = tween.color(0, 0);
+        grid.items[0][0] = Tile::Black;
+        tween.update(&grid, 1.5);
+        assert_eq!(tween.color(0, 0), halfway);
+    }
+}
@@ -0,0 +1,168 @@
+//! Making a grid N times larger by replacing every cell with an N x N patch looked up by its tile,
+//! eg. a wall tile becoming a brick pattern. Used as a final decoration pass, and by Refine to
+//! carry a coarse model's result over to a finer grid.
+
+use rand::RngCore;
+
+use crate::determinism;
+use crate::rewrite::Grid;
+use crate::symmetry::Symmetry;
+
+/// The patch a tile becomes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template<T, const N: usize> {
+    pub patch: Grid<T, N, N>,
+    /// Orientations the patch is picked from at random for each cell, only as written by default
+    pub symmetry: Symmetry,
+}
+
+/// Table of templates by tile. Tiles without a template become a patch filled with themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upscale<T, const N: usize> {
+    pub templates: Vec<(T, Template<T, N>)>,
+}
+
+impl<T, const N: usize> Default for Upscale<T, N> {
+    fn default() -> Self {
+        Self {
+            templates: Vec::new(),
+        }
+    }
+}
+
+impl<T: PartialEq + Copy + Default, const N: usize> Upscale<T, N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_template(self, tile: T, patch: Grid<T, N, N>) -> Self {
+        self.with_oriented_template(tile, patch, Symmetry::Identity)
+    }
+
+    /// Each cell of `tile` gets a random orientation of `patch` out of those in `symmetry`
+    pub fn with_oriented_template(
+        mut self,
+        tile: T,
+        patch: Grid<T, N, N>,
+        symmetry: Symmetry,
+    ) -> Self {
+        self.templates.push((tile, Template { patch, symmetry }));
+        self
+    }
+
+    pub fn template(&self, tile: &T) -> Option<&Template<T, N>> {
+        self.templates
+            .iter()
+            .find(|(from, _)| from == tile)
+            .map(|(_, template)| template)
+    }
+
+    /// The patch of `tile`, oriented at random if its template has more than one orientation
+    pub fn patch(&self, tile: T, rng: &mut dyn RngCore) -> Grid<T, N, N> {
+        match self.template(&tile) {
+            Some(template) => {
+                let orientations = template.symmetry.orientations();
+                match orientations {
+                    [(0, false)] => template.patch.clone(),
+                    _ => {
+                        let (rotation_times, reflected) =
+                            orientations[determinism::index(rng, orientations.len())];
+                        template.patch.orient(rotation_times, reflected)
+                    }
+                }
+            }
+            None => Grid {
+                items: [[tile; N]; N],
+            },
+        }
+    }
+
+    /// `grid` with every cell replaced by its patch. The larger grid must be exactly N times the
+    /// size of `grid` in both directions.
+    pub fn upscale<const W: usize, const H: usize, const UW: usize, const UH: usize>(
+        &self,
+        grid: &Grid<T, W, H>,
+        rng: &mut dyn RngCore,
+    ) -> Grid<T, UW, UH> {
+        assert!(
+            UW == W * N && UH == H * N,
+            "a {}x{} grid in {}x{} patches doesn't fill a {}x{} grid",
+            W,
+            H,
+            N,
+            N,
+            UW,
+            UH
+        );
+        let mut upscaled: Grid<T, UW, UH> = Default::default();
+        for (y, row) in grid.items.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let patch = self.patch(*tile, rng);
+                for (py, patch_row) in patch.items.iter().enumerate() {
+                    upscaled.items[y * N + py][x * N..(x + 1) * N].copy_from_slice(patch_row);
+                }
+            }
+        }
+        upscaled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::determinism::SimRng;
+    use crate::tile::Tile;
+
+    #[test]
+    fn cells_become_patches() {
+        let upscale = Upscale::new().with_template(
+            Tile::Red,
+            Grid {
+                items: [[Tile::Red, Tile::White], [Tile::White, Tile::Red]],
+            },
+        );
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Blue]],
+        };
+        let upscaled: Grid<Tile, 4, 2> = upscale.upscale(&grid, &mut SimRng::seed_from_u64(0));
+        assert_eq!(
+            upscaled.items,
+            [
+                [Tile::Red, Tile::White, Tile::Blue, Tile::Blue],
+                [Tile::White, Tile::Red, Tile::Blue, Tile::Blue],
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_are_oriented() {
+        // a corner piece, which every rotation moves
+        let corner = Grid {
+            items: [[Tile::Red, Tile::Black], [Tile::Black, Tile::Black]],
+        };
+        let upscale =
+            Upscale::new().with_oriented_template(Tile::Red, corner.clone(), Symmetry::Rotations);
+        let mut rng = SimRng::seed_from_u64(0);
+        let mut seen = Vec::new();
+        for _ in 0..64 {
+            let patch = upscale.patch(Tile::Red, &mut rng);
+            assert!((0..4).any(|times| patch == corner.rotate(times)));
+            if !seen.contains(&patch) {
+                seen.push(patch);
+            }
+        }
+        assert_eq!(seen.len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fill")]
+    fn sizes_must_agree() {
+        let upscale: Upscale<Tile, 2> = Upscale::new();
+        let _: Grid<Tile, 3, 2> = upscale.upscale(
+            &Grid::<Tile, 2, 1>::default(),
+            &mut SimRng::seed_from_u64(0),
+        );
+    }
+}
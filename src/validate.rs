@@ -0,0 +1,268 @@
+//! Checks on a rule set before it is run, for rules which can't do anything useful. Problems are
+//! only warnings: the rules still run as written.
+
+use std::fmt;
+
+use crate::rewrite::{Grid, ReplacementRule};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem<T> {
+    /// The find patch needs a tile which isn't in the initial grid and which no rule that can
+    /// fire ever writes
+    Unreachable(T),
+    /// Applying the rule changes nothing
+    NoOp,
+    /// The find patch is all don't-cares, so it matches everywhere
+    MatchesAnything,
+    /// With the priority scheduler the rule never fires: whenever it matches, the earlier rule
+    /// matches in the same place
+    Shadowed { by: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic<T> {
+    pub rule_id: usize,
+    pub problem: Problem<T>,
+}
+
+impl<T: fmt::Debug> fmt::Display for Diagnostic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rule {}: ", self.rule_id)?;
+        match &self.problem {
+            Problem::Unreachable(tile) => write!(
+                f,
+                "never matches, its find patch needs {:?} which never appears",
+                tile
+            ),
+            Problem::NoOp => write!(f, "its replace patch doesn't change anything"),
+            Problem::MatchesAnything => write!(f, "its find patch is all don't-cares"),
+            Problem::Shadowed { by } => write!(f, "never fires, rule {} always fires first", by),
+        }
+    }
+}
+
+/// Every problem found with `rules` when run on `grid`, in rule order. `priority` is whether the
+/// rules are scheduled by strict priority, which is when rules can be shadowed.
+pub fn validate<T, const W: usize, const H: usize, const S: usize>(
+    grid: &Grid<T, W, H>,
+    rules: &[ReplacementRule<T, S>],
+    priority: bool,
+) -> Vec<Diagnostic<T>>
+where
+    T: PartialEq + Copy,
+{
+    let reachable = reachable_tiles(grid, rules);
+    let mut diagnostics = Vec::new();
+    for (rule_id, rule) in rules.iter().enumerate() {
+        let mut report = |problem| diagnostics.push(Diagnostic { rule_id, problem });
+        if let Some(tile) = find_tiles(rule).find(|tile| !reachable.contains(tile)) {
+            report(Problem::Unreachable(tile));
+        }
+        if is_no_op(rule) {
+            report(Problem::NoOp);
+        }
+        if find_tiles(rule).next().is_none() {
+            report(Problem::MatchesAnything);
+        }
+        if priority {
+            if let Some(by) = (0..rule_id).find(|&earlier| shadows(&rules[earlier], rule)) {
+                report(Problem::Shadowed { by });
+            }
+        }
+    }
+    diagnostics
+}
+
+fn find_tiles<T: Copy, const S: usize>(
+    rule: &ReplacementRule<T, S>,
+) -> impl Iterator<Item = T> + '_ {
+    rule.find.items.iter().flatten().flatten().copied()
+}
+
+/// Tiles in the grid, plus those written by rules whose find patches only need tiles which can
+/// appear, until no more are added
+fn reachable_tiles<T, const W: usize, const H: usize, const S: usize>(
+    grid: &Grid<T, W, H>,
+    rules: &[ReplacementRule<T, S>],
+) -> Vec<T>
+where
+    T: PartialEq + Copy,
+{
+    let mut reachable = Vec::new();
+    let add = |reachable: &mut Vec<T>, tile: T| {
+        let new = !reachable.contains(&tile);
+        if new {
+            reachable.push(tile);
+        }
+        new
+    };
+    for tile in grid.items.iter().flatten() {
+        add(&mut reachable, *tile);
+    }
+    let mut fired = vec![false; rules.len()];
+    loop {
+        let mut changed = false;
+        for (rule, fired) in rules.iter().zip(fired.iter_mut()) {
+            if *fired || !find_tiles(rule).all(|tile| reachable.contains(&tile)) {
+                continue;
+            }
+            *fired = true;
+            let written = rule.replace.items.iter().flatten().flatten().copied();
+            let sampled = rule
+                .random_cells
+                .iter()
+                .flat_map(|cell| cell.choices.iter().map(|(tile, _)| *tile));
+            for tile in written.chain(sampled) {
+                changed |= add(&mut reachable, tile);
+            }
+        }
+        if !changed {
+            return reachable;
+        }
+    }
+}
+
+/// The replace patch only writes what the find patch already requires, and there are no random
+/// cells or counter effects
+fn is_no_op<T: PartialEq, const S: usize>(rule: &ReplacementRule<T, S>) -> bool {
+    let writes_nothing = rule
+        .find
+        .items
+        .iter()
+        .flatten()
+        .zip(rule.replace.items.iter().flatten())
+        .all(|(find, replace)| replace.is_none() || replace == find);
+    writes_nothing && rule.random_cells.is_empty() && rule.effects.is_empty()
+}
+
+/// `earlier` matches wherever `later` does: it is active with nothing turning it off, tries every
+/// orientation `later` does in the same places, and each of its cells is a don't-care or the
+/// same as `later`'s
+fn shadows<T: PartialEq, const S: usize>(
+    earlier: &ReplacementRule<T, S>,
+    later: &ReplacementRule<T, S>,
+) -> bool {
+    let unconditional = earlier.active
+        && earlier.guards.is_empty()
+        && earlier.conditions.is_empty()
+        && earlier.neighbour_counts.is_empty()
+        && earlier.field_guards.is_empty();
+    let orientations = later
+        .symmetry
+        .orientations()
+        .iter()
+        .all(|orientation| earlier.symmetry.orientations().contains(orientation));
+    let more_general = earlier
+        .find
+        .items
+        .iter()
+        .flatten()
+        .zip(later.find.items.iter().flatten())
+        .all(|(earlier, later)| earlier.is_none() || earlier == later);
+    unconditional
+        && orientations
+        && more_general
+        && earlier.placement == later.placement
+        && earlier.boundaries == later.boundaries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::counters::{Comparison, Effect, Guard};
+    use crate::models;
+    use crate::symmetry::Symmetry;
+    use crate::tile::Tile;
+
+    const R: Option<Tile> = Some(Tile::Red);
+    const G: Option<Tile> = Some(Tile::Green);
+    const B: Option<Tile> = Some(Tile::Blue);
+    const X: Option<Tile> = None;
+
+    fn rule(find: [Option<Tile>; 2], replace: [Option<Tile>; 2]) -> ReplacementRule<Tile, 2> {
+        ReplacementRule::new(
+            Grid {
+                items: [find, [X, X]],
+            },
+            Grid {
+                items: [replace, [X, X]],
+            },
+        )
+    }
+
+    fn problems(rules: &[ReplacementRule<Tile, 2>], priority: bool) -> Vec<(usize, Problem<Tile>)> {
+        let grid = Grid {
+            items: [[Tile::Red, Tile::Black]],
+        };
+        validate(&grid, rules, priority)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.rule_id, diagnostic.problem))
+            .collect()
+    }
+
+    #[test]
+    fn bundled_model_is_clean() {
+        assert_eq!(
+            validate(&models::initial_grid(), &models::rules(), true),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn unreachable_tiles() {
+        // Green is written by rule 0, Blue by nothing
+        let rules = [rule([R, X], [G, X]), rule([G, B], [R, X])];
+        assert_eq!(
+            problems(&rules, false),
+            vec![(1, Problem::Unreachable(Tile::Blue))]
+        );
+        // rule 1 can only fire after Blue appears, so the Yellow it writes doesn't count
+        let rules = [
+            rule([R, X], [G, X]),
+            rule([B, X], [Some(Tile::Yellow), X]),
+            rule([Some(Tile::Yellow), X], [R, X]),
+        ];
+        assert_eq!(
+            problems(&rules, false),
+            vec![
+                (1, Problem::Unreachable(Tile::Blue)),
+                (2, Problem::Unreachable(Tile::Yellow)),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_ops_and_wildcards() {
+        let rules = [rule([R, X], [R, X]), rule([X, X], [G, X])];
+        assert_eq!(
+            problems(&rules, false),
+            vec![(0, Problem::NoOp), (1, Problem::MatchesAnything)]
+        );
+        // a counter effect is still something
+        let rules = [rule([R, X], [R, X]).with_effect(Effect::Add("steps".to_string(), 1))];
+        assert_eq!(problems(&rules, false), vec![]);
+    }
+
+    #[test]
+    fn shadowed_rules() {
+        let rules = [rule([R, X], [G, X]), rule([R, R], [B, B])];
+        assert_eq!(
+            problems(&rules, true),
+            vec![(1, Problem::Shadowed { by: 0 })]
+        );
+        // only with the priority scheduler
+        assert_eq!(problems(&rules, false), vec![]);
+        // a guard can turn the earlier rule off
+        let rules = [
+            rule([R, X], [G, X]).with_guard(Guard::new("n", Comparison::Less, 3)),
+            rule([R, R], [B, B]),
+        ];
+        assert_eq!(problems(&rules, true), vec![]);
+        // the earlier rule doesn't try every orientation the later one does
+        let rules = [
+            rule([R, X], [G, X]).with_symmetry(Symmetry::Identity),
+            rule([R, R], [B, B]),
+        ];
+        assert_eq!(problems(&rules, true), vec![]);
+    }
+}
@@ -0,0 +1,196 @@
+use nannou::prelude::*;
+
+use bimp::ndcoord::Coord;
+use bimp::ndgrid::NGrid;
+use bimp::tile::Colorable;
+
+use crate::layout::{self, Scaling};
+use crate::nannou_color;
+
+/// How a 3D grid is projected onto the window
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum VolumeProjection {
+    /// A single z layer drawn as a flat 2D grid
+    Slice,
+    /// Every layer up to and including the current slice, drawn as isometric cubes. Layers above
+    /// the slice are cut away so the inside of the volume can be inspected.
+    Isometric,
+}
+
+/// View state for a 3D grid of (x, y, z), where z is the layer axis
+pub struct VolumeView {
+    pub projection: VolumeProjection,
+    pub slice: usize,
+}
+
+impl Default for VolumeView {
+    fn default() -> Self {
+        Self {
+            projection: VolumeProjection::Isometric,
+            slice: 0,
+        }
+    }
+}
+
+impl VolumeView {
+    /// Tab switches projection, Up and Down move between slices. Returns true if the key was used.
+    pub fn key_pressed<T>(&mut self, grid: &NGrid<T, 3>, key: Key) -> bool {
+        let depth = grid.size().axes()[2].max(1) as usize;
+        match key {
+            Key::Tab => {
+                self.projection = match self.projection {
+                    VolumeProjection::Slice => VolumeProjection::Isometric,
+                    VolumeProjection::Isometric => VolumeProjection::Slice,
+                };
+            }
+            Key::Up => self.slice = (self.slice + 1).min(depth - 1),
+            Key::Down => self.slice = self.slice.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn draw<T: Colorable + Default + PartialEq>(
+        &self,
+        grid: &NGrid<T, 3>,
+        draw: &Draw,
+        bounds: Rect,
+        scaling: Scaling,
+    ) {
+        match self.projection {
+            VolumeProjection::Slice => {
+                let [w, h, _] = *grid.size().axes();
+                let rect = layout::grid_rect(bounds, w as usize, h as usize, scaling);
+                draw_slice(grid, self.slice as isize, draw, rect)
+            }
+            // the isometric projection always preserves aspect ratio
+            VolumeProjection::Isometric => draw_isometric(grid, self.slice as isize, draw, bounds),
+        }
+    }
+}
+
+fn draw_slice<T: Colorable>(grid: &NGrid<T, 3>, z: isize, draw: &Draw, rect: Rect) {
+    let [w, h, _] = *grid.size().axes();
+    let tile_w = rect.w() / w as f32;
+    let tile_h = rect.h() / h as f32;
+
+    for at in Coord::new_3d(0, 0, z).iter_volume(&Coord::new_3d(w, h, 1)) {
+        let [x, y, _] = *at.axes();
+        let tile_rect = Rect::from_x_y_w_h(
+            rect.left() + (x as f32 + 0.5) * tile_w,
+            rect.top() - (y as f32 + 0.5) * tile_h,
+            tile_w,
+            tile_h,
+        )
+        .pad(tile_w / 10.0);
+
+        draw.rect()
+            .xy(tile_rect.xy())
+            .wh(tile_rect.wh())
+            .color(nannou_color(grid[&at].color()));
+    }
+}
+
+/// The non-default voxels in layers up to `max_z`, in the order draw_isometric paints them
+fn isometric_voxels<T: Default + PartialEq>(grid: &NGrid<T, 3>, max_z: isize) -> Vec<Coord<3>> {
+    let [w, h, d] = *grid.size().axes();
+    let layers = (max_z + 1).min(d);
+    let empty = T::default();
+    let mut voxels = Coord::ZERO
+        .iter_volume(&Coord::new_3d(w, h, layers))
+        .filter(|at| grid[at] != empty)
+        .collect::<Vec<_>>();
+    voxels.sort_by_key(|at| {
+        let [x, y, z] = *at.axes();
+        (x + y, z)
+    });
+    voxels
+}
+
+/// Draws each non-default voxel as a cube with a lit top face and two shaded side faces. Voxels
+/// are visited back to front (by x + y, then z) so nearer cubes paint over further ones.
+fn draw_isometric<T: Colorable + Default + PartialEq>(
+    grid: &NGrid<T, 3>,
+    max_z: isize,
+    draw: &Draw,
+    rect: Rect,
+) {
+    let [w, h, d] = *grid.size().axes();
+
+    // u is half the width of a cube. Cube tops are twice as wide as they are tall.
+    let u = (rect.w() / (w + h) as f32).min(rect.h() / ((w + h) as f32 / 2.0 + d as f32));
+    let total_h = (w + h) as f32 * u / 2.0 + d as f32 * u;
+    let origin = pt2(
+        rect.x() - (w - h) as f32 * u / 2.0,
+        rect.top() - (rect.h() - total_h) / 2.0 - (d as f32 - 0.5) * u,
+    );
+
+    for at in isometric_voxels(grid, max_z) {
+        let [x, y, z] = *at.axes();
+        let c = origin + vec2((x - y) as f32 * u, -(x + y) as f32 * u / 2.0 + z as f32 * u);
+
+        let top = c + vec2(0.0, u / 2.0);
+        let right = c + vec2(u, 0.0);
+        let bottom = c + vec2(0.0, -u / 2.0);
+        let left = c + vec2(-u, 0.0);
+        let down = vec2(0.0, -u);
+
+        let color = grid[&at].color();
+        let shade = |amount: f32| {
+            rgb(
+                color.red as f32 / 255.0 * amount,
+                color.green as f32 / 255.0 * amount,
+                color.blue as f32 / 255.0 * amount,
+            )
+        };
+
+        draw.quad()
+            .points(left, bottom, bottom + down, left + down)
+            .color(shade(0.6));
+        draw.quad()
+            .points(bottom, right, right + down, bottom + down)
+            .color(shade(0.8));
+        draw.quad()
+            .points(top, right, bottom, left)
+            .color(shade(1.0));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bimp::tile::Tile;
+
+    use super::*;
+
+    fn tower() -> NGrid<Tile, 3> {
+        let text = "RB\nBB\n\nRB\nBG\n\nWB\nBB\n";
+        bimp::ascii::read_volume(&mut text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn keys_move_between_slices() {
+        let grid = tower();
+        let mut view = VolumeView::default();
+        assert!(view.key_pressed(&grid, Key::Down));
+        assert_eq!(view.slice, 0);
+        for _ in 0..5 {
+            view.key_pressed(&grid, Key::Up);
+        }
+        assert_eq!(view.slice, 2);
+        view.key_pressed(&grid, Key::Tab);
+        assert_eq!(view.projection, VolumeProjection::Slice);
+        assert!(!view.key_pressed(&grid, Key::A));
+    }
+
+    #[test]
+    fn layers_above_the_slice_are_cut_away() {
+        let grid = tower();
+        let axes = |voxels: Vec<Coord<3>>| voxels.iter().map(|at| *at.axes()).collect::<Vec<_>>();
+        assert_eq!(axes(isometric_voxels(&grid, 0)), [[0, 0, 0]]);
+        // back to front, then bottom to top
+        assert_eq!(
+            axes(isometric_voxels(&grid, 2)),
+            [[0, 0, 0], [0, 0, 1], [0, 0, 2], [1, 1, 1]]
+        );
+    }
+}
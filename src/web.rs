@@ -0,0 +1,52 @@
+//! Browser frontend. Build with
+//!
+//! ```text
+//! wasm-pack build --target web --no-default-features --features web
+//! ```
+//!
+//! then serve the repository root over http and open `web/index.html`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::models;
+use crate::simulation::Simulation;
+use crate::tile::{Colorable, Tile};
+
+/// The model from `models` with a seeded RNG, so a seed always produces the same run
+#[wasm_bindgen]
+pub struct WebSimulation(Simulation<Tile, { models::WIDTH }, { models::HEIGHT }, 3>);
+
+#[wasm_bindgen]
+impl WebSimulation {
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Self {
+        Self(models::simulation(seed as u64))
+    }
+
+    pub fn width(&self) -> usize {
+        models::WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        models::HEIGHT
+    }
+
+    /// Apply up to `steps` rules. Returns false once no rule matches any more.
+    pub fn step(&mut self, steps: u32) -> bool {
+        self.0.run(steps as usize) == steps as usize
+    }
+
+    /// One RGBA pixel per tile in row major order, ready to be wrapped in an `ImageData`
+    pub fn pixels(&self) -> Vec<u8> {
+        self.0
+            .grid
+            .items
+            .iter()
+            .flatten()
+            .flat_map(|tile| {
+                let c = tile.color();
+                [c.red, c.green, c.blue, 255]
+            })
+            .collect()
+    }
+}